@@ -0,0 +1,43 @@
+#ifndef VIVE_HASHLIFE_H
+#define VIVE_HASHLIFE_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct ViveUniverse ViveUniverse;
+
+ViveUniverse *vive_universe_new(const char *rule);
+
+void vive_universe_free(ViveUniverse *universe);
+
+int vive_set_cell(ViveUniverse *universe, int64_t x, int64_t y, uint8_t state);
+
+uint8_t vive_get_cell(const ViveUniverse *universe, int64_t x, int64_t y);
+
+int vive_step_n(ViveUniverse *universe, uint64_t n);
+
+uint64_t vive_generation(const ViveUniverse *universe);
+
+uint64_t vive_population(const ViveUniverse *universe);
+
+int vive_export_region(const ViveUniverse *universe,
+                       int64_t left,
+                       int64_t top,
+                       int64_t width,
+                       int64_t height,
+                       uint8_t *out,
+                       size_t len);
+
+int vive_load_rle(ViveUniverse *universe, const char *rle, int64_t x, int64_t y);
+
+int vive_load_file(ViveUniverse *universe, const char *path);
+
+char *vive_to_rle(const ViveUniverse *universe);
+
+void vive_string_free(char *text);
+
+#endif /* VIVE_HASHLIFE_H */
@@ -0,0 +1,86 @@
+"""Tests of the Python bindings, run against the built shared library:
+
+    cargo build --release --features ffi
+    python3 -m unittest discover python
+
+Set VIVE_HASHLIFE_LIB to test a library built elsewhere.
+"""
+
+import doctest
+import os
+import tempfile
+import unittest
+
+import vive_hashlife
+from vive_hashlife import Universe, ViveError
+
+GLIDER = "bo$2bo$3o!"
+
+
+def load_tests(loader, tests, ignore):
+    tests.addTests(doctest.DocTestSuite(vive_hashlife))
+    return tests
+
+
+class TestUniverse(unittest.TestCase):
+    def test_cells(self):
+        universe = Universe()
+        self.assertEqual((universe.generation, universe.population), (0, 0))
+        universe[3, -4] = 1
+        self.assertEqual((universe[3, -4], universe[4, -4], universe.population), (1, 0, 1))
+        universe[3, -4] = 0
+        self.assertEqual(universe.population, 0)
+        with self.assertRaises(ViveError):
+            universe[1 << 62, 0] = 1
+
+    def test_rules(self):
+        self.assertEqual(Universe("B36/S23").to_rle(), "x = 0, y = 0, rule = B36/S23\n!\n")
+        with self.assertRaises(ViveError):
+            Universe("B3/S2x")
+
+    def test_step(self):
+        universe = Universe()
+        universe.load_rle(GLIDER, 10, 0)
+        universe.step(4)
+        self.assertEqual((universe.generation, universe.population), (4, 5))
+        for x, y in [(12, 1), (13, 2), (11, 3), (12, 3), (13, 3)]:
+            self.assertEqual(universe[x, y], 1)
+        universe.step(1 << 40)
+        self.assertEqual((universe.generation, universe.population), (4 + (1 << 40), 5))
+
+        # A glider on a 129 by 128 torus does not come round within the step limit
+        torus = Universe()
+        torus.load_rle("x = 3, y = 3, rule = B3/S23:T129,128\n" + GLIDER)
+        with self.assertRaises(ViveError):
+            torus.step(1 << 20)
+        self.assertEqual((torus.generation, torus.population), (0, 5))
+
+    def test_rle(self):
+        universe = Universe()
+        universe.load_rle(GLIDER)
+        self.assertEqual(universe.to_rle(), "x = 3, y = 3, rule = B3/S23\n" + GLIDER + "\n")
+        with self.assertRaises(ViveError):
+            universe.load_rle("2oz!")
+
+        with tempfile.TemporaryDirectory() as directory:
+            path = os.path.join(directory, "glider.rle")
+            with open(path, "w") as file:
+                file.write(GLIDER)
+            copy = Universe()
+            copy.load_file(path)
+            self.assertEqual(copy.to_rle(), universe.to_rle())
+            with self.assertRaises(ViveError):
+                copy.load_file(os.path.join(directory, "missing.rle"))
+
+    def test_region(self):
+        universe = Universe()
+        universe.load_rle(GLIDER)
+        rows = [bytes(row) for row in universe.region(0, 0, 3, 3)]
+        self.assertEqual(rows, [b"\x00\x01\x00", b"\x00\x00\x01", b"\x01\x01\x01"])
+        self.assertEqual(len(universe.region(0, 0, 0, 0)), 0)
+        with self.assertRaises(ViveError):
+            universe.region(0, 0, -1, 3)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,131 @@
+"""Python bindings to the vive_hashlife engine, over its C interface
+
+Build the shared library with the ffi feature first:
+
+    cargo build --release --features ffi
+
+The library is looked for in ../target/release next to this file, or where
+the VIVE_HASHLIFE_LIB environment variable points.
+
+    >>> from vive_hashlife import Universe
+    >>> universe = Universe("B3/S23")
+    >>> universe.load_rle("bo$2bo$3o!")
+    >>> universe.step(1000)
+    >>> universe.population
+    5
+    >>> universe[251, 250]
+    1
+    >>> cells = universe.region(250, 250, 3, 3)  # a numpy array if numpy is installed
+"""
+
+import ctypes
+import os
+import sys
+
+__all__ = ["Universe", "ViveError"]
+
+
+def _library_path():
+    if "VIVE_HASHLIFE_LIB" in os.environ:
+        return os.environ["VIVE_HASHLIFE_LIB"]
+    name = {"win32": "vive_hashlife.dll", "darwin": "libvive_hashlife.dylib"}.get(sys.platform, "libvive_hashlife.so")
+    return os.path.join(os.path.dirname(os.path.abspath(__file__)), "..", "target", "release", name)
+
+
+_lib = ctypes.CDLL(_library_path())
+
+_universe = ctypes.c_void_p
+for _name, _args, _result in [
+    ("vive_universe_new", [ctypes.c_char_p], _universe),
+    ("vive_universe_free", [_universe], None),
+    ("vive_set_cell", [_universe, ctypes.c_int64, ctypes.c_int64, ctypes.c_uint8], ctypes.c_int),
+    ("vive_get_cell", [_universe, ctypes.c_int64, ctypes.c_int64], ctypes.c_uint8),
+    ("vive_step_n", [_universe, ctypes.c_uint64], ctypes.c_int),
+    ("vive_generation", [_universe], ctypes.c_uint64),
+    ("vive_population", [_universe], ctypes.c_uint64),
+    ("vive_export_region", [_universe, ctypes.c_int64, ctypes.c_int64, ctypes.c_int64, ctypes.c_int64,
+                            ctypes.c_void_p, ctypes.c_size_t], ctypes.c_int),
+    ("vive_load_rle", [_universe, ctypes.c_char_p, ctypes.c_int64, ctypes.c_int64], ctypes.c_int),
+    ("vive_load_file", [_universe, ctypes.c_char_p], ctypes.c_int),
+    ("vive_to_rle", [_universe], ctypes.c_void_p),
+    ("vive_string_free", [ctypes.c_void_p], None),
+]:
+    _function = getattr(_lib, _name)
+    _function.argtypes = _args
+    _function.restype = _result
+
+
+class ViveError(Exception):
+    """A rule, pattern or file the engine could not use"""
+
+
+class Universe:
+    """An unbounded universe stepped with HashLife
+
+    `rule` is a rulestring such as "B36/S23"; by default it is Conway's Game
+    of Life. Loading a pattern that names its own rule switches to that rule.
+    """
+
+    def __init__(self, rule=None):
+        self._handle = _lib.vive_universe_new(rule.encode() if rule is not None else None)
+        if not self._handle:
+            raise ViveError("invalid rule %r" % rule)
+
+    def __del__(self):
+        if getattr(self, "_handle", None):
+            _lib.vive_universe_free(self._handle)
+            self._handle = None
+
+    def __getitem__(self, cell):
+        x, y = cell
+        return _lib.vive_get_cell(self._handle, x, y)
+
+    def __setitem__(self, cell, state):
+        x, y = cell
+        if _lib.vive_set_cell(self._handle, x, y, int(state)) != 0:
+            raise ViveError("cell (%d, %d) is out of range" % (x, y))
+
+    @property
+    def generation(self):
+        return _lib.vive_generation(self._handle)
+
+    @property
+    def population(self):
+        return _lib.vive_population(self._handle)
+
+    def step(self, n=1):
+        """Advance n generations, in supersteps"""
+        if _lib.vive_step_n(self._handle, n) != 0:
+            raise ViveError("this finite grid does not repeat within the step limit")
+
+    def load_rle(self, rle, x=0, y=0):
+        """Replace the contents with an RLE pattern whose top left corner goes at (x, y)"""
+        if _lib.vive_load_rle(self._handle, rle.encode(), x, y) != 0:
+            raise ViveError("invalid RLE")
+
+    def load_file(self, path):
+        """Replace the contents with a pattern file (.rle, .mc, .cells, .lif)"""
+        if _lib.vive_load_file(self._handle, os.fsencode(path)) != 0:
+            raise ViveError("cannot load %s" % path)
+
+    def to_rle(self):
+        text = _lib.vive_to_rle(self._handle)
+        try:
+            return ctypes.string_at(text).decode()
+        finally:
+            _lib.vive_string_free(text)
+
+    def region(self, left, top, width, height):
+        """Cell states of a rectangle as a height by width array of uint8,
+        as a numpy array if numpy is installed and otherwise as rows of bytes"""
+        if width < 0 or height < 0:
+            raise ViveError("invalid region")
+        buffer = bytearray(width * height)
+        pointer = (ctypes.c_uint8 * len(buffer)).from_buffer(buffer) if buffer else None
+        if _lib.vive_export_region(self._handle, left, top, width, height, pointer, len(buffer)) != 0:
+            raise ViveError("invalid region")
+        try:
+            import numpy
+        except ImportError:
+            return [bytes(buffer[row * width:(row + 1) * width]) for row in range(height)]
+        return numpy.frombuffer(bytes(buffer), dtype=numpy.uint8).reshape(height, width)
@@ -0,0 +1,778 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::hashlife::{Node, Universe};
+use crate::journal::JournalEntry;
+use crate::pattern::{Rect, Transform};
+use crate::rule::State;
+
+/// How a pattern repeats: after `period` generations it is back in the same
+/// shape, moved by `(dx, dy)`. Still lifes and oscillators have no displacement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Periodicity {
+    pub period: u64,
+    pub dx: i64,
+    pub dy: i64,
+}
+
+impl Periodicity {
+    /// True if the pattern moves (a spaceship, or a puffer's moving front)
+    pub fn is_spaceship(&self) -> bool {
+        self.dx != 0 || self.dy != 0
+    }
+}
+
+impl fmt::Display for Periodicity {
+    /// Describes the pattern in the usual notation: "still life", "p2 oscillator",
+    /// "c/4 diagonal", "c/2 orthogonal", "(2,1)c/6 oblique"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_spaceship() {
+            return if self.period == 1 {
+                write!(f, "still life")
+            } else {
+                write!(f, "p{} oscillator", self.period)
+            };
+        }
+
+        let (dx, dy) = (self.dx.unsigned_abs(), self.dy.unsigned_abs());
+        let (distance, direction) = if dx == 0 || dy == 0 {
+            (dx.max(dy), "orthogonal")
+        } else if dx == dy {
+            (dx, "diagonal")
+        } else {
+            return write!(f, "({},{})c/{} oblique", dx.max(dy), dx.min(dy), self.period);
+        };
+        let divisor = gcd(distance, self.period);
+        let (distance, period) = (distance / divisor, self.period / divisor);
+        if distance == 1 && period == 1 {
+            write!(f, "c {}", direction)
+        } else if distance == 1 {
+            write!(f, "c/{} {}", period, direction)
+        } else {
+            write!(f, "{}c/{} {}", distance, period, direction)
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// The live cells of a universe relative to their bounding box, so two
+/// translated copies of a pattern compare (and hash) equal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NormalizedPattern {
+    /// Top-left corner of the bounding box
+    pub(crate) left: i64,
+    pub(crate) top: i64,
+    pub(crate) background: State,
+    /// `(x, y, state)` relative to the corner, sorted
+    pub(crate) cells: Vec<(i64, i64, State)>,
+    pub(crate) hash: u64,
+}
+
+impl NormalizedPattern {
+    pub(crate) fn of(universe: &Universe) -> Self {
+        let (left, top) = universe.bounding_box().map_or((0, 0), |(left, top, _, _)| (left, top));
+        let mut cells: Vec<_> = universe.live_cells().into_iter()
+            .map(|(x, y, state)| (x - left, y - top, state))
+            .collect();
+        cells.sort_unstable();
+
+        let mut hasher = DefaultHasher::new();
+        universe.background().hash(&mut hasher);
+        cells.hash(&mut hasher);
+        NormalizedPattern { left, top, background: universe.background(), cells, hash: hasher.finish() }
+    }
+
+    /// Same shape and background, wherever it is
+    pub(crate) fn same_shape(&self, other: &NormalizedPattern) -> bool {
+        self.hash == other.hash && self.background == other.background && self.cells == other.cells
+    }
+}
+
+/// When `Universe::run_until_stable` gives up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunLimits {
+    /// Stop after this many generations
+    pub max_generations: u64,
+    /// Stop once the population exceeds this
+    pub max_population: Option<u64>,
+    /// Longest period (in generations) recognised as the pattern having stabilised
+    pub max_period: u64,
+}
+
+impl Default for RunLimits {
+    fn default() -> Self {
+        RunLimits { max_generations: 100_000, max_population: None, max_period: 1000 }
+    }
+}
+
+/// Why `Universe::run_until_stable` stopped. Each variant carries the
+/// generation the universe was left at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Every cell is back to the (dead) background
+    Died { generation: u128 },
+    /// The pattern repeats, possibly moving; it first repeated at `generation`
+    Periodic { generation: u128, periodicity: Periodicity },
+    /// `max_generations` generations went by without the pattern settling
+    GenerationLimit { generation: u128 },
+    /// The population went over `max_population`
+    PopulationLimit { generation: u128, population: u64 },
+}
+
+impl StopReason {
+    pub fn generation(&self) -> u128 {
+        match *self {
+            StopReason::Died { generation }
+            | StopReason::Periodic { generation, .. }
+            | StopReason::GenerationLimit { generation }
+            | StopReason::PopulationLimit { generation, .. } => generation,
+        }
+    }
+}
+
+/// How the population of a pattern grows in the long run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GrowthClass {
+    /// The population stays bounded (still lifes, oscillators, spaceships, ash)
+    Bounded,
+    /// The population grows linearly (guns, puffers)
+    Linear,
+    /// The population grows quadratically (breeders, space-filling patterns)
+    Quadratic,
+}
+
+/// Result of `Universe::classify_growth`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GrowthEstimate {
+    pub class: GrowthClass,
+    /// Fitted growth in cells per generation for linear growth, cells per
+    /// generation squared for quadratic growth, and 0 for bounded patterns
+    pub rate: f64,
+    /// Exponent `k` of the fit `population ~ t^k` over the later samples
+    pub exponent: f64,
+    /// How fast the longer side of the bounding box grows, in cells per generation.
+    /// Bounded patterns that keep expanding have spaceships flying apart.
+    pub expansion: f64,
+}
+
+/// How often the cells of a region were alive over a run of generations, from
+/// `Universe::activity_map`. Cells are counted in square tiles; with tiles of
+/// one cell each count is the number of generations the cell was alive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActivityMap {
+    pub rect: Rect,
+    /// Side of a tile in cells
+    pub tile: u32,
+    pub generations: u64,
+    /// Tiles across and down, covering `rect` (the last ones may stick out)
+    pub width: u32,
+    pub height: u32,
+    /// Live cells summed over the generations, per tile, row by row
+    pub counts: Vec<u64>,
+}
+
+impl ActivityMap {
+    /// Count of the tile containing cell `(x, y)`, or 0 outside the region
+    pub fn get(&self, x: i64, y: i64) -> u64 {
+        if !self.rect.contains(x, y) {
+            return 0;
+        }
+        let tile = self.tile as i64;
+        let (column, row) = ((x - self.rect.left) / tile, (y - self.rect.top) / tile);
+        self.counts[(row * self.width as i64 + column) as usize]
+    }
+
+    /// Largest count, for scaling a heatmap
+    pub fn max(&self) -> u64 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// Walks two trees side by side for `Universe::diff`
+struct Differ {
+    /// Backgrounds of the two universes, to compare real states when a tree
+    /// is stored complemented
+    backgrounds: (State, State),
+    /// Pairs of nodes already found to hold the same cells
+    same: HashMap<(*const Node, *const Node), bool>,
+    /// Cells that differ, with their states in the second tree
+    cells: Vec<(i64, i64, State)>,
+}
+
+impl Differ {
+    /// Compare the cells of two nodes of the same level at `(x, y)`, where
+    /// None stands for a node with every cell stored as 0
+    fn compare(&mut self, a: Option<&Rc<Node>>, b: Option<&Rc<Node>>, level: u8, x: i64, y: i64) {
+        let empty = |node: Option<&Rc<Node>>| node.is_none_or(|node| node.population() == 0);
+        if self.backgrounds.0 == self.backgrounds.1 {
+            if empty(a) && empty(b) {
+                return;
+            }
+            if let (Some(a), Some(b)) = (a, b) {
+                if Rc::ptr_eq(a, b) {
+                    return;
+                }
+            }
+        }
+        if level == 0 {
+            let real = |node: Option<&Rc<Node>>, background: State| {
+                let stored = node.map_or(0, |node| node.state());
+                if background == 0 { stored } else { (stored == 0) as State }
+            };
+            let (ours, theirs) = (real(a, self.backgrounds.0), real(b, self.backgrounds.1));
+            if ours != theirs {
+                self.cells.push((x, y, theirs));
+            }
+            return;
+        }
+        let key = (a.map_or(std::ptr::null(), Rc::as_ptr), b.map_or(std::ptr::null(), Rc::as_ptr));
+        if self.same.contains_key(&key) {
+            return;
+        }
+        let found = self.cells.len();
+        let half = 1i64 << (level - 1);
+        let (a, b) = (a.map(|node| node.children()), b.map(|node| node.children()));
+        for (i, (dx, dy)) in [(0, 0), (half, 0), (0, half), (half, half)].into_iter().enumerate() {
+            self.compare(a.map(|children| children[i]), b.map(|children| children[i]), level - 1, x + dx, y + dy);
+        }
+        if self.cells.len() == found {
+            self.same.insert(key, true);
+        }
+    }
+
+    /// Compare `node` of the larger tree, at `(x, y)`, with the same square of
+    /// the smaller tree `small`. Both roots are centred on the origin.
+    fn compare_with_smaller(&mut self, node: &Rc<Node>, x: i64, y: i64, small: &Rc<Node>, larger_is_ours: bool) {
+        if node.level() + 1 > small.level() {
+            let half = 1i64 << (node.level() - 1);
+            let children = node.children();
+            for (i, (dx, dy)) in [(0, 0), (half, 0), (0, half), (half, half)].into_iter().enumerate() {
+                self.compare_with_smaller(children[i], x + dx, y + dy, small, larger_is_ours);
+            }
+            return;
+        }
+        // At the level of the smaller root's children, which sit on the same grid
+        let half = 1i64 << node.level();
+        let inside = x >= -half && y >= -half && x < half && y < half;
+        let other = inside.then(|| small.children()[((y >= 0) as usize) * 2 + (x >= 0) as usize]);
+        if larger_is_ours {
+            self.compare(Some(node), other, node.level(), x, y);
+        } else {
+            self.compare(other, Some(node), node.level(), x, y);
+        }
+    }
+}
+
+/// Cells of the second tree that differ from the first, with their real
+/// states in the second, sorted by position
+pub(crate) fn diff_trees((ours, our_background): (&Rc<Node>, State), (theirs, their_background): (&Rc<Node>, State))
+                         -> Vec<(i64, i64, State)> {
+    let mut differ = Differ { backgrounds: (our_background, their_background), same: HashMap::new(), cells: Vec::new() };
+    let half = 1i64 << (ours.level().max(theirs.level()) - 1);
+    if ours.level() >= theirs.level() {
+        differ.compare_with_smaller(ours, -half, -half, theirs, true);
+    } else {
+        differ.compare_with_smaller(theirs, -half, -half, ours, false);
+    }
+    differ.cells.sort_unstable();
+    differ.cells
+}
+
+fn alive(cells: Vec<(i64, i64, State)>) -> Vec<(i64, i64, bool)> {
+    cells.into_iter().map(|(x, y, state)| (x, y, state != 0)).collect()
+}
+
+/// Samples `Universe::growth_rate` takes of its window
+pub const GROWTH_SAMPLES: usize = 32;
+
+/// Least-squares slope of `ys` against `xs`
+fn slope(xs: &[f64], ys: &[f64]) -> f64 {
+    let n = xs.len() as f64;
+    let (mean_x, mean_y) = (xs.iter().sum::<f64>() / n, ys.iter().sum::<f64>() / n);
+    let covariance: f64 = xs.iter().zip(ys).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = xs.iter().map(|x| (x - mean_x) * (x - mean_x)).sum();
+    if variance == 0.0 { 0.0 } else { covariance / variance }
+}
+
+impl Universe {
+    /// Sample the population and bounding box `samples` times over the next
+    /// `generations` generations and classify the growth. The fit uses the
+    /// later half of the samples, so transients at the start are ignored.
+    /// The universe is left unchanged.
+    pub fn classify_growth(&mut self, generations: u64, samples: usize) -> GrowthEstimate {
+        let samples = samples.max(4) as u64;
+        let start = self.snapshot();
+        let (mut times, mut populations, mut sides) = (Vec::new(), Vec::new(), Vec::new());
+        let mut elapsed = 0;
+        for i in 1..=samples {
+            let target = generations * i / samples;
+            self.advance_generations((target - elapsed) as u128);
+            elapsed = target;
+            if i > samples / 2 {
+                times.push(elapsed as f64);
+                populations.push(self.population_f64());
+                let side = self.bounding_box().map_or(0, |(_, _, width, height)| width.max(height));
+                sides.push(side as f64);
+            }
+        }
+        self.restore(start);
+
+        let expansion = slope(&times, &sides);
+        let exponent = if populations.iter().all(|&p| p > 0.0) {
+            let logs = |values: &[f64]| values.iter().map(|v| v.ln()).collect::<Vec<_>>();
+            slope(&logs(&times), &logs(&populations))
+        } else {
+            0.0
+        };
+        let (class, rate) = if exponent < 0.5 {
+            (GrowthClass::Bounded, 0.0)
+        } else if exponent < 1.5 {
+            (GrowthClass::Linear, slope(&times, &populations))
+        } else {
+            let squares: Vec<f64> = times.iter().map(|t| t * t).collect();
+            (GrowthClass::Quadratic, slope(&squares, &populations))
+        };
+        GrowthEstimate { class, rate, exponent, expansion }
+    }
+
+    /// Count how often each cell of `rect` is alive over `generations`
+    /// generations, starting with the current one, in tiles of `tile` by
+    /// `tile` cells. For an oscillator this shows its rotor (cells alive in
+    /// some generations but not all) against its stator, and for a gun the
+    /// paths its output takes. The universe is left unchanged.
+    pub fn activity_map(&mut self, rect: Rect, generations: u64, tile: u32) -> ActivityMap {
+        let tile = tile.max(1);
+        let width = (rect.width.max(0) as u64).div_ceil(tile as u64) as u32;
+        let height = (rect.height.max(0) as u64).div_ceil(tile as u64) as u32;
+        let mut counts = vec![0; width as usize * height as usize];
+        let start = self.snapshot();
+        for generation in 0..generations {
+            if generation > 0 {
+                self.advance_root(0);
+            }
+            for &(x, y, state) in self.extract(rect).cells() {
+                if state != 0 {
+                    let (column, row) = ((x - rect.left) / tile as i64, (y - rect.top) / tile as i64);
+                    counts[(row * width as i64 + column) as usize] += 1;
+                }
+            }
+        }
+        self.restore(start);
+        ActivityMap { rect, tile, generations, width, height, counts }
+    }
+
+    /// The cells that differ between this universe and `other`, with whether
+    /// they are alive in `other`, sorted by position. The trees are walked
+    /// side by side, skipping empty subtrees and pairs of subtrees already
+    /// found equal, so the cost follows the patterns' structure rather than
+    /// their area. Both trees should have the
+    /// same background: otherwise every cell of the larger tree differs.
+    pub fn diff(&self, other: &Universe) -> Vec<(i64, i64, bool)> {
+        alive(diff_trees((self.root(), self.stored_background()), (other.root(), other.stored_background())))
+    }
+
+    /// The cells changed by the most recent step (or run of steps kept as one
+    /// history entry), with whether they are alive now, for redrawing only
+    /// what changed. Consecutive generations share most of their subtrees,
+    /// so this is cheap. None if the step history is empty.
+    pub fn diff_from_previous(&self) -> Option<Vec<(i64, i64, bool)>> {
+        let previous = self.previous_root()?;
+        Some(alive(diff_trees(previous, (self.root(), self.stored_background()))))
+    }
+
+    /// If `other` holds the same pattern (and background) as this universe,
+    /// moved by some offset, that offset `(dx, dy)`. Two empty universes are
+    /// the same at `(0, 0)`. The rules and generations are not compared.
+    pub fn equals_translated(&self, other: &Universe) -> Option<(i64, i64)> {
+        let (ours, theirs) = (NormalizedPattern::of(self), NormalizedPattern::of(other));
+        ours.same_shape(&theirs).then_some((theirs.left - ours.left, theirs.top - ours.top))
+    }
+
+    /// Glide symmetries of a pattern of the given period: the rotations and
+    /// reflections (other than the identity) that turn the current phase into
+    /// the one half a period later, up to where it is. A glider has one, since
+    /// two generations on it is mirrored along a diagonal. Odd periods have no
+    /// half-period phase and give none. The universe is left unchanged.
+    pub fn glide_symmetries(&mut self, period: u64) -> Vec<Transform> {
+        if !period.is_multiple_of(2) {
+            return Vec::new();
+        }
+        let start = self.to_pattern();
+        let snapshot = self.snapshot();
+        self.advance_generations(period as u128 / 2);
+        let halfway = self.to_pattern().normalized();
+        self.restore(snapshot);
+        Transform::ALL[1..].iter().copied()
+            .filter(|&transform| start.transformed(transform).normalized() == halfway)
+            .collect()
+    }
+
+    /// A hash of the cells, where they are and the background, for detecting
+    /// cycles, keying caches and checking that runs are reproducible. It is
+    /// read off hashes kept in the tree's nodes, so it takes no longer than
+    /// centring the tree on the pattern; it does not depend on the tree's size,
+    /// the rule or the generation, and is the same on every machine.
+    pub fn fingerprint(&self) -> u64 {
+        let root = self.root();
+        let mut level = root.level();
+        let mut quadrants = root.children();
+        // Shrink to the smallest centred square holding every cell
+        while level > 1 {
+            let [nw, ne, sw, se] = quadrants;
+            let inner = [nw.children()[3], ne.children()[2], sw.children()[1], se.children()[0]];
+            if (0..4).any(|i| inner[i].population() != quadrants[i].population()) {
+                break;
+            }
+            quadrants = inner;
+            level -= 1;
+        }
+        let hash = Node::combine_hashes(level, quadrants.map(|quadrant| quadrant.content_hash()));
+        Node::combine_hashes(level, [hash, self.stored_background() as u64, 0, 0])
+    }
+
+    /// `classify_growth` over the next `window` generations with
+    /// `GROWTH_SAMPLES` samples: the check a soup search runs to flag guns
+    /// and puffers (linear growth) and breeders (quadratic growth). The window
+    /// should span several periods of whatever is doing the growing.
+    pub fn growth_rate(&mut self, window: u64) -> GrowthEstimate {
+        self.classify_growth(window, GROWTH_SAMPLES)
+    }
+
+    /// Keep stepping until the pattern dies out, becomes periodic (as a still
+    /// life, oscillator or spaceship) or hits one of the `limits`. The whole
+    /// run is a single entry in the step history, if it took any steps.
+    pub fn run_until_stable(&mut self, limits: RunLimits) -> StopReason {
+        let start = self.generation();
+        let reason = self.run(limits);
+        if self.generation() != start {
+            self.record_progress();
+            let generation = self.generation();
+            self.journal(|_| JournalEntry::StepTo(generation));
+        }
+        reason
+    }
+
+    fn run(&mut self, limits: RunLimits) -> StopReason {
+        let start = self.generation();
+        // Recent generations by the hash of their normalized pattern
+        let mut seen: HashMap<u64, Vec<(u128, NormalizedPattern)>> = HashMap::new();
+        let mut window = VecDeque::new();
+
+        loop {
+            let generation = self.generation();
+            if self.population() == 0 && self.background() == 0 {
+                return StopReason::Died { generation };
+            }
+            if let Some(max) = limits.max_population {
+                let population = self.population();
+                if population > max {
+                    return StopReason::PopulationLimit { generation, population };
+                }
+            }
+
+            let pattern = NormalizedPattern::of(self);
+            let previous = seen.get(&pattern.hash)
+                .and_then(|candidates| candidates.iter().find(|(_, p)| p.same_shape(&pattern)));
+            if let Some((earlier, p)) = previous {
+                let periodicity = Periodicity {
+                    period: (generation - earlier) as u64,
+                    dx: pattern.left - p.left,
+                    dy: pattern.top - p.top,
+                };
+                return StopReason::Periodic { generation, periodicity };
+            }
+            if generation - start >= limits.max_generations as u128 {
+                return StopReason::GenerationLimit { generation };
+            }
+
+            window.push_back(pattern.hash);
+            seen.entry(pattern.hash).or_default().push((generation, pattern));
+            if window.len() as u64 > limits.max_period {
+                let oldest = window.pop_front().unwrap();
+                let candidates = seen.get_mut(&oldest).unwrap();
+                candidates.remove(0);
+                if candidates.is_empty() {
+                    seen.remove(&oldest);
+                }
+            }
+            if generation == start {
+                self.record_history();
+            }
+            self.advance_root(0);
+        }
+    }
+
+    /// Look for the smallest period (up to `max_period`) after which the
+    /// current pattern repeats, allowing it to move. A glider gives
+    /// `Periodicity { period: 4, dx: 1, dy: 1 }` (or another diagonal,
+    /// depending on its orientation). The universe is left unchanged.
+    /// Returns None for empty universes and patterns that do not repeat in time.
+    pub fn find_period(&mut self, max_period: u64) -> Option<Periodicity> {
+        if self.population() == 0 && self.background() == 0 {
+            return None;
+        }
+        let start = self.snapshot();
+        let initial = NormalizedPattern::of(self);
+
+        let mut found = None;
+        for period in 1..=max_period {
+            self.advance_root(0);
+            let current = NormalizedPattern::of(self);
+            if current.same_shape(&initial) {
+                found = Some(Periodicity {
+                    period,
+                    dx: current.left - initial.left,
+                    dy: current.top - initial.top,
+                });
+                break;
+            }
+        }
+        self.restore(start);
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::Rule;
+
+    fn universe_with(cells: &[(i64, i64)], rule: &str) -> Universe {
+        let mut universe = Universe::with_rule(4, rule.parse::<Rule>().unwrap());
+        for &(x, y) in cells {
+            universe.set_cell(x, y, true);
+        }
+        universe
+    }
+
+    #[test]
+    fn test_find_period() {
+        let mut block = universe_with(&[(0, 0), (1, 0), (0, 1), (1, 1)], "B3/S23");
+        assert_eq!(block.find_period(10), Some(Periodicity { period: 1, dx: 0, dy: 0 }));
+
+        let mut blinker = universe_with(&[(0, 0), (1, 0), (2, 0)], "B3/S23");
+        let period = blinker.find_period(10).unwrap();
+        assert_eq!(period, Periodicity { period: 2, dx: 0, dy: 0 });
+        assert_eq!(period.to_string(), "p2 oscillator");
+
+        // The universe is left where it was
+        let mut glider = universe_with(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], "B3/S23");
+        glider.step();
+        let period = glider.find_period(10).unwrap();
+        assert_eq!(period, Periodicity { period: 4, dx: 1, dy: 1 });
+        assert_eq!(period.to_string(), "c/4 diagonal");
+        assert_eq!(glider.generation(), 1);
+        assert_eq!(glider.population(), 5);
+
+        let mut lwss = universe_with(&[(1, 0), (4, 0), (0, 1), (0, 2), (4, 2), (0, 3), (1, 3), (2, 3), (3, 3)], "B3/S23");
+        let period = lwss.find_period(10).unwrap();
+        assert_eq!(period, Periodicity { period: 4, dx: -2, dy: 0 });
+        assert_eq!(period.to_string(), "c/2 orthogonal");
+
+        let mut r_pentomino = universe_with(&[(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)], "B3/S23");
+        assert_eq!(r_pentomino.find_period(50), None);
+        assert_eq!(Universe::new(3).find_period(10), None);
+    }
+
+    #[test]
+    fn test_run_until_stable() {
+        // The R-pentomino settles at generation 1103, but its gliders keep
+        // the whole pattern from repeating
+        let limits = RunLimits { max_generations: 2000, ..RunLimits::default() };
+        let mut r_pentomino = universe_with(&[(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)], "B3/S23");
+        assert_eq!(r_pentomino.run_until_stable(limits), StopReason::GenerationLimit { generation: 2000 });
+        assert_eq!(r_pentomino.population(), 116);
+        assert!(r_pentomino.step_back());
+        assert_eq!(r_pentomino.generation(), 0);
+
+        // A pre-block becomes a block after one generation
+        let mut pre_block = universe_with(&[(0, 0), (1, 0), (0, 1)], "B3/S23");
+        let periodicity = Periodicity { period: 1, dx: 0, dy: 0 };
+        assert_eq!(pre_block.run_until_stable(limits), StopReason::Periodic { generation: 2, periodicity });
+
+        let mut glider = universe_with(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], "B3/S23");
+        let periodicity = Periodicity { period: 4, dx: 1, dy: 1 };
+        assert_eq!(glider.run_until_stable(limits), StopReason::Periodic { generation: 4, periodicity });
+
+        let mut diehard = universe_with(&[(6, 0), (0, 1), (1, 1), (1, 2), (5, 2), (6, 2), (7, 2)], "B3/S23");
+        assert_eq!(diehard.run_until_stable(limits), StopReason::Died { generation: 130 });
+
+        let limits = RunLimits { max_population: Some(100), ..limits };
+        let mut replicator = universe_with(&[(0, 0), (1, 0), (2, 0), (0, 1)], "B1357/S1357");
+        let StopReason::PopulationLimit { population, .. } = replicator.run_until_stable(limits) else {
+            panic!("replicator should grow without limit");
+        };
+        assert!(population > 100);
+    }
+
+    #[test]
+    fn test_classify_growth() {
+        let mut blinker = universe_with(&[(0, 0), (1, 0), (2, 0)], "B3/S23");
+        let estimate = blinker.classify_growth(1000, 20);
+        assert_eq!(estimate.class, GrowthClass::Bounded);
+        assert_eq!(estimate.expansion, 0.0);
+        assert_eq!(blinker.generation(), 0);
+
+        // A glider moves, but its bounding box does not grow
+        let mut glider = universe_with(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], "B3/S23");
+        let estimate = glider.classify_growth(4000, 20);
+        assert_eq!(estimate.class, GrowthClass::Bounded);
+        assert_eq!(estimate.expansion, 0.0);
+
+        // The Gosper glider gun adds a 5-cell glider every 30 generations
+        let gun = [
+            (24, 0), (22, 1), (24, 1), (12, 2), (13, 2), (20, 2), (21, 2), (34, 2), (35, 2),
+            (11, 3), (15, 3), (20, 3), (21, 3), (34, 3), (35, 3), (0, 4), (1, 4), (10, 4),
+            (16, 4), (20, 4), (21, 4), (0, 5), (1, 5), (10, 5), (14, 5), (16, 5), (17, 5),
+            (22, 5), (24, 5), (10, 6), (16, 6), (24, 6), (11, 7), (15, 7), (12, 8), (13, 8),
+        ];
+        let mut gun = universe_with(&gun, "B3/S23");
+        let estimate = gun.classify_growth(30 * 200, 20);
+        assert_eq!(estimate.class, GrowthClass::Linear);
+        assert!((estimate.rate - 5.0 / 30.0).abs() < 0.01, "{:?}", estimate);
+        assert!((estimate.expansion - 0.25).abs() < 0.01, "{:?}", estimate);
+        let estimate = gun.growth_rate(30 * 100);
+        assert_eq!(estimate.class, GrowthClass::Linear);
+        assert!((estimate.rate - 5.0 / 30.0).abs() < 0.01, "{:?}", estimate);
+        assert_eq!(gun.generation(), 0);
+
+        // A single cell under B1..8/S0..8 fills a (2t + 1)-sided square
+        let mut square = universe_with(&[(0, 0)], "B12345678/S012345678");
+        let estimate = square.classify_growth(1000, 20);
+        assert_eq!(estimate.class, GrowthClass::Quadratic);
+        assert!((estimate.rate - 4.0).abs() < 0.01, "{:?}", estimate);
+    }
+
+    #[test]
+    fn test_activity_map() {
+        // A blinker's centre is always alive and its four tips half the time
+        let mut universe = universe_with(&[(0, -1), (0, 0), (0, 1)], "B3/S23");
+        let map = universe.activity_map(Rect::new(-2, -2, 5, 5), 10, 1);
+        assert_eq!((map.width, map.height, map.max()), (5, 5, 10));
+        assert_eq!(map.get(0, 0), 10);
+        assert_eq!((map.get(0, -1), map.get(1, 0)), (5, 5));
+        assert_eq!((map.get(1, 1), map.get(9, 9)), (0, 0));
+        assert_eq!(universe.generation(), 0);
+        assert!(universe.get_cell(0, -1));
+
+        let tiled = universe.activity_map(Rect::new(-2, -2, 5, 5), 10, 2);
+        assert_eq!((tiled.width, tiled.height), (3, 3));
+        assert_eq!(tiled.get(0, 0), 20);
+        assert_eq!((tiled.get(1, -1), tiled.get(-1, 0), tiled.get(-1, -1)), (5, 5, 0));
+        assert_eq!(tiled.counts.iter().sum::<u64>(), 30);
+        assert_eq!(universe.activity_map(Rect::new(0, 0, 3, 3), 0, 1).max(), 0);
+    }
+
+    #[test]
+    fn test_diff() {
+        let vertical = universe_with(&[(0, -1), (0, 0), (0, 1)], "B3/S23");
+        let mut horizontal = universe_with(&[(0, -1), (0, 0), (0, 1)], "B3/S23");
+        horizontal.step();
+        assert_eq!(vertical.diff(&horizontal), vec![(-1, 0, true), (0, -1, false), (0, 1, false), (1, 0, true)]);
+        horizontal.step();
+        assert!(vertical.diff(&horizontal).is_empty());
+        assert_eq!(horizontal.diff_from_previous(), Some(vec![(-1, 0, false), (0, -1, true), (0, 1, true), (1, 0, false)]));
+        assert_eq!(vertical.diff_from_previous(), None);
+
+        // Trees of different sizes are lined up on the origin
+        let mut far = Universe::new(10);
+        for (x, y) in [(0, -1), (0, 0), (0, 1), (300, -200)] {
+            far.set_cell(x, y, true);
+        }
+        assert_eq!(vertical.diff(&far), vec![(300, -200, true)]);
+        assert_eq!(far.diff(&vertical), vec![(300, -200, false)]);
+        assert!(Universe::new(3).diff(&Universe::new(8)).is_empty());
+
+        // Live backgrounds are compared as the real cells: the neighbourhoods
+        // of the two cells die while the empty background comes alive
+        let mut inverted = universe_with(&[], "B0/S8");
+        let mut negative = universe_with(&[(0, 0), (4, 4)], "B0/S8");
+        negative.step();
+        inverted.step();
+        inverted.set_cell(2, 3, false);
+        let diff = negative.diff(&inverted);
+        assert_eq!(diff.len(), 19);
+        assert!(diff.contains(&(-1, -1, true)) && diff.contains(&(5, 5, true)) && diff.contains(&(2, 3, false)));
+    }
+
+    #[test]
+    fn test_equals_translated() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let universe = universe_with(&glider, "B3/S23");
+        let moved: Vec<_> = glider.iter().map(|&(x, y)| (x - 7, y + 300)).collect();
+        assert_eq!(universe.equals_translated(&universe_with(&moved, "B3/S23")), Some((-7, 300)));
+        assert_eq!(universe.equals_translated(&universe), Some((0, 0)));
+
+        let mut later = universe_with(&glider, "B3/S23");
+        later.step();
+        assert_eq!(universe.equals_translated(&later), None);
+        later.step_pow2(1).unwrap();
+        later.step();
+        assert_eq!(later.equals_translated(&universe), Some((-1, -1)));
+
+        let mut two_state = universe_with(&[(5, 5)], "B3/S23");
+        let mut generations = universe_with(&[], "23/3/3");
+        generations.set_cell_state(0, 0, 2);
+        assert_eq!(two_state.equals_translated(&generations), None);
+        two_state.set_cell(5, 5, false);
+        assert_eq!(two_state.equals_translated(&Universe::new(3)), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_glide_symmetries() {
+        let mut glider = universe_with(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], "B3/S23");
+        assert_eq!(glider.glide_symmetries(4), [Transform::FlipDiagonal]);
+        assert_eq!(glider.generation(), 0);
+        assert!(glider.glide_symmetries(3).is_empty());
+
+        // A blinker's two phases are quarter turns of each other
+        let mut blinker = universe_with(&[(0, -1), (0, 0), (0, 1)], "B3/S23");
+        assert_eq!(blinker.glide_symmetries(2), [Transform::Rotate90, Transform::Rotate270,
+                                                  Transform::FlipDiagonal, Transform::FlipAntiDiagonal]);
+        let mut r_pentomino = universe_with(&[(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)], "B3/S23");
+        assert!(r_pentomino.glide_symmetries(8).is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut universe = universe_with(&glider, "B3/S23");
+        let mut larger = Universe::new(9);
+        for (x, y) in glider {
+            larger.set_cell(x, y, true);
+        }
+        let fingerprint = universe.fingerprint();
+        assert_eq!(fingerprint, larger.fingerprint());
+        // Fixed by the hash function, so it stays the same across machines and releases
+        assert_eq!(fingerprint, 0x58ac_1b14_abce_73f8);
+
+        larger.set_cell(1, 0, false);
+        larger.set_cell(2, 0, true);
+        assert_ne!(fingerprint, larger.fingerprint());
+        universe.step_pow2(2).unwrap();
+        assert_ne!(fingerprint, universe.fingerprint());
+        let shifted: Vec<_> = glider.iter().map(|&(x, y)| (x + 1, y + 1)).collect();
+        assert_eq!(universe.fingerprint(), universe_with(&shifted, "B3/S23").fingerprint());
+
+        assert_eq!(Universe::new(3).fingerprint(), Universe::new(12).fingerprint());
+        let mut inverted = universe_with(&[], "B0/S8");
+        inverted.step();
+        assert_ne!(inverted.fingerprint(), Universe::new(3).fingerprint());
+    }
+
+    #[test]
+    fn test_periodicity_display() {
+        let speed = |period, dx, dy| Periodicity { period, dx, dy }.to_string();
+        assert_eq!(speed(1, 0, 0), "still life");
+        assert_eq!(speed(3, 0, 3), "c orthogonal");
+        assert_eq!(speed(7, 0, -2), "2c/7 orthogonal");
+        assert_eq!(speed(6, 2, -1), "(2,1)c/6 oblique");
+        assert_eq!(speed(12, -3, 3), "c/4 diagonal");
+    }
+}
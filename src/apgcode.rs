@@ -0,0 +1,215 @@
+//! apgcodes, the object names used by apgsearch and Catagolue
+//!
+//! An apgcode is a prefix naming the kind of object (`xs4` for a still life
+//! of 4 cells, `xp2` for a period 2 oscillator, `xq4` for a period 4
+//! spaceship), an underscore, and the object's cells in extended Wechsler
+//! format. The pattern is cut into strips 5 rows high, separated by `z`; each
+//! column of a strip is one character `0-9a-v` whose bits are its cells from
+//! the top, and runs of empty columns are shortened to `w` (2), `x` (3) or
+//! `y` followed by a digit `0-9a-z` (4 to 39). Only two-state patterns have
+//! apgcodes; every non-zero state counts as alive.
+
+use std::fmt;
+
+use crate::hashlife::Universe;
+use crate::pattern::Pattern;
+use crate::rule::Rule;
+
+const DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Error returned when an apgcode cannot be decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseApgcodeError {
+    /// The part before the underscore is not `xs`, `xp` or `xq` followed by a number
+    UnsupportedPrefix(String),
+    /// A character that is not part of the extended Wechsler format
+    InvalidCharacter(char),
+    /// `y` at the end of the code, without the length of the run of empty columns
+    UnfinishedRun,
+}
+
+impl fmt::Display for ParseApgcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseApgcodeError::UnsupportedPrefix(s) => write!(f, "unsupported apgcode prefix: {:?}", s),
+            ParseApgcodeError::InvalidCharacter(c) => write!(f, "invalid character in apgcode: {:?}", c),
+            ParseApgcodeError::UnfinishedRun => write!(f, "apgcode ends in the middle of a run"),
+        }
+    }
+}
+
+impl std::error::Error for ParseApgcodeError {}
+
+/// Extended Wechsler encoding of a pattern, relative to its bounding box
+pub fn wechsler(pattern: &Pattern) -> String {
+    let Some((left, top, width, height)) = pattern.bounding_box() else {
+        return "0".to_string();
+    };
+    let mut code = String::new();
+    for strip in 0..(height + 4) / 5 {
+        if strip > 0 {
+            code.push('z');
+        }
+        // Empty columns at the end of a strip are left out
+        let mut empty = 0;
+        for x in 0..width {
+            let column = (0..5).fold(0, |column, row| {
+                let alive = pattern.get(left + x, top + 5 * strip + row) != 0;
+                column | (alive as usize) << row
+            });
+            if column == 0 {
+                empty += 1;
+                continue;
+            }
+            while empty > 0 {
+                let run = empty.min(39);
+                match run {
+                    1 => code.push('0'),
+                    2 => code.push('w'),
+                    3 => code.push('x'),
+                    _ => {
+                        code.push('y');
+                        code.push(DIGITS[run - 4] as char);
+                    }
+                }
+                empty -= run;
+            }
+            code.push(DIGITS[column] as char);
+        }
+    }
+    code
+}
+
+/// Decode an extended Wechsler string (without prefix) into a two-state
+/// pattern whose top-left strip starts at the origin
+pub fn parse_wechsler(code: &str) -> Result<Pattern, ParseApgcodeError> {
+    let mut cells = Vec::new();
+    let (mut x, mut strip) = (0, 0);
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            'z' => {
+                x = 0;
+                strip += 1;
+            }
+            'w' => x += 2,
+            'x' => x += 3,
+            'y' => {
+                let run = chars.next().ok_or(ParseApgcodeError::UnfinishedRun)?;
+                x += 4 + run.to_digit(36).ok_or(ParseApgcodeError::InvalidCharacter(run))? as i64;
+            }
+            _ => {
+                let column = c.to_digit(32).filter(|_| !c.is_ascii_uppercase())
+                    .ok_or(ParseApgcodeError::InvalidCharacter(c))?;
+                for row in 0..5 {
+                    if column & 1 << row != 0 {
+                        cells.push((x, 5 * strip + row));
+                    }
+                }
+                x += 1;
+            }
+        }
+    }
+    Ok(Pattern::from_live_cells(cells))
+}
+
+/// Decode an apgcode such as `xq4_153`. The prefix must be `xs`, `xp` or
+/// `xq` followed by a number; a bare Wechsler string is also accepted.
+pub fn parse_apgcode(code: &str) -> Result<Pattern, ParseApgcodeError> {
+    let Some((prefix, cells)) = code.split_once('_') else {
+        return parse_wechsler(code);
+    };
+    let valid = ["xs", "xp", "xq"].iter().any(|kind| {
+        prefix.strip_prefix(kind).is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    });
+    if !valid {
+        return Err(ParseApgcodeError::UnsupportedPrefix(prefix.to_string()));
+    }
+    parse_wechsler(cells)
+}
+
+/// The Wechsler code Catagolue picks among several phases or orientations:
+/// the shortest, then the first in ASCII order
+pub(crate) fn canonical_wechsler<'a, I: IntoIterator<Item = &'a Pattern>>(patterns: I) -> String {
+    patterns.into_iter()
+        .flat_map(|pattern| pattern.orientations())
+        .map(|pattern| wechsler(&pattern))
+        .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+        .unwrap_or_else(|| "0".to_string())
+}
+
+/// Canonical apgcode of an object under `rule`, the same for every placement,
+/// orientation and phase, or None if it does not repeat within `max_period`
+/// generations
+pub fn apgcode(pattern: &Pattern, rule: &Rule, max_period: u64) -> Option<String> {
+    let mut universe = Universe::with_rule(4, rule.clone());
+    universe.set_history_limit(0);
+    for &(x, y, _) in pattern.cells() {
+        universe.set_cell(x, y, true);
+    }
+    let periodicity = universe.find_period(max_period)?;
+
+    let mut phases = Vec::new();
+    for _ in 0..periodicity.period {
+        phases.push(universe.to_pattern());
+        universe.step();
+    }
+    let code = canonical_wechsler(&phases);
+    Some(if periodicity.is_spaceship() {
+        format!("xq{}_{}", periodicity.period, code)
+    } else if periodicity.period == 1 {
+        format!("xs{}_{}", pattern.population(), code)
+    } else {
+        format!("xp{}_{}", periodicity.period, code)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn live(cells: &[(i64, i64)]) -> Pattern {
+        Pattern::from_live_cells(cells.iter().copied())
+    }
+
+    #[test]
+    fn test_wechsler() {
+        let glider = live(&[(0, 0), (1, 0), (2, 0), (2, 1), (1, 2)]);
+        assert_eq!(wechsler(&glider), "153");
+        assert_eq!(parse_wechsler("153").unwrap(), glider);
+
+        // Runs of empty columns, and a second strip
+        let spread = live(&[(0, 0), (3, 0), (7, 0), (18, 0), (18, 6)]);
+        assert_eq!(wechsler(&spread), "1w1x1y61zye2");
+        for code in ["33", "7", "4r4z4r4", "69bqic", "1w1x1y61zye2"] {
+            assert_eq!(wechsler(&parse_wechsler(code).unwrap()), code);
+        }
+        assert_eq!(parse_apgcode("xq4_153").unwrap(), glider);
+        assert_eq!(wechsler(&Pattern::new()), "0");
+
+        assert_eq!(parse_apgcode("yl144_1_16_afb5f3"), Err(ParseApgcodeError::UnsupportedPrefix("yl144".into())));
+        assert_eq!(parse_apgcode("xs_33"), Err(ParseApgcodeError::UnsupportedPrefix("xs".into())));
+        assert_eq!(parse_wechsler("3!"), Err(ParseApgcodeError::InvalidCharacter('!')));
+        assert_eq!(parse_wechsler("3y"), Err(ParseApgcodeError::UnfinishedRun));
+    }
+
+    #[test]
+    fn test_apgcode() {
+        let rule = Rule::conway();
+        let cases: [(&[(i64, i64)], &str); 6] = [
+            (&[(0, 0), (1, 0), (0, 1), (1, 1)], "xs4_33"),
+            (&[(0, 0), (1, 0), (2, 0)], "xp2_7"),
+            (&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], "xq4_153"),
+            (&[(1, 0), (2, 0), (0, 1), (3, 1), (1, 2), (2, 2)], "xs6_696"),
+            (&[(1, 0), (2, 0), (0, 1), (3, 1), (1, 2), (3, 2), (2, 3)], "xs7_2596"),
+            (&[(0, 0), (1, 0), (0, 1), (3, 2), (2, 3), (3, 3)], "xp2_318c"),
+        ];
+        for (cells, code) in cases {
+            let object = live(cells);
+            assert_eq!(apgcode(&object, &rule, 30).as_deref(), Some(code));
+            // Decoding gives back a phase of the same object
+            assert_eq!(apgcode(&parse_apgcode(code).unwrap(), &rule, 30).as_deref(), Some(code));
+        }
+        assert_eq!(apgcode(&live(&[(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)]), &rule, 30), None);
+    }
+}
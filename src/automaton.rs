@@ -0,0 +1,106 @@
+//! A common interface to cellular automaton engines
+//!
+//! `CellularAutomaton` covers what most code needs from an engine: stepping,
+//! reading and writing cells, and measuring the pattern. `Universe`
+//! implements it, as does `naive::Grid`, so code written against the trait
+//! runs on either:
+//!
+//! ```
+//! # use vive_hashlife::automaton::CellularAutomaton;
+//! # use vive_hashlife::hashlife::Universe;
+//! fn run_glider<A: CellularAutomaton>(engine: &mut A) -> Option<(i64, i64, i64, i64)> {
+//!     for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+//!         engine.set_cell(x, y, true);
+//!     }
+//!     for _ in 0..4 {
+//!         engine.step();
+//!     }
+//!     engine.bounding_box()
+//! }
+//! assert_eq!(run_glider(&mut Universe::new(4)), Some((1, 1, 3, 3)));
+//! ```
+
+use crate::hashlife::Universe;
+use crate::rule::State;
+
+/// An engine that steps a grid of cells one generation at a time
+pub trait CellularAutomaton {
+    /// Advance one generation
+    fn step(&mut self);
+
+    fn generation(&self) -> u128;
+
+    /// State of a cell (0 = dead)
+    fn get_cell_state(&self, x: i64, y: i64) -> State;
+
+    fn set_cell_state(&mut self, x: i64, y: i64, state: State);
+
+    /// Whether a cell is in any non-dead state
+    fn get_cell(&self, x: i64, y: i64) -> bool {
+        self.get_cell_state(x, y) != 0
+    }
+
+    fn set_cell(&mut self, x: i64, y: i64, alive: bool) {
+        self.set_cell_state(x, y, alive as State);
+    }
+
+    /// Number of cells that differ from the background
+    fn population(&self) -> u64;
+
+    /// Smallest rectangle `(left, top, width, height)` containing every cell
+    /// that differs from the background, or None if there are none
+    fn bounding_box(&self) -> Option<(i64, i64, i64, i64)>;
+}
+
+impl CellularAutomaton for Universe {
+    fn step(&mut self) {
+        Universe::step(self);
+    }
+
+    fn generation(&self) -> u128 {
+        Universe::generation(self)
+    }
+
+    fn get_cell_state(&self, x: i64, y: i64) -> State {
+        Universe::get_cell_state(self, x, y)
+    }
+
+    fn set_cell_state(&mut self, x: i64, y: i64, state: State) {
+        Universe::set_cell_state(self, x, y, state);
+    }
+
+    fn population(&self) -> u64 {
+        Universe::population(self)
+    }
+
+    fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        Universe::bounding_box(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::naive::Grid;
+    use crate::rule::Rule;
+    use crate::topology::Topology;
+
+    /// The population and bounding box of an R-pentomino after 30 generations
+    fn r_pentomino(engine: &mut impl CellularAutomaton) -> (u64, Option<(i64, i64, i64, i64)>) {
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            engine.set_cell(x, y, true);
+        }
+        while engine.generation() < 30 {
+            engine.step();
+        }
+        (engine.population(), engine.bounding_box())
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        let mut universe = Universe::new(4);
+        let mut grid = Grid::new(Rule::conway(), Topology::Plane);
+        assert_eq!(r_pentomino(&mut universe), r_pentomino(&mut grid));
+        assert_eq!(Grid::new(Rule::conway(), Topology::Plane).bounding_box(), None);
+    }
+}
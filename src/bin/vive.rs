@@ -0,0 +1,436 @@
+//! Command-line interface to the vive_hashlife engine
+//!
+//! ```text
+//! vive search [--rule B3/S23] [--soups 1000] [--seed 1] [--size 16] [--density 0.5]
+//!             [--max-gen 50000] [--top 10]
+//! vive census [--rule B3/S23] [--soups 1000] [--seed 1] [--size 16] [--density 0.5]
+//!             [--max-gen 50000]
+//! vive bench [--soups 100] [--seed 1]
+//! vive run <file> [--generations 0] [--output out.rle]
+//! vive info <file> [--max-period 1000]
+//! vive compare <file> <file> [--generations 0]
+//! vive view <file> [--width 80] [--height 24]   (with the tui feature)
+//! vive serve [--addr 127.0.0.1:8080]    (with the server feature)
+//! ```
+
+#[cfg(feature = "tui")]
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+use std::time::Instant;
+
+use vive_hashlife::census::soup_census;
+use vive_hashlife::hashlife::Universe;
+#[cfg(feature = "tui")]
+use vive_hashlife::pattern::Rect;
+#[cfg(feature = "tui")]
+use vive_hashlife::render::{rasterize, Palette};
+use vive_hashlife::search::{methuselah_search, soup, soup_seed, time_to_stabilise, SearchConfig};
+
+const USAGE: &str = "usage: vive <command> [options]
+
+commands:
+  search    evaluate random soups and report the longest-lived ones
+            --rule RULE      rulestring (default B3/S23)
+            --soups N        number of soups (default 1000)
+            --seed N         master seed (default 1)
+            --size N         soup width and height (default 16)
+            --density P      probability of a live cell (default 0.5)
+            --max-gen N      give up on a soup after N generations (default 50000)
+            --top N          number of candidates to report (default 10)
+  census    run random soups to stabilisation and tally the objects left
+            takes the same options as search, except --top
+  bench     time a standard suite of runs, to compare versions of the engine
+            --soups N        number of soups in the soup run (default 100)
+            --seed N         master seed of the soups (default 1)
+  run FILE  load a pattern (.rle, .mc, .cells, .lif, optionally .gz) and step it
+            --generations N  generations to run, in supersteps (default 0)
+            --output FILE    where to write the result, in the format its
+                             extension names (default RLE on standard output)
+  info FILE print a pattern's rule, extent, population, period and the memory
+            used to find it
+            --max-period N   longest period to look for (default 1000)
+  compare FILE FILE
+            run two patterns and report whether they end up the same
+            --generations N  generations to run each, in supersteps (default 0)
+  view FILE show a pattern in the terminal and step it (needs the tui
+            feature), reading one command per line: h/j/k/l pan, + and -
+            zoom, n (or an empty line) steps, r N runs N generations, f fits
+            the pattern, q quits
+            --width N        columns to draw (default 80)
+            --height N       rows to draw (default 24)
+  serve     run simulations for WebSocket clients (needs the server feature)
+            --addr ADDR      address to listen on (default 127.0.0.1:8080)";
+
+/// Options of the form `--name value`
+struct Options {
+    values: Vec<(String, String)>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut values = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                return Err(format!("unexpected argument {:?}", arg));
+            };
+            let value = args.next().ok_or_else(|| format!("missing value for --{}", name))?;
+            values.push((name.to_string(), value.clone()));
+        }
+        Ok(Options { values })
+    }
+
+    /// Parse the value of `--name`, or return `default` if it was not given
+    fn get<T: std::str::FromStr>(&mut self, name: &str, default: T) -> Result<T, String>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let Some(i) = self.values.iter().position(|(n, _)| n == name) else {
+            return Ok(default);
+        };
+        let (_, value) = self.values.remove(i);
+        value.parse().map_err(|e| format!("invalid --{} {:?}: {}", name, value, e))
+    }
+
+    /// Fail on options no command asked for
+    fn finish(self) -> Result<(), String> {
+        match self.values.first() {
+            Some((name, _)) => Err(format!("unknown option --{}", name)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The pattern file a command takes before its options, and the options
+fn file_and_options(args: &[String]) -> Result<(&str, Options), String> {
+    match args.split_first() {
+        Some((path, rest)) if !path.starts_with("--") => Ok((path, Options::parse(rest)?)),
+        _ => Err("missing pattern file".to_string()),
+    }
+}
+
+/// A universe holding the pattern in the file at `path`
+fn load(path: &str) -> Result<Universe, String> {
+    let mut universe = Universe::new(3);
+    universe.set_history_limit(0);
+    universe.load_file(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(universe)
+}
+
+/// Soup options shared by `search` and `census`
+fn soup_config(options: &mut Options) -> Result<SearchConfig, String> {
+    let defaults = SearchConfig::default();
+    let size = options.get("size", defaults.soup_width)?;
+    Ok(SearchConfig {
+        rule: options.get("rule", defaults.rule)?,
+        soup_width: size,
+        soup_height: size,
+        density: options.get("density", defaults.density)?,
+        soups: options.get("soups", defaults.soups)?,
+        seed: options.get("seed", defaults.seed)?,
+        max_generations: options.get("max-gen", defaults.max_generations)?,
+        ..defaults
+    })
+}
+
+fn search(mut options: Options) -> Result<(), String> {
+    let mut config = soup_config(&mut options)?;
+    config.keep = options.get("top", config.keep)?;
+    options.finish()?;
+
+    println!("{:>4}  {:>20}  {:>10}  {:>10}", "rank", "seed", "lifespan", "population");
+    for (rank, candidate) in methuselah_search(&config).iter().enumerate() {
+        let lifespan = if candidate.stabilised {
+            candidate.lifespan.to_string()
+        } else {
+            format!(">{}", candidate.lifespan)
+        };
+        println!("{:>4}  {:>20}  {:>10}  {:>10}", rank + 1, candidate.seed, lifespan, candidate.final_population);
+    }
+    Ok(())
+}
+
+fn census(mut options: Options) -> Result<(), String> {
+    let config = soup_config(&mut options)?;
+    options.finish()?;
+    print!("{}", soup_census(&config));
+    Ok(())
+}
+
+/// A universe holding a library pattern, without step history
+fn pattern(name: &str) -> Universe {
+    let mut universe = Universe::from_library(name).expect("built-in pattern");
+    universe.set_history_limit(0);
+    universe
+}
+
+fn bench(mut options: Options) -> Result<(), String> {
+    let soups: u64 = options.get("soups", 100)?;
+    let seed = options.get("seed", 1)?;
+    options.finish()?;
+
+    // Each run returns the generations it covered and the nodes it created
+    type Run = Box<dyn Fn() -> (u128, u64)>;
+    let runs: [(&str, Run); 4] = [
+        ("r-pentomino to stable", Box::new(|| {
+            let mut universe = pattern("r-pentomino");
+            let generations = time_to_stabilise(&mut universe, 5000, 2).unwrap_or(5000);
+            (generations, universe.cache_counters().nodes_created)
+        })),
+        ("gosper gun, 10000 steps", Box::new(|| {
+            let mut universe = pattern("gosper-glider-gun");
+            for _ in 0..10_000 {
+                universe.step();
+            }
+            (10_000, universe.cache_counters().nodes_created)
+        })),
+        ("gosper gun, superstep 2^30", Box::new(|| {
+            let mut universe = pattern("gosper-glider-gun");
+            universe.step_pow2(30).unwrap();
+            (1 << 30, universe.cache_counters().nodes_created)
+        })),
+        ("random soups to stable", Box::new(move || {
+            let config = SearchConfig::default();
+            let (mut generations, mut nodes) = (0, 0);
+            for index in 0..soups {
+                let mut universe = soup(&config, soup_seed(seed, index));
+                generations += time_to_stabilise(&mut universe, config.max_generations, 2)
+                    .unwrap_or(config.max_generations as u128);
+                nodes += universe.cache_counters().nodes_created;
+            }
+            (generations, nodes)
+        })),
+    ];
+
+    println!("{:<28}  {:>12}  {:>9}  {:>14}  {:>13}", "run", "generations", "seconds", "generations/s", "nodes created");
+    for (name, run) in &runs {
+        let start = Instant::now();
+        let (generations, nodes) = run();
+        let seconds = start.elapsed().as_secs_f64();
+        println!("{:<28}  {:>12}  {:>9.3}  {:>14.0}  {:>13}", name, generations, seconds,
+                 generations as f64 / seconds, nodes);
+    }
+    Ok(())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (path, mut options) = file_and_options(args)?;
+    let generations: u128 = options.get("generations", 0)?;
+    let output: String = options.get("output", String::new())?;
+    options.finish()?;
+
+    let mut universe = load(path)?;
+    universe.step_to(universe.generation() + generations).map_err(|e| e.to_string())?;
+    if output.is_empty() {
+        print!("{}", universe.to_rle(None));
+        return Ok(());
+    }
+    universe.save_file(&output).map_err(|e| format!("{}: {}", output, e))
+}
+
+fn info(args: &[String]) -> Result<(), String> {
+    let (path, mut options) = file_and_options(args)?;
+    let max_period = options.get("max-period", 1000)?;
+    options.finish()?;
+
+    let mut universe = load(path)?;
+    println!("rule          {}", universe.rule());
+    if !universe.topology().is_infinite() {
+        println!("topology      {}", universe.topology());
+    }
+    match universe.bounding_box() {
+        Some((left, top, width, height)) => println!("bounding box  {} x {} at ({}, {})", width, height, left, top),
+        None => println!("bounding box  empty"),
+    }
+    println!("population    {}", universe.exact_population());
+    match universe.find_period(max_period) {
+        Some(periodicity) if periodicity.is_spaceship() => {
+            println!("period        {}, moving ({}, {}) per period", periodicity, periodicity.dx, periodicity.dy);
+        }
+        Some(periodicity) => println!("period        {}", periodicity),
+        None => println!("period        none up to {}", max_period),
+    }
+    // Finding the period ran the pattern, so this is roughly what it takes to simulate
+    let memory = universe.memory_usage().total();
+    println!("memory        {:.1} MiB", memory as f64 / (1 << 20) as f64);
+    Ok(())
+}
+
+fn compare(args: &[String]) -> Result<(), String> {
+    let (first, args) = args.split_first().ok_or("missing pattern files")?;
+    let (second, mut options) = file_and_options(args)?;
+    let generations: u128 = options.get("generations", 0)?;
+    options.finish()?;
+
+    let mut universes = [load(first)?, load(second)?];
+    for universe in &mut universes {
+        universe.step_to(universe.generation() + generations).map_err(|e| e.to_string())?;
+    }
+    let [a, b] = &universes;
+    if a.rule() != b.rule() {
+        println!("note: the rules differ ({} and {})", a.rule(), b.rule());
+    }
+    if a.background() != b.background() {
+        println!("different: the background is {} and {}", a.background(), b.background());
+        return Ok(());
+    }
+    let differences = a.diff(b);
+    match differences.first() {
+        None => println!("identical after {} generations (population {})", generations, a.exact_population()),
+        Some(&(x, y, alive)) => {
+            println!("different after {} generations: {} cells differ, the first at ({}, {}), alive in {}",
+                     generations, differences.len(), x, y, if alive { second } else { first });
+        }
+    }
+    Ok(())
+}
+
+/// Largest zoom out, as a power of two cells per character, which keeps the
+/// drawn region well inside the universe's coordinates
+#[cfg(feature = "tui")]
+const MAX_ZOOM: u32 = 40;
+
+/// Where `view` is looking: the cell at the centre and how many cells, as a
+/// power of two, each character covers
+#[cfg(feature = "tui")]
+struct View {
+    x: i64,
+    y: i64,
+    zoom: u32,
+    width: i64,
+    height: i64,
+}
+
+#[cfg(feature = "tui")]
+impl View {
+    fn rect(&self) -> Rect {
+        let (width, height) = (self.width << self.zoom, self.height << self.zoom);
+        Rect::new(self.x - width / 2, self.y - height / 2, width, height)
+    }
+
+    /// Centre on the pattern, zoomed out just far enough to show all of it
+    fn fit(&mut self, universe: &Universe) {
+        let Some((left, top, width, height)) = universe.bounding_box() else {
+            return;
+        };
+        (self.x, self.y) = (left + width / 2, top + height / 2);
+        self.zoom = 0;
+        while self.zoom < MAX_ZOOM && ((self.width << self.zoom) < width || (self.height << self.zoom) < height) {
+            self.zoom += 1;
+        }
+    }
+
+    /// The region as rows of `.` and `O`, where a character covering several
+    /// cells is `O` if any of them is alive
+    fn draw(&self, universe: &Universe) -> String {
+        let rect = self.rect();
+        if self.zoom == 0 {
+            return universe.format_region(rect);
+        }
+        let palette = Palette::default();
+        let image = rasterize(universe, rect, 1.0 / (1u64 << self.zoom) as f64, &palette);
+        let rows: Vec<String> = (0..image.height)
+            .map(|y| (0..image.width).map(|x| if image.pixel(x, y) == palette.background { '.' } else { 'O' }).collect())
+            .collect();
+        rows.join("\n")
+    }
+}
+
+/// An interactive viewer on plain standard input and output, redrawing the
+/// screen with ANSI escapes after each command
+#[cfg(feature = "tui")]
+fn view(args: &[String]) -> Result<(), String> {
+    let (path, mut options) = file_and_options(args)?;
+    let width = options.get("width", 80i64)?.max(1);
+    let height = options.get("height", 24i64)?.max(1);
+    options.finish()?;
+
+    let mut universe = load(path)?;
+    let mut view = View { x: 0, y: 0, zoom: 0, width, height };
+    view.fit(&universe);
+    let mut message = String::new();
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        print!("\x1b[2J\x1b[H{}\ngeneration {}  population {}  centre ({}, {})  {}:1 zoom out\n{}\n> ",
+               view.draw(&universe), universe.generation(), universe.exact_population(), view.x, view.y,
+               1u64 << view.zoom, message);
+        std::io::stdout().flush().map_err(|e| e.to_string())?;
+        message.clear();
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        let line = line.map_err(|e| e.to_string())?;
+        let mut words = line.split_whitespace();
+        // Panning moves a quarter of the view
+        let (dx, dy) = ((view.width << view.zoom) / 4, (view.height << view.zoom) / 4);
+        match (words.next().unwrap_or("n"), words.next()) {
+            ("q", _) => return Ok(()),
+            ("h", _) => view.x -= dx,
+            ("l", _) => view.x += dx,
+            ("k", _) => view.y -= dy,
+            ("j", _) => view.y += dy,
+            ("+", _) => view.zoom = view.zoom.saturating_sub(1),
+            ("-", _) => view.zoom = (view.zoom + 1).min(MAX_ZOOM),
+            ("f", _) => view.fit(&universe),
+            ("n", _) => universe.step(),
+            ("r", Some(count)) => match count.parse::<u128>() {
+                Ok(count) => {
+                    if let Err(e) = universe.step_to(universe.generation() + count) {
+                        message = e.to_string();
+                    }
+                }
+                Err(e) => message = format!("invalid count {:?}: {}", count, e),
+            },
+            (command, _) => message = format!("unknown command {:?}", command),
+        }
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn view(_: &[String]) -> Result<(), String> {
+    Err("vive was built without the tui feature".to_string())
+}
+
+#[cfg(feature = "server")]
+fn serve(mut options: Options) -> Result<(), String> {
+    let addr: String = options.get("addr", "127.0.0.1:8080".to_string())?;
+    options.finish()?;
+    eprintln!("listening on ws://{}", addr);
+    vive_hashlife::server::serve(&addr).map_err(|e| format!("{}: {}", addr, e))
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_: Options) -> Result<(), String> {
+    Err("vive was built without the server feature".to_string())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(command) = args.first() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let args = &args[1..];
+    let result = match command.as_str() {
+        "search" => Options::parse(args).and_then(search),
+        "census" => Options::parse(args).and_then(census),
+        "bench" => Options::parse(args).and_then(bench),
+        "run" => run(args),
+        "info" => info(args),
+        "compare" => compare(args),
+        "view" => view(args),
+        "serve" => Options::parse(args).and_then(serve),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command {:?}", command)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("vive: {}\n\n{}", message, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
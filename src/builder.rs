@@ -0,0 +1,194 @@
+//! Building a universe from named options
+//!
+//! `Universe::builder` starts from the defaults of `Universe::new` (Conway's
+//! Game of Life on the plane, an empty root of level 3) and each method
+//! changes one of them, so new options don't have to become more positional
+//! parameters of the constructors:
+//!
+//! ```
+//! # use vive_hashlife::hashlife::Universe;
+//! # use vive_hashlife::pattern::Pattern;
+//! # use vive_hashlife::topology::Topology;
+//! let glider = Pattern::from_cells([(1, 0, 1), (2, 1, 1), (0, 2, 1), (1, 2, 1), (2, 2, 1)]);
+//! let universe = Universe::builder()
+//!     .rule("B36/S23")
+//!     .topology(Topology::Torus { width: 32, height: 32 })
+//!     .history_limit(10)
+//!     .initial_pattern(glider)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(universe.population(), 5);
+//! ```
+
+use std::convert::Infallible;
+use std::fmt;
+
+use crate::edit::PasteMode;
+use crate::hashlife::{NodeCache, Universe};
+use crate::pattern::Pattern;
+use crate::rule::{ParseRuleError, Rule};
+use crate::topology::{Topology, MAX_GRID_SIZE};
+
+/// Error returned by `UniverseBuilder::build`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    InvalidRule(ParseRuleError),
+    /// A finite topology has a side of 0 or above `MAX_GRID_SIZE`
+    InvalidTopology(Topology),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::InvalidRule(e) => write!(f, "{}", e),
+            BuildError::InvalidTopology(topology) => {
+                write!(f, "grid dimensions must be between 1 and {}: {:?}", MAX_GRID_SIZE, topology)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<ParseRuleError> for BuildError {
+    fn from(e: ParseRuleError) -> Self {
+        BuildError::InvalidRule(e)
+    }
+}
+
+/// Lets `UniverseBuilder::rule` take a `Rule` as well as a rulestring
+impl From<Infallible> for BuildError {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+/// Options for a new universe, from `Universe::builder`
+#[derive(Clone)]
+pub struct UniverseBuilder {
+    size_level: usize,
+    rule: Result<Rule, BuildError>,
+    topology: Topology,
+    cache: Option<NodeCache>,
+    history_limit: Option<usize>,
+    memory_limit: Option<usize>,
+    pattern: Option<Pattern>,
+}
+
+impl Universe {
+    /// Start building a universe (see the `builder` module)
+    pub fn builder() -> UniverseBuilder {
+        UniverseBuilder {
+            size_level: 3,
+            rule: Ok(Rule::conway()),
+            topology: Topology::Plane,
+            cache: None,
+            history_limit: None,
+            memory_limit: None,
+            pattern: None,
+        }
+    }
+}
+
+impl UniverseBuilder {
+    /// Level of the empty root to start from. The root grows as cells are
+    /// set, so this only saves expanding it.
+    pub fn size_level(mut self, size_level: usize) -> Self {
+        self.size_level = size_level;
+        self
+    }
+
+    /// The rule, as a `Rule` or a rulestring, which `build` reports if it
+    /// doesn't parse
+    pub fn rule<R: TryInto<Rule>>(mut self, rule: R) -> Self
+    where
+        R::Error: Into<BuildError>,
+    {
+        self.rule = rule.try_into().map_err(Into::into);
+        self
+    }
+
+    pub fn topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Store nodes in `cache`, shared with other universes (see `Universe::with_cache`)
+    pub fn node_cache(mut self, cache: NodeCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Keep at most `limit` generations for `step_back` (see `Universe::set_history_limit`)
+    pub fn history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = Some(limit);
+        self
+    }
+
+    /// Keep the universe within about `megabytes` (see `Universe::set_memory_limit`)
+    pub fn max_memory_mb(mut self, megabytes: usize) -> Self {
+        self.memory_limit = Some(megabytes.saturating_mul(1 << 20));
+        self
+    }
+
+    /// Cells to start with, at the pattern's own coordinates
+    pub fn initial_pattern(mut self, pattern: Pattern) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    pub fn build(self) -> Result<Universe, BuildError> {
+        let rule = self.rule?;
+        if !self.topology.is_valid() {
+            return Err(BuildError::InvalidTopology(self.topology));
+        }
+        let cache = self.cache.unwrap_or_default();
+        let mut universe = Universe::with_cache(self.size_level, rule, self.topology, cache);
+        if let Some(limit) = self.history_limit {
+            universe.set_history_limit(limit);
+        }
+        universe.set_memory_limit(self.memory_limit);
+        if let Some(pattern) = &self.pattern {
+            universe.paste(pattern, 0, 0, PasteMode::Or);
+            // The starting cells are not an edit to undo
+            universe.clear_undo();
+        }
+        Ok(universe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let universe = Universe::builder().build().unwrap();
+        assert_eq!((universe.rule(), universe.topology(), universe.population()), (&Rule::conway(), Topology::Plane, 0));
+
+        let blinker = Pattern::from_cells([(-1, 5, 1), (0, 5, 1), (1, 5, 1)]);
+        let cache = NodeCache::new();
+        let mut universe = Universe::builder()
+            .rule(Rule::wireworld())
+            .topology(Topology::Torus { width: 20, height: 20 })
+            .node_cache(cache.clone())
+            .history_limit(1)
+            .max_memory_mb(64)
+            .initial_pattern(blinker)
+            .build()
+            .unwrap();
+        assert_eq!(universe.live_cells(), [(-1, 5, 1), (0, 5, 1), (1, 5, 1)]);
+        assert!(universe.node_cache().same_as(&cache) && !universe.can_undo());
+        assert_eq!(universe.memory_limit(), Some(64 << 20));
+        universe.step();
+        universe.step();
+        assert_eq!(universe.history_len(), 1);
+
+        let error = Universe::builder().rule("B3/S9").build().err();
+        assert!(matches!(error, Some(BuildError::InvalidRule(_))));
+        let torus = Topology::Torus { width: 0, height: 10 };
+        assert_eq!(Universe::builder().topology(torus).build().err(), Some(BuildError::InvalidTopology(torus)));
+        let huge = Topology::Torus { width: i64::MAX as u64, height: 4 };
+        assert_eq!(Universe::builder().topology(huge).build().err(), Some(BuildError::InvalidTopology(huge)));
+    }
+}
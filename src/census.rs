@@ -0,0 +1,252 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::apgcode::{apgcode, canonical_wechsler};
+use crate::hashlife::Universe;
+use crate::pattern::Pattern;
+use crate::rule::Rule;
+use crate::search::{soup, soup_seed, time_to_stabilise, SearchConfig};
+
+/// Split cells into objects: two cells belong to the same object if a chain of
+/// cells connects them with each step at most `gap + 1` cells apart in x and y
+/// (so `gap = 0` is the usual diagonal adjacency)
+pub fn separate_objects(cells: &[(i64, i64)], gap: u32) -> Vec<Vec<(i64, i64)>> {
+    let reach = gap as i64 + 1;
+    let index: HashMap<(i64, i64), usize> = cells.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+    let mut seen = vec![false; cells.len()];
+    let mut objects = Vec::new();
+
+    for start in 0..cells.len() {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut object = Vec::new();
+        let mut stack = vec![start];
+        while let Some(i) = stack.pop() {
+            let (x, y) = cells[i];
+            object.push((x, y));
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    if let Some(&j) = index.get(&(x + dx, y + dy)) {
+                        if !seen[j] {
+                            seen[j] = true;
+                            stack.push(j);
+                        }
+                    }
+                }
+            }
+        }
+        object.sort_unstable();
+        objects.push(object);
+    }
+    objects
+}
+
+/// apgcode of an object under `rule` (see `apgcode::apgcode`), or `zz_`
+/// followed by its Wechsler code if it does not repeat within `max_period`
+/// generations
+pub fn classify_object(cells: &[(i64, i64)], rule: &Rule, max_period: u64) -> String {
+    let object = Pattern::from_live_cells(cells.iter().copied());
+    apgcode(&object, rule, max_period)
+        .unwrap_or_else(|| format!("zz_{}", canonical_wechsler([&object])))
+}
+
+/// Objects whose cells are at most this many empty cells apart count as one
+/// in `Universe::census`, so that pseudo still lifes stay together
+pub const OBJECT_GAP: u32 = 1;
+
+/// An object found by `Universe::census`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CensusObject {
+    /// Smallest rectangle `(left, top, width, height)` containing the object
+    pub bounding_box: (i64, i64, i64, i64),
+    pub population: u64,
+    /// Hash of the object's cells up to translation, rotation and reflection,
+    /// so copies of the same object in any orientation share it
+    pub hash: u64,
+}
+
+impl Universe {
+    /// Split the cells that differ from the background into objects (cells
+    /// connected through neighbours, or through gaps of up to `OBJECT_GAP`
+    /// empty cells), in reading order of their top-left corners
+    pub fn census(&self) -> Vec<CensusObject> {
+        let states: HashMap<(i64, i64), _> = self.live_cells().into_iter().map(|(x, y, state)| ((x, y), state)).collect();
+        let cells: Vec<_> = states.keys().copied().collect();
+        let mut objects: Vec<_> = separate_objects(&cells, OBJECT_GAP).into_iter()
+            .map(|object| {
+                let pattern = Pattern::from_cells(object.iter().map(|&(x, y)| (x, y, states[&(x, y)])));
+                let mut hasher = DefaultHasher::new();
+                pattern.canonicalize().hash(&mut hasher);
+                CensusObject {
+                    bounding_box: pattern.bounding_box().unwrap(),
+                    population: pattern.population(),
+                    hash: hasher.finish(),
+                }
+            })
+            .collect();
+        objects.sort_by_key(|object| (object.bounding_box.1, object.bounding_box.0));
+        objects
+    }
+}
+
+/// Object tallies from a soup census
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CensusReport {
+    /// Number of soups run
+    pub soups: u64,
+    /// Soups that had not stabilised by the generation limit (their ash is not counted)
+    pub unstabilised: u64,
+    /// Object name (see `classify_object`) to number of occurrences
+    pub counts: HashMap<String, u64>,
+}
+
+impl CensusReport {
+    /// Objects from most to least common (ties by name)
+    pub fn sorted(&self) -> Vec<(&str, u64)> {
+        let mut objects: Vec<_> = self.counts.iter().map(|(name, &count)| (name.as_str(), count)).collect();
+        objects.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        objects
+    }
+
+    /// Add the tallies of another census
+    pub fn merge(&mut self, other: &CensusReport) {
+        self.soups += other.soups;
+        self.unstabilised += other.unstabilised;
+        for (name, count) in &other.counts {
+            *self.counts.entry(name.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+impl fmt::Display for CensusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} soups ({} unstabilised)", self.soups, self.unstabilised)?;
+        for (name, count) in self.sorted() {
+            writeln!(f, "{:>10}  {}", count, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `config.soups` random soups to stabilisation, separate the ash into
+/// objects and tally them by name. Uses the soup size, density, seed and
+/// limits of the methuselah search config.
+pub fn soup_census(config: &SearchConfig) -> CensusReport {
+    let mut report = CensusReport::default();
+    let mut names = HashMap::new();
+    for index in 0..config.soups {
+        report.soups += 1;
+        let mut universe = soup(config, soup_seed(config.seed, index));
+        if time_to_stabilise(&mut universe, config.max_generations, config.max_period).is_none() {
+            report.unstabilised += 1;
+            continue;
+        }
+
+        let cells: Vec<_> = universe.live_cells().into_iter().map(|(x, y, _)| (x, y)).collect();
+        for object in separate_objects(&cells, 0) {
+            // Most ash is made of a few common objects, so remember their names
+            let key = canonical_wechsler([&Pattern::from_live_cells(object.iter().copied())]);
+            let name = names.entry(key)
+                .or_insert_with(|| classify_object(&object, &config.rule, config.max_period));
+            *report.counts.entry(name.clone()).or_insert(0) += 1;
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_separate_objects() {
+        let block = [(0, 0), (0, 1), (1, 0), (1, 1)];
+        let blinker = [(5, 0), (5, 1), (5, 2)];
+        let cells: Vec<_> = block.iter().chain(&blinker).copied().collect();
+        let objects = separate_objects(&cells, 0);
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0], block);
+
+        // They are three empty columns apart
+        assert_eq!(separate_objects(&cells, 2).len(), 2);
+        assert_eq!(separate_objects(&cells, 3).len(), 1);
+        assert_eq!(separate_objects(&[(0, 0), (1, 1), (2, 2)], 0).len(), 1);
+    }
+
+    #[test]
+    fn test_classify_object() {
+        let rule = Rule::conway();
+        let block = classify_object(&[(0, 0), (1, 0), (0, 1), (1, 1)], &rule, 30);
+        assert_eq!(block, "xs4_33");
+
+        // Every phase and orientation of the blinker gets the same name
+        let horizontal = classify_object(&[(0, 0), (1, 0), (2, 0)], &rule, 30);
+        let vertical = classify_object(&[(7, 3), (7, 4), (7, 5)], &rule, 30);
+        assert_eq!(horizontal, vertical);
+        assert_eq!(horizontal, "xp2_7");
+
+        let glider = classify_object(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], &rule, 30);
+        let other_glider = classify_object(&[(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)], &rule, 30);
+        assert_eq!(glider, other_glider);
+        assert_eq!(glider, "xq4_153");
+
+        let r_pentomino = classify_object(&[(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)], &rule, 30);
+        assert!(r_pentomino.starts_with("zz_"));
+    }
+
+    #[test]
+    fn test_universe_census() {
+        let mut universe = Universe::new(6);
+        // A block, a blinker, a glider in two orientations, and two pieces with a
+        // one-cell gap between them
+        let objects: [&[(i64, i64)]; 5] = [
+            &[(0, 0), (1, 0), (0, 1), (1, 1)],
+            &[(10, 0), (11, 0), (12, 0)],
+            &[(-10, 5), (-9, 6), (-11, 7), (-10, 7), (-9, 7)],
+            &[(20, 10), (21, 10), (22, 10), (20, 11), (21, 12)],
+            &[(0, 20), (1, 20), (0, 21), (0, 22), (3, 21), (3, 22), (2, 23), (3, 23)],
+        ];
+        for object in objects {
+            for &(x, y) in object {
+                universe.set_cell(x, y, true);
+            }
+        }
+
+        let census = universe.census();
+        assert_eq!(census.len(), 5);
+        assert_eq!(census[0].bounding_box, (0, 0, 2, 2));
+        assert_eq!(census[1].bounding_box, (10, 0, 3, 1));
+        assert_eq!(census[1].population, 3);
+        assert_eq!(census[2].bounding_box, (-11, 5, 3, 3));
+        assert_eq!(census[3].bounding_box, (20, 10, 3, 3));
+        assert_eq!(census[2].hash, census[3].hash);
+        assert_ne!(census[0].hash, census[2].hash);
+        assert_eq!((census[4].bounding_box, census[4].population), ((0, 20, 4, 4), 8));
+
+        assert!(Universe::new(4).census().is_empty());
+    }
+
+    #[test]
+    fn test_soup_census() {
+        let config = SearchConfig {
+            soups: 10, soup_width: 8, soup_height: 8, max_generations: 1000, ..SearchConfig::default()
+        };
+        let report = soup_census(&config);
+        assert_eq!(report.soups, 10);
+        assert_eq!(report, soup_census(&config));
+
+        // Blocks and blinkers are the most common ash objects
+        let sorted = report.sorted();
+        assert!(sorted.iter().take(3).any(|(name, _)| *name == "xs4_33"));
+        assert!(report.to_string().starts_with("10 soups"));
+
+        let mut merged = report.clone();
+        merged.merge(&report);
+        assert_eq!(merged.soups, 20);
+        assert_eq!(merged.counts["xs4_33"], 2 * report.counts["xs4_33"]);
+    }
+}
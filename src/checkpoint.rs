@@ -0,0 +1,183 @@
+//! Checkpoints taken automatically as a universe runs
+//!
+//! Every so many generations, or every so many seconds, the universe keeps a
+//! reference to its root in a ring buffer, and `Universe::restore_checkpoint`
+//! goes back to any of them. Roots share their subtrees through the node
+//! cache, so a checkpoint costs one pointer plus the nodes only it still uses.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::hashlife::Node;
+
+/// Number of checkpoints a universe keeps unless told otherwise
+pub const DEFAULT_CHECKPOINT_LIMIT: usize = 16;
+
+/// How often `Universe::set_checkpoint_interval` takes checkpoints
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheckpointInterval {
+    /// After a step that ends at least this many generations after the last checkpoint
+    Generations(u64),
+    /// After a step that ends at least this many seconds after the last checkpoint
+    Seconds(f64),
+}
+
+/// A checkpoint in `Universe::checkpoints`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Number of the checkpoint, counting up from 0 in the order they were taken
+    pub id: u64,
+    pub generation: u128,
+    pub population: u64,
+}
+
+/// A saved root with its generation and inversion, as in the step history
+#[derive(Clone)]
+struct Saved {
+    checkpoint: Checkpoint,
+    root: Rc<Node>,
+    inverted: bool,
+    /// When it was taken, in seconds
+    time: f64,
+}
+
+/// The ring buffer of checkpoints and when to take the next one
+#[derive(Clone)]
+pub(crate) struct Checkpoints {
+    interval: Option<CheckpointInterval>,
+    limit: usize,
+    saved: VecDeque<Saved>,
+    next_id: u64,
+    /// Generation and time the next checkpoint is measured from
+    last: (u128, f64),
+}
+
+impl Default for Checkpoints {
+    fn default() -> Self {
+        Checkpoints { interval: None, limit: DEFAULT_CHECKPOINT_LIMIT, saved: VecDeque::new(), next_id: 0, last: (0, 0.0) }
+    }
+}
+
+impl Checkpoints {
+    pub(crate) fn interval(&self) -> Option<CheckpointInterval> {
+        self.interval
+    }
+
+    pub(crate) fn set_interval(&mut self, interval: Option<CheckpointInterval>) {
+        self.interval = interval;
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.saved.len() > limit {
+            self.saved.pop_front();
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.saved.capacity() * std::mem::size_of::<Saved>()
+    }
+
+    pub(crate) fn list(&self) -> Vec<Checkpoint> {
+        self.saved.iter().map(|saved| saved.checkpoint).collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.saved.clear();
+    }
+
+    /// Whether a step that ended at `generation` and `time` is due a checkpoint
+    pub(crate) fn is_due(&self, generation: u128, time: f64) -> bool {
+        let (generation0, time0) = self.last;
+        match self.interval {
+            None => false,
+            Some(CheckpointInterval::Generations(interval)) => generation.saturating_sub(generation0) >= interval as u128,
+            Some(CheckpointInterval::Seconds(interval)) => time - time0 >= interval,
+        }
+    }
+
+    /// Keep `root`, dropping the oldest checkpoint if the buffer is full
+    pub(crate) fn save(&mut self, root: Rc<Node>, generation: u128, inverted: bool, time: f64) {
+        self.last = (generation, time);
+        if self.limit == 0 {
+            return;
+        }
+        if self.saved.len() == self.limit {
+            self.saved.pop_front();
+        }
+        let checkpoint = Checkpoint { id: self.next_id, generation, population: u64::try_from(root.population()).unwrap_or(u64::MAX) };
+        self.next_id += 1;
+        self.saved.push_back(Saved { checkpoint, root, inverted, time });
+    }
+
+    /// The root, generation and inversion of checkpoint `id`, which later
+    /// checkpoints are then measured from
+    pub(crate) fn get(&mut self, id: u64) -> Option<(Rc<Node>, u128, bool)> {
+        let saved = self.saved.iter().find(|saved| saved.checkpoint.id == id)?;
+        self.last = (saved.checkpoint.generation, saved.time);
+        Some((saved.root.clone(), saved.checkpoint.generation, saved.inverted))
+    }
+}
+
+/// Seconds since the Unix epoch, for timed checkpoints
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> f64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+/// Seconds since the Unix epoch, from the browser's clock (the standard
+/// library has none on this target)
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub(crate) fn now() -> f64 {
+    date_now() / 1000.0
+}
+
+/// No clock to read without the bindings, so timed checkpoints are never due
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+pub(crate) fn now() -> f64 {
+    0.0
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date, js_name = now)]
+    fn date_now() -> f64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashlife::Universe;
+
+    #[test]
+    fn test_checkpoints() {
+        let root = Universe::new(3).root().clone();
+        let mut checkpoints = Checkpoints::default();
+        assert!(!checkpoints.is_due(100, 100.0));
+
+        checkpoints.set_interval(Some(CheckpointInterval::Generations(10)));
+        checkpoints.set_limit(3);
+        assert!(checkpoints.is_due(10, 0.0) && !checkpoints.is_due(9, 0.0));
+        for generation in [0, 10, 20, 30] {
+            checkpoints.save(root.clone(), generation, false, 0.0);
+        }
+        let ids: Vec<_> = checkpoints.list().iter().map(|checkpoint| (checkpoint.id, checkpoint.generation)).collect();
+        assert_eq!(ids, [(1, 10), (2, 20), (3, 30)]);
+        assert!(!checkpoints.is_due(39, 0.0));
+
+        // Later checkpoints count from a restored one
+        assert_eq!(checkpoints.get(1).map(|(_, generation, _)| generation), Some(10));
+        assert!(checkpoints.get(0).is_none());
+        assert!(checkpoints.is_due(20, 0.0) && !checkpoints.is_due(19, 0.0));
+
+        checkpoints.set_interval(Some(CheckpointInterval::Seconds(2.5)));
+        assert!(checkpoints.is_due(0, 2.5) && !checkpoints.is_due(1000, 2.0));
+        checkpoints.set_limit(1);
+        assert_eq!(checkpoints.list().len(), 1);
+    }
+}
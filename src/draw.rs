@@ -0,0 +1,180 @@
+//! Drawing shapes into a universe
+//!
+//! Shapes are drawn in a numbered state, so tools and generators can draw
+//! live or dead cells (or any state of a multi-state rule). On a finite grid
+//! only the cells inside the grid are drawn.
+
+use crate::hashlife::Universe;
+use crate::pattern::Rect;
+use crate::rule::State;
+
+impl Universe {
+    /// Set the cells of the straight line from `(x0, y0)` to `(x1, y1)`, both
+    /// ends included, as Bresenham's algorithm picks them
+    pub fn draw_line(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), state: State) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+        let mut cells = Vec::with_capacity((dx - dy + 1) as usize);
+        loop {
+            cells.push((x, y, state));
+            if (x, y) == (x1, y1) {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+        self.draw_cells(cells);
+    }
+
+    /// Set the cells of `rect`, or only its one-cell-wide border
+    pub fn draw_rect(&mut self, rect: Rect, state: State, filled: bool) {
+        if filled || rect.width <= 2 || rect.height <= 2 {
+            self.fill_region(rect, state);
+            return;
+        }
+        let Rect { left, top, width, height } = rect;
+        self.begin_edit_group();
+        for edge in [
+            Rect::new(left, top, width, 1),
+            Rect::new(left, top + height - 1, width, 1),
+            Rect::new(left, top + 1, 1, height - 2),
+            Rect::new(left + width - 1, top + 1, 1, height - 2),
+        ] {
+            self.fill_region(edge, state);
+        }
+        self.end_edit_group();
+    }
+
+    /// Set the cells of the ellipse filling `rect`, or only those with a
+    /// neighbour outside it. A cell is inside when its centre is.
+    pub fn draw_ellipse(&mut self, rect: Rect, state: State, filled: bool) {
+        if rect.width <= 0 || rect.height <= 0 {
+            return;
+        }
+        let spans: Vec<_> = (rect.top..rect.top + rect.height).map(|y| ellipse_span(rect, y)).collect();
+        let mut cells = Vec::new();
+        self.begin_edit_group();
+        for (i, &span) in spans.iter().enumerate() {
+            let Some((left, right)) = span else {
+                continue;
+            };
+            let y = rect.top + i as i64;
+            if filled {
+                self.fill_region(Rect::new(left, y, right - left + 1, 1), state);
+                continue;
+            }
+            // A cell is on the outline unless the rows above and below both cover it
+            let neighbour = |j: Option<usize>| j.and_then(|j| spans.get(j).copied().flatten());
+            let covered = match (neighbour(i.checked_sub(1)), neighbour(Some(i + 1))) {
+                (Some((l1, r1)), Some((l2, r2))) => Some((l1.max(l2).max(left + 1), r1.min(r2).min(right - 1))),
+                _ => None,
+            };
+            match covered {
+                Some((inner_left, inner_right)) if inner_left <= inner_right => {
+                    cells.extend((left..inner_left).map(|x| (x, y, state)));
+                    cells.extend((inner_right + 1..=right).map(|x| (x, y, state)));
+                }
+                _ => cells.extend((left..=right).map(|x| (x, y, state))),
+            }
+        }
+        self.draw_cells(cells);
+        self.end_edit_group();
+    }
+
+    /// Set the given cells, dropping those beyond a finite grid
+    fn draw_cells(&mut self, mut cells: Vec<(i64, i64, State)>) {
+        if let Some((left, top, width, height)) = self.topology().bounds() {
+            let grid = Rect::new(left, top, width, height);
+            cells.retain(|&(x, y, _)| grid.contains(x, y));
+        }
+        self.set_cell_states(&cells);
+    }
+}
+
+/// First and last cell of row `y` inside the ellipse filling `rect`
+fn ellipse_span(rect: Rect, y: i64) -> Option<(i64, i64)> {
+    let (a, b) = (rect.width as f64 / 2.0, rect.height as f64 / 2.0);
+    let (centre_x, centre_y) = (rect.left as f64 + a, rect.top as f64 + b);
+    let dy = (y as f64 + 0.5 - centre_y) / b;
+    let half_width = a * (1.0 - dy * dy).max(0.0).sqrt();
+    let left = (centre_x - half_width - 0.5).ceil() as i64;
+    let right = (centre_x + half_width - 0.5).floor() as i64;
+    (left <= right).then_some((left, right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::Pattern;
+    use crate::rule::Rule;
+    use crate::topology::Topology;
+
+    #[test]
+    fn test_draw_line() {
+        let mut universe = Universe::new(3);
+        universe.draw_line((0, 0), (4, 2), 1);
+        assert_eq!(universe.to_pattern(), Pattern::from_live_cells([(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]));
+        let mut reversed = Universe::new(3);
+        reversed.draw_line((4, 2), (0, 0), 1);
+        assert_eq!(reversed.population(), 5);
+
+        universe.draw_line((-3, 5), (-3, -5), 1);
+        universe.draw_line((2, 1), (2, 1), 0);
+        assert_eq!(universe.population(), 15);
+        assert!(universe.get_cell(-3, -5) && !universe.get_cell(2, 1));
+    }
+
+    #[test]
+    fn test_draw_rect() {
+        let mut universe = Universe::new(3);
+        universe.draw_rect(Rect::new(0, 0, 5, 4), 1, false);
+        assert_eq!(universe.population(), 14);
+        assert!(universe.get_cell(4, 3) && !universe.get_cell(2, 2));
+        universe.draw_rect(Rect::new(0, 0, 5, 4), 1, true);
+        assert_eq!(universe.population(), 20);
+        universe.draw_rect(Rect::new(1, 1, 3, 2), 0, false);
+        assert_eq!(universe.population(), 14);
+    }
+
+    #[test]
+    fn test_draw_ellipse() {
+        // A circle of diameter 5 is a plus-shaped disc; its outline lacks the centre
+        let mut disc = Universe::new(3);
+        disc.draw_ellipse(Rect::new(0, 0, 5, 5), 1, true);
+        let rows: Vec<_> = (0..5).map(|y| ellipse_span(Rect::new(0, 0, 5, 5), y)).collect();
+        assert_eq!(rows, [Some((1, 3)), Some((0, 4)), Some((0, 4)), Some((0, 4)), Some((1, 3))]);
+        assert_eq!(disc.population(), 21);
+        let mut ring = Universe::new(3);
+        ring.draw_ellipse(Rect::new(0, 0, 5, 5), 1, false);
+        assert_eq!(ring.population(), 12);
+        assert!(!ring.get_cell(2, 2) && ring.get_cell(0, 2) && !ring.get_cell(1, 1));
+
+        // Drawn shapes are symmetric and the outline lies within the disc
+        let rect = Rect::new(-40, -13, 81, 27);
+        let mut disc = Universe::new(3);
+        disc.draw_ellipse(rect, 1, true);
+        let mut ring = Universe::new(3);
+        ring.draw_ellipse(rect, 1, false);
+        for universe in [&disc, &ring] {
+            let cells = universe.to_pattern();
+            assert_eq!(cells.bounding_box(), Some((-40, -13, 81, 27)));
+            assert!(cells.cells().iter().all(|&(x, y, _)| cells.get(-x, y) == 1 && cells.get(x, -y) == 1));
+        }
+        let mut outside = ring;
+        outside.difference(&disc);
+        assert_eq!(outside.population(), 0);
+
+        let mut universe = Universe::with_topology(4, Rule::conway(), Topology::Torus { width: 10, height: 10 });
+        universe.draw_ellipse(Rect::new(-20, -20, 40, 40), 1, false);
+        universe.draw_line((-20, 0), (20, 0), 1);
+        assert_eq!(universe.population(), 10);
+    }
+}
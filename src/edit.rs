@@ -0,0 +1,904 @@
+//! Editing whole regions of a universe
+//!
+//! These operations rebuild only the nodes along the edges of the region they
+//! touch. Nodes the region covers whole are swapped for canonical ones rather
+//! than visited cell by cell, and results are shared between equal nodes cut
+//! the same way, so the cost depends on the pattern, not on the region's size.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::hashlife::{Node, Universe, EXTENT};
+use crate::pattern::{Pattern, Rect, Symmetry};
+use crate::random::Rng;
+use crate::rule::State;
+
+/// Cells `fill_random` sets at a time
+const BATCH_CELLS: usize = 1 << 16;
+
+/// Mirroring of edits about a centre (see `Universe::set_edit_symmetry`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EditSymmetry {
+    pub symmetry: Symmetry,
+    /// The centre in half cells: `(2x, 2y)` is the middle of cell `(x, y)`, and
+    /// `(2x + 1, 2y + 1)` the corner it shares with cell `(x + 1, y + 1)`
+    pub centre: (i64, i64),
+}
+
+impl EditSymmetry {
+    /// The distinct cells the symmetry takes `(x, y)` to, starting with itself
+    pub fn images(&self, x: i64, y: i64) -> Vec<(i64, i64)> {
+        let (cx, cy) = self.centre;
+        let mut images: Vec<(i64, i64)> = Vec::with_capacity(8);
+        for transform in self.symmetry.transforms() {
+            let (u, v) = transform.apply(2 * x - cx, 2 * y - cy);
+            let image = ((u + cx).div_euclid(2), (v + cy).div_euclid(2));
+            if !images.contains(&image) {
+                images.push(image);
+            }
+        }
+        images
+    }
+}
+
+/// How `Universe::paste` combines a pattern with the cells under it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PasteMode {
+    /// Replace every cell in the pattern's bounding box with the pattern's
+    Copy,
+    /// Set the pattern's live cells, leaving the rest as they are
+    Or,
+    /// Toggle: cells alive in exactly one of the two end up alive
+    Xor,
+    /// Keep only the cells in the pattern's bounding box that are alive in
+    /// both, in the universe's state
+    And,
+}
+
+impl PasteMode {
+    fn combine(self, under: State, over: State) -> State {
+        match self {
+            PasteMode::Copy => over,
+            PasteMode::Or | PasteMode::Xor if under == 0 => over,
+            PasteMode::Or | PasteMode::Xor if over == 0 => under,
+            PasteMode::Or => over,
+            PasteMode::Xor => 0,
+            PasteMode::And => if over != 0 { under } else { 0 },
+        }
+    }
+}
+
+impl Universe {
+    /// Kill every cell in `rect`
+    pub fn clear_region(&mut self, rect: Rect) {
+        self.fill_region(rect, 0);
+    }
+
+    /// Set every cell in `rect` to `state`. On a finite grid only the part of
+    /// `rect` inside the grid changes.
+    pub fn fill_region(&mut self, rect: Rect, state: State) {
+        let Some(rect) = self.clip_to_grid(rect) else {
+            return;
+        };
+        let stored = self.to_stored(state);
+        if stored != 0 {
+            self.expand_to(rect);
+        }
+        let root = self.root().clone();
+        let half = 1i64 << (root.level() - 1);
+        let mut fill = Fill { uniform: vec![self.leaf_node(stored)], memo: HashMap::new() };
+        let root = self.fill_recursive(&root, -half, -half, rect, &mut fill);
+        self.replace_root(root);
+    }
+
+    /// Replace the cells in `rect` with random ones, each alive with probability
+    /// `density`, drawn row by row from an `Rng` seeded with `seed`, so the same
+    /// seed always gives the same soup. Every cell of `rect` is drawn, so its
+    /// area should stay within what a loop can visit.
+    pub fn fill_random(&mut self, rect: Rect, density: f64, seed: u64) {
+        self.begin_edit_group();
+        self.clear_region(rect);
+        let mut rng = Rng::new(seed);
+        let mut cells = Vec::new();
+        for y in rect.top..rect.top + rect.height {
+            for x in rect.left..rect.left + rect.width {
+                if rng.chance(density) {
+                    cells.push((x, y, 1));
+                }
+            }
+            if cells.len() >= BATCH_CELLS {
+                self.set_cell_states(&cells);
+                cells.clear();
+            }
+        }
+        self.set_cell_states(&cells);
+        self.end_edit_group();
+    }
+
+    /// Combine `pattern`, moved by `(x, y)`, with the cells under it, together
+    /// with its mirror images if an edit symmetry is set. The
+    /// pattern is built into a tree and merged subtree by subtree, so parts of
+    /// the universe it does not reach are shared rather than copied. On a
+    /// finite grid the pattern is clipped to the grid rather than wrapped.
+    pub fn paste(&mut self, pattern: &Pattern, x: i64, y: i64, mode: PasteMode) {
+        if let Some(symmetry) = self.edit_symmetry() {
+            // The copies are pasted together, so cells on a mirror line are only pasted once
+            let images = pattern.cells().iter().flat_map(|&(cx, cy, state)| {
+                symmetry.images(cx + x, cy + y).into_iter().map(move |(ix, iy)| (ix, iy, state))
+            });
+            let mirrored = Pattern::from_cells(images);
+            self.set_edit_symmetry(None);
+            self.paste(&mirrored, 0, 0, mode);
+            self.set_edit_symmetry(Some(symmetry));
+            return;
+        }
+        let Some((left, top, width, height)) = pattern.bounding_box() else {
+            return;
+        };
+        let Some(bounds) = self.clip_to_grid(Rect::new(left.saturating_add(x), top.saturating_add(y), width, height)) else {
+            return;
+        };
+        let mut cells: Vec<_> = pattern.cells().iter()
+            .filter_map(|&(cx, cy, state)| Some((cx.checked_add(x)?, cy.checked_add(y)?, state)))
+            .filter(|&(cx, cy, _)| bounds.contains(cx, cy))
+            .collect();
+        self.expand_to(bounds);
+        let root = self.root().clone();
+        let over = self.build_node(&mut cells, root.level());
+        let half = 1i64 << (root.level() - 1);
+        let mut paste = Paste {
+            mode,
+            bounds,
+            cleared: Fill { uniform: vec![self.leaf_node(self.to_stored(0))], memo: HashMap::new() },
+            memo: HashMap::new(),
+        };
+        let root = self.paste_recursive(&root, &over, -half, -half, &mut paste);
+        self.replace_root(root);
+    }
+
+    /// `under` with `over`, its cells in real states, pasted on it
+    fn paste_recursive(&mut self, under: &Rc<Node>, over: &Rc<Node>, node_x: i64, node_y: i64,
+                       paste: &mut Paste) -> Rc<Node> {
+        let size = 1i64 << under.level();
+        let node_rect = Rect::new(node_x, node_y, size, size);
+        let Some(covered) = paste.bounds.intersection(node_rect) else {
+            return under.clone();
+        };
+        let inside = covered == node_rect;
+        let plain = self.stored_background() == 0;
+        if over.population() == 0 {
+            match paste.mode {
+                PasteMode::Or | PasteMode::Xor => return under.clone(),
+                PasteMode::Copy | PasteMode::And if inside => {
+                    return self.uniform_node(&mut paste.cleared.uniform, under.level());
+                }
+                _ => {}
+            }
+        }
+        if plain {
+            match paste.mode {
+                PasteMode::Copy if inside => return over.clone(),
+                PasteMode::Or | PasteMode::Xor if under.population() == 0 => return over.clone(),
+                PasteMode::And if under.population() == 0 => return under.clone(),
+                _ => {}
+            }
+        }
+        if under.level() == 0 {
+            let state = paste.mode.combine(self.to_stored(under.state()), over.state());
+            return self.leaf_node(self.to_stored(state));
+        }
+        let key = (Rc::as_ptr(under), Rc::as_ptr(over));
+        if inside {
+            if let Some(result) = paste.memo.get(&key) {
+                return result.clone();
+            }
+        }
+
+        let half = size / 2;
+        let [nw, ne, sw, se] = under.children().map(Rc::clone);
+        let [onw, one, osw, ose] = over.children().map(Rc::clone);
+        let nw = self.paste_recursive(&nw, &onw, node_x, node_y, paste);
+        let ne = self.paste_recursive(&ne, &one, node_x + half, node_y, paste);
+        let sw = self.paste_recursive(&sw, &osw, node_x, node_y + half, paste);
+        let se = self.paste_recursive(&se, &ose, node_x + half, node_y + half, paste);
+        let result = self.inner_node(nw, ne, sw, se);
+        if inside {
+            paste.memo.insert(key, result.clone());
+        }
+        result
+    }
+
+    /// Move every cell by `(dx, dy)`. The shifted tree is assembled from the
+    /// old one's nodes: shifts by a multiple of a node's size reuse the node
+    /// whole, and other nodes are recombined once per distinct neighbourhood.
+    /// On a finite grid the cells wrap around as `set_cell_state` wraps them.
+    pub fn translate(&mut self, dx: i64, dy: i64) {
+        if (dx, dy) == (0, 0) {
+            return;
+        }
+        let Some((left, top, width, height)) = self.bounding_box() else {
+            return;
+        };
+        let moved = left.checked_add(dx).zip(top.checked_add(dy)).filter(|&(left, top)| {
+            EXTENT.intersection(Rect::new(left, top, width, height)) == Some(Rect::new(left, top, width, height))
+        });
+        if !self.topology().is_infinite() || moved.is_none() {
+            // Cells wrap around a finite grid, and are lost past the edge of the plane
+            let cells: Vec<_> = self.live_cells().into_iter()
+                .filter_map(|(x, y, state)| Some((x.checked_add(dx)?, y.checked_add(dy)?, state)))
+                .collect();
+            let state = self.background();
+            let grid = self.topology().bounds().map_or(EXTENT, |(left, top, width, height)| Rect::new(left, top, width, height));
+            self.begin_edit_group();
+            self.fill_region(grid, state);
+            self.set_cell_states(&cells);
+            self.end_edit_group();
+            return;
+        }
+        self.expand_to(Rect::new(left + dx, top + dy, width, height));
+        let root = self.root().clone();
+        let half = 1i64 << (root.level() - 1);
+        let mut memo = HashMap::new();
+        let translated = self.shifted_region(&root, -half - dx, -half - dy, root.level(), &mut memo);
+        self.replace_root(translated);
+    }
+
+    /// The node of `root` at the given level starting at `(x, y)`, which must
+    /// be a multiple of its size from the corner of `root`; empty outside `root`
+    fn aligned_node(&mut self, root: &Rc<Node>, x: i64, y: i64, level: u8) -> Rc<Node> {
+        let mut half = 1i64 << (root.level() - 1);
+        if x < -half || y < -half || x >= half || y >= half {
+            return self.empty_node(level);
+        }
+        let (mut node, mut node_x, mut node_y) = (root.clone(), -half, -half);
+        while node.level() > level {
+            let (east, south) = (x >= node_x + half, y >= node_y + half);
+            node = node.children()[2 * south as usize + east as usize].clone();
+            node_x += half * east as i64;
+            node_y += half * south as i64;
+            half /= 2;
+        }
+        node
+    }
+
+    /// The square of `root` of the given level starting at `(x, y)`, aligned or
+    /// not. Unaligned squares are memoised by the aligned nodes they overlap,
+    /// which determine them since all squares of a level share one offset.
+    fn shifted_region(&mut self, root: &Rc<Node>, x: i64, y: i64, level: u8,
+                      memo: &mut HashMap<[*const Node; 4], Rc<Node>>) -> Rc<Node> {
+        let size = 1i64 << level;
+        let corner = -(1i64 << (root.level() - 1));
+        let (ax, ay) = (x - (x - corner).rem_euclid(size), y - (y - corner).rem_euclid(size));
+        if (ax, ay) == (x, y) {
+            return self.aligned_node(root, x, y, level);
+        }
+        let overlapped = [(ax, ay), (ax + size, ay), (ax, ay + size), (ax + size, ay + size)]
+            .map(|(nx, ny)| self.aligned_node(root, nx, ny, level));
+        if overlapped.iter().all(|node| node.population() == 0) {
+            return overlapped[0].clone();
+        }
+        let key = overlapped.each_ref().map(Rc::as_ptr);
+        if let Some(result) = memo.get(&key) {
+            return result.clone();
+        }
+        let half = size / 2;
+        let nw = self.shifted_region(root, x, y, level - 1, memo);
+        let ne = self.shifted_region(root, x + half, y, level - 1, memo);
+        let sw = self.shifted_region(root, x, y + half, level - 1, memo);
+        let se = self.shifted_region(root, x + half, y + half, level - 1, memo);
+        let result = self.inner_node(nw, ne, sw, se);
+        memo.insert(key, result.clone());
+        result
+    }
+
+    /// Kill every live cell in `rect` and bring every dead one to life in
+    /// state 1. Whole nodes inside `rect` are complemented once each however
+    /// often they occur, so large regions invert quickly. On a finite grid only
+    /// the part of `rect` inside the grid changes.
+    pub fn invert_region(&mut self, rect: Rect) {
+        let Some(rect) = self.clip_to_grid(rect) else {
+            return;
+        };
+        self.expand_to(rect);
+        let root = self.root().clone();
+        let half = 1i64 << (root.level() - 1);
+        let mut memo = Invert { complements: HashMap::new(), edges: HashMap::new() };
+        let inverted = self.invert_recursive(&root, -half, -half, rect, &mut memo);
+        self.replace_root(inverted);
+    }
+
+    fn invert_recursive(&mut self, node: &Rc<Node>, node_x: i64, node_y: i64, rect: Rect,
+                        memo: &mut Invert) -> Rc<Node> {
+        let size = 1i64 << node.level();
+        let node_rect = Rect::new(node_x, node_y, size, size);
+        let Some(covered) = rect.intersection(node_rect) else {
+            return node.clone();
+        };
+        if covered == node_rect {
+            return self.complement(node, &mut memo.complements);
+        }
+        let key = (Rc::as_ptr(node), Rect { left: covered.left - node_x, top: covered.top - node_y, ..covered });
+        if let Some(result) = memo.edges.get(&key) {
+            return result.clone();
+        }
+        let half = size / 2;
+        let [nw, ne, sw, se] = node.children().map(Rc::clone);
+        let nw = self.invert_recursive(&nw, node_x, node_y, rect, memo);
+        let ne = self.invert_recursive(&ne, node_x + half, node_y, rect, memo);
+        let sw = self.invert_recursive(&sw, node_x, node_y + half, rect, memo);
+        let se = self.invert_recursive(&se, node_x + half, node_y + half, rect, memo);
+        let result = self.inner_node(nw, ne, sw, se);
+        memo.edges.insert(key, result.clone());
+        result
+    }
+
+    /// `node` with every cell inverted as `invert_region` inverts it
+    fn complement(&mut self, node: &Rc<Node>, memo: &mut HashMap<*const Node, Rc<Node>>) -> Rc<Node> {
+        if node.level() == 0 {
+            let state = (self.to_stored(node.state()) == 0) as State;
+            return self.leaf_node(self.to_stored(state));
+        }
+        if let Some(result) = memo.get(&Rc::as_ptr(node)) {
+            return result.clone();
+        }
+        let [nw, ne, sw, se] = node.children().map(|child| self.complement(child, memo));
+        let result = self.inner_node(nw, ne, sw, se);
+        memo.insert(Rc::as_ptr(node), result.clone());
+        result
+    }
+
+    /// A copy of the cells in `rect`, in their own states and coordinates, to
+    /// transform or paste elsewhere. While the background is alive every live
+    /// cell of `rect` is in the copy, so `rect` should then be of modest size.
+    pub fn extract(&self, rect: Rect) -> Pattern {
+        if self.background() == 0 {
+            return Pattern::from_cells(self.cells_in(rect));
+        }
+        let dead: HashSet<_> = self.stored_cells(rect).into_iter().map(|(x, y, _)| (x, y)).collect();
+        Pattern::from_live_cells((rect.top..rect.top + rect.height)
+            .flat_map(|y| (rect.left..rect.left + rect.width).map(move |x| (x, y)))
+            .filter(|cell| !dead.contains(cell)))
+    }
+
+    /// Discard every cell outside `rect`, returning it to the background (or
+    /// killing it on a finite grid), and shrink the tree to the smallest root
+    /// that holds what is left
+    pub fn crop(&mut self, rect: Rect) {
+        if let Some((left, top, width, height)) = self.topology().bounds() {
+            // The tree may hold the grid's complement, so the rest of the grid
+            // is cleared in real states rather than clipped away
+            let grid = Rect::new(left, top, width, height);
+            let bands = match rect.intersection(grid) {
+                None => vec![grid],
+                Some(keep) => vec![
+                    Rect::new(grid.left, grid.top, grid.width, keep.top - grid.top),
+                    Rect::new(grid.left, keep.top + keep.height, grid.width, grid.top + grid.height - keep.top - keep.height),
+                    Rect::new(grid.left, keep.top, keep.left - grid.left, keep.height),
+                    Rect::new(keep.left + keep.width, keep.top, grid.left + grid.width - keep.left - keep.width, keep.height),
+                ],
+            };
+            self.begin_edit_group();
+            for band in bands {
+                self.clear_region(band);
+            }
+            self.end_edit_group();
+            self.shrink();
+            return;
+        }
+        let root = self.root().clone();
+        let half = 1i64 << (root.level() - 1);
+        let cropped = self.clip(&root, -half, -half, (rect.left, rect.top, rect.width, rect.height));
+        self.replace_root(cropped);
+        self.shrink();
+    }
+
+    /// Shrink the tree to the smallest root that holds the bounding box, for
+    /// example after a burst of debris has been cleared. The cells are unchanged.
+    pub fn crop_to_bounds(&mut self) {
+        self.shrink();
+    }
+
+    /// Add the cells alive in `other`, keeping this universe's state where both
+    /// are alive. The trees are merged node by node with each distinct pair of
+    /// nodes combined once, so the cost follows the patterns' structure rather
+    /// than their area. Outside both trees the background stays this universe's.
+    pub fn union(&mut self, other: &Universe) {
+        self.combine_with(other, BooleanOp::Union);
+    }
+
+    /// Kill the cells that are dead in `other`, as `union` combines trees
+    pub fn intersect(&mut self, other: &Universe) {
+        self.combine_with(other, BooleanOp::Intersect);
+    }
+
+    /// Kill the cells that are alive in `other`, as `union` combines trees
+    pub fn difference(&mut self, other: &Universe) {
+        self.combine_with(other, BooleanOp::Difference);
+    }
+
+    fn combine_with(&mut self, other: &Universe, op: BooleanOp) {
+        // Universes sharing a node cache can use each other's nodes as they are
+        let mut theirs = if self.node_cache().same_as(other.node_cache()) {
+            other.root().clone()
+        } else {
+            self.import_node(other.root(), &mut HashMap::new())
+        };
+        let half = 1i64 << (theirs.level() - 1);
+        self.expand_to(Rect::new(-half, -half, 2 * half, 2 * half));
+        while theirs.level() < self.root_level() {
+            theirs = self.pad(&theirs);
+        }
+        let ours = self.root().clone();
+        let backgrounds = (self.stored_background(), other.stored_background());
+        let combined = self.combine_recursive(&ours, &theirs, op, backgrounds, &mut HashMap::new());
+        self.begin_edit_group();
+        self.replace_root(combined);
+        if let Some(bounds) = self.topology().bounds() {
+            let root = self.root().clone();
+            let half = 1i64 << (root.level() - 1);
+            let clipped = self.clip(&root, -half, -half, bounds);
+            self.replace_root(clipped);
+        }
+        self.end_edit_group();
+    }
+
+    /// A copy of a node of another universe, made of this universe's nodes
+    fn import_node(&mut self, node: &Rc<Node>, memo: &mut HashMap<*const Node, Rc<Node>>) -> Rc<Node> {
+        if node.level() == 0 {
+            return self.leaf_node(node.state());
+        }
+        if let Some(copy) = memo.get(&Rc::as_ptr(node)) {
+            return copy.clone();
+        }
+        let [nw, ne, sw, se] = node.children().map(|child| self.import_node(child, memo));
+        let copy = self.inner_node(nw, ne, sw, se);
+        memo.insert(Rc::as_ptr(node), copy.clone());
+        copy
+    }
+
+    /// `ours` combined with `theirs`, whose cells are stored complemented when
+    /// the universes' backgrounds `(ours, theirs)` are alive
+    fn combine_recursive(&mut self, ours: &Rc<Node>, theirs: &Rc<Node>, op: BooleanOp, backgrounds: (State, State),
+                         memo: &mut HashMap<(*const Node, *const Node), Rc<Node>>) -> Rc<Node> {
+        if ours.level() == 0 {
+            let real = |stored: State, background: State| if background == 0 { stored } else { (stored == 0) as State };
+            let state = op.combine(real(ours.state(), backgrounds.0), real(theirs.state(), backgrounds.1));
+            return self.leaf_node(real(state, backgrounds.0));
+        }
+        if backgrounds == (0, 0) {
+            match op {
+                BooleanOp::Union if theirs.population() == 0 => return ours.clone(),
+                BooleanOp::Union if ours.population() == 0 => return theirs.clone(),
+                BooleanOp::Intersect | BooleanOp::Difference if ours.population() == 0 => return ours.clone(),
+                BooleanOp::Intersect if theirs.population() == 0 => return theirs.clone(),
+                BooleanOp::Difference if theirs.population() == 0 => return ours.clone(),
+                _ => {}
+            }
+        }
+        let key = (Rc::as_ptr(ours), Rc::as_ptr(theirs));
+        if let Some(result) = memo.get(&key) {
+            return result.clone();
+        }
+        let ours_children = ours.children().map(Rc::clone);
+        let theirs_children = theirs.children().map(Rc::clone);
+        let [nw, ne, sw, se] = [0, 1, 2, 3]
+            .map(|i| self.combine_recursive(&ours_children[i], &theirs_children[i], op, backgrounds, memo));
+        let result = self.inner_node(nw, ne, sw, se);
+        memo.insert(key, result.clone());
+        result
+    }
+
+    /// The part of `rect` a universe can hold: all of it on the plane, the
+    /// part inside the grid on a finite topology
+    pub(crate) fn clip_to_grid(&self, rect: Rect) -> Option<Rect> {
+        match self.topology().bounds() {
+            Some((left, top, width, height)) => rect.intersection(Rect::new(left, top, width, height)),
+            None => rect.intersection(EXTENT),
+        }
+    }
+
+    /// A node of the given level with every cell in the state of `uniform[0]`
+    fn uniform_node(&mut self, uniform: &mut Vec<Rc<Node>>, level: u8) -> Rc<Node> {
+        while uniform.len() <= level as usize {
+            let below = uniform[uniform.len() - 1].clone();
+            let above = self.inner_node(below.clone(), below.clone(), below.clone(), below);
+            uniform.push(above);
+        }
+        uniform[level as usize].clone()
+    }
+
+    /// `node` with every cell in `rect` set to the fill state
+    fn fill_recursive(&mut self, node: &Rc<Node>, node_x: i64, node_y: i64, rect: Rect, fill: &mut Fill) -> Rc<Node> {
+        let size = 1i64 << node.level();
+        let node_rect = Rect::new(node_x, node_y, size, size);
+        let Some(covered) = rect.intersection(node_rect) else {
+            return node.clone();
+        };
+        if fill.uniform[0].state() == 0 && node.population() == 0 {
+            return node.clone();
+        }
+        if covered == node_rect {
+            return self.uniform_node(&mut fill.uniform, node.level());
+        }
+        let key = (Rc::as_ptr(node), Rect { left: covered.left - node_x, top: covered.top - node_y, ..covered });
+        if let Some(result) = fill.memo.get(&key) {
+            return result.clone();
+        }
+
+        let half = size / 2;
+        let [nw, ne, sw, se] = node.children().map(Rc::clone);
+        let nw = self.fill_recursive(&nw, node_x, node_y, rect, fill);
+        let ne = self.fill_recursive(&ne, node_x + half, node_y, rect, fill);
+        let sw = self.fill_recursive(&sw, node_x, node_y + half, rect, fill);
+        let se = self.fill_recursive(&se, node_x + half, node_y + half, rect, fill);
+        let result = self.inner_node(nw, ne, sw, se);
+        fill.memo.insert(key, result.clone());
+        result
+    }
+}
+
+/// How `Universe::union` and the like combine two cells
+#[derive(Clone, Copy)]
+enum BooleanOp {
+    Union,
+    Intersect,
+    Difference,
+}
+
+impl BooleanOp {
+    fn combine(self, a: State, b: State) -> State {
+        match self {
+            BooleanOp::Union if a == 0 => b,
+            BooleanOp::Intersect if b == 0 => 0,
+            BooleanOp::Difference if b != 0 => 0,
+            _ => a,
+        }
+    }
+}
+
+/// State of a `paste` pass
+struct Paste {
+    mode: PasteMode,
+    /// The pattern's bounding box where it lands
+    bounds: Rect,
+    /// Dead nodes, for parts of `bounds` the pattern leaves empty
+    cleared: Fill,
+    /// Results for nodes inside `bounds` by the two nodes combined
+    memo: HashMap<(*const Node, *const Node), Rc<Node>>,
+}
+
+/// Results of an `invert_region` pass
+struct Invert {
+    /// Complements of nodes inside the region
+    complements: HashMap<*const Node, Rc<Node>>,
+    /// Results for nodes on its edges, as `Fill::memo` holds them
+    edges: HashMap<(*const Node, Rect), Rc<Node>>,
+}
+
+/// State of a `fill_region` pass
+struct Fill {
+    /// Nodes of every cell in the fill state, by level, grown as needed
+    uniform: Vec<Rc<Node>>,
+    /// Results by node and the part of it covered, relative to its corner
+    memo: HashMap<(*const Node, Rect), Rc<Node>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::Transform;
+    use crate::rule::Rule;
+    use crate::topology::Topology;
+
+    #[test]
+    fn test_clear_region() {
+        let mut universe = Universe::new(4);
+        for x in -20..20 {
+            for y in -20..20 {
+                if (x * 7 + y * 3) % 5 == 0 {
+                    universe.set_cell(x, y, true);
+                }
+            }
+        }
+        let rect = Rect::new(-7, -3, 19, 11);
+        let expected = Pattern::from_cells(universe.live_cells().into_iter().filter(|&(x, y, _)| !rect.contains(x, y)));
+        universe.clear_region(rect);
+        assert_eq!(universe.to_pattern(), expected);
+
+        // Huge regions touch only the nodes along their edges
+        universe.clear_region(Rect::new(-(1 << 40), -(1 << 40), 1 << 41, 1 << 41));
+        assert_eq!(universe.population(), 0);
+        universe.fill_region(Rect::new(-(1 << 30), 0, 1 << 31, 3), 1);
+        assert_eq!(universe.population(), 3 << 31);
+        assert!(universe.get_cell(-(1 << 30), 2) && !universe.get_cell(1 << 30, 0));
+        universe.clear_region(Rect::new(0, 1, 1 << 40, 1));
+        assert_eq!(universe.population(), (3 << 31) - (1 << 30));
+        universe.clear_region(Rect::new(0, 0, 0, 5));
+        assert_eq!(universe.population(), (3 << 31) - (1 << 30));
+    }
+
+    #[test]
+    fn test_fill_random() {
+        let rect = Rect::new(-50, -20, 100, 40);
+        let mut universe = Universe::new(4);
+        universe.fill_region(Rect::new(-60, -60, 120, 120), 1);
+        universe.fill_random(rect, 0.3, 12);
+        let soup = universe.to_pattern();
+        assert!(soup.cells().iter().filter(|&&(x, y, _)| rect.contains(x, y)).count().abs_diff(1200) < 150);
+        assert_eq!(universe.population(), soup.population());
+        assert!(universe.get_cell(-60, -60) && universe.get_cell(59, 59));
+
+        let mut copy = Universe::new(4);
+        copy.fill_region(Rect::new(-60, -60, 120, 120), 1);
+        copy.fill_random(rect, 0.3, 12);
+        assert_eq!(copy.to_pattern(), soup);
+        copy.fill_random(rect, 0.3, 13);
+        assert_ne!(copy.to_pattern(), soup);
+        copy.fill_random(rect, 0.0, 13);
+        assert_eq!(copy.population(), 120 * 120 - 100 * 40);
+    }
+
+    #[test]
+    fn test_paste() {
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        let block = Pattern::from_live_cells([(0, 0), (1, 0), (0, 1), (1, 1)]);
+        let scene = |mode| {
+            let mut universe = Universe::new(3);
+            universe.paste(&block, 10, 10, PasteMode::Or);
+            universe.paste(&block, 101, 100, PasteMode::Or);
+            universe.paste(&glider, 100, 100, mode);
+            universe
+        };
+        // Only the glider's bounding box is affected
+        let expected = |cells: &[(i64, i64)]| {
+            let mut cells = cells.to_vec();
+            cells.extend([(10, 10), (11, 10), (10, 11), (11, 11)]);
+            Pattern::from_live_cells(cells)
+        };
+        assert_eq!(scene(PasteMode::Copy).to_pattern(), expected(&[(101, 100), (102, 101), (100, 102), (101, 102), (102, 102)]));
+        assert_eq!(scene(PasteMode::Or).to_pattern(),
+                   expected(&[(101, 100), (102, 100), (101, 101), (102, 101), (100, 102), (101, 102), (102, 102)]));
+        assert_eq!(scene(PasteMode::Xor).to_pattern(), expected(&[(102, 100), (101, 101), (100, 102), (101, 102), (102, 102)]));
+        assert_eq!(scene(PasteMode::And).to_pattern(), expected(&[(101, 100), (102, 101)]));
+
+        // A large paste over an empty universe shares the pattern's tree
+        let mut universe = Universe::new(3);
+        let row = Pattern::from_live_cells((0..1000).map(|x| (x, 0)));
+        universe.paste(&row, -(1 << 40), 1 << 40, PasteMode::Xor);
+        assert_eq!(universe.bounding_box(), Some((-(1 << 40), 1 << 40, 1000, 1)));
+        universe.paste(&row, -(1 << 40), 1 << 40, PasteMode::Xor);
+        assert_eq!(universe.population(), 0);
+
+        // States are pasted as they are, and dead means dead on a live background
+        let mut universe = Universe::with_rule(4, "B0123478/S01234678".parse::<Rule>().unwrap());
+        universe.step();
+        universe.paste(&Pattern::from_live_cells([(0, 0), (2, 0)]), 0, 0, PasteMode::Copy);
+        assert_eq!((0..4).map(|x| universe.get_cell_state(x, 0)).collect::<Vec<_>>(), [1, 0, 1, 1]);
+        let mut universe = Universe::with_rule(4, Rule::wireworld());
+        universe.paste(&Pattern::from_cells([(0, 0, 3), (1, 0, 1)]), 5, 5, PasteMode::Or);
+        assert_eq!((universe.get_cell_state(5, 5), universe.get_cell_state(6, 5)), (3, 1));
+
+        // On a finite grid the pattern is clipped
+        let mut universe = Universe::with_topology(4, Rule::conway(), Topology::Torus { width: 10, height: 10 });
+        universe.paste(&block, 4, 0, PasteMode::Or);
+        assert_eq!(universe.population(), 2);
+    }
+
+    #[test]
+    fn test_translate() {
+        let mut universe = Universe::new(3);
+        universe.fill_random(Rect::new(-30, -20, 60, 40), 0.4, 5);
+        let soup = universe.to_pattern();
+        for (dx, dy) in [(1, 0), (0, -3), (64, -128), (-37, 1001), (1 << 40, -(1 << 41) + 5)] {
+            let mut copy = Universe::new(3);
+            copy.paste(&soup, 0, 0, PasteMode::Or);
+            copy.translate(dx, dy);
+            assert_eq!(copy.to_pattern(), soup.translated(dx, dy), "({}, {})", dx, dy);
+            copy.translate(-dx, -dy);
+            assert_eq!(copy.to_pattern(), soup);
+        }
+
+        // A repetitive pattern recombines cheaply and keeps its sharing
+        let mut universe = Universe::new(3);
+        universe.paste(&Pattern::from_live_cells((0..1 << 16).map(|x| (3 * x, 0))), 0, 0, PasteMode::Or);
+        universe.translate(5, 7);
+        assert_eq!(universe.population(), 1 << 16);
+        assert_eq!(universe.bounding_box(), Some((5, 7, 3 * (1 << 16) - 2, 1)));
+
+        // Finite grids wrap
+        let mut universe = Universe::with_topology(4, Rule::conway(), Topology::Torus { width: 10, height: 8 });
+        universe.set_cell(4, 0, true);
+        universe.translate(3, 9);
+        assert_eq!(universe.to_pattern(), Pattern::from_live_cells([(-3, 1)]));
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut universe = Universe::new(3);
+        universe.fill_random(Rect::new(-10, -10, 20, 20), 0.5, 1);
+        universe.set_cell(1 << 40, 0, true);
+        universe.set_cell(-5, 1 << 30, true);
+        assert_eq!(universe.root_level(), 42);
+        let inside = universe.to_pattern();
+
+        universe.crop(Rect::new(-10, -10, 20, 1 << 31));
+        assert_eq!(universe.root_level(), 32);
+        universe.clear_region(Rect::new(-5, 1 << 30, 1, 1));
+        universe.crop_to_bounds();
+        assert_eq!(universe.root_level(), 5);
+        let soup = Pattern::from_cells(inside.cells().iter().copied().filter(|&(x, y, _)| x.abs() <= 10 && y.abs() <= 10));
+        assert_eq!(universe.to_pattern(), soup);
+        universe.step();
+        universe.crop(Rect::new(0, 0, 0, 0));
+        assert_eq!((universe.population(), universe.root_level()), (0, 3));
+
+        // After an odd generation of a B0 rule a torus is stored complemented
+        let torus = Topology::Torus { width: 10, height: 8 };
+        let mut universe = Universe::with_topology(3, "B0123478/S34678".parse().unwrap(), torus);
+        universe.set_cell(0, 0, true);
+        universe.step();
+        let kept = universe.extract(Rect::new(0, 0, 2, 2));
+        universe.crop(Rect::new(0, 0, 2, 2));
+        assert_eq!(universe.exact_population(), kept.population() as u128);
+        assert_eq!(universe.extract(Rect::new(-5, -4, 10, 8)), kept);
+        assert!(!universe.get_cell(5, 5));
+        universe.undo();
+        assert_eq!(universe.exact_population(), 79);
+    }
+
+    #[test]
+    fn test_extract() {
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        let mut universe = Universe::new(3);
+        universe.paste(&glider, 50, 50, PasteMode::Or);
+        universe.set_cell(53, 50, true);
+        let copy = universe.extract(Rect::new(50, 50, 3, 3));
+        assert_eq!(copy, glider.translated(50, 50));
+
+        // The copy can be moved, turned and stepped elsewhere
+        universe.paste(&copy.normalized().transformed(Transform::Rotate180), -20, 0, PasteMode::Or);
+        universe.clear_region(Rect::new(0, 0, 100, 100));
+        universe.step_to(4).unwrap();
+        assert_eq!(universe.to_pattern().normalized(), glider.transformed(Transform::Rotate180).normalized());
+        assert_eq!(universe.extract(Rect::new(0, 0, 100, 100)), Pattern::new());
+
+        let mut universe = Universe::with_rule(4, "B0123478/S01234678".parse::<Rule>().unwrap());
+        universe.step();
+        universe.clear_region(Rect::new(1, 0, 1, 1));
+        assert_eq!(universe.extract(Rect::new(0, 0, 3, 1)), Pattern::from_live_cells([(0, 0), (2, 0)]));
+    }
+
+    #[test]
+    fn test_boolean_ops() {
+        let universe = |rect: Rect| {
+            let mut universe = Universe::new(3);
+            universe.fill_region(rect, 1);
+            universe
+        };
+        let a = Rect::new(0, 0, 4, 4);
+        let b = Rect::new(2, 2, 4, 4);
+        let cells = |universe: &Universe| universe.to_pattern().population();
+
+        let mut union = universe(a);
+        union.union(&universe(b));
+        assert_eq!(cells(&union), 28);
+        let mut intersection = universe(a);
+        intersection.intersect(&universe(b));
+        assert_eq!(intersection.to_pattern(), Pattern::from_live_cells([(2, 2), (3, 2), (2, 3), (3, 3)]));
+        let mut difference = universe(a);
+        difference.difference(&universe(b));
+        assert_eq!(cells(&difference), 12);
+        assert!(!difference.get_cell(3, 3) && difference.get_cell(3, 1));
+
+        // Huge uniform areas combine in a few steps, and trees of any size line up
+        let mut big = universe(Rect::new(-(1 << 30), -(1 << 30), 1 << 31, 1 << 31));
+        big.difference(&universe(Rect::new(-(1 << 29), -(1 << 29), 1 << 30, 1 << 30)));
+        assert_eq!(big.population(), 3 << 60);
+        let mut small = universe(Rect::new(-8, -8, 16, 16));
+        small.intersect(&big);
+        assert_eq!(small.population(), 0);
+        small.union(&big);
+        assert_eq!(small.bounding_box(), Some((-(1 << 30), -(1 << 30), 1 << 31, 1 << 31)));
+
+        // States come from this universe where both are alive
+        let mut wires = Universe::with_rule(4, Rule::wireworld());
+        wires.set_cell_state(0, 0, 3);
+        let mut other = Universe::with_rule(4, Rule::wireworld());
+        other.set_cell_state(0, 0, 1);
+        other.set_cell_state(1, 0, 2);
+        wires.union(&other);
+        assert_eq!((wires.get_cell_state(0, 0), wires.get_cell_state(1, 0)), (3, 2));
+
+        // Cells are combined by their real states on a live background
+        let mut alive = Universe::with_rule(4, "B0123478/S01234678".parse::<Rule>().unwrap());
+        alive.step();
+        alive.clear_region(Rect::new(0, 0, 2, 1));
+        alive.difference(&universe(Rect::new(1, 0, 2, 1)));
+        assert_eq!((0..4).map(|x| alive.get_cell(x, 0)).collect::<Vec<_>>(), [false, false, false, true]);
+        assert!(alive.get_cell(1000, 1000));
+    }
+
+    #[test]
+    fn test_invert_region() {
+        let mut universe = Universe::new(3);
+        universe.fill_random(Rect::new(-10, -10, 20, 20), 0.3, 9);
+        let soup = universe.to_pattern();
+        let rect = Rect::new(-5, -20, 30, 18);
+        universe.invert_region(rect);
+        for y in -25..25 {
+            for x in -25..25 {
+                assert_eq!(universe.get_cell(x, y), (soup.get(x, y) != 0) != rect.contains(x, y), "({}, {})", x, y);
+            }
+        }
+        universe.invert_region(rect);
+        assert_eq!(universe.to_pattern(), soup);
+
+        // Large regions and live backgrounds
+        let huge = Rect::new(-(1 << 30), -(1 << 30), 1 << 31, 1 << 31);
+        universe.invert_region(huge);
+        assert_eq!(universe.population(), (1 << 62) - soup.population());
+        let mut alive = Universe::with_rule(4, "B0123478/S01234678".parse::<Rule>().unwrap());
+        alive.step();
+        alive.invert_region(Rect::new(0, 0, 3, 3));
+        assert_eq!(alive.population(), 9);
+        assert!(!alive.get_cell(2, 2) && alive.get_cell(3, 3));
+
+        let mut wires = Universe::with_rule(4, Rule::wireworld());
+        wires.set_cell_state(0, 0, 3);
+        wires.invert_region(Rect::new(0, 0, 2, 1));
+        assert_eq!((wires.get_cell_state(0, 0), wires.get_cell_state(1, 0)), (0, 1));
+    }
+
+    #[test]
+    fn test_edit_symmetry() {
+        let cell = EditSymmetry { symmetry: Symmetry::D8, centre: (0, 0) };
+        assert_eq!(cell.images(0, 0), [(0, 0)]);
+        assert_eq!(cell.images(2, 0).len(), 4);
+        assert_eq!(cell.images(2, 1).len(), 8);
+        let corner = EditSymmetry { symmetry: Symmetry::C4, centre: (1, 1) };
+        assert_eq!(corner.images(0, 0), [(0, 0), (1, 0), (1, 1), (0, 1)]);
+        let edge = EditSymmetry { symmetry: Symmetry::D2, centre: (7, 0) };
+        assert_eq!(edge.images(0, 5), [(0, 5), (7, 5)]);
+        assert_eq!(EditSymmetry { symmetry: Symmetry::C2, centre: (0, 1) }.images(3, 0), [(3, 0), (-3, 1)]);
+
+        let mut universe = Universe::new(3);
+        universe.set_edit_symmetry(Some(EditSymmetry { symmetry: Symmetry::D4, centre: (1, 0) }));
+        universe.set_cell(3, 2, true);
+        assert_eq!(universe.to_pattern(), Pattern::from_live_cells([(3, 2), (-2, 2), (3, -2), (-2, -2)]));
+        universe.set_cell(-2, -2, false);
+        assert_eq!(universe.population(), 0);
+
+        // Pasted copies are combined before pasting, so Xor toggles cells on the axis once
+        universe.set_edit_symmetry(Some(EditSymmetry { symmetry: Symmetry::D2, centre: (0, 0) }));
+        universe.paste(&Pattern::from_live_cells([(0, 0), (1, 0)]), 0, 3, PasteMode::Xor);
+        assert_eq!(universe.to_pattern(), Pattern::from_live_cells([(-1, 3), (0, 3), (1, 3)]));
+        assert_eq!(universe.edit_symmetry().map(|s| s.symmetry), Some(Symmetry::D2));
+        universe.set_edit_symmetry(None);
+        universe.set_cell(5, 5, true);
+        assert_eq!(universe.population(), 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_edit_symmetry_centre() {
+        Universe::new(3).set_edit_symmetry(Some(EditSymmetry { symmetry: Symmetry::D8, centre: (0, 1) }));
+    }
+
+    #[test]
+    fn test_clear_region_background() {
+        // On an alive background the region is made dead, not left as stored
+        let mut universe = Universe::with_rule(4, "B0123478/S01234678".parse::<Rule>().unwrap());
+        universe.step();
+        assert_eq!(universe.background(), 1);
+        universe.clear_region(Rect::new(100, 100, 4, 2));
+        assert_eq!(universe.population(), 8);
+        assert!(!universe.get_cell(103, 101) && universe.get_cell(104, 101));
+
+        // On a finite grid only the cells inside the grid are cleared
+        let mut universe = Universe::with_topology(4, Rule::conway(), Topology::Torus { width: 10, height: 10 });
+        universe.fill_region(Rect::new(-100, -100, 200, 200), 1);
+        assert_eq!(universe.population(), 100);
+        universe.clear_region(Rect::new(-100, -100, 102, 200));
+        assert_eq!(universe.population(), 30);
+    }
+}
@@ -0,0 +1,231 @@
+//! A C interface to the engine, for embedding it in C, C++ or game engine
+//! frontends
+//!
+//! A universe is an opaque pointer made by `vive_universe_new` and freed by
+//! `vive_universe_free`. Functions that can fail return 0 on success and -1
+//! on failure. Strings passed in are NUL-terminated UTF-8; strings handed out
+//! are freed with `vive_string_free`. The declarations are in
+//! `include/vive_hashlife.h`, which `cbindgen --config cbindgen.toml` writes
+//! from this module.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::hashlife::Universe;
+use crate::pattern::Rect;
+use crate::rule::{Rule, State};
+
+/// A string passed in from C, or None if it is null or not UTF-8
+unsafe fn text<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        return None;
+    }
+    CStr::from_ptr(text).to_str().ok()
+}
+
+/// A new empty universe running `rule`, a rulestring such as "B3/S23", or
+/// Conway's Game of Life if `rule` is null. Returns null if the rule does
+/// not parse.
+///
+/// # Safety
+///
+/// `rule` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vive_universe_new(rule: *const c_char) -> *mut Universe {
+    let rule = if rule.is_null() {
+        Rule::conway()
+    } else {
+        match text(rule).and_then(|rule| rule.parse().ok()) {
+            Some(rule) => rule,
+            None => return ptr::null_mut(),
+        }
+    };
+    let mut universe = Universe::with_rule(3, rule);
+    universe.set_history_limit(0);
+    Box::into_raw(Box::new(universe))
+}
+
+/// Free a universe from `vive_universe_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `universe` must be null or a universe not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn vive_universe_free(universe: *mut Universe) {
+    if !universe.is_null() {
+        drop(Box::from_raw(universe));
+    }
+}
+
+/// Set the state of a cell (0 = dead). Returns -1 if the coordinates are
+/// beyond the universe's reach.
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vive_set_cell(universe: *mut Universe, x: i64, y: i64, state: State) -> c_int {
+    match (*universe).try_set_cell_state(x, y, state) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// State of a cell (0 = dead)
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vive_get_cell(universe: *const Universe, x: i64, y: i64) -> State {
+    (*universe).get_cell_state(x, y)
+}
+
+/// Advance `n` generations, in supersteps. Returns -1, leaving the universe
+/// unchanged, if it is a finite grid that does not repeat within
+/// `MAX_FINITE_STEPS` generations.
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vive_step_n(universe: *mut Universe, n: u64) -> c_int {
+    let universe = &mut *universe;
+    match universe.step_to(universe.generation() + n as u128) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// The generation, saturating at the largest `uint64_t`
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vive_generation(universe: *const Universe) -> u64 {
+    u64::try_from((*universe).generation()).unwrap_or(u64::MAX)
+}
+
+/// Number of cells that differ from the background, saturating
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vive_population(universe: *const Universe) -> u64 {
+    (*universe).population()
+}
+
+/// Write the states of the `width` by `height` cells from `(left, top)` to
+/// `out`, one byte per cell, row by row. Returns -1 without writing anything
+/// if the size is negative or `out` holds fewer than `width * height` bytes.
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`, and `out`
+/// must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vive_export_region(universe: *const Universe, left: i64, top: i64, width: i64,
+                                            height: i64, out: *mut u8, len: usize) -> c_int {
+    let universe = &*universe;
+    let Some(area) = usize::try_from(width).ok().zip(usize::try_from(height).ok()).and_then(|(w, h)| w.checked_mul(h)) else {
+        return -1;
+    };
+    if area > len || (area > 0 && out.is_null()) {
+        return -1;
+    }
+    if area == 0 {
+        return 0;
+    }
+    let out = std::slice::from_raw_parts_mut(out, area);
+    // The copy holds the live cells, whatever the background
+    out.fill(0);
+    for &(x, y, state) in universe.extract(Rect::new(left, top, width, height)).cells() {
+        out[((y - top) * width + (x - left)) as usize] = state;
+    }
+    0
+}
+
+/// Replace the universe's contents with an RLE pattern whose top left
+/// corner goes at `(x, y)`. Returns -1 if it does not parse.
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`, and `rle` a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vive_load_rle(universe: *mut Universe, rle: *const c_char, x: i64, y: i64) -> c_int {
+    match text(rle).map(|rle| (*universe).load_rle(rle, x, y)) {
+        Some(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// Replace the universe's contents with the pattern in a file, in the
+/// format its extension names, as `Universe::load_file` does. Returns -1 if
+/// it cannot be read or does not parse.
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`, and `path` a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vive_load_file(universe: *mut Universe, path: *const c_char) -> c_int {
+    match text(path).map(|path| (*universe).load_file(path)) {
+        Some(Ok(())) => 0,
+        _ => -1,
+    }
+}
+
+/// The live cells as RLE, to free with `vive_string_free`
+///
+/// # Safety
+///
+/// `universe` must be a live universe from `vive_universe_new`.
+#[no_mangle]
+pub unsafe extern "C" fn vive_to_rle(universe: *const Universe) -> *mut c_char {
+    // RLE never contains a NUL byte
+    CString::new((*universe).to_rle(None)).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Free a string from `vive_to_rle`. Null is ignored.
+///
+/// # Safety
+///
+/// `text` must be null or a string from this library not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn vive_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        unsafe {
+            assert!(vive_universe_new(c"B3/S2x".as_ptr()).is_null());
+            let universe = vive_universe_new(c"B36/S23".as_ptr());
+            assert_eq!(vive_load_rle(universe, c"bo$2bo$3o!".as_ptr(), 0, 0), 0);
+            assert_eq!(vive_load_rle(universe, ptr::null(), 0, 0), -1);
+            assert_eq!(vive_step_n(universe, 4), 0);
+            assert_eq!((vive_generation(universe), vive_population(universe)), (4, 5));
+            assert_eq!(vive_set_cell(universe, -5, -5, 1), 0);
+            assert_eq!(vive_get_cell(universe, -5, -5), 1);
+
+            let mut region = [9; 9];
+            assert_eq!(vive_export_region(universe, 1, 1, 3, 3, region.as_mut_ptr(), 8), -1);
+            assert_eq!(vive_export_region(universe, 1, 1, 3, 3, region.as_mut_ptr(), 9), 0);
+            assert_eq!(region, [0, 1, 0, 0, 0, 1, 1, 1, 1]);
+
+            let rle = vive_to_rle(universe);
+            assert!(CStr::from_ptr(rle).to_str().unwrap().contains("rule = B36/S23"));
+            vive_string_free(rle);
+            vive_universe_free(universe);
+        }
+    }
+}
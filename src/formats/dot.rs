@@ -0,0 +1,126 @@
+//! Graphviz DOT drawings of the HashLife quadtree, for seeing how nodes are
+//! shared
+//!
+//! ```text
+//! digraph quadtree {
+//!     node [shape=box, fontname="monospace"];
+//!     n0 [label="level 3\npopulation 3"];
+//!     n0 -> n1 [label="nw"];
+//!     ...
+//! }
+//! ```
+//!
+//! Each distinct node is drawn once, so a node used in several places has
+//! several arrows into it and is filled in, with the number of uses in its
+//! label. Render with `dot -Tsvg tree.dot -o tree.svg`.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::hashlife::{Node, Universe};
+
+/// Quadrant names, in the order of `Node::children`
+const QUADRANTS: [&str; 4] = ["nw", "ne", "sw", "se"];
+
+struct Writer {
+    max_depth: u8,
+    /// Number of each node drawn so far, by address
+    numbers: HashMap<*const Node, usize>,
+    /// Labels and whether the node's children were left out, by number
+    nodes: Vec<(String, bool)>,
+    edges: Vec<(usize, usize, &'static str)>,
+}
+
+impl Writer {
+    fn number(&mut self, node: &Rc<Node>, depth: u8) -> usize {
+        if let Some(&number) = self.numbers.get(&Rc::as_ptr(node)) {
+            return number;
+        }
+        let number = self.nodes.len();
+        let label = match node.level() {
+            0 => format!("cell\\nstate {}", node.state()),
+            level if node.population() == 0 => format!("level {}\\nempty", level),
+            level => format!("level {}\\npopulation {}", level, node.population()),
+        };
+        let cut = node.level() > 0 && depth == self.max_depth;
+        self.numbers.insert(Rc::as_ptr(node), number);
+        self.nodes.push((label, cut));
+        if node.level() > 0 && !cut {
+            for (child, quadrant) in node.children().into_iter().zip(QUADRANTS) {
+                let child = self.number(child, depth + 1);
+                self.edges.push((number, child, quadrant));
+            }
+        }
+        number
+    }
+}
+
+impl Universe {
+    /// The quadtree as a Graphviz DOT graph, down to `max_depth` levels below
+    /// the root. Nodes whose children are left out are drawn dashed. Empty
+    /// nodes are drawn grey; a two-state tree shares one per level.
+    pub fn to_dot(&self, max_depth: u8) -> String {
+        let mut writer = Writer { max_depth, numbers: HashMap::new(), nodes: Vec::new(), edges: Vec::new() };
+        writer.number(self.root(), 0);
+        let mut uses = vec![0; writer.nodes.len()];
+        for &(_, child, _) in &writer.edges {
+            uses[child] += 1;
+        }
+
+        let mut text = String::from("digraph quadtree {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for (number, (label, cut)) in writer.nodes.iter().enumerate() {
+            let (mut attributes, mut styles) = (Vec::new(), Vec::new());
+            if uses[number] > 1 {
+                attributes.push(format!("label=\"{}\\nused {} times\"", label, uses[number]));
+                attributes.push("fillcolor=lightblue".to_string());
+                styles.push("filled");
+            } else {
+                attributes.push(format!("label=\"{}\"", label));
+            }
+            if *cut {
+                styles.push("dashed");
+            }
+            if !styles.is_empty() {
+                attributes.push(format!("style=\"{}\"", styles.join(",")));
+            }
+            if label.ends_with("empty") || label.ends_with("state 0") {
+                attributes.push("color=grey".to_string());
+            }
+            text.push_str(&format!("    n{} [{}];\n", number, attributes.join(", ")));
+        }
+        for (parent, child, quadrant) in &writer.edges {
+            text.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", parent, child, quadrant));
+        }
+        text.push_str("}\n");
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_dot() {
+        let mut universe = Universe::new(3);
+        universe.set_cell(-1, -1, true);
+        universe.set_cell(0, 0, true);
+        let dot = universe.to_dot(8);
+        assert!(dot.starts_with("digraph quadtree {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("n0 [label=\"level 3\\npopulation 2\"];"));
+        assert!(dot.contains("n0 -> n1 [label=\"nw\"];"));
+
+        // Nine distinct nodes: the root, two quadrants with a cell and one
+        // empty quadrant, the same on level 1, and the two leaves
+        let nodes = dot.lines().filter(|line| line.contains(" [label=") && !line.contains("->")).count();
+        let edges = dot.lines().filter(|line| line.contains("->")).count();
+        assert_eq!((nodes, edges), (9, 4 * 7));
+        assert!(dot.contains("level 2\\nempty\\nused 2 times"));
+        assert!(dot.contains("cell\\nstate 1\\nused 2 times"));
+
+        let shallow = universe.to_dot(1);
+        assert_eq!(shallow.lines().filter(|line| line.contains("->")).count(), 4);
+        assert!(shallow.contains("style=\"filled,dashed\""));
+    }
+}
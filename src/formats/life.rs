@@ -0,0 +1,214 @@
+//! The classic Life 1.05 and Life 1.06 formats
+//!
+//! Life 1.06 is a list of live cells, one `x y` pair per line:
+//!
+//! ```text
+//! #Life 1.06
+//! 1 0
+//! 2 1
+//! 0 2
+//! ```
+//!
+//! Life 1.05 draws the pattern as blocks of `.` (dead) and `*` (alive) rows,
+//! each starting at the position given by a `#P x y` line, with `#D`
+//! description lines and the rule as `#N` (Conway's Life) or `#R 23/3`
+//! (survival/birth counts).
+
+use std::fmt;
+
+use crate::pattern::Pattern;
+use crate::rule::{Family, Neighbourhood, ParseRuleError, Rule};
+
+/// Error returned when a Life 1.05 or 1.06 file cannot be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseLifeError {
+    /// The file does not start with `#Life 1.05` or `#Life 1.06` as expected
+    MissingHeader,
+    /// A coordinate line (numbered from 1) that is not two integers
+    InvalidCoordinates(usize),
+    InvalidRule(ParseRuleError),
+    /// A character other than `.` or `*` in a Life 1.05 row
+    UnexpectedCharacter(char),
+}
+
+impl fmt::Display for ParseLifeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseLifeError::MissingHeader => write!(f, "missing #Life header"),
+            ParseLifeError::InvalidCoordinates(line) => write!(f, "invalid coordinates on line {}", line),
+            ParseLifeError::InvalidRule(e) => write!(f, "invalid rule: {}", e),
+            ParseLifeError::UnexpectedCharacter(c) => write!(f, "unexpected character in Life 1.05 row: {:?}", c),
+        }
+    }
+}
+
+impl std::error::Error for ParseLifeError {}
+
+/// A parsed Life 1.05 file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Life105 {
+    pub pattern: Pattern,
+    /// None if the file gives no rule (readers assume Conway's Life)
+    pub rule: Option<Rule>,
+    /// Text of each `#D` line
+    pub description: Vec<String>,
+}
+
+/// Parse two whitespace-separated integers
+fn parse_pair(line: &str) -> Option<(i64, i64)> {
+    let mut fields = line.split_whitespace().map(str::parse::<i64>);
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Some((x, y)),
+        _ => None,
+    }
+}
+
+/// Parse a Life 1.06 file
+pub fn parse_106(text: &str) -> Result<Pattern, ParseLifeError> {
+    let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+    if !lines.next().is_some_and(|(_, line)| line.starts_with("#Life 1.06")) {
+        return Err(ParseLifeError::MissingHeader);
+    }
+    let mut cells = Vec::new();
+    for (number, line) in lines {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        cells.push(parse_pair(line).ok_or(ParseLifeError::InvalidCoordinates(number))?);
+    }
+    Ok(Pattern::from_live_cells(cells))
+}
+
+/// Write a Life 1.06 file listing every non-zero cell of the pattern
+pub fn write_106(pattern: &Pattern) -> String {
+    let mut text = "#Life 1.06\n".to_string();
+    for &(x, y, _) in pattern.cells() {
+        text.push_str(&format!("{} {}\n", x, y));
+    }
+    text
+}
+
+/// Parse a Life 1.05 file
+pub fn parse_105(text: &str) -> Result<Life105, ParseLifeError> {
+    let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+    if !lines.next().is_some_and(|(_, line)| line.starts_with("#Life 1.05")) {
+        return Err(ParseLifeError::MissingHeader);
+    }
+    let mut cells = Vec::new();
+    let mut rule = None;
+    let mut description = Vec::new();
+    let (mut left, mut y) = (0, 0);
+    for (number, line) in lines {
+        if let Some(text) = line.strip_prefix("#D") {
+            description.push(text.strip_prefix(' ').unwrap_or(text).to_string());
+        } else if line.starts_with("#N") {
+            rule = Some(Rule::conway());
+        } else if let Some(value) = line.strip_prefix("#R") {
+            rule = Some(value.trim().parse().map_err(ParseLifeError::InvalidRule)?);
+        } else if let Some(position) = line.strip_prefix("#P") {
+            (left, y) = parse_pair(position).ok_or(ParseLifeError::InvalidCoordinates(number))?;
+        } else if line.starts_with('#') || line.is_empty() {
+            continue;
+        } else {
+            for (x, c) in (left..).zip(line.chars()) {
+                match c {
+                    '*' => cells.push((x, y)),
+                    '.' => {}
+                    _ => return Err(ParseLifeError::UnexpectedCharacter(c)),
+                }
+            }
+            y += 1;
+        }
+    }
+    Ok(Life105 { pattern: Pattern::from_live_cells(cells), rule, description })
+}
+
+/// The `#N` or `#R` line for a rule. Rules the S/B notation cannot express
+/// are written in this crate's own rulestring form.
+fn rule_line(rule: &Rule) -> String {
+    if *rule == Rule::conway() {
+        return "#N".to_string();
+    }
+    let totalistic = rule.family() == Family::Totalistic && rule.states() == 2
+        && rule.neighbourhood() == Neighbourhood::Moore && rule.radius() == 1;
+    if !totalistic {
+        return format!("#R {}", rule);
+    }
+    let counts = |f: &dyn Fn(u32) -> bool| (0..=8).filter(|&n| f(n)).map(|n| n.to_string()).collect::<String>();
+    format!("#R {}/{}", counts(&|n| rule.is_survival(n)), counts(&|n| rule.is_birth(n)))
+}
+
+/// Write a Life 1.05 file: the description, the rule if there is one, and the
+/// pattern as one block starting at its top-left corner
+pub fn write_105(life: &Life105) -> String {
+    let mut text = "#Life 1.05\n".to_string();
+    for line in &life.description {
+        text.push_str(&format!("#D {}\n", line));
+    }
+    if let Some(rule) = &life.rule {
+        text.push_str(&rule_line(rule));
+        text.push('\n');
+    }
+    let Some((left, top, width, height)) = life.pattern.bounding_box() else {
+        return text;
+    };
+    text.push_str(&format!("#P {} {}\n", left, top));
+    let mut rows = vec![vec!['.'; width as usize]; height as usize];
+    for &(x, y, _) in life.pattern.cells() {
+        rows[(y - top) as usize][(x - left) as usize] = '*';
+    }
+    for row in rows {
+        let row: String = row.into_iter().collect();
+        text.push_str(row.trim_end_matches('.'));
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_life_106() {
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        let text = "#Life 1.06\n# a comment\n1 0\n2 1\n0 2\n 1 2\n2\t2\n";
+        assert_eq!(parse_106(text).unwrap(), glider);
+        assert_eq!(parse_106(&write_106(&glider)).unwrap(), glider);
+        assert_eq!(write_106(&glider.translated(-5, 0)).lines().nth(1), Some("-5 2"));
+
+        assert_eq!(parse_106("1 0\n"), Err(ParseLifeError::MissingHeader));
+        assert_eq!(parse_106("#Life 1.06\n1 0\n2\n"), Err(ParseLifeError::InvalidCoordinates(3)));
+    }
+
+    #[test]
+    fn test_life_105() {
+        let text = "#Life 1.05
+#D Two blocks of a glider and a blinker
+#R 23/3
+#P -1 -1
+.*
+..*
+***
+#P 10 0
+***
+";
+        let life = parse_105(text).unwrap();
+        assert_eq!(life.rule, Some(Rule::conway()));
+        assert_eq!(life.description, ["Two blocks of a glider and a blinker"]);
+        assert_eq!(life.pattern, Pattern::from_live_cells([
+            (0, -1), (1, 0), (-1, 1), (0, 1), (1, 1), (10, 0), (11, 0), (12, 0),
+        ]));
+
+        let written = write_105(&life);
+        assert!(written.starts_with("#Life 1.05\n#D Two blocks of a glider and a blinker\n#N\n#P -1 -1\n.*\n"));
+        assert_eq!(parse_105(&written).unwrap(), life);
+
+        let highlife = Life105 { pattern: Pattern::new(), rule: Some("B36/S23".parse().unwrap()), description: Vec::new() };
+        assert_eq!(write_105(&highlife), "#Life 1.05\n#R 23/36\n");
+        assert_eq!(parse_105(&write_105(&highlife)).unwrap(), highlife);
+
+        assert_eq!(parse_105("#Life 1.05\n*o\n"), Err(ParseLifeError::UnexpectedCharacter('o')));
+        assert_eq!(parse_105("#Life 1.05\n#P 1\n"), Err(ParseLifeError::InvalidCoordinates(2)));
+    }
+}
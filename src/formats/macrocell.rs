@@ -0,0 +1,322 @@
+//! Golly's macrocell format, which writes out the HashLife quadtree itself
+//!
+//! ```text
+//! [M2] (vive_hashlife)
+//! #R B3/S23
+//! .*$..*$***$
+//! 4 0 0 0 1
+//! ```
+//!
+//! Each line after the header defines the next node, numbered from 1; 0
+//! stands for an empty node. Two-state patterns are built from 8x8 leaves
+//! written as rows of `.` and `*` ended by `$`; other rules start from level 1
+//! nodes whose four children are cell states (`1 s0 s1 s2 s3`). Every larger
+//! node is `level nw ne sw se`, and the last node is the root, centred on the
+//! origin. Nodes shared in the tree are written once, so the file stays small
+//! however large the pattern.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::rle::{metadata_comments, parse_metadata};
+use crate::hashlife::{Node, Universe, MAX_LEVEL};
+use crate::rule::{ParseRuleError, State};
+use crate::topology::ParseTopologyError;
+
+/// Error returned when a macrocell file cannot be loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseMacrocellError {
+    /// The file does not start with `[M2]`
+    MissingHeader,
+    InvalidRule(ParseRuleError),
+    InvalidTopology(ParseTopologyError),
+    /// The `#G` line does not hold a generation number
+    InvalidGeneration(String),
+    /// A node line that cannot be parsed (numbered from 1)
+    InvalidNode(usize),
+    /// A node refers to a node not defined before it, or of the wrong level
+    InvalidReference(usize),
+    /// A cell state the rule does not have
+    InvalidState(usize),
+    /// The root is larger than 2^62 cells across
+    TooLarge,
+}
+
+impl fmt::Display for ParseMacrocellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseMacrocellError::MissingHeader => write!(f, "macrocell file does not start with [M2]"),
+            ParseMacrocellError::InvalidRule(e) => write!(f, "invalid rule in macrocell file: {}", e),
+            ParseMacrocellError::InvalidTopology(e) => write!(f, "invalid grid in macrocell file: {}", e),
+            ParseMacrocellError::InvalidGeneration(s) => write!(f, "invalid generation: {:?}", s),
+            ParseMacrocellError::InvalidNode(line) => write!(f, "invalid node on line {}", line),
+            ParseMacrocellError::InvalidReference(line) => write!(f, "invalid node reference on line {}", line),
+            ParseMacrocellError::InvalidState(line) => write!(f, "invalid cell state on line {}", line),
+            ParseMacrocellError::TooLarge => write!(f, "macrocell pattern is too large"),
+        }
+    }
+}
+
+impl std::error::Error for ParseMacrocellError {}
+
+/// Writes each distinct non-empty node once, children first
+struct Writer {
+    two_state: bool,
+    numbers: HashMap<*const Node, usize>,
+    lines: Vec<String>,
+}
+
+impl Writer {
+    fn number(&mut self, node: &Rc<Node>) -> usize {
+        if node.population() == 0 {
+            return 0;
+        }
+        if let Some(&number) = self.numbers.get(&Rc::as_ptr(node)) {
+            return number;
+        }
+        let line = if self.two_state && node.level() == 3 {
+            let mut cells = [0; 64];
+            Universe::extract_cells(node, &mut cells, 8, 0, 0);
+            let rows: Vec<String> = cells.chunks(8)
+                .map(|row| {
+                    let row: String = row.iter().map(|&state| if state == 0 { '.' } else { '*' }).collect();
+                    format!("{}$", row.trim_end_matches('.'))
+                })
+                .collect();
+            rows.concat().trim_end_matches('$').to_string() + "$"
+        } else if node.level() == 1 {
+            let states = node.children().map(|child| child.state().to_string());
+            format!("1 {}", states.join(" "))
+        } else {
+            let children = node.children().map(|child| self.number(child).to_string());
+            format!("{} {}", node.level(), children.join(" "))
+        };
+        self.lines.push(line);
+        self.numbers.insert(Rc::as_ptr(node), self.lines.len());
+        self.lines.len()
+    }
+}
+
+/// Build the node for an 8x8 leaf line
+fn parse_leaf(universe: &mut Universe, line: &str, number: usize) -> Result<Rc<Node>, ParseMacrocellError> {
+    let mut cells = [0 as State; 64];
+    let (mut x, mut y) = (0, 0);
+    for c in line.chars() {
+        match c {
+            '$' => {
+                x = 0;
+                y += 1;
+            }
+            '.' | '*' if x < 8 && y < 8 => {
+                cells[y * 8 + x] = (c == '*') as State;
+                x += 1;
+            }
+            _ => return Err(ParseMacrocellError::InvalidNode(number)),
+        }
+    }
+    Ok(universe.build_from_cells(&cells, 8, 0, 0, 3))
+}
+
+impl Universe {
+    /// The pattern in macrocell format, with the rule (and grid, for finite
+    /// topologies), the generation and the metadata as `#N`, `#O` and `#C`
+    /// lines. On a live background (rules with B0)
+    /// the cells are written complemented, as Golly stores them.
+    pub fn to_macrocell(&self) -> String {
+        let mut text = format!("[M2] (vive_hashlife)\n#R {}", self.rule());
+        if !self.topology().is_infinite() {
+            text.push_str(&self.topology().to_string());
+        }
+        text.push('\n');
+        if self.generation() > 0 {
+            text.push_str(&format!("#G {}\n", self.generation()));
+        }
+        for comment in metadata_comments(self.metadata()) {
+            text.push_str(&format!("#{}\n", comment));
+        }
+        let mut writer = Writer { two_state: self.rule().states() == 2, numbers: HashMap::new(), lines: Vec::new() };
+        writer.number(self.root());
+        for line in writer.lines {
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Replace the contents of the universe with a macrocell pattern. The
+    /// universe switches to the file's rule and grid if it gives them, takes
+    /// its metadata, and continues from the file's generation with no history.
+    pub fn load_macrocell(&mut self, text: &str) -> Result<(), ParseMacrocellError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        if !lines.next().is_some_and(|(_, line)| line.starts_with("[M2]")) {
+            return Err(ParseMacrocellError::MissingHeader);
+        }
+
+        let mut rule = self.rule().clone();
+        let mut topology = self.topology();
+        let mut stated_rule = None;
+        let mut generation = 0;
+        let mut comments = Vec::new();
+        let mut body = Vec::new();
+        for (number, line) in lines {
+            if let Some(value) = line.strip_prefix("#R") {
+                let value = value.trim();
+                let (rule_part, grid) = match value.split_once(':') {
+                    Some((rule, grid)) => (rule, Some(grid)),
+                    None => (value, None),
+                };
+                rule = rule_part.parse().map_err(ParseMacrocellError::InvalidRule)?;
+                stated_rule = Some(rule.clone());
+                if let Some(grid) = grid {
+                    topology = grid.parse().map_err(ParseMacrocellError::InvalidTopology)?;
+                }
+            } else if let Some(value) = line.strip_prefix("#G") {
+                let value = value.trim();
+                generation = value.parse().map_err(|_| ParseMacrocellError::InvalidGeneration(value.to_string()))?;
+            } else if let Some(comment) = line.strip_prefix('#') {
+                comments.push(comment.to_string());
+            } else if !line.is_empty() {
+                body.push((number, line));
+            }
+        }
+
+        let mut universe = Universe::with_topology(self.root_level() as usize, rule, topology);
+        universe.set_history_limit(self.history_limit());
+        universe.set_adaptive_stepping(self.is_adaptive_stepping());
+
+        // Node 0 is the empty node of whatever level it is used at
+        let mut nodes: Vec<Option<Rc<Node>>> = vec![None];
+        for (number, line) in body {
+            let node = if line.starts_with(['.', '*', '$']) {
+                parse_leaf(&mut universe, line, number)?
+            } else {
+                let fields = line.split_whitespace()
+                    .map(str::parse::<u64>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| ParseMacrocellError::InvalidNode(number))?;
+                let &[level, ref children @ ..] = fields.as_slice() else {
+                    return Err(ParseMacrocellError::InvalidNode(number));
+                };
+                if children.len() != 4 || level == 0 {
+                    return Err(ParseMacrocellError::InvalidNode(number));
+                }
+                if level > MAX_LEVEL as u64 {
+                    return Err(ParseMacrocellError::TooLarge);
+                }
+                let level = level as u8;
+                let mut quadrants = Vec::with_capacity(4);
+                for &child in children {
+                    let child = if level == 1 {
+                        let state = State::try_from(child).ok()
+                            .filter(|&state| state < universe.rule().states())
+                            .ok_or(ParseMacrocellError::InvalidState(number))?;
+                        universe.leaf_node(state)
+                    } else {
+                        match nodes.get(child as usize) {
+                            Some(None) => universe.empty_node(level - 1),
+                            Some(Some(node)) if node.level() == level - 1 => node.clone(),
+                            _ => return Err(ParseMacrocellError::InvalidReference(number)),
+                        }
+                    };
+                    quadrants.push(child);
+                }
+                let [nw, ne, sw, se]: [Rc<Node>; 4] = quadrants.try_into().unwrap();
+                universe.inner_node(nw, ne, sw, se)
+            };
+            nodes.push(Some(node));
+        }
+
+        let root = match nodes.pop() {
+            Some(Some(root)) => root,
+            _ => universe.empty_node(3),
+        };
+        let inverted = universe.inverted_at(generation);
+        universe.set_root(root, generation, inverted);
+        universe.set_metadata(parse_metadata(&comments, stated_rule));
+        self.load_universe(universe);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::Pattern;
+    use crate::rule::Rule;
+    use crate::topology::Topology;
+
+    #[test]
+    fn test_macrocell() {
+        let mut universe = Universe::new(4);
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        for &(x, y, _) in glider.cells() {
+            universe.set_cell(x, y, true);
+        }
+        let text = universe.to_macrocell();
+        assert_eq!(text, "[M2] (vive_hashlife)\n#R B3/S23\n.*$..*$***$\n4 0 0 0 1\n");
+
+        let mut copy = Universe::new(4);
+        copy.load_macrocell(&text).unwrap();
+        assert_eq!(copy.to_pattern(), glider);
+
+        // Repeated subtrees are written once
+        for i in 1..40 {
+            for &(x, y, _) in glider.cells() {
+                universe.set_cell(x + 1024 * i, y, true);
+            }
+        }
+        universe.step_to(100).unwrap();
+        let text = universe.to_macrocell();
+        assert!(text.lines().count() < 100);
+        assert!(text.contains("#G 100\n"));
+        copy.load_macrocell(&text).unwrap();
+        assert_eq!(copy.generation(), 100);
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+        copy.step();
+        universe.step();
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+
+        // Metadata survives a round trip
+        copy.load_macrocell("[M2] (golly 4.2)\n#R B3/S23\n#N Gliders\n#C Many of them\n#C\n.*$..*$***$\n4 0 0 0 1\n").unwrap();
+        assert_eq!(copy.metadata().name.as_deref(), Some("Gliders"));
+        assert_eq!(copy.metadata().comments, ["Many of them", ""]);
+        assert_eq!(copy.metadata().rule, Some(Rule::conway()));
+        let text = copy.to_macrocell();
+        assert!(text.starts_with("[M2] (vive_hashlife)\n#R B3/S23\n#N Gliders\n#C Many of them\n#C \n"));
+        universe.load_macrocell(&text).unwrap();
+        assert_eq!(universe.metadata(), copy.metadata());
+    }
+
+    #[test]
+    fn test_multistate_macrocell() {
+        let mut universe = Universe::with_topology(4, Rule::wireworld(), Topology::Torus { width: 12, height: 6 });
+        for (x, state) in [(-3, 3), (-2, 3), (-1, 2), (0, 1), (1, 3), (2, 3)] {
+            universe.set_cell_state(x, 0, state);
+        }
+        let text = universe.to_macrocell();
+        assert!(text.starts_with("[M2] (vive_hashlife)\n#R WireWorld:T12,6\n1 "));
+
+        let mut copy = Universe::new(4);
+        copy.load_macrocell(&text).unwrap();
+        assert_eq!(copy.rule(), &Rule::wireworld());
+        assert_eq!(copy.topology(), universe.topology());
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+    }
+
+    #[test]
+    fn test_invalid_macrocell() {
+        let mut universe = Universe::new(4);
+        assert_eq!(universe.load_macrocell("#R B3/S23\n"), Err(ParseMacrocellError::MissingHeader));
+        assert_eq!(universe.load_macrocell("[M2]\n.*$\n5 1 0 0 0\n"), Err(ParseMacrocellError::InvalidReference(3)));
+        assert_eq!(universe.load_macrocell("[M2]\n4 2 0 0 0\n"), Err(ParseMacrocellError::InvalidReference(2)));
+        assert_eq!(universe.load_macrocell("[M2]\n1 0 2 0 0\n"), Err(ParseMacrocellError::InvalidState(2)));
+        assert_eq!(universe.load_macrocell("[M2]\n4 1 0 0\n"), Err(ParseMacrocellError::InvalidNode(2)));
+        assert_eq!(universe.load_macrocell("[M2]\n.*x$\n"), Err(ParseMacrocellError::InvalidNode(2)));
+        assert_eq!(universe.load_macrocell("[M2]\n#G x\n"), Err(ParseMacrocellError::InvalidGeneration("x".into())));
+        assert_eq!(universe.load_macrocell("[M2]\n70 0 0 0 0\n"), Err(ParseMacrocellError::TooLarge));
+
+        universe.load_macrocell("[M2] (golly 4.2)\n#R B3/S23\n").unwrap();
+        assert_eq!(universe.population(), 0);
+    }
+}
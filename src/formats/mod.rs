@@ -0,0 +1,348 @@
+//! Reading and writing pattern files
+//!
+//! `Universe::load_file` and `save_file` pick the format from the file's extension. Files
+//! compressed with gzip, as Golly ships most large patterns (`.mc.gz`,
+//! `.rle.gz`), are decompressed as they are read, and compressed as they are
+//! written, when the crate is built with the `gzip` feature.
+
+pub mod dot;
+pub mod life;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+#[cfg(feature = "share")]
+pub mod share;
+pub mod snapshot;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::hashlife::Universe;
+use crate::pattern::{Pattern, PatternMetadata};
+use life::ParseLifeError;
+use macrocell::ParseMacrocellError;
+use plaintext::ParsePlaintextError;
+use rle::{ParseRleError, ReadRleError};
+
+/// The first two bytes of gzip data
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A pattern file format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Rle,
+    Macrocell,
+    /// Plaintext `.cells`
+    Plaintext,
+    /// Life 1.05 or 1.06, told apart by the header
+    Life,
+}
+
+impl Format {
+    /// The format a file name's extension stands for, ignoring a final `.gz`
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        match name.rsplit_once('.')?.1 {
+            "rle" => Some(Format::Rle),
+            "mc" => Some(Format::Macrocell),
+            "cells" => Some(Format::Plaintext),
+            "lif" | "life" => Some(Format::Life),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned when a pattern file cannot be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// The file name has no extension `Format::from_path` knows
+    UnknownFormat(String),
+    Rle(ParseRleError),
+    Macrocell(ParseMacrocellError),
+    Plaintext(ParsePlaintextError),
+    Life(ParseLifeError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::UnknownFormat(name) => write!(f, "unknown pattern format: {:?}", name),
+            LoadError::Rle(e) => write!(f, "{}", e),
+            LoadError::Macrocell(e) => write!(f, "{}", e),
+            LoadError::Plaintext(e) => write!(f, "{}", e),
+            LoadError::Life(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<ReadRleError> for LoadError {
+    fn from(e: ReadRleError) -> Self {
+        match e {
+            ReadRleError::Io(e) => LoadError::Io(e),
+            ReadRleError::Parse(e) => LoadError::Rle(e),
+        }
+    }
+}
+
+/// Error returned when a universe cannot be saved to a file
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The file name has no extension `Format::from_path` knows
+    UnknownFormat(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::UnknownFormat(name) => write!(f, "unknown pattern format: {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+/// Read from `reader`, decompressing it if it starts like gzip data. Without
+/// the `gzip` feature compressed data is an `InvalidData` error.
+pub fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    if !reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        return Ok(Box::new(reader));
+    }
+    #[cfg(feature = "gzip")]
+    {
+        Ok(Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))))
+    }
+    #[cfg(not(feature = "gzip"))]
+    {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "gzip-compressed file, but the gzip feature is not enabled"))
+    }
+}
+
+/// Open a file for reading, decompressing it if it is gzip data
+pub fn open(path: impl AsRef<Path>) -> io::Result<Box<dyn BufRead>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+impl Universe {
+    /// Replace the contents of the universe with the pattern in a file, in the
+    /// format given by its extension (see `Format::from_path`). The pattern is
+    /// placed as `load_rle` and `load_macrocell` place it; `.cells` and Life
+    /// patterns keep their own coordinates.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), LoadError> {
+        let path = path.as_ref();
+        let format = Format::from_path(path)
+            .ok_or_else(|| LoadError::UnknownFormat(path.display().to_string()))?;
+        match format {
+            Format::Rle => self.read_rle(open(path)?, 0, 0, |_| {})?,
+            Format::Macrocell => self.load_macrocell(&read_text(path)?).map_err(LoadError::Macrocell)?,
+            Format::Plaintext => {
+                let plaintext = plaintext::parse(&read_text(path)?).map_err(LoadError::Plaintext)?;
+                self.load_pattern(&plaintext.pattern, plaintext::parse_metadata(&plaintext.comments));
+            }
+            Format::Life => {
+                let text = read_text(path)?;
+                let (pattern, metadata) = if text.trim_start().starts_with("#Life 1.05") {
+                    let life = life::parse_105(&text).map_err(LoadError::Life)?;
+                    let metadata = PatternMetadata { comments: life.description, rule: life.rule, ..Default::default() };
+                    (life.pattern, metadata)
+                } else {
+                    (life::parse_106(&text).map_err(LoadError::Life)?, PatternMetadata::default())
+                };
+                self.load_pattern(&pattern, metadata);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the universe to a file in the format given by its extension
+    /// (see `Format::from_path`), compressing it if the name ends in `.gz`.
+    /// Macrocell files keep the whole universe and Life 1.06 files keep the
+    /// live cells where they are; RLE and plaintext start the cells at
+    /// their bounding box, and RLE keeps the rule. Without the
+    /// `gzip` feature a `.gz` name is an `InvalidInput` error.
+    pub fn save_file(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let path = path.as_ref();
+        let format = Format::from_path(path)
+            .ok_or_else(|| SaveError::UnknownFormat(path.display().to_string()))?;
+        let text = match format {
+            Format::Rle => self.to_rle(None),
+            Format::Macrocell => self.to_macrocell(),
+            Format::Plaintext => self.to_pattern().to_plaintext(),
+            Format::Life => life::write_106(&self.to_pattern()),
+        };
+        let compressed = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"));
+        if !compressed {
+            return Ok(std::fs::write(path, text)?);
+        }
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Write;
+            let mut encoder = flate2::write::GzEncoder::new(File::create(path)?, flate2::Compression::default());
+            encoder.write_all(text.as_bytes())?;
+            encoder.finish()?;
+            Ok(())
+        }
+        #[cfg(not(feature = "gzip"))]
+        {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "gzip-compressed file, but the gzip feature is not enabled").into())
+        }
+    }
+
+    /// Replace the contents with a two-state pattern, switching to the
+    /// metadata's rule if it has one
+    fn load_pattern(&mut self, pattern: &Pattern, metadata: PatternMetadata) {
+        let rule = metadata.rule.clone().unwrap_or_else(|| self.rule().clone());
+        let mut universe = Universe::with_topology(self.root_level() as usize, rule, self.topology());
+        universe.set_history_limit(self.history_limit());
+        universe.set_adaptive_stepping(self.is_adaptive_stepping());
+        universe.set_edit_symmetry(self.edit_symmetry());
+        universe.set_cell_states(pattern.cells());
+        universe.set_metadata(metadata);
+        self.load_universe(universe);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::Rule;
+
+    /// A file in the temporary directory, removed when dropped
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("vive_hashlife_{}_{}", std::process::id(), name));
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    const GLIDER: &str = "x = 3, y = 3, rule = B36/S23\nbo$2bo$3o!\n";
+
+    #[test]
+    fn test_load_file() {
+        assert_eq!(Format::from_path(Path::new("gun.RLE")), Some(Format::Rle));
+        assert_eq!(Format::from_path(Path::new("dir.rle/big.mc.gz")), Some(Format::Macrocell));
+        assert_eq!(Format::from_path(Path::new("gz")), None);
+
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        let mut universe = Universe::new(4);
+        let file = TempFile::new("glider.rle", GLIDER.as_bytes());
+        universe.load_file(&file.0).unwrap();
+        assert_eq!(universe.rule().to_string(), "B36/S23");
+        assert_eq!(universe.to_pattern(), glider);
+
+        let file = TempFile::new("glider.mc", universe.to_macrocell().as_bytes());
+        let mut copy = Universe::new(4);
+        copy.load_file(&file.0).unwrap();
+        assert_eq!(copy.to_pattern(), glider);
+
+        let file = TempFile::new("glider.lif", b"#Life 1.05\n#D A glider\n#N\n#P 0 0\n.*\n..*\n***\n");
+        universe.load_file(&file.0).unwrap();
+        assert_eq!(universe.rule(), &Rule::conway());
+        assert_eq!(universe.to_pattern(), glider);
+        assert_eq!(universe.metadata().comments, ["A glider"]);
+
+        let file = TempFile::new("glider.cells", b"!Name: Glider\n!Author: Richard K. Guy\n!\n.O\n..O\nOOO\n");
+        universe.load_file(&file.0).unwrap();
+        assert_eq!(universe.metadata().name.as_deref(), Some("Glider"));
+        assert_eq!(universe.metadata().author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(universe.metadata().comments, [""]);
+        assert_eq!(universe.metadata().rule, None);
+
+        let file = TempFile::new("glider.txt", GLIDER.as_bytes());
+        assert!(matches!(universe.load_file(&file.0), Err(LoadError::UnknownFormat(_))));
+        let file = TempFile::new("bad.cells", b".O\nxO\n");
+        assert!(matches!(universe.load_file(&file.0), Err(LoadError::Plaintext(_))));
+    }
+
+    #[test]
+    fn test_save_file() {
+        let mut universe = Universe::new(4);
+        universe.load_rle(GLIDER, 10, -3).unwrap();
+        universe.step();
+        for name in ["saved.rle", "saved.mc", "saved.lif"] {
+            let file = TempFile::new(name, b"");
+            universe.save_file(&file.0).unwrap();
+            let mut loaded = Universe::new(4);
+            loaded.load_file(&file.0).unwrap();
+            let pattern = |universe: &Universe| match name {
+                "saved.rle" => universe.to_pattern().normalized(),
+                _ => universe.to_pattern(),
+            };
+            assert_eq!(pattern(&loaded), pattern(&universe), "{}", name);
+            assert_eq!(loaded.rule() == universe.rule(), name != "saved.lif", "{}", name);
+        }
+        let file = TempFile::new("saved.cells", b"");
+        universe.save_file(&file.0).unwrap();
+        assert_eq!(std::fs::read_to_string(&file.0).unwrap(), universe.to_pattern().to_plaintext());
+        assert!(matches!(universe.save_file("saved.txt"), Err(SaveError::UnknownFormat(_))));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(GLIDER.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut text = String::new();
+        decompress(compressed.as_slice()).unwrap().read_to_string(&mut text).unwrap();
+        assert_eq!(text, GLIDER);
+
+        let file = TempFile::new("glider.rle.gz", &compressed);
+        let mut universe = Universe::new(4);
+        universe.load_file(&file.0).unwrap();
+        assert_eq!(universe.population(), 5);
+
+        let saved = TempFile::new("saved.mc.gz", b"");
+        universe.save_file(&saved.0).unwrap();
+        assert!(std::fs::read(&saved.0).unwrap().starts_with(&GZIP_MAGIC));
+        let mut loaded = Universe::new(4);
+        loaded.load_file(&saved.0).unwrap();
+        assert_eq!(loaded.live_cells(), universe.live_cells());
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_gzip() {
+        let error = decompress([0x1f, 0x8b, 8, 0].as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = Universe::new(3).save_file("saved.rle.gz").err();
+        assert!(matches!(error, Some(SaveError::Io(e)) if e.kind() == io::ErrorKind::InvalidInput));
+    }
+}
@@ -0,0 +1,204 @@
+//! The plaintext `.cells` format used on LifeWiki
+//!
+//! ```text
+//! !Name: Glider
+//! .O
+//! ..O
+//! OOO
+//! ```
+//!
+//! Lines starting with `!` are comments, `O` is a live cell and `.` a dead
+//! one. Rows may stop short; the rest of the row is dead.
+//!
+//! The same characters draw regions of a universe as text, for debugging
+//! and doctests: `Universe::format_region`, and `Display` for `Pattern`.
+
+use std::fmt;
+
+use crate::hashlife::Universe;
+use crate::pattern::{Pattern, PatternMetadata, Rect};
+
+/// Error returned when a plaintext file cannot be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParsePlaintextError {
+    /// A character other than `.` or `O` in a row
+    UnexpectedCharacter(char),
+}
+
+impl fmt::Display for ParsePlaintextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsePlaintextError::UnexpectedCharacter(c) => write!(f, "unexpected character in plaintext pattern: {:?}", c),
+        }
+    }
+}
+
+impl std::error::Error for ParsePlaintextError {}
+
+/// A parsed plaintext file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Plaintext {
+    /// Cells, with the first row and column of the grid at the origin
+    pub pattern: Pattern,
+    /// Text of each `!` line, without the `!`
+    pub comments: Vec<String>,
+}
+
+/// Parse a plaintext file. `*` is accepted for live cells too, as some
+/// older files use it.
+pub fn parse(text: &str) -> Result<Plaintext, ParsePlaintextError> {
+    let mut comments = Vec::new();
+    let mut cells = Vec::new();
+    let mut y = 0;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if let Some(comment) = line.strip_prefix('!') {
+            comments.push(comment.to_string());
+            continue;
+        }
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                'O' | '*' => cells.push((x as i64, y)),
+                '.' => {}
+                _ => return Err(ParsePlaintextError::UnexpectedCharacter(c)),
+            }
+        }
+        y += 1;
+    }
+    Ok(Plaintext { pattern: Pattern::from_live_cells(cells), comments })
+}
+
+/// Metadata from the comments of a plaintext file: `Name:` and `Author:`
+/// lines, and the rest as comments
+pub fn parse_metadata(comments: &[String]) -> PatternMetadata {
+    let mut metadata = PatternMetadata::default();
+    for comment in comments {
+        if let Some(name) = comment.strip_prefix("Name:") {
+            metadata.name = Some(name.trim().to_string());
+        } else if let Some(author) = comment.strip_prefix("Author:") {
+            metadata.author = Some(author.trim().to_string());
+        } else {
+            metadata.comments.push(comment.clone());
+        }
+    }
+    metadata
+}
+
+/// Comments for a plaintext file holding the metadata (the rule is left out,
+/// as the format has no place for it)
+pub fn metadata_comments(metadata: &PatternMetadata) -> Vec<String> {
+    let name = metadata.name.iter().map(|name| format!("Name: {}", name));
+    let author = metadata.author.iter().map(|author| format!("Author: {}", author));
+    name.chain(author).chain(metadata.comments.iter().cloned()).collect()
+}
+
+/// Write a plaintext file: the comments, then the pattern's bounding box with
+/// dead cells at the ends of rows left out (an empty row is a single `.`)
+pub fn write(plaintext: &Plaintext) -> String {
+    let mut text = String::new();
+    for comment in &plaintext.comments {
+        text.push('!');
+        text.push_str(comment);
+        text.push('\n');
+    }
+    let Some((left, top, width, height)) = plaintext.pattern.bounding_box() else {
+        return text;
+    };
+    let mut rows = vec![vec!['.'; width as usize]; height as usize];
+    for &(x, y, _) in plaintext.pattern.cells() {
+        rows[(y - top) as usize][(x - left) as usize] = 'O';
+    }
+    for row in rows {
+        let row: String = row.into_iter().collect();
+        let row = row.trim_end_matches('.');
+        text.push_str(if row.is_empty() { "." } else { row });
+        text.push('\n');
+    }
+    text
+}
+
+impl Pattern {
+    /// Pattern from plaintext, with the first row and column of the grid at
+    /// the origin. Comments are ignored.
+    pub fn from_plaintext(text: &str) -> Result<Pattern, ParsePlaintextError> {
+        Ok(parse(text)?.pattern)
+    }
+
+    /// The pattern's bounding box as plaintext without comments. The position
+    /// is not kept, and states other than dead are all written as alive.
+    pub fn to_plaintext(&self) -> String {
+        write(&Plaintext { pattern: self.clone(), comments: Vec::new() })
+    }
+}
+
+/// Rows of `.` and `O` for the cells of `rect`, separated by newlines
+fn text_art(rect: Rect, alive: impl Fn(i64, i64) -> bool) -> String {
+    let rows: Vec<String> = (rect.top..rect.top + rect.height)
+        .map(|y| (rect.left..rect.left + rect.width).map(|x| if alive(x, y) { 'O' } else { '.' }).collect())
+        .collect();
+    rows.join("\n")
+}
+
+/// The pattern's bounding box as rows of `.` and `O` (any non-zero state),
+/// without a final newline
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((left, top, width, height)) = self.bounding_box() else {
+            return Ok(());
+        };
+        f.write_str(&text_art(Rect::new(left, top, width, height), |x, y| self.get(x, y) != 0))
+    }
+}
+
+impl Universe {
+    /// Every cell of `rect` as `.` or `O` (any non-dead state), one row per
+    /// line without a final newline, as a pattern with the same cells displays
+    pub fn format_region(&self, rect: Rect) -> String {
+        text_art(rect, |x, y| self.get_cell(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plaintext() {
+        let text = "!Name: Glider\n!\n.O\n..O\nOOO\n";
+        let glider = parse(text).unwrap();
+        assert_eq!(glider.comments, ["Name: Glider", ""]);
+        assert_eq!(glider.pattern, Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]));
+        assert_eq!(write(&glider), text);
+        let metadata = parse_metadata(&glider.comments);
+        assert_eq!(metadata.name.as_deref(), Some("Glider"));
+        assert_eq!(metadata.comments, [""]);
+        assert_eq!(metadata_comments(&metadata), glider.comments);
+
+        // Blank rows, short rows and `*` cells
+        let spaced = parse("*\n\n..O.\n").unwrap();
+        assert_eq!(spaced.pattern, Pattern::from_live_cells([(0, 0), (2, 2)]));
+        assert_eq!(write(&spaced), "O\n.\n..O\n");
+        assert_eq!(parse(&write(&spaced)).unwrap(), spaced);
+
+        let moved = Plaintext { pattern: glider.pattern.translated(5, -3), comments: Vec::new() };
+        assert_eq!(write(&moved), ".O\n..O\nOOO\n");
+        assert_eq!(write(&Plaintext::default()), "");
+        assert_eq!(parse(".O\nxO\n"), Err(ParsePlaintextError::UnexpectedCharacter('x')));
+
+        assert_eq!(Pattern::from_plaintext(".O\n..O\nOOO\n").unwrap(), glider.pattern);
+        assert_eq!(glider.pattern.translated(5, -3).to_plaintext(), ".O\n..O\nOOO\n");
+    }
+
+    #[test]
+    fn test_format_region() {
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(glider.to_string(), ".O.\n..O\nOOO");
+        assert_eq!(Pattern::new().to_string(), "");
+
+        let mut universe = Universe::new(4);
+        universe.paste(&glider, 0, 0, crate::edit::PasteMode::Or);
+        assert_eq!(universe.format_region(Rect::new(0, 0, 3, 3)), glider.to_string());
+        assert_eq!(universe.format_region(Rect::new(-1, 1, 5, 2)), "...O.\n.OOO.");
+        assert_eq!(universe.format_region(Rect::new(0, 0, 0, 0)), "");
+    }
+}
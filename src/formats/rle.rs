@@ -0,0 +1,851 @@
+//! Run Length Encoded patterns, the format used by Golly and LifeWiki
+//!
+//! ```text
+//! #N Glider
+//! x = 3, y = 3, rule = B3/S23
+//! bob$2bo$3o!
+//! ```
+//!
+//! Cells are written row by row as runs: an optional count followed by `b`
+//! (dead) or `o` (alive), `$` ending a row and `!` ending the pattern. Rules
+//! with more states use `.` for state 0, `A` to `X` for states 1 to 24, and
+//! `p` to `y` followed by a letter for the states after that.
+//!
+//! Files too large to hold in memory can be loaded as they are read with
+//! `RleLoader` or `Universe::read_rle`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+
+use crate::hashlife::{Universe, MAX_COORDINATE, MIN_COORDINATE};
+use crate::pattern::{Pattern, PatternMetadata, Rect};
+use crate::rule::{ParseRuleError, Rule, State};
+use crate::topology::{ParseTopologyError, Topology};
+
+/// A parsed RLE file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rle {
+    /// Cells relative to the top-left corner of the pattern's box, or to the
+    /// origin of the file's coordinates if it has a `#CXRLE Pos=` line
+    pub pattern: Pattern,
+    /// Size from the header, or the pattern's own size if there is no header
+    pub width: u64,
+    pub height: u64,
+    pub rule: Option<Rule>,
+    /// Grid given after a `:` in the header's rule (`rule = B3/S23:T40,30`)
+    pub topology: Option<Topology>,
+    /// Text of each `#` line, without the `#`
+    pub comments: Vec<String>,
+}
+
+/// Error returned when an RLE file cannot be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseRleError {
+    /// The `x = ..., y = ...` line is malformed
+    InvalidHeader(String),
+    InvalidRule(ParseRuleError),
+    InvalidTopology(ParseTopologyError),
+    /// A character that is not a run count, cell state, `$` or `!`
+    UnexpectedCharacter(char),
+    /// A state beyond 255, or beyond the number of states of the rule
+    InvalidState(u32),
+    /// A run, row or `#CXRLE Pos=` position beyond `MIN_COORDINATE` or
+    /// `MAX_COORDINATE`
+    OutOfRange,
+    /// More live cells than `parse` holds in a `Pattern`; `RleLoader` loads
+    /// such files into a universe instead
+    TooManyCells(u64),
+}
+
+impl fmt::Display for ParseRleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseRleError::InvalidHeader(s) => write!(f, "invalid RLE header: {:?}", s),
+            ParseRleError::InvalidRule(e) => write!(f, "invalid rule in RLE header: {}", e),
+            ParseRleError::InvalidTopology(e) => write!(f, "invalid grid in RLE header: {}", e),
+            ParseRleError::UnexpectedCharacter(c) => write!(f, "unexpected character in RLE: {:?}", c),
+            ParseRleError::InvalidState(state) => write!(f, "invalid cell state in RLE: {}", state),
+            ParseRleError::OutOfRange => {
+                write!(f, "RLE pattern reaches beyond the plane ({} to {})", MIN_COORDINATE, MAX_COORDINATE)
+            }
+            ParseRleError::TooManyCells(cells) => {
+                write!(f, "RLE pattern has {} live cells, more than the {} a pattern holds", cells, MAX_PATTERN_CELLS)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseRleError {}
+
+/// The `x = 3, y = 3, rule = B3/S23` line
+struct Header {
+    width: u64,
+    height: u64,
+    rule: Option<Rule>,
+    topology: Option<Topology>,
+}
+
+/// Parse a header line. The rule comes last and may itself contain commas
+/// (HROT rules), so it runs to the end of the line.
+fn parse_header(line: &str) -> Result<Header, ParseRleError> {
+    let invalid = || ParseRleError::InvalidHeader(line.to_string());
+    let (sizes, rule, topology) = match line.find("rule") {
+        Some(i) => {
+            let value = line[i + 4..].trim_start().strip_prefix('=').ok_or_else(invalid)?.trim();
+            let (rule, topology) = match value.split_once(':') {
+                Some((rule, topology)) => (rule, Some(topology)),
+                None => (value, None),
+            };
+            let rule = rule.parse().map_err(ParseRleError::InvalidRule)?;
+            let topology = topology.map(str::parse).transpose().map_err(ParseRleError::InvalidTopology)?;
+            (&line[..i], Some(rule), topology)
+        }
+        None => (line, None, None),
+    };
+
+    let (mut width, mut height) = (None, None);
+    for field in sizes.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim().parse().map_err(|_| invalid())?;
+        match key.trim() {
+            "x" => width = Some(value),
+            "y" => height = Some(value),
+            _ => {}
+        }
+    }
+    Ok(Header { width: width.ok_or_else(invalid)?, height: height.ok_or_else(invalid)?, rule, topology })
+}
+
+/// Position given by a Golly `#CXRLE Pos=x,y` comment
+fn parse_position(comment: &str) -> Option<(i64, i64)> {
+    let fields = comment.strip_prefix("CXRLE")?;
+    let position = fields.split_whitespace().find_map(|field| field.strip_prefix("Pos="))?;
+    let (x, y) = position.split_once(',')?;
+    Some((x.parse().ok()?, y.parse().ok()?))
+}
+
+/// Metadata from the `#N` (name), `#O` (author) and `#C` or `#c` (comment)
+/// lines of an RLE or macrocell file, given without the `#`
+pub(crate) fn parse_metadata(comments: &[String], rule: Option<Rule>) -> PatternMetadata {
+    let mut metadata = PatternMetadata { rule, ..PatternMetadata::default() };
+    for comment in comments {
+        if comment.starts_with("CXRLE") {
+            continue;
+        }
+        let mut chars = comment.chars();
+        let tag = chars.next();
+        let text = chars.as_str().trim().to_string();
+        match tag {
+            Some('N') => metadata.name = Some(text),
+            Some('O') => metadata.author = Some(text),
+            Some('C' | 'c') => metadata.comments.push(text),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// The `#N`, `#O` and `#C` lines for metadata, without the `#`
+pub(crate) fn metadata_comments(metadata: &PatternMetadata) -> Vec<String> {
+    let name = metadata.name.iter().map(|name| format!("N {}", name));
+    let author = metadata.author.iter().map(|author| format!("O {}", author));
+    let comments = metadata.comments.iter().map(|comment| format!("C {}", comment));
+    name.chain(author).chain(comments).collect()
+}
+
+/// Where the parser is in the file
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    /// Comments and the header line, read a whole line at a time
+    Header,
+    Body,
+    /// After the `!`, where everything is ignored
+    Done,
+}
+
+/// Incremental RLE parser behind `parse` and `RleLoader`. It takes the file
+/// in pieces of any size and collects decoded cells until they are taken.
+struct Parser {
+    section: Section,
+    /// Unfinished line while in the header
+    line: Vec<u8>,
+    comments: Vec<String>,
+    header: Option<Header>,
+    offset: (i64, i64),
+    x: i64,
+    y: i64,
+    count: Option<i64>,
+    prefix: Option<u32>,
+    /// `(x, y, length, state)` of each decoded run of live cells, in file
+    /// coordinates, so a long run takes no more room than a short one
+    runs: Vec<(i64, i64, i64, State)>,
+}
+
+impl Parser {
+    fn new() -> Self {
+        Parser {
+            section: Section::Header,
+            line: Vec::new(),
+            comments: Vec::new(),
+            header: None,
+            offset: (0, 0),
+            x: 0,
+            y: 0,
+            count: None,
+            prefix: None,
+            runs: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, mut bytes: &[u8]) -> Result<(), ParseRleError> {
+        while self.section == Section::Header {
+            let Some(end) = bytes.iter().position(|&b| b == b'\n') else {
+                self.line.extend_from_slice(bytes);
+                return Ok(());
+            };
+            self.line.extend_from_slice(&bytes[..end]);
+            bytes = &bytes[end + 1..];
+            let line = std::mem::take(&mut self.line);
+            self.header_line(&String::from_utf8_lossy(&line))?;
+        }
+        if self.section == Section::Body {
+            self.body(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Handle a line before the cells, which may turn out to be the first
+    /// line of cells
+    fn header_line(&mut self, line: &str) -> Result<(), ParseRleError> {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            self.comments.push(comment.to_string());
+        } else if line.starts_with('x') && self.header.is_none() {
+            self.header = Some(parse_header(line)?);
+        } else if !line.is_empty() {
+            self.offset = self.comments.iter().find_map(|c| parse_position(c)).unwrap_or((0, 0));
+            if !(MIN_COORDINATE..=MAX_COORDINATE).contains(&self.offset.0)
+                || !(MIN_COORDINATE..=MAX_COORDINATE).contains(&self.offset.1) {
+                return Err(ParseRleError::OutOfRange);
+            }
+            self.section = Section::Body;
+            self.body(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn body(&mut self, bytes: &[u8]) -> Result<(), ParseRleError> {
+        for &byte in bytes {
+            let c = if byte.is_ascii() { byte as char } else { char::REPLACEMENT_CHARACTER };
+            let state = match c {
+                '0'..='9' if self.prefix.is_none() => {
+                    let digit = c.to_digit(10).unwrap() as i64;
+                    self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                    continue;
+                }
+                'p'..='y' if self.prefix.is_none() => {
+                    self.prefix = Some(c as u32 - 'p' as u32 + 1);
+                    continue;
+                }
+                'A'..='X' => self.prefix.take().unwrap_or(0) * 24 + (c as u32 - 'A' as u32 + 1),
+                'b' | '.' | 'o' if self.prefix.is_none() => (c == 'o') as u32,
+                '$' if self.prefix.is_none() => {
+                    self.y = advance(self.y, self.count.take().unwrap_or(1), self.offset.1)?;
+                    self.x = 0;
+                    continue;
+                }
+                '!' if self.prefix.is_none() => {
+                    self.section = Section::Done;
+                    return Ok(());
+                }
+                c if c.is_whitespace() && self.prefix.is_none() => continue,
+                _ => return Err(ParseRleError::UnexpectedCharacter(c)),
+            };
+            let state = State::try_from(state).map_err(|_| ParseRleError::InvalidState(state))?;
+            let run = self.count.take().unwrap_or(1);
+            let end = advance(self.x, run, self.offset.0)?;
+            if state != 0 && run > 0 {
+                let y = self.y + self.offset.1;
+                if y > MAX_COORDINATE {
+                    return Err(ParseRleError::OutOfRange);
+                }
+                self.runs.push((self.x + self.offset.0, y, run, state));
+            }
+            self.x = end;
+        }
+        Ok(())
+    }
+
+    /// Handle a last line with no newline after it
+    fn finish(&mut self) -> Result<(), ParseRleError> {
+        if self.section == Section::Header {
+            let line = std::mem::take(&mut self.line);
+            self.header_line(&String::from_utf8_lossy(&line))?;
+        }
+        Ok(())
+    }
+}
+
+/// `start + run` for a run or row count starting at `start`, which is at
+/// least 0, or an error if it would take the pattern moved by `offset` off the
+/// plane
+fn advance(start: i64, run: i64, offset: i64) -> Result<i64, ParseRleError> {
+    start.checked_add(run)
+        .filter(|end| end.saturating_add(offset) <= MAX_COORDINATE + 1)
+        .ok_or(ParseRleError::OutOfRange)
+}
+
+/// Most live cells `parse` expands runs into
+pub const MAX_PATTERN_CELLS: u64 = 1 << 24;
+
+/// Parse an RLE file. Patterns of more than `MAX_PATTERN_CELLS` live cells
+/// fail with `ParseRleError::TooManyCells`.
+pub fn parse(text: &str) -> Result<Rle, ParseRleError> {
+    let mut parser = Parser::new();
+    parser.feed(text.as_bytes())?;
+    parser.finish()?;
+    let cells = parser.runs.iter().map(|&(_, _, length, _)| length as u64).fold(0, u64::saturating_add);
+    if cells > MAX_PATTERN_CELLS {
+        return Err(ParseRleError::TooManyCells(cells));
+    }
+
+    let cells = parser.runs.iter().flat_map(|&(x, y, length, state)| (x..x + length).map(move |x| (x, y, state)));
+    let pattern = Pattern::from_cells(cells);
+    let header = parser.header.unwrap_or_else(|| {
+        let (_, _, width, height) = pattern.bounding_box().unwrap_or((0, 0, 0, 0));
+        Header { width: width as u64, height: height as u64, rule: None, topology: None }
+    });
+    Ok(Rle {
+        pattern,
+        width: header.width,
+        height: header.height,
+        rule: header.rule,
+        topology: header.topology,
+        comments: parser.comments,
+    })
+}
+
+/// Cells decoded before `RleLoader` adds them to the universe
+const BATCH_CELLS: usize = 1 << 16;
+
+/// Runs at least this long are filled as a span rather than cell by cell
+const SPAN_CELLS: i64 = 64;
+
+/// Loads an RLE file into a new universe as the file arrives, for patterns
+/// too large to hold as text or as a list of cells. Decoded cells are added to
+/// the quadtree in batches and long runs as whole spans, so memory use follows
+/// the size of the tree.
+pub struct RleLoader {
+    parser: Parser,
+    /// Rule, grid and settings for the new universe where the file gives none
+    rule: Rule,
+    topology: Topology,
+    size_level: usize,
+    history_limit: usize,
+    adaptive: bool,
+    position: (i64, i64),
+    universe: Option<Universe>,
+    bytes_read: u64,
+}
+
+impl RleLoader {
+    /// Start loading a pattern moved by `(x, y)`, with the rule, grid and
+    /// settings of `like` unless the file says otherwise
+    pub fn new(like: &Universe, x: i64, y: i64) -> Self {
+        RleLoader {
+            parser: Parser::new(),
+            rule: like.rule().clone(),
+            topology: like.topology(),
+            size_level: like.root_level() as usize,
+            history_limit: like.history_limit(),
+            adaptive: like.is_adaptive_stepping(),
+            position: (x, y),
+            universe: None,
+            bytes_read: 0,
+        }
+    }
+
+    /// Parse the next piece of the file, which may end anywhere
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), ParseRleError> {
+        self.parser.feed(chunk)?;
+        self.bytes_read += chunk.len() as u64;
+        if self.parser.runs.len() >= BATCH_CELLS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Bytes fed so far
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Add the decoded cells to the universe, creating it the first time.
+    /// Only called once the header is complete.
+    fn flush(&mut self) -> Result<(), ParseRleError> {
+        let universe = self.universe.get_or_insert_with(|| {
+            let header = self.parser.header.as_ref();
+            let rule = header.and_then(|h| h.rule.clone()).unwrap_or_else(|| self.rule.clone());
+            let topology = header.and_then(|h| h.topology).unwrap_or(self.topology);
+            let mut universe = Universe::with_topology(self.size_level, rule, topology);
+            universe.set_history_limit(self.history_limit);
+            universe.set_adaptive_stepping(self.adaptive);
+            universe
+        });
+        let states = universe.rule().states();
+        if let Some(&(_, _, _, state)) = self.parser.runs.iter().find(|run| run.3 >= states) {
+            return Err(ParseRleError::InvalidState(state as u32));
+        }
+        let (dx, dy) = self.position;
+        let topology = universe.topology();
+        // Along a row a grid with joined edges repeats every two widths at most
+        let wrap = topology.bounds().filter(|_| topology.edge().is_none()).map(|(_, _, width, _)| width.saturating_mul(2));
+        let mut cells = Vec::new();
+        universe.begin_edit_group();
+        for (x, y, length, state) in self.parser.runs.drain(..) {
+            let (x, y) = (x.saturating_add(dx), y.saturating_add(dy));
+            match wrap {
+                Some(period) => cells.extend((x..x.saturating_add(length.min(period))).map(|x| (x, y, state))),
+                None if length >= SPAN_CELLS => universe.fill_region(Rect::new(x, y, length, 1), state),
+                None => cells.extend((x..x.saturating_add(length)).map(|x| (x, y, state))),
+            }
+            if cells.len() >= BATCH_CELLS {
+                universe.set_cell_states(&cells);
+                cells.clear();
+            }
+        }
+        universe.set_cell_states(&cells);
+        universe.end_edit_group();
+        Ok(())
+    }
+
+    /// Finish the file and return the universe, at generation 0, with the
+    /// file's metadata
+    pub fn finish(mut self) -> Result<Universe, ParseRleError> {
+        self.parser.finish()?;
+        self.flush()?;
+        let mut universe = self.universe.unwrap();
+        let rule = self.parser.header.and_then(|header| header.rule);
+        universe.set_metadata(parse_metadata(&self.parser.comments, rule));
+        Ok(universe)
+    }
+}
+
+/// Error returned by `Universe::read_rle`
+#[derive(Debug)]
+pub enum ReadRleError {
+    Io(std::io::Error),
+    Parse(ParseRleError),
+}
+
+impl fmt::Display for ReadRleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadRleError::Io(e) => write!(f, "error reading RLE: {}", e),
+            ReadRleError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReadRleError {}
+
+impl From<std::io::Error> for ReadRleError {
+    fn from(e: std::io::Error) -> Self {
+        ReadRleError::Io(e)
+    }
+}
+
+impl From<ParseRleError> for ReadRleError {
+    fn from(e: ParseRleError) -> Self {
+        ReadRleError::Parse(e)
+    }
+}
+
+/// Longest line `write` produces, as recommended for RLE files
+const LINE_WIDTH: usize = 70;
+
+/// Letters for a run of `state`: `b` and `o` for two-state patterns, `.`,
+/// `A` to `X` and prefixed letters otherwise
+fn state_tag(state: State, multistate: bool) -> String {
+    match (state, multistate) {
+        (0, false) => "b".to_string(),
+        (_, false) => "o".to_string(),
+        (0, true) => ".".to_string(),
+        (1..=24, true) => ((b'A' + state - 1) as char).to_string(),
+        _ => {
+            let (prefix, letter) = ((state - 1) / 24, (state - 1) % 24);
+            format!("{}{}", (b'p' + prefix - 1) as char, (b'A' + letter) as char)
+        }
+    }
+}
+
+/// Append a run, merging it with the previous one if it has the same tag
+fn push_run(runs: &mut Vec<(i64, String)>, length: i64, tag: String) {
+    match runs.last_mut() {
+        Some((n, last)) if *last == tag => *n += length,
+        _ => runs.push((length, tag)),
+    }
+}
+
+/// Write an RLE file: the comments as `#` lines, a header (with the rule
+/// only if there is one) and the cells of the pattern inside the
+/// `width` by `height` box at the origin, wrapped to 70 columns
+pub fn write(rle: &Rle) -> String {
+    let mut text = String::new();
+    for comment in &rle.comments {
+        text.push('#');
+        text.push_str(comment);
+        text.push('\n');
+    }
+    text.push_str(&format!("x = {}, y = {}", rle.width, rle.height));
+    if let Some(rule) = &rle.rule {
+        text.push_str(&format!(", rule = {}{}", rule, rle.topology.unwrap_or_default()));
+    }
+    text.push('\n');
+
+    let multistate = rle.rule.as_ref().is_some_and(|rule| rule.states() > 2)
+        || rle.pattern.cells().iter().any(|cell| cell.2 > 1);
+    let (width, height) = (rle.width as i64, rle.height as i64);
+    let mut rows: BTreeMap<i64, Vec<(i64, State)>> = BTreeMap::new();
+    for &(x, y, state) in rle.pattern.cells() {
+        if (0..width).contains(&x) && (0..height).contains(&y) {
+            rows.entry(y).or_default().push((x, state));
+        }
+    }
+
+    // Cells are sorted by x, so each row is in order. Dead cells at the end of
+    // a row and empty rows at the end of the pattern are left out, and empty
+    // rows in between become one `$` run.
+    let mut runs = Vec::new();
+    let mut last_y = 0;
+    for (y, row) in rows {
+        if y > last_y {
+            push_run(&mut runs, y - last_y, "$".to_string());
+        }
+        last_y = y;
+        let mut x = 0;
+        for (cell_x, state) in row {
+            if cell_x > x {
+                push_run(&mut runs, cell_x - x, state_tag(0, multistate));
+            }
+            push_run(&mut runs, 1, state_tag(state, multistate));
+            x = cell_x + 1;
+        }
+    }
+
+    let mut line = String::new();
+    let tokens = runs.into_iter()
+        .map(|(n, tag)| if n == 1 { tag } else { format!("{}{}", n, tag) })
+        .chain(std::iter::once("!".to_string()));
+    for token in tokens {
+        if line.len() + token.len() > LINE_WIDTH {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        line.push_str(&token);
+    }
+    text.push_str(&line);
+    text.push('\n');
+    text
+}
+
+impl Pattern {
+    /// Pattern from RLE text, placed where a `#CXRLE Pos=` line puts it or
+    /// with the top-left corner of its box at the origin. The rule and
+    /// comments are ignored.
+    pub fn from_rle(text: &str) -> Result<Pattern, ParseRleError> {
+        Ok(parse(text)?.pattern)
+    }
+
+    /// The pattern as RLE text without a rule, starting with a `#CXRLE Pos=`
+    /// line if its bounding box does not start at the origin, so `from_rle`
+    /// gives it back unchanged
+    pub fn to_rle(&self) -> String {
+        let (left, top, width, height) = self.bounding_box().unwrap_or((0, 0, 0, 0));
+        let comments = if (left, top) == (0, 0) { Vec::new() } else { vec![format!("CXRLE Pos={},{}", left, top)] };
+        write(&Rle {
+            pattern: self.translated(-left, -top),
+            width: width as u64,
+            height: height as u64,
+            rule: None,
+            topology: None,
+            comments,
+        })
+    }
+}
+
+impl Universe {
+    /// RLE text for the cells in `region`, or for the whole pattern if None,
+    /// with the universe's metadata as comments. The header gives the
+    /// universe's rule and, for finite grids, its topology. On a live background (rules with B0 on odd generations) only
+    /// the region itself can be written, with the background cells spelled out.
+    pub fn to_rle(&self, region: Option<Rect>) -> String {
+        let region = region.or_else(|| {
+            self.bounding_box().map(|(left, top, width, height)| Rect::new(left, top, width, height))
+        });
+        let region = region.unwrap_or(Rect::new(0, 0, 0, 0));
+        let pattern = if self.background() == 0 {
+            let cells = self.live_cells().into_iter().filter(|&(x, y, _)| region.contains(x, y));
+            Pattern::from_cells(cells).translated(-region.left, -region.top)
+        } else {
+            let cells = (0..region.height).flat_map(|y| {
+                (0..region.width).map(move |x| (x, y, self.get_cell_state(region.left + x, region.top + y)))
+            });
+            Pattern::from_cells(cells)
+        };
+        write(&Rle {
+            pattern,
+            width: region.width as u64,
+            height: region.height as u64,
+            rule: Some(self.rule().clone()),
+            topology: Some(self.topology()).filter(|topology| !topology.is_infinite()),
+            comments: metadata_comments(self.metadata()),
+        })
+    }
+
+    /// Replace the contents of the universe with an RLE pattern, moved by
+    /// `(x, y)`. The universe switches to the file's rule and grid if it gives
+    /// them, takes its metadata, and starts again from generation 0 with no
+    /// history.
+    pub fn load_rle(&mut self, text: &str, x: i64, y: i64) -> Result<(), ParseRleError> {
+        let mut loader = RleLoader::new(self, x, y);
+        loader.feed(text.as_bytes())?;
+        self.load_universe(loader.finish()?);
+        Ok(())
+    }
+
+    /// Like `load_rle`, but reading the file from `reader` a piece at a time.
+    /// `progress` is called after each piece with the number of bytes read so
+    /// far. The universe is left unchanged if reading or parsing fails.
+    pub fn read_rle<R: Read>(&mut self, mut reader: R, x: i64, y: i64,
+                             mut progress: impl FnMut(u64)) -> Result<(), ReadRleError> {
+        let mut loader = RleLoader::new(self, x, y);
+        let mut buffer = vec![0; 1 << 16];
+        loop {
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            loader.feed(&buffer[..n])?;
+            progress(loader.bytes_read());
+        }
+        self.load_universe(loader.finish()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "#N Glider
+#O Richard K. Guy
+#C The smallest, most common, and first discovered spaceship.
+x = 3, y = 3, rule = B3/S23
+bob$2bo$3o!";
+
+    const GOSPER_GUN: &str = "x = 36, y = 9, rule = B3/S23
+24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b
+obo$10bo5bo7bo$11bo3bo$12b2o!";
+
+    #[test]
+    fn test_parse() {
+        let rle = parse(GLIDER).unwrap();
+        assert_eq!(rle.pattern, Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]));
+        assert_eq!((rle.width, rle.height), (3, 3));
+        assert_eq!(rle.rule, Some(Rule::conway()));
+        assert_eq!(rle.topology, None);
+        assert_eq!(rle.comments[0], "N Glider");
+        assert_eq!(rle.comments.len(), 3);
+
+        // Runs split across lines, and a header without spaces
+        let gun = parse(GOSPER_GUN).unwrap();
+        assert_eq!(gun.pattern.population(), 36);
+        assert_eq!(gun.pattern.bounding_box(), Some((0, 0, 36, 9)));
+        let compact = parse("x=2,y=2\n2o$2o!").unwrap();
+        assert_eq!(compact.pattern.population(), 4);
+        assert_eq!(compact.rule, None);
+
+        // Multi-state cells, a grid and a Golly position
+        let wire = parse("#CXRLE Pos=-2,5\nx = 4, y = 2, rule = WireWorld:T10,8\n.ABC$2pA!").unwrap();
+        assert_eq!(wire.pattern.cells(), &[(-2, 6, 25), (-1, 5, 1), (-1, 6, 25), (0, 5, 2), (1, 5, 3)]);
+        assert_eq!(wire.rule, Some(Rule::wireworld()));
+        assert_eq!(wire.topology, Some(Topology::Torus { width: 10, height: 8 }));
+
+        // HROT rules contain commas
+        let hrot = parse("x = 1, y = 1, rule = R2,C2,M1,S6..11,B9..11,NM\no!").unwrap();
+        assert_eq!(hrot.rule.unwrap().radius(), 2);
+
+        let blank_rows = parse("o2$o!").unwrap();
+        assert_eq!(blank_rows.pattern.cells(), &[(0, 0, 1), (0, 2, 1)]);
+        assert_eq!((blank_rows.width, blank_rows.height), (1, 3));
+
+        assert_eq!(parse("x = 3\nbob!"), Err(ParseRleError::InvalidHeader("x = 3".into())));
+        assert!(matches!(parse("x = 1, y = 1, rule = B9\no!"), Err(ParseRleError::InvalidRule(_))));
+        assert_eq!(parse("2oz!"), Err(ParseRleError::UnexpectedCharacter('z')));
+        assert_eq!(parse("yX!"), Err(ParseRleError::InvalidState(264)));
+
+        // Runs and rows that would leave the plane
+        assert_eq!(parse("o99999999999999999999b!"), Err(ParseRleError::OutOfRange));
+        assert_eq!(parse("99999999999999999999$o!"), Err(ParseRleError::OutOfRange));
+        assert!(parse(&format!("{}bo!", MAX_COORDINATE)).is_ok());
+        assert_eq!(parse(&format!("{}b2o!", MAX_COORDINATE)), Err(ParseRleError::OutOfRange));
+        assert_eq!(parse(&format!("#CXRLE Pos=0,{}\n$o!", MAX_COORDINATE)), Err(ParseRleError::OutOfRange));
+        assert_eq!(parse("#CXRLE Pos=-9223372036854775808,0\no!"), Err(ParseRleError::OutOfRange));
+    }
+
+    #[test]
+    fn test_write() {
+        let glider = parse(GLIDER).unwrap();
+        assert_eq!(write(&glider), format!("{}\n", GLIDER.replace("bob$", "bo$")));
+        let gun = parse(GOSPER_GUN).unwrap();
+        assert_eq!(write(&gun), format!("{}\n", GOSPER_GUN));
+
+        let wire = Rle {
+            pattern: Pattern::from_cells([(1, 1, 1), (2, 1, 2), (3, 1, 3), (0, 3, 30)]),
+            width: 4,
+            height: 4,
+            rule: Some(Rule::wireworld()),
+            topology: Some(Topology::Torus { width: 10, height: 8 }),
+            comments: vec!["N wire".to_string()],
+        };
+        let text = write(&wire);
+        assert_eq!(text, "#N wire\nx = 4, y = 4, rule = WireWorld:T10,8\n$.ABC2$pF!\n");
+        assert_eq!(parse(&text).unwrap(), wire);
+
+        // Long rows wrap without splitting runs
+        let dotted = Pattern::from_live_cells((0..100).map(|x| (2 * x, 0)));
+        let rle = Rle { pattern: dotted, width: 199, height: 1, rule: None, topology: None, comments: Vec::new() };
+        let text = write(&rle);
+        assert!(text.lines().all(|line| line.len() <= 70));
+        assert_eq!(parse(&text).unwrap(), rle);
+    }
+
+    #[test]
+    fn test_pattern_rle() {
+        let glider = Pattern::from_rle(GLIDER).unwrap();
+        assert_eq!(glider, Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]));
+        assert_eq!(glider.to_rle(), "x = 3, y = 3\nbo$2bo$3o!\n");
+        for pattern in [glider.translated(-7, 3), Pattern::from_cells([(0, 5, 2), (4, 0, 1)]), Pattern::new()] {
+            assert_eq!(Pattern::from_rle(&pattern.to_rle()).unwrap(), pattern);
+        }
+        assert!(glider.translated(-7, 3).to_rle().starts_with("#CXRLE Pos=-7,3\n"));
+
+        // Rows far apart are one run of row ends, not a row each
+        let tall = Pattern::from_live_cells([(0, 0), (0, 1 << 40)]);
+        assert_eq!(tall.to_rle(), format!("x = 1, y = {}\no{}$o!\n", (1u64 << 40) + 1, 1u64 << 40));
+        assert_eq!(Pattern::from_rle(&tall.to_rle()).unwrap(), tall);
+    }
+
+    #[test]
+    fn test_to_rle() {
+        let mut universe = Universe::new(4);
+        universe.load_rle(GOSPER_GUN, -18, -4).unwrap();
+        assert_eq!(universe.to_rle(None), format!("{}\n", GOSPER_GUN));
+        assert_eq!(universe.to_rle(Some(Rect::new(-18, -3, 8, 4))), "x = 8, y = 4, rule = B3/S23\n3$2o!\n");
+        assert_eq!(Universe::new(4).to_rle(None), "x = 0, y = 0, rule = B3/S23\n!\n");
+
+        let mut copy = Universe::new(4);
+        copy.load_rle(&universe.to_rle(None), -18, -4).unwrap();
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+
+        // On a live background the region is written in full
+        let mut universe = Universe::with_rule(4, "B0123478/S34678".parse().unwrap());
+        universe.set_cell(0, 0, true);
+        universe.step();
+        assert_eq!(universe.background(), 1);
+        let region = Rect::new(-1, -1, 3, 3);
+        let mut copy = Universe::new(4);
+        copy.load_rle(&universe.to_rle(Some(region)), -1, -1).unwrap();
+        for (x, y) in (-1..2).flat_map(|y| (-1..2).map(move |x| (x, y))) {
+            assert_eq!(copy.get_cell_state(x, y), universe.get_cell_state(x, y));
+        }
+    }
+
+    #[test]
+    fn test_load_rle() {
+        let mut universe = Universe::new(4);
+        universe.set_cell(100, 100, true);
+        universe.step();
+        universe.load_rle(GOSPER_GUN, -18, -4).unwrap();
+        assert_eq!(universe.generation(), 0);
+        assert_eq!(universe.population(), 36);
+        assert!(!universe.get_cell(100, 100));
+        assert!(universe.get_cell(-18, 0));
+        assert_eq!(universe.bounding_box(), Some((-18, -4, 36, 9)));
+        universe.step_to(30).unwrap();
+        assert_eq!(universe.population(), 41);
+
+        universe.load_rle("x = 4, y = 1, rule = WireWorld\n.ABC!", 0, 0).unwrap();
+        assert_eq!(universe.rule(), &Rule::wireworld());
+        assert_eq!(universe.get_cell_state(2, 0), 2);
+
+        // States the rule does not have are rejected, leaving the universe as it was
+        assert_eq!(universe.load_rle("x = 1, y = 1, rule = B3/S23\nB!", 0, 0), Err(ParseRleError::InvalidState(2)));
+        assert_eq!(universe.rule(), &Rule::wireworld());
+
+        // Grids too large for the plane are rejected too
+        for size in ["9223372036854775808", "9223372036854775807"] {
+            let text = format!("x = 1, y = 1, rule = B3/S23:T{},4\no!", size);
+            assert!(matches!(universe.load_rle(&text, 0, 0), Err(ParseRleError::InvalidTopology(_))));
+        }
+
+        // Metadata is kept and written back out
+        universe.load_rle(&format!("#CXRLE Pos=0,0\n#r 23/3\n{}", GLIDER), 0, 0).unwrap();
+        let metadata = universe.metadata();
+        assert_eq!(metadata.name.as_deref(), Some("Glider"));
+        assert_eq!(metadata.author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(metadata.comments, ["The smallest, most common, and first discovered spaceship."]);
+        assert_eq!(metadata.rule, Some(Rule::conway()));
+        assert_eq!(universe.to_rle(None), format!("{}\n", GLIDER.replace("bob$", "bo$")));
+    }
+
+    #[test]
+    fn test_rle_loader() {
+        // Pieces may split lines, runs and the header anywhere
+        let text = format!("#CXRLE Pos=-18,-4\r\n{}", GOSPER_GUN);
+        let mut expected = Universe::new(4);
+        expected.load_rle(GOSPER_GUN, -18, -4).unwrap();
+        for size in [1, 7, 1000] {
+            let mut loader = RleLoader::new(&Universe::new(4), 0, 0);
+            for chunk in text.as_bytes().chunks(size) {
+                loader.feed(chunk).unwrap();
+            }
+            assert_eq!(loader.bytes_read(), text.len() as u64);
+            assert_eq!(loader.finish().unwrap().to_pattern(), expected.to_pattern());
+        }
+
+        // A pattern of several batches, read from a slice
+        let text = format!("x = 400, y = 400\n{}!", "400o$".repeat(400));
+        let mut universe = Universe::new(4);
+        let mut progress = Vec::new();
+        universe.read_rle(text.as_bytes(), 10, 10, |bytes| progress.push(bytes)).unwrap();
+        assert_eq!(universe.population(), 160_000);
+        assert_eq!(universe.bounding_box(), Some((10, 10, 400, 400)));
+        assert_eq!(progress.last(), Some(&(text.len() as u64)));
+
+        // Long runs are filled as spans, not decoded cell by cell
+        let run = 1i64 << 60;
+        let mut long = Universe::new(4);
+        long.load_rle(&format!("x = {0}, y = 2\n{0}o$3o!", run), 0, 0).unwrap();
+        assert_eq!(long.population(), run as u64 + 3);
+        assert_eq!(long.bounding_box(), Some((0, 0, run, 2)));
+        assert_eq!(long.load_rle("x = 1, y = 1\n4611686018427387904o!", 0, 0), Err(ParseRleError::OutOfRange));
+        // Only the loader takes runs like this; parse would need a cell each
+        long.load_rle("x = 0, y = 0\n4000000000o!", 0, 0).unwrap();
+        assert_eq!(long.population(), 4_000_000_000);
+        assert_eq!(parse("x = 0, y = 0\n4000000000o!"), Err(ParseRleError::TooManyCells(4_000_000_000)));
+        assert_eq!(parse(&format!("{}o$o!", MAX_PATTERN_CELLS)), Err(ParseRleError::TooManyCells(MAX_PATTERN_CELLS + 1)));
+        let mut torus = Universe::with_topology(4, Rule::conway(), Topology::Torus { width: 10, height: 8 });
+        torus.load_rle(&format!("x = {0}, y = 1\n{0}o!", run), 0, 0).unwrap();
+        assert_eq!(torus.population(), 10);
+
+        let error = universe.read_rle("x = 1, y = 1\n2oz!".as_bytes(), 0, 0, |_| {}).err().unwrap();
+        assert!(matches!(error, ReadRleError::Parse(ParseRleError::UnexpectedCharacter('z'))));
+        assert_eq!(universe.population(), 160_000);
+    }
+}
@@ -0,0 +1,153 @@
+//! Share strings: a pattern packed small enough to put in a link
+//!
+//! A share string is a version character followed by the pattern's RLE
+//! (with its position, rule, grid and metadata), compressed with deflate and
+//! written in unpadded URL-safe base64, so it can go in a URL fragment or
+//! query without escaping.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use super::rle::ParseRleError;
+use crate::hashlife::Universe;
+
+/// First character of share strings in the current form
+const VERSION: char = '1';
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Error returned when a share string cannot be decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseShareError {
+    /// The string is empty or starts with an unknown version
+    UnsupportedVersion,
+    /// A character outside the URL-safe base64 alphabet, or a length no
+    /// base64 string has
+    InvalidBase64,
+    /// The data does not decompress to text
+    InvalidData,
+    InvalidRle(ParseRleError),
+}
+
+impl fmt::Display for ParseShareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseShareError::UnsupportedVersion => write!(f, "unsupported share string version"),
+            ParseShareError::InvalidBase64 => write!(f, "invalid base64 in share string"),
+            ParseShareError::InvalidData => write!(f, "share string does not hold a pattern"),
+            ParseShareError::InvalidRle(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseShareError {}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            text.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, ParseShareError> {
+    if text.len() % 4 == 1 {
+        return Err(ParseShareError::InvalidBase64);
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3 + 2);
+    for chunk in text.as_bytes().chunks(4) {
+        let mut bits = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|&a| a == c).ok_or(ParseShareError::InvalidBase64)?;
+            bits |= (value as u32) << (18 - 6 * i);
+        }
+        bytes.extend((0..chunk.len() - 1).map(|i| (bits >> (16 - 8 * i)) as u8));
+    }
+    Ok(bytes)
+}
+
+impl Universe {
+    /// The pattern as a share string. The generation and settings such as the
+    /// history limit are not included.
+    pub fn to_share_string(&self) -> String {
+        let mut rle = String::new();
+        if let Some((left, top, _, _)) = self.bounding_box() {
+            rle.push_str(&format!("#CXRLE Pos={},{}\n", left, top));
+        }
+        rle.push_str(&self.to_rle(None));
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(rle.as_bytes()).unwrap();
+        let mut text = VERSION.to_string();
+        text.push_str(&encode_base64(&encoder.finish().unwrap()));
+        text
+    }
+
+    /// Read a universe from `to_share_string` output, at generation 0
+    pub fn from_share_string(text: &str) -> Result<Universe, ParseShareError> {
+        let data = text.strip_prefix(VERSION).ok_or(ParseShareError::UnsupportedVersion)?;
+        let compressed = decode_base64(data)?;
+        let mut rle = String::new();
+        DeflateDecoder::new(compressed.as_slice())
+            .read_to_string(&mut rle)
+            .map_err(|_| ParseShareError::InvalidData)?;
+        let mut universe = Universe::new(3);
+        universe.load_rle(&rle, 0, 0).map_err(ParseShareError::InvalidRle)?;
+        Ok(universe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::PatternMetadata;
+    use crate::rule::Rule;
+    use crate::topology::Topology;
+
+    #[test]
+    fn test_base64() {
+        for bytes in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\xfb\xff\x00"] {
+            assert_eq!(decode_base64(&encode_base64(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(encode_base64(b"foob"), "Zm9vYg");
+        assert_eq!(encode_base64(b"\xfb\xff"), "-_8");
+        assert_eq!(decode_base64("Zm9vY"), Err(ParseShareError::InvalidBase64));
+        assert_eq!(decode_base64("Zm9v+g"), Err(ParseShareError::InvalidBase64));
+    }
+
+    #[test]
+    fn test_share_string() {
+        let mut universe = Universe::with_topology(4, Rule::wireworld(), Topology::Torus { width: 40, height: 30 });
+        for x in -10..10 {
+            universe.set_cell_state(x, -5, 3);
+        }
+        universe.set_cell_state(-10, -5, 1);
+        universe.set_metadata(PatternMetadata { name: Some("Wire".to_string()), ..PatternMetadata::default() });
+        let text = universe.to_share_string();
+        assert!(text.starts_with('1'));
+        assert!(text.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+
+        let copy = Universe::from_share_string(&text).unwrap();
+        assert_eq!((copy.rule(), copy.topology()), (universe.rule(), universe.topology()));
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+        assert_eq!(copy.metadata().name.as_deref(), Some("Wire"));
+
+        // Large regular patterns compress well
+        let mut universe = Universe::new(4);
+        for x in 0..1000 {
+            universe.set_cell(2 * x, 0, true);
+        }
+        assert!(universe.to_share_string().len() < universe.to_rle(None).len() / 10);
+        assert_eq!(Universe::from_share_string(&universe.to_share_string()).unwrap().population(), 1000);
+
+        assert_eq!(Universe::from_share_string(&Universe::new(4).to_share_string()).unwrap().population(), 0);
+        assert_eq!(Universe::from_share_string("").err(), Some(ParseShareError::UnsupportedVersion));
+        assert_eq!(Universe::from_share_string("1Zm9v").err(), Some(ParseShareError::InvalidData));
+    }
+}
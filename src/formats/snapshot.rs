@@ -0,0 +1,422 @@
+//! Whole-universe snapshots: the rule, grid, generation and the quadtree as a
+//! table of its distinct nodes, so shared subtrees are stored once
+//!
+//! `Universe::to_bytes` writes the table in a compact versioned binary form,
+//! and with the `serde` feature `Universe` implements `Serialize` and
+//! `Deserialize` through it. Settings such as the history limit are not part
+//! of a snapshot, and neither is the step history.
+//!
+//! The binary form is the magic `VIVE` and a version byte, then the rule and
+//! grid as length-prefixed strings, the generation, a flags byte (bit 0 set
+//! for a complemented background), the node count, and each node as its level
+//! byte followed by four children. Every length, count and child is an
+//! unsigned LEB128 varint, so small tables take a few bytes per node.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::hashlife::{Node, Universe, MAX_LEVEL};
+use crate::rule::{ParseRuleError, Rule, State};
+use crate::topology::{ParseTopologyError, Topology};
+
+/// Error returned when a snapshot cannot be turned back into a universe
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    InvalidRule(ParseRuleError),
+    InvalidTopology(ParseTopologyError),
+    /// A node (numbered from 1) whose level is 0 or above 62
+    InvalidLevel(usize),
+    /// A node refers to a node not defined before it, or of the wrong level
+    InvalidReference(usize),
+    /// A level 1 node holds a cell state the rule does not have
+    InvalidState(usize),
+    /// A live background with a rule of more than two states
+    InvalidBackground,
+    /// The data does not start with the snapshot magic
+    InvalidHeader,
+    /// A snapshot version this build cannot read
+    UnsupportedVersion(u8),
+    /// The data stops in the middle of a field or a varint overflows
+    UnexpectedEnd,
+    /// The rule or grid string is not UTF-8
+    InvalidText,
+    /// Bytes remain after the last node
+    TrailingData,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidRule(e) => write!(f, "invalid rule in snapshot: {}", e),
+            SnapshotError::InvalidTopology(e) => write!(f, "invalid grid in snapshot: {}", e),
+            SnapshotError::InvalidLevel(node) => write!(f, "invalid level for node {}", node),
+            SnapshotError::InvalidReference(node) => write!(f, "invalid child reference in node {}", node),
+            SnapshotError::InvalidState(node) => write!(f, "invalid cell state in node {}", node),
+            SnapshotError::InvalidBackground => write!(f, "live background with a multi-state rule"),
+            SnapshotError::InvalidHeader => write!(f, "not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {}", version),
+            SnapshotError::UnexpectedEnd => write!(f, "snapshot ends unexpectedly"),
+            SnapshotError::InvalidText => write!(f, "rule or grid in snapshot is not UTF-8"),
+            SnapshotError::TrailingData => write!(f, "unexpected data after snapshot"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// The parts of a universe a snapshot keeps
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SnapshotData {
+    pub(crate) rule: Rule,
+    pub(crate) topology: Topology,
+    pub(crate) generation: u128,
+    /// Whether the stored cells are complemented (see `Universe::background`)
+    pub(crate) inverted: bool,
+    /// Distinct non-empty nodes as `(level, children)`, children before
+    /// parents and the root last. The children of a level 1 node are cell
+    /// states; otherwise 0 is an empty node and `i` is the `i`th node.
+    pub(crate) nodes: Vec<(u8, [u64; 4])>,
+}
+
+/// Number each distinct non-empty node, children first
+fn number(node: &Rc<Node>, numbers: &mut HashMap<*const Node, u64>, nodes: &mut Vec<(u8, [u64; 4])>) -> u64 {
+    if node.population() == 0 {
+        return 0;
+    }
+    if let Some(&number) = numbers.get(&Rc::as_ptr(node)) {
+        return number;
+    }
+    let children = if node.level() == 1 {
+        node.children().map(|child| child.state() as u64)
+    } else {
+        node.children().map(|child| number(child, numbers, nodes))
+    };
+    nodes.push((node.level(), children));
+    numbers.insert(Rc::as_ptr(node), nodes.len() as u64);
+    nodes.len() as u64
+}
+
+impl SnapshotData {
+    pub(crate) fn of(universe: &Universe) -> Self {
+        let mut nodes = Vec::new();
+        number(universe.root(), &mut HashMap::new(), &mut nodes);
+        SnapshotData {
+            rule: universe.rule().clone(),
+            topology: universe.topology(),
+            generation: universe.generation(),
+            inverted: universe.stored_background() != 0,
+            nodes,
+        }
+    }
+
+    /// Rebuild the universe, checking every node
+    pub(crate) fn into_universe(self) -> Result<Universe, SnapshotError> {
+        if self.inverted && self.rule.states() > 2 {
+            return Err(SnapshotError::InvalidBackground);
+        }
+        let mut universe = Universe::with_topology(3, self.rule, self.topology);
+        let mut built: Vec<Rc<Node>> = Vec::with_capacity(self.nodes.len());
+        for (i, &(level, children)) in self.nodes.iter().enumerate() {
+            let number = i + 1;
+            if level == 0 || level > MAX_LEVEL {
+                return Err(SnapshotError::InvalidLevel(number));
+            }
+            let mut quadrants = Vec::with_capacity(4);
+            for child in children {
+                let child = if level == 1 {
+                    let state = State::try_from(child).ok()
+                        .filter(|&state| state < universe.rule().states())
+                        .ok_or(SnapshotError::InvalidState(number))?;
+                    universe.leaf_node(state)
+                } else if child == 0 {
+                    universe.empty_node(level - 1)
+                } else {
+                    match usize::try_from(child).ok().and_then(|child| built.get(child - 1)) {
+                        Some(node) if node.level() == level - 1 => node.clone(),
+                        _ => return Err(SnapshotError::InvalidReference(number)),
+                    }
+                };
+                quadrants.push(child);
+            }
+            let [nw, ne, sw, se]: [Rc<Node>; 4] = quadrants.try_into().unwrap();
+            built.push(universe.inner_node(nw, ne, sw, se));
+        }
+
+        let root = match built.pop() {
+            Some(root) => root,
+            None => universe.empty_node(3),
+        };
+        universe.set_root(root, self.generation, self.inverted);
+        Ok(universe)
+    }
+}
+
+const MAGIC: &[u8; 4] = b"VIVE";
+const VERSION: u8 = 1;
+
+fn write_varint(bytes: &mut Vec<u8>, value: impl Into<u128>) {
+    let mut value = value.into();
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_str(bytes: &mut Vec<u8>, text: &str) {
+    write_varint(bytes, text.len() as u64);
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+/// Reads the fields of a binary snapshot in order
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if n > self.bytes.len() {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// A varint, which overflows if it does not fit in a `T`
+    fn varint<T: TryFrom<u128>>(&mut self) -> Result<T, SnapshotError> {
+        let mut value = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u128::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                return Err(SnapshotError::UnexpectedEnd);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return T::try_from(value).map_err(|_| SnapshotError::UnexpectedEnd);
+            }
+        }
+        Err(SnapshotError::UnexpectedEnd)
+    }
+
+    fn str(&mut self) -> Result<&'a str, SnapshotError> {
+        let len = self.varint()?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| SnapshotError::InvalidText)
+    }
+}
+
+impl SnapshotData {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        write_str(&mut bytes, &self.rule.to_string());
+        write_str(&mut bytes, &self.topology.to_string());
+        write_varint(&mut bytes, self.generation);
+        bytes.push(self.inverted as u8);
+        write_varint(&mut bytes, self.nodes.len() as u64);
+        for &(level, children) in &self.nodes {
+            bytes.push(level);
+            for child in children {
+                write_varint(&mut bytes, child);
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SnapshotError::InvalidHeader);
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let rule = reader.str()?.parse().map_err(SnapshotError::InvalidRule)?;
+        let topology = reader.str()?.parse().map_err(SnapshotError::InvalidTopology)?;
+        let generation = reader.varint()?;
+        let inverted = reader.byte()? & 1 != 0;
+        let count: u64 = reader.varint()?;
+        // Each node takes at least five bytes, which bounds the allocation
+        let mut nodes = Vec::with_capacity(count.min(reader.bytes.len() as u64 / 5) as usize);
+        for _ in 0..count {
+            let level = reader.byte()?;
+            let mut children = [0; 4];
+            for child in &mut children {
+                *child = reader.varint()?;
+            }
+            nodes.push((level, children));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(SnapshotError::TrailingData);
+        }
+        Ok(SnapshotData { rule, topology, generation, inverted, nodes })
+    }
+}
+
+impl Universe {
+    /// Write the universe as a binary snapshot (see the module documentation)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        SnapshotData::of(self).to_bytes()
+    }
+
+    /// Read a universe written by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Universe, SnapshotError> {
+        SnapshotData::from_bytes(bytes)?.into_universe()
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{SnapshotData, SnapshotError};
+    use crate::hashlife::Universe;
+
+    /// Rules and grids are written as their usual strings
+    #[derive(Serialize, Deserialize)]
+    struct SerializedUniverse {
+        rule: String,
+        topology: String,
+        generation: u128,
+        inverted: bool,
+        nodes: Vec<(u8, [u64; 4])>,
+    }
+
+    impl Serialize for Universe {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let data = SnapshotData::of(self);
+            SerializedUniverse {
+                rule: data.rule.to_string(),
+                topology: data.topology.to_string(),
+                generation: data.generation,
+                inverted: data.inverted,
+                nodes: data.nodes,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Universe {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let serialized = SerializedUniverse::deserialize(deserializer)?;
+            let data = SnapshotData {
+                rule: serialized.rule.parse().map_err(SnapshotError::InvalidRule).map_err(D::Error::custom)?,
+                topology: serialized.topology.parse().map_err(SnapshotError::InvalidTopology).map_err(D::Error::custom)?,
+                generation: serialized.generation,
+                inverted: serialized.inverted,
+                nodes: serialized.nodes,
+            };
+            data.into_universe().map_err(D::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glider_field() -> Universe {
+        let mut universe = Universe::new(4);
+        for i in 0..8 {
+            for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+                universe.set_cell(x + 64 * i, y - 64 * i, true);
+            }
+        }
+        universe.step_to(37).unwrap();
+        universe
+    }
+
+    #[test]
+    fn test_snapshot_data() {
+        let universe = glider_field();
+        let data = SnapshotData::of(&universe);
+        // The gliders share their subtrees
+        assert!(data.nodes.len() < 8 * universe.root_level() as usize);
+        let copy = data.clone().into_universe().unwrap();
+        assert_eq!(copy.generation(), 37);
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+        assert_eq!(SnapshotData::of(&copy), data);
+
+        let bad = |nodes| SnapshotData { nodes, ..data.clone() }.into_universe().err();
+        assert_eq!(bad(vec![(1, [0, 0, 0, 2])]), Some(SnapshotError::InvalidState(1)));
+        assert_eq!(bad(vec![(1, [0, 0, 0, 1]), (3, [1, 0, 0, 0])]), Some(SnapshotError::InvalidReference(2)));
+        assert_eq!(bad(vec![(2, [1, 0, 0, 0])]), Some(SnapshotError::InvalidReference(1)));
+        assert_eq!(bad(vec![(0, [0, 0, 0, 0])]), Some(SnapshotError::InvalidLevel(1)));
+
+        // Rules with B0 keep their stored background
+        let mut universe = Universe::with_rule(4, "B0123478/S34678".parse().unwrap());
+        universe.set_cell(0, 0, true);
+        universe.step();
+        let copy = SnapshotData::of(&universe).into_universe().unwrap();
+        assert_eq!(copy.background(), 1);
+        assert_eq!(copy.get_cell(5, 5), universe.get_cell(5, 5));
+        assert_eq!(copy.get_cell(0, 0), universe.get_cell(0, 0));
+    }
+
+    #[test]
+    fn test_bytes() {
+        let universe = glider_field();
+        let bytes = universe.to_bytes();
+        assert!(bytes.starts_with(b"VIVE\x01\x06B3/S23\x00\x25\x00"));
+        let copy = Universe::from_bytes(&bytes).unwrap();
+        assert_eq!(copy.generation(), 37);
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+        assert_eq!(copy.to_bytes(), bytes);
+
+        let mut torus = Universe::with_topology(4, Rule::wireworld(), Topology::Torus { width: 8, height: 8 });
+        torus.set_cell_state(1, 2, 3);
+        torus.step_to(5).unwrap();
+        let copy = Universe::from_bytes(&torus.to_bytes()).unwrap();
+        assert_eq!((copy.rule(), copy.topology(), copy.generation()), (torus.rule(), torus.topology(), 5));
+        assert_eq!(copy.to_pattern(), torus.to_pattern());
+
+        // Generations take as many bytes as they need
+        let mut blinker = Universe::new(4);
+        for x in 0..3 {
+            blinker.set_cell(x, 0, true);
+        }
+        blinker.step_to(1 << 40).unwrap();
+        assert_eq!(Universe::from_bytes(&blinker.to_bytes()).unwrap().generation(), 1 << 40);
+        let text = blinker.to_macrocell().replace("#G 1099511627776", "#G 18446744073709551615");
+        blinker.load_macrocell(&text).unwrap();
+        blinker.step();
+        let copy = Universe::from_bytes(&blinker.to_bytes()).unwrap();
+        assert_eq!((copy.generation(), copy.population()), (1 << 64, 3));
+        assert!(copy.to_macrocell().contains("#G 18446744073709551616\n"));
+
+        let error = |bytes: &[u8]| Universe::from_bytes(bytes).err();
+        assert_eq!(error(b"VIV"), Some(SnapshotError::InvalidHeader));
+        assert_eq!(error(b"VIVE\x02"), Some(SnapshotError::UnsupportedVersion(2)));
+        assert_eq!(error(&bytes[..bytes.len() - 1]), Some(SnapshotError::UnexpectedEnd));
+        assert_eq!(error(&[bytes.as_slice(), &[0]].concat()), Some(SnapshotError::TrailingData));
+        assert_eq!(error(b"VIVE\x01\x01\xff"), Some(SnapshotError::InvalidText));
+        assert_eq!(error(b"VIVE\x01\x06B3/S23\x00\xff\xff\xff\xff\xff\xff\xff\xff\xff\x7f"), Some(SnapshotError::UnexpectedEnd));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let universe = glider_field();
+        let json = serde_json::to_string(&universe).unwrap();
+        assert!(json.starts_with(r#"{"rule":"B3/S23","topology":"","generation":37,"inverted":false,"nodes":[[1,"#));
+        let mut copy: Universe = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.to_pattern(), universe.to_pattern());
+        copy.step();
+        assert_eq!(copy.generation(), 38);
+
+        let torus = Universe::with_topology(4, Rule::wireworld(), Topology::Torus { width: 8, height: 8 });
+        let copy: Universe = serde_json::from_str(&serde_json::to_string(&torus).unwrap()).unwrap();
+        assert_eq!((copy.rule(), copy.topology()), (torus.rule(), torus.topology()));
+
+        let error = serde_json::from_str::<Universe>(&json.replace("B3/S23", "B3/S2x")).err().unwrap();
+        assert!(error.to_string().starts_with("invalid rule in snapshot"));
+    }
+}
@@ -1,14 +1,34 @@
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
-use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::rc::{Rc, Weak};
+
+use crate::checkpoint::{self, Checkpoint, CheckpointInterval, Checkpoints};
+use crate::edit::EditSymmetry;
+use crate::journal::{Journal, JournalEntry, JournalSink};
+use crate::memory::{table_size, MemoryLimit, MemoryUsage};
+use crate::pattern::{PatternMetadata, Rect};
+use crate::rule::{BitRule, Neighbourhood, Rule, State};
+use crate::stats::{CacheCounters, CellAges, PopulationHistory};
+use crate::timeline::{Timeline, TimelineError};
+use crate::undo::UndoStack;
+use crate::topology::{Edge, Topology};
 
 /// A node in the HashLife quadtree
 #[derive(Clone, Debug)]
 pub struct Node {
     /// Level of this node (0 = single cell, 1 = 2x2, 2 = 4x4, etc.)
     level: u8,
-    /// Population count (number of live cells)
-    population: u64,
+    /// Population count (number of non-dead cells). A root of `MAX_LEVEL`
+    /// holds up to 2^124 cells, more than a u64 counts.
+    population: u128,
+    /// Per-state population counts, indexed by `state - 1`.
+    /// `None` means every non-dead cell in this subtree is in state 1, which
+    /// keeps two-state patterns free of the extra bookkeeping.
+    state_counts: Option<Box<[u128]>>,
+    /// Hash of the cells and level, the same on every machine and in every
+    /// universe (unlike the node's address)
+    content_hash: u64,
     /// Node content
     content: NodeContent,
 }
@@ -16,7 +36,7 @@ pub struct Node {
 #[derive(Clone, Debug)]
 enum NodeContent {
     /// Leaf node containing a single cell state
-    Leaf(bool),
+    Leaf(State),
     /// Inner node with 4 quadrants (NW, NE, SW, SE)
     Inner {
         nw: Rc<Node>,
@@ -51,9 +71,9 @@ impl Hash for Node {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.level.hash(state);
         match &self.content {
-            NodeContent::Leaf(alive) => {
+            NodeContent::Leaf(cell) => {
                 0u8.hash(state);
-                alive.hash(state);
+                cell.hash(state);
             }
             NodeContent::Inner { nw, ne, sw, se, .. } => {
                 1u8.hash(state);
@@ -67,11 +87,18 @@ impl Hash for Node {
 }
 
 impl Node {
-    fn leaf(alive: bool) -> Self {
+    fn leaf(state: State) -> Self {
+        let state_counts = (state > 1).then(|| {
+            let mut counts = vec![0; state as usize];
+            counts[state as usize - 1] = 1;
+            counts.into_boxed_slice()
+        });
         Node {
             level: 0,
-            population: if alive { 1 } else { 0 },
-            content: NodeContent::Leaf(alive),
+            population: (state != 0) as u128,
+            state_counts,
+            content_hash: mix(state as u64 + 1),
+            content: NodeContent::Leaf(state),
         }
     }
 
@@ -81,45 +108,239 @@ impl Node {
         assert_eq!(nw.level, se.level);
         
         let population = nw.population + ne.population + sw.population + se.population;
+        let state_counts = Node::sum_state_counts([&nw, &ne, &sw, &se]);
+        let content_hash = Node::combine_hashes(nw.level + 1, [&nw, &ne, &sw, &se].map(|child| child.content_hash));
         
         Node {
             level: nw.level + 1,
             population,
+            state_counts,
+            content_hash,
             content: NodeContent::Inner { nw, ne, sw, se },
         }
     }
 
-    fn is_alive(&self) -> bool {
-        matches!(self.content, NodeContent::Leaf(true))
+    /// Content hash of an inner node of the given level from its quadrants' hashes
+    pub(crate) fn combine_hashes(level: u8, children: [u64; 4]) -> u64 {
+        children.into_iter().fold(mix(level as u64), |hash, child| mix(hash ^ child).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    pub(crate) fn content_hash(&self) -> u64 {
+        self.content_hash
+    }
+
+    fn sum_state_counts(children: [&Rc<Node>; 4]) -> Option<Box<[u128]>> {
+        let len = children.iter()
+            .filter_map(|child| child.state_counts.as_ref().map(|c| c.len()))
+            .max()?;
+        let mut counts = vec![0; len];
+        for child in children {
+            match &child.state_counts {
+                Some(child_counts) => {
+                    for (total, count) in counts.iter_mut().zip(child_counts.iter()) {
+                        *total += count;
+                    }
+                }
+                None => counts[0] += child.population,
+            }
+        }
+        Some(counts.into_boxed_slice())
+    }
+
+    /// 0 for a single cell, L for a 2^L by 2^L square
+    pub(crate) fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Number of non-dead cells
+    pub(crate) fn population(&self) -> u128 {
+        self.population
+    }
+
+    pub(crate) fn state(&self) -> State {
+        match self.content {
+            NodeContent::Leaf(state) => state,
+            NodeContent::Inner { .. } => unreachable!(),
+        }
+    }
+
+    /// Number of cells in the given state (state 0 is not counted)
+    fn state_population(&self, state: State) -> u128 {
+        match &self.state_counts {
+            Some(counts) => counts.get((state as usize).wrapping_sub(1)).copied().unwrap_or(0),
+            None if state == 1 => self.population,
+            None => 0,
+        }
+    }
+
+    /// The most common non-dead state in this subtree (1 if empty)
+    fn dominant_state(&self) -> State {
+        match &self.state_counts {
+            Some(counts) => counts.iter()
+                .enumerate()
+                .max_by_key(|&(i, &count)| (count, std::cmp::Reverse(i)))
+                .map_or(1, |(i, _)| i as State + 1),
+            None => 1,
+        }
+    }
+
+    /// The four quadrants (NW, NE, SW, SE) of an inner node
+    pub(crate) fn children(&self) -> [&Rc<Node>; 4] {
+        let NodeContent::Inner { nw, ne, sw, se, .. } = &self.content else {
+            unreachable!();
+        };
+        [nw, ne, sw, se]
+    }
+}
+
+/// SplitMix64's finaliser, which spreads every input bit over the output
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// The multiply-and-rotate hash of rustc's FxHasher, for keys made of node
+/// addresses and small integers. SipHash's resistance to chosen keys buys
+/// nothing for addresses and costs a large share of a step that misses the
+/// caches.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct FxHasher {
+    hash: u64,
+}
+
+impl FxHasher {
+    fn add(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.add(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.add(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.add(n);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.add(n as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        // The multiplications carry entropy upwards only, and node addresses
+        // have their low bits clear, so bring the high bits down to where the
+        // table looks for its bucket
+        self.hash.rotate_left(26)
     }
 }
 
-/// Cache for canonical nodes
+/// A `HashMap` hashed with `FxHasher`
+pub(crate) type FxHashMap<K, V> = HashMap<K, V, BuildHasherDefault<FxHasher>>;
+
+/// A memoized `Universe::advance` result: the node it is for and the result
+type MemoEntry = (Weak<Node>, Rc<Node>);
+
+/// Entries a new node cache and result memo make room for, which small
+/// patterns never outgrow
+const INITIAL_CAPACITY: usize = 1 << 10;
+
+/// Store of canonical nodes, so that equal subtrees are one node. Every
+/// universe has one; cloning the handle and passing it to
+/// `Universe::with_cache` lets several universes share theirs, so that
+/// identical subtrees are stored once between them and their trees can be
+/// compared and combined node by node. Canonical nodes stay in the cache until
+/// `Universe::collect_garbage` frees those nothing uses any more.
+#[derive(Clone)]
 pub struct NodeCache {
-    leaves: [Rc<Node>; 2],
-    inner_cache: HashMap<(usize, usize, usize, usize), Rc<Node>>,
-    /// Cache for next_generation_single results (node pointer -> result node)
-    result_cache: HashMap<usize, Rc<Node>>,
+    nodes: Rc<RefCell<CanonicalNodes>>,
+}
+
+struct CanonicalNodes {
+    /// One canonical leaf per possible cell state
+    leaves: Vec<Rc<Node>>,
+    inner: FxHashMap<(usize, usize, usize, usize), Rc<Node>>,
+    /// Bytes of the per-state counts of the inner nodes
+    state_count_bytes: usize,
+    /// Inner nodes created, and requests for ones already held
+    created: u64,
+    hits: u64,
+}
+
+impl Default for NodeCache {
+    fn default() -> Self {
+        NodeCache::new()
+    }
 }
 
 impl NodeCache {
-    fn new() -> Self {
-        NodeCache {
-            leaves: [
-                Rc::new(Node::leaf(false)),
-                Rc::new(Node::leaf(true)),
-            ],
-            inner_cache: HashMap::new(),
-            result_cache: HashMap::new(),
+    pub fn new() -> Self {
+        let nodes = CanonicalNodes {
+            leaves: (0..=State::MAX).map(|state| Rc::new(Node::leaf(state))).collect(),
+            inner: FxHashMap::with_capacity_and_hasher(INITIAL_CAPACITY, Default::default()),
+            state_count_bytes: 0,
+            created: 0,
+            hits: 0,
+        };
+        NodeCache { nodes: Rc::new(RefCell::new(nodes)) }
+    }
+
+    /// Number of canonical inner nodes stored
+    pub fn len(&self) -> usize {
+        self.nodes.borrow().inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether two handles refer to the same store
+    pub fn same_as(&self, other: &NodeCache) -> bool {
+        Rc::ptr_eq(&self.nodes, &other.nodes)
+    }
+
+    /// Remove the inner nodes only the cache refers to, returning how many
+    /// there were. Freeing them can leave their children unused in turn, for
+    /// the next sweep to find.
+    fn sweep(&self) -> usize {
+        let mut nodes = self.nodes.borrow_mut();
+        let unused: Vec<_> = nodes.inner.iter()
+            .filter(|(_, node)| Rc::strong_count(node) == 1)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in &unused {
+            if let Some(node) = nodes.inner.remove(key) {
+                nodes.state_count_bytes -= node.state_counts.as_ref().map_or(0, |counts| counts.len() * std::mem::size_of::<u128>());
+            }
         }
+        nodes.inner.shrink_to_fit();
+        unused.len()
     }
 
-    fn clear_result_cache(&mut self) {
-        self.result_cache.clear();
+    /// Approximate bytes held by the nodes and the table of inner nodes
+    pub(crate) fn heap_size(&self) -> usize {
+        let nodes = self.nodes.borrow();
+        // Each node is allocated with its two reference counts
+        let node_size = std::mem::size_of::<Node>() + 2 * std::mem::size_of::<usize>();
+        let leaf_counts: usize = nodes.leaves.iter()
+            .map(|leaf| leaf.state_counts.as_ref().map_or(0, |counts| counts.len() * std::mem::size_of::<u128>()))
+            .sum();
+        (nodes.leaves.len() + nodes.inner.len()) * node_size + leaf_counts + nodes.state_count_bytes
+            + nodes.leaves.capacity() * std::mem::size_of::<Rc<Node>>()
+            + table_size::<(usize, usize, usize, usize), Rc<Node>>(nodes.inner.capacity())
     }
 
-    fn get_leaf(&self, alive: bool) -> Rc<Node> {
-        self.leaves[alive as usize].clone()
+    fn get_leaf(&self, state: State) -> Rc<Node> {
+        self.nodes.borrow().leaves[state as usize].clone()
     }
 
     fn get_inner(&mut self, nw: Rc<Node>, ne: Rc<Node>, sw: Rc<Node>, se: Rc<Node>) -> Rc<Node> {
@@ -130,529 +351,2198 @@ impl NodeCache {
             Rc::as_ptr(&se) as usize,
         );
 
-        if let Some(node) = self.inner_cache.get(&key) {
-            return node.clone();
+        let mut nodes = self.nodes.borrow_mut();
+        if let Some(node) = nodes.inner.get(&key).cloned() {
+            nodes.hits += 1;
+            return node;
         }
 
+        nodes.created += 1;
         let node = Rc::new(Node::inner(nw, ne, sw, se));
-        self.inner_cache.insert(key, node.clone());
+        nodes.state_count_bytes += node.state_counts.as_ref().map_or(0, |counts| counts.len() * std::mem::size_of::<u128>());
+        nodes.inner.insert(key, node.clone());
         node
     }
 
     fn get_empty(&mut self, level: u8) -> Rc<Node> {
         if level == 0 {
-            return self.get_leaf(false);
+            return self.get_leaf(0);
         }
         let sub = self.get_empty(level - 1);
         self.get_inner(sub.clone(), sub.clone(), sub.clone(), sub.clone())
     }
+
+    /// Whether `node` is the canonical node with its children
+    #[cfg(any(debug_assertions, feature = "validate"))]
+    fn is_canonical(&self, node: &Rc<Node>) -> bool {
+        let key = node.children().map(|child| Rc::as_ptr(child) as usize);
+        self.nodes.borrow().inner.get(&(key[0], key[1], key[2], key[3])).is_some_and(|canonical| Rc::ptr_eq(canonical, node))
+    }
+}
+
+/// Default number of past generations kept for `step_back`
+pub const DEFAULT_HISTORY_LIMIT: usize = 256;
+
+/// Largest root level. A root this large holds every cell from
+/// `MIN_COORDINATE` to `MAX_COORDINATE` in both directions, and the position
+/// and size of every node in it fit in an i64. Stepping needs room around the
+/// pattern, so cells more than 2^59 from the origin are lost when stepping.
+pub const MAX_LEVEL: u8 = 62;
+
+/// Smallest x or y coordinate a cell of the plane can have
+pub const MIN_COORDINATE: i64 = -(1 << (MAX_LEVEL - 1));
+
+/// Largest x or y coordinate a cell of the plane can have
+pub const MAX_COORDINATE: i64 = (1 << (MAX_LEVEL - 1)) - 1;
+
+/// Every cell of the plane a universe can hold
+pub(crate) const EXTENT: Rect = Rect { left: MIN_COORDINATE, top: MIN_COORDINATE, width: 1 << MAX_LEVEL, height: 1 << MAX_LEVEL };
+
+/// Error returned by `Universe::try_set_cell_state`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoordinateError {
+    /// The cell lies beyond `MIN_COORDINATE` or `MAX_COORDINATE`
+    OutOfRange { x: i64, y: i64 },
+}
+
+impl std::fmt::Display for CoordinateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoordinateError::OutOfRange { x, y } => {
+                write!(f, "cell ({}, {}) is outside the plane ({} to {})", x, y, MIN_COORDINATE, MAX_COORDINATE)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CoordinateError {}
+
+/// Largest exponent accepted by `step_pow2`, which keeps the root's
+/// coordinates within i64 for any neighbourhood radius
+pub const MAX_STEP_EXPONENT: u32 = 47;
+
+/// Most generations `step_pow2` and `step_to` step one at a time on a finite
+/// grid without finding its pattern repeating. Once it repeats, whole periods
+/// are skipped, so a grid that settles reaches any generation.
+pub const MAX_FINITE_STEPS: u128 = 1 << 16;
+
+/// Number of newly memoized results per step above which adaptive stepping
+/// lowers the step size; it raises it again while steps need under a quarter of this
+pub const ADAPTIVE_WORK_BUDGET: usize = 1 << 16;
+
+/// A B0-free rule the stepper applies to the stored cells (see `Rule::complemented`)
+struct Phase {
+    /// Whether the cells written are stored complemented
+    output: bool,
+    rule: Rule,
+    /// 4x4 -> 2x2 lookup table for two-state radius-1 rules (see `Rule::block_table`)
+    block_table: Option<Box<[u8]>>,
+    /// The same rules in bit-parallel form, for 8x8 nodes (see `Rule::bit_rule`)
+    bit_rule: Option<BitRule>,
+}
+
+/// Error returned by `Universe::set_rule`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetRuleError {
+    /// The background is alive and the new rule has more than two states
+    LiveBackground,
+}
+
+impl std::fmt::Display for SetRuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetRuleError::LiveBackground => {
+                write!(f, "cannot switch to a multi-state rule while the background is alive")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SetRuleError {}
+
+/// Error returned by `Universe::step_to`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepToError {
+    /// The target generation is older than the oldest generation in the step history
+    InPast { target: u128, earliest: u128 },
+    /// A finite grid was asked for `generations` generations, but its pattern
+    /// did not repeat within the first `MAX_FINITE_STEPS` of them
+    TooManySteps { generations: u128 },
+}
+
+impl std::fmt::Display for StepToError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepToError::InPast { target, earliest } => {
+                write!(f, "generation {} is before the earliest recorded generation {}", target, earliest)
+            }
+            StepToError::TooManySteps { generations } => {
+                write!(f, "the finite grid does not repeat within {} of the {} generations asked for", MAX_FINITE_STEPS, generations)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StepToError {}
+
+/// A broken invariant of the quadtree, found by `Universe::validate`. Each
+/// names the level of the offending node.
+#[cfg(any(debug_assertions, feature = "validate"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TreeError {
+    /// The root is smaller than the 8x8 every universe has
+    RootTooSmall { level: u8 },
+    /// A child is not one level below its parent
+    ChildLevel { level: u8, child_level: u8 },
+    /// The population differs from the sum of the children's
+    Population { level: u8, population: u128, children: u128 },
+    /// The per-state counts differ from the sum of the children's
+    StateCounts { level: u8 },
+    /// The content hash differs from the one the children give
+    ContentHash { level: u8 },
+    /// The node is not the one the node cache holds for its children (or
+    /// its state, for a leaf), so equal subtrees are not shared
+    NotCanonical { level: u8 },
+    /// A cell outside the grid of a finite topology is not stored as 0
+    OutsideGrid { x: i64, y: i64 },
+}
+
+#[cfg(any(debug_assertions, feature = "validate"))]
+impl std::fmt::Display for TreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeError::RootTooSmall { level } => write!(f, "root of level {} is below level 3", level),
+            TreeError::ChildLevel { level, child_level } => {
+                write!(f, "node of level {} has a child of level {}", level, child_level)
+            }
+            TreeError::Population { level, population, children } => {
+                write!(f, "node of level {} has population {} but its children {}", level, population, children)
+            }
+            TreeError::StateCounts { level } => {
+                write!(f, "node of level {} has state counts differing from its children's", level)
+            }
+            TreeError::ContentHash { level } => {
+                write!(f, "node of level {} has a content hash differing from its children's", level)
+            }
+            TreeError::NotCanonical { level } => write!(f, "node of level {} is not the canonical copy", level),
+            TreeError::OutsideGrid { x, y } => write!(f, "cell ({}, {}) outside the grid is not stored as 0", x, y),
+        }
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "validate"))]
+impl std::error::Error for TreeError {}
+
+/// A saved position of a universe (see `Universe::snapshot`)
+pub(crate) struct Snapshot {
+    root: Rc<Node>,
+    generation: u128,
+    inverted: bool,
 }
 
 /// Main HashLife universe
 pub struct Universe {
     root: Rc<Node>,
     cache: NodeCache,
-    generation: u64,
+    /// Memoized `Universe::advance` results ((node pointer, phase, step exponent) -> (node, result node)).
+    /// Entries stay valid across steps while their node is canonical, so they
+    /// are dropped when the rule changes and when garbage collection frees the node.
+    /// The weak reference keeps a freed node's address from being reused
+    /// until the entry is dropped, even when another universe sharing the
+    /// cache freed it.
+    results: FxHashMap<(usize, u8, u8), MemoEntry>,
+    rule: Rule,
+    topology: Topology,
+    /// Rules applied to the stored cells, indexed by whether they are stored complemented
+    phases: Vec<Phase>,
+    generation: u128,
+    /// Whether the tree stores the complement of the real cells, because the
+    /// infinite background is alive (only possible with B0 rules)
+    inverted: bool,
+    /// Roots of previous generations (oldest first) with their generation and
+    /// inversion. Roots share structure through the node cache, so each entry is one pointer.
+    history: VecDeque<(Rc<Node>, u128, bool)>,
+    history_limit: usize,
+    /// `step_adaptive` advances 2^step_exponent generations
+    step_exponent: u32,
+    adaptive: bool,
+    /// Name, author and comments of the loaded pattern, written back on export
+    metadata: PatternMetadata,
+    /// Mirroring applied to `set_cell_state` and `paste`
+    edit_symmetry: Option<EditSymmetry>,
+    /// Population after each step, if recording is on
+    population_history: PopulationHistory,
+    /// Birth generations of live cells, if age tracking is on
+    ages: Option<CellAges>,
+    /// Where changes are written for crash recovery, if anywhere
+    journal: Option<Journal>,
+    /// Roots saved automatically as the universe runs
+    checkpoints: Checkpoints,
+    /// Keyframes of the run so far, if recording
+    timeline: Timeline,
+    /// Edits that can be undone and redone
+    undo: UndoStack,
+    memory_limit: MemoryLimit,
+    /// Memoized results found and computed, and garbage collections (the
+    /// node counts are the cache's)
+    counters: CacheCounters,
 }
 
 impl Universe {
-    /// Create a new empty universe
+    /// Create a new empty universe running Conway's Game of Life
     pub fn new(size_level: usize) -> Self {
-        let mut cache = NodeCache::new();
+        Universe::with_rule(size_level, Rule::conway())
+    }
+
+    /// Create a new empty universe running the given rule
+    pub fn with_rule(size_level: usize, rule: Rule) -> Self {
+        Universe::with_topology(size_level, rule, Topology::Plane)
+    }
+
+    /// Create a new empty universe running the given rule on the given topology.
+    /// Panics if a finite topology has a zero dimension.
+    pub fn with_topology(size_level: usize, rule: Rule, topology: Topology) -> Self {
+        Universe::with_cache(size_level, rule, topology, NodeCache::new())
+    }
+
+    /// Create a new empty universe that stores its nodes in `cache`, shared
+    /// with the universes already using it (see `NodeCache`). Memoized steps
+    /// are not shared, since they depend on the rule.
+    pub fn with_cache(size_level: usize, rule: Rule, topology: Topology, mut cache: NodeCache) -> Self {
+        topology.validate();
         let level = size_level.max(3) as u8;
         let root = cache.get_empty(level);
+        let phases = Universe::phases(&rule);
         
         Universe {
             root,
             cache,
+            results: FxHashMap::with_capacity_and_hasher(INITIAL_CAPACITY, Default::default()),
+            rule,
+            topology,
+            phases,
             generation: 0,
+            inverted: false,
+            history: VecDeque::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            step_exponent: 0,
+            adaptive: false,
+            metadata: PatternMetadata::default(),
+            edit_symmetry: None,
+            population_history: PopulationHistory::default(),
+            ages: None,
+            journal: None,
+            checkpoints: Checkpoints::default(),
+            timeline: Timeline::default(),
+            undo: UndoStack::default(),
+            memory_limit: MemoryLimit::default(),
+            counters: CacheCounters::default(),
         }
     }
 
-    /// Set a cell at the given coordinates
-    pub fn set_cell(&mut self, x: i64, y: i64, alive: bool) {
-        let size = 1i64 << self.root.level;
-        let half_size = size / 2;
-        
-        if x < -half_size || x >= half_size || y < -half_size || y >= half_size {
-            self.expand();
-            return self.set_cell(x, y, alive);
-        }
-        
-        let root = self.root.clone();
-        self.root = self.set_cell_recursive(&root, x, y, alive, -half_size, -half_size);
+    /// The universe's node cache, to clone for `with_cache`
+    pub fn node_cache(&self) -> &NodeCache {
+        &self.cache
     }
 
-    fn set_cell_recursive(&mut self, node: &Rc<Node>, x: i64, y: i64, alive: bool,
-                          node_x: i64, node_y: i64) -> Rc<Node> {
-        if node.level == 0 {
-            return self.cache.get_leaf(alive);
+    /// Approximate bytes used by the universe, by category (see `MemoryUsage`)
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let history = self.history.capacity() * std::mem::size_of::<(Rc<Node>, u128, bool)>()
+            + self.checkpoints.heap_size() + self.timeline.heap_size() + self.undo.heap_size();
+        let tables: usize = self.phases.iter()
+            .map(|phase| phase.block_table.as_ref().map_or(0, |table| table.len()))
+            .sum();
+        MemoryUsage {
+            nodes: self.cache.heap_size(),
+            results: table_size::<(usize, u8, u8), MemoEntry>(self.results.capacity()),
+            history,
+            other: tables + self.population_history.heap_size() + self.ages.as_ref().map_or(0, CellAges::heap_size),
         }
+    }
 
-        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
-            unreachable!();
-        };
+    /// How often the caches have been hit and missed so far, and how often
+    /// garbage was collected (see `CacheCounters`)
+    pub fn cache_counters(&self) -> CacheCounters {
+        let nodes = self.cache.nodes.borrow();
+        CacheCounters { nodes_created: nodes.created, node_hits: nodes.hits, ..self.counters }
+    }
 
-        let half_size = 1i64 << (node.level - 1);
-        let mid_x = node_x + half_size;
-        let mid_y = node_y + half_size;
+    /// Keep the universe within about `bytes` of memory as measured by
+    /// `memory_usage`, or let it grow without limit (None). See the `memory` module.
+    /// On a node cache shared with other universes, the nodes they use
+    /// (including those their memoized results hold) count towards the limit
+    /// but are not freed, so the limit holds only as far as they allow.
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+        self.memory_limit.set(bytes);
+    }
 
-        if x < mid_x && y < mid_y {
-            let new_nw = self.set_cell_recursive(nw, x, y, alive, node_x, node_y);
-            self.cache.get_inner(new_nw, ne.clone(), sw.clone(), se.clone())
-        } else if x >= mid_x && y < mid_y {
-            let new_ne = self.set_cell_recursive(ne, x, y, alive, mid_x, node_y);
-            self.cache.get_inner(nw.clone(), new_ne, sw.clone(), se.clone())
-        } else if x < mid_x && y >= mid_y {
-            let new_sw = self.set_cell_recursive(sw, x, y, alive, node_x, mid_y);
-            self.cache.get_inner(nw.clone(), ne.clone(), new_sw, se.clone())
-        } else {
-            let new_se = self.set_cell_recursive(se, x, y, alive, mid_x, mid_y);
-            self.cache.get_inner(nw.clone(), ne.clone(), sw.clone(), new_se)
-        }
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit.bytes()
     }
 
-    /// Get cell value at coordinates
-    pub fn get_cell(&self, x: i64, y: i64) -> bool {
-        let size = 1i64 << self.root.level;
-        let half_size = size / 2;
-        
-        if x < -half_size || x >= half_size || y < -half_size || y >= half_size {
-            return false;
+    /// Free the canonical nodes that no universe, history, checkpoint or
+    /// memoized result uses any more, with this universe's memoized results
+    /// of the freed nodes. Other universes sharing the node cache drop theirs
+    /// when they next collect garbage.
+    pub fn collect_garbage(&mut self) {
+        self.counters.collections += 1;
+        loop {
+            self.results.retain(|_, (node, _)| node.strong_count() > 0);
+            if self.cache.sweep() == 0 {
+                break;
+            }
         }
-        
-        self.get_cell_recursive(&self.root, x, y, -half_size, -half_size)
+        self.results.shrink_to_fit();
     }
 
-    fn get_cell_recursive(&self, node: &Rc<Node>, x: i64, y: i64, 
-                          node_x: i64, node_y: i64) -> bool {
-        if node.level == 0 {
-            return node.is_alive();
+    /// Collect garbage if the universe is over its memory limit, and drop
+    /// every memoized result if that is not enough
+    fn enforce_memory_limit(&mut self) {
+        if self.memory_limit.is_over(self.memory_usage().total()) {
+            self.collect_garbage();
+            if self.memory_limit.is_over(self.memory_usage().total()) {
+                self.results = FxHashMap::with_capacity_and_hasher(INITIAL_CAPACITY, Default::default());
+                self.collect_garbage();
+            }
         }
+        self.memory_limit.checked(self.cache.len(), self.memory_usage().total());
+    }
 
-        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
-            unreachable!();
-        };
+    /// An independent copy of the universe to try things out on, such as
+    /// adding a glider and running on, without disturbing this one. The copy
+    /// shares the original's node cache, so it costs nothing until it
+    /// changes, and starts with its memoized steps, so it runs through
+    /// patterns the original has already run without computing them again.
+    /// Settings, the step history, checkpoints, the timeline and the undo
+    /// stack are copied; the journal is not.
+    pub fn fork(&self) -> Universe {
+        Universe {
+            root: self.root.clone(),
+            cache: self.cache.clone(),
+            results: self.results.clone(),
+            rule: self.rule.clone(),
+            topology: self.topology,
+            phases: Universe::phases(&self.rule),
+            generation: self.generation,
+            inverted: self.inverted,
+            history: self.history.clone(),
+            history_limit: self.history_limit,
+            step_exponent: self.step_exponent,
+            adaptive: self.adaptive,
+            metadata: self.metadata.clone(),
+            edit_symmetry: self.edit_symmetry,
+            population_history: self.population_history.clone(),
+            ages: self.ages.clone(),
+            journal: None,
+            checkpoints: self.checkpoints.clone(),
+            timeline: self.timeline.clone(),
+            undo: self.undo.clone(),
+            memory_limit: self.memory_limit,
+            counters: self.counters,
+        }
+    }
 
-        let half_size = 1i64 << (node.level - 1);
-        let mid_x = node_x + half_size;
-        let mid_y = node_y + half_size;
+    fn phases(rule: &Rule) -> Vec<Phase> {
+        [false, true].into_iter()
+            .map(|input| {
+                let output = rule.next_background(input);
+                let rule = rule.complemented(input, output);
+                Phase { output, block_table: rule.block_table(), bit_rule: rule.bit_rule(), rule }
+            })
+            .collect()
+    }
 
-        if x < mid_x && y < mid_y {
-            self.get_cell_recursive(nw, x, y, node_x, node_y)
-        } else if x >= mid_x && y < mid_y {
-            self.get_cell_recursive(ne, x, y, mid_x, node_y)
-        } else if x < mid_x && y >= mid_y {
-            self.get_cell_recursive(sw, x, y, node_x, mid_y)
-        } else {
-            self.get_cell_recursive(se, x, y, mid_x, mid_y)
+    /// Switch to a different rule from the current generation on. Memoized
+    /// results of the old rule are dropped, and so are the step history and
+    /// checkpoints. A timeline starts over from the current generation.
+    /// Fails if the background is alive and the new rule has more than two
+    /// states, since Generations decay of an infinite background cannot be stored.
+    pub fn set_rule(&mut self, rule: Rule) -> Result<(), SetRuleError> {
+        if self.inverted && rule.states() > 2 {
+            return Err(SetRuleError::LiveBackground);
         }
+        self.phases = Universe::phases(&rule);
+        self.rule = rule;
+        self.results.clear();
+        self.history.clear();
+        self.checkpoints.clear();
+        self.reset_timeline(self.timeline.limit());
+        self.interrupt_adaptive();
+        self.journal(|universe| JournalEntry::Rule(universe.rule.clone()));
+        Ok(())
     }
 
-    fn expand(&mut self) {
-        let empty = self.cache.get_empty(self.root.level - 1);
-        let NodeContent::Inner { nw, ne, sw, se, .. } = &self.root.content else {
-            unreachable!();
-        };
-
-        let new_nw = self.cache.get_inner(
-            empty.clone(), empty.clone(),
-            empty.clone(), nw.clone()
-        );
-        let new_ne = self.cache.get_inner(
-            empty.clone(), empty.clone(),
-            ne.clone(), empty.clone()
-        );
-        let new_sw = self.cache.get_inner(
-            empty.clone(), sw.clone(),
-            empty.clone(), empty.clone()
-        );
-        let new_se = self.cache.get_inner(
-            se.clone(), empty.clone(),
-            empty.clone(), empty.clone()
-        );
+    /// Let `step_adaptive` pick the step size: it doubles while supersteps stay
+    /// cheap (most results come from the memo cache) and halves when a step
+    /// computes more than `ADAPTIVE_WORK_BUDGET` new results. Edits, rule
+    /// changes and `step_back` drop it back to single generations.
+    pub fn set_adaptive_stepping(&mut self, enabled: bool) {
+        self.adaptive = enabled;
+        self.step_exponent = 0;
+    }
 
-        self.root = self.cache.get_inner(new_nw, new_ne, new_sw, new_se);
+    pub fn is_adaptive_stepping(&self) -> bool {
+        self.adaptive
     }
 
-    /// Step forward in time by exactly one generation
-    /// This ensures proper step-by-step progression for UI display
-    pub fn step(&mut self) {
-        while self.root.level < 3 {
-            if self.root.population == 0 {
-                self.generation += 1;
-                return;
-            }
-            self.expand();
-        }
+    /// Use a fixed step of `2^exponent` generations for `step_adaptive` (until
+    /// adaptive stepping changes it)
+    pub fn set_step_exponent(&mut self, exponent: u32) {
+        self.step_exponent = exponent.min(MAX_STEP_EXPONENT);
+    }
 
-        // Clear result cache before each step since cell states change
-        self.cache.clear_result_cache();
+    /// `step_adaptive` currently advances `2^step_exponent()` generations
+    pub fn step_exponent(&self) -> u32 {
+        self.step_exponent
+    }
 
-        let root = self.root.clone();
-        let result = self.next_generation_single(&root);
-        
-        // The result is at level (root.level - 1), representing the center portion
-        // We need to embed it back at the original level with empty borders
-        
-        // Extract quadrants from the result (each at level result.level - 1)
-        let NodeContent::Inner { nw: r_nw, ne: r_ne, sw: r_sw, se: r_se, .. } = &result.content else {
-            unreachable!();
+    /// Advance `2^step_exponent()` generations, then adjust the step size if
+    /// adaptive stepping is on. On a finite grid a step is at most
+    /// `MAX_FINITE_STEPS` generations.
+    pub fn step_adaptive(&mut self) {
+        let cached = self.results.len();
+        let exponent = if self.topology.is_infinite() {
+            self.step_exponent
+        } else {
+            self.step_exponent.min(MAX_FINITE_STEPS.ilog2())
         };
-        
-        // Create empty border at the same level as result's quadrants
-        let border = self.cache.get_empty(result.level - 1);
-        
-        // Build new quadrants at level result.level by adding borders
-        let new_nw = self.cache.get_inner(border.clone(), border.clone(), border.clone(), r_nw.clone());
-        let new_ne = self.cache.get_inner(border.clone(), border.clone(), r_ne.clone(), border.clone());
-        let new_sw = self.cache.get_inner(border.clone(), r_sw.clone(), border.clone(), border.clone());
-        let new_se = self.cache.get_inner(r_se.clone(), border.clone(), border.clone(), border.clone());
-        
-        // Combine into new root at original level
-        self.root = self.cache.get_inner(new_nw, new_ne, new_sw, new_se);
-        self.generation += 1;
+        self.step_pow2(exponent).expect("finite grids step at most MAX_FINITE_STEPS generations");
+        if !self.adaptive {
+            return;
+        }
+        let computed = self.results.len().saturating_sub(cached);
+        if computed > ADAPTIVE_WORK_BUDGET {
+            self.step_exponent = self.step_exponent.saturating_sub(1);
+        } else if computed <= ADAPTIVE_WORK_BUDGET / 4 && self.step_exponent < MAX_STEP_EXPONENT {
+            self.step_exponent += 1;
+        }
     }
 
-    /// Compute the next generation advancing by exactly 1 step
-    /// This always advances by exactly 1 generation
-    fn next_generation_single(&mut self, node: &Rc<Node>) -> Rc<Node> {
-        if node.level == 2 {
-            // Base case: compute_level2 advances by 1 generation
-            return self.compute_level2(node);
+    /// Drop adaptive stepping back to single generations after user interaction
+    fn interrupt_adaptive(&mut self) {
+        if self.adaptive {
+            self.step_exponent = 0;
         }
+    }
 
-        // Check if we already computed the result for this node
-        let node_key = Rc::as_ptr(node) as usize;
-        if let Some(cached_result) = self.cache.result_cache.get(&node_key) {
-            return cached_result.clone();
+    /// Set how many past generations are kept for `step_back` (0 disables history)
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
         }
+        self.journal(|_| JournalEntry::HistoryLimit(limit));
+    }
 
-        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
-            unreachable!();
-        };
+    pub fn history_limit(&self) -> usize {
+        self.history_limit
+    }
 
-        // For level > 2, we need to compute 1 generation for a result at level (node.level - 1)
-        // We do this by applying the level-2 computation to the 9 overlapping level-2 regions
-        
-        // Get the 9 overlapping level-2 subnodes that cover the interior
-        let center_nw_ne = self.center_subnode_horizontal(nw, ne);
-        let center_nw_sw = self.center_subnode_vertical(nw, sw);
-        let center_ne_se = self.center_subnode_vertical(ne, se);
-        let center_sw_se = self.center_subnode_horizontal(sw, se);
-        let center = self.center_node(node);
+    /// Number of generations that can currently be undone with `step_back`
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
 
-        // Recursively compute 1 generation for each of the 9 regions
-        let n00 = self.next_generation_single(nw);
-        let n01 = self.next_generation_single(&center_nw_ne);
-        let n02 = self.next_generation_single(ne);
-        let n10 = self.next_generation_single(&center_nw_sw);
-        let n11 = self.next_generation_single(&center);
-        let n12 = self.next_generation_single(&center_ne_se);
-        let n20 = self.next_generation_single(sw);
-        let n21 = self.next_generation_single(&center_sw_se);
-        let n22 = self.next_generation_single(se);
-
-        // Now assemble these 9 results into a result at level (node.level - 1)
-        // Each of the 9 results is at level (node.level - 2)
-        // We need to extract their inner quarters and combine them
-        
-        // Helper to get quadrant subnodes
-        fn get_quadrants(node: &Rc<Node>) -> (Rc<Node>, Rc<Node>, Rc<Node>, Rc<Node>) {
-            if let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content {
-                (nw.clone(), ne.clone(), sw.clone(), se.clone())
-            } else {
-                unreachable!()
-            }
-        }
+    /// Drop all recorded history
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
 
-        let (_, _, _, n00_se) = get_quadrants(&n00);
-        let (_, _, n01_sw, n01_se) = get_quadrants(&n01);
-        let (_, _, n02_sw, _) = get_quadrants(&n02);
-        
-        let (_, n10_ne, _, n10_se) = get_quadrants(&n10);
-        let (n11_nw, n11_ne, n11_sw, n11_se) = get_quadrants(&n11);
-        let (n12_nw, _, n12_sw, _) = get_quadrants(&n12);
-        
-        let (_, n20_ne, _, _) = get_quadrants(&n20);
-        let (n21_nw, n21_ne, _, _) = get_quadrants(&n21);
-        let (n22_nw, _, _, _) = get_quadrants(&n22);
+    /// Go back to the state before the most recent `step`.
+    /// Edits made since that step are discarded along with it.
+    /// Returns false if there is no history to rewind.
+    pub fn step_back(&mut self) -> bool {
+        if !self.rewind() {
+            return false;
+        }
+        self.journal(|_| JournalEntry::StepBack);
+        true
+    }
 
-        // Build result quadrants at level (node.level - 2)
-        let result_nw = self.cache.get_inner(n00_se, n01_sw, n10_ne, n11_nw);
-        let result_ne = self.cache.get_inner(n01_se, n02_sw, n11_ne, n12_nw);
-        let result_sw = self.cache.get_inner(n10_se, n11_sw, n20_ne, n21_nw);
-        let result_se = self.cache.get_inner(n11_se, n12_sw, n21_ne, n22_nw);
+    fn rewind(&mut self) -> bool {
+        if let Some(journal) = &mut self.journal {
+            journal.rewound(self.history.len());
+        }
+        let Some((root, generation, inverted)) = self.history.pop_back() else {
+            return false;
+        };
+        self.root = root;
+        self.generation = generation;
+        self.inverted = inverted;
+        self.interrupt_adaptive();
+        self.record_stats();
+        true
+    }
 
-        // Return result at level (node.level - 1)
-        let result = self.cache.get_inner(result_nw, result_ne, result_sw, result_se);
-        
-        // Cache the result for this node
-        self.cache.result_cache.insert(node_key, result.clone());
-        
-        result
+    /// The root and background before the most recent step, if it is in the history
+    pub(crate) fn previous_root(&self) -> Option<(&Rc<Node>, State)> {
+        self.history.back().map(|(root, _, inverted)| (root, *inverted as State))
     }
 
-    fn center_node(&mut self, node: &Rc<Node>) -> Rc<Node> {
-        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
-            unreachable!();
-        };
+    pub(crate) fn record_history(&mut self) {
+        self.push_history(self.snapshot());
+    }
 
-        let NodeContent::Inner { se: nw_se, .. } = &nw.content else { unreachable!(); };
-        let NodeContent::Inner { sw: ne_sw, .. } = &ne.content else { unreachable!(); };
-        let NodeContent::Inner { ne: sw_ne, .. } = &sw.content else { unreachable!(); };
-        let NodeContent::Inner { nw: se_nw, .. } = &se.content else { unreachable!(); };
+    /// Add an earlier position to the step history
+    fn push_history(&mut self, snapshot: Snapshot) {
+        if self.history_limit == 0 {
+            return;
+        }
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+        }
+        self.history.push_back((snapshot.root, snapshot.generation, snapshot.inverted));
+        if let Some(journal) = &mut self.journal {
+            journal.recorded();
+        }
+    }
 
-        self.cache.get_inner(
-            nw_se.clone(),
-            ne_sw.clone(),
-            sw_ne.clone(),
-            se_nw.clone(),
-        )
+    /// Take a checkpoint after each step that ends `interval` after the last
+    /// one, starting with one now, or stop taking them (see the `checkpoint`
+    /// module). Checkpoints already taken are kept.
+    pub fn set_checkpoint_interval(&mut self, interval: Option<CheckpointInterval>) {
+        self.checkpoints.set_interval(interval);
+        if interval.is_some() {
+            self.checkpoints.save(self.root.clone(), self.generation, self.inverted, checkpoint::now());
+        }
     }
 
-    fn center_subnode_horizontal(&mut self, left: &Rc<Node>, right: &Rc<Node>) -> Rc<Node> {
-        let NodeContent::Inner { ne: left_ne, se: left_se, .. } = &left.content else { unreachable!(); };
-        let NodeContent::Inner { nw: right_nw, sw: right_sw, .. } = &right.content else { unreachable!(); };
+    pub fn checkpoint_interval(&self) -> Option<CheckpointInterval> {
+        self.checkpoints.interval()
+    }
 
-        self.cache.get_inner(
-            left_ne.clone(),
-            right_nw.clone(),
-            left_se.clone(),
-            right_sw.clone(),
-        )
+    /// Keep at most `limit` checkpoints, dropping the oldest first
+    /// (`DEFAULT_CHECKPOINT_LIMIT` unless set)
+    pub fn set_checkpoint_limit(&mut self, limit: usize) {
+        self.checkpoints.set_limit(limit);
     }
 
-    fn center_subnode_vertical(&mut self, top: &Rc<Node>, bottom: &Rc<Node>) -> Rc<Node> {
-        let NodeContent::Inner { sw: top_sw, se: top_se, .. } = &top.content else { unreachable!(); };
-        let NodeContent::Inner { nw: bottom_nw, ne: bottom_ne, .. } = &bottom.content else { unreachable!(); };
+    pub fn checkpoint_limit(&self) -> usize {
+        self.checkpoints.limit()
+    }
 
-        self.cache.get_inner(
-            top_sw.clone(),
-            top_se.clone(),
-            bottom_nw.clone(),
-            bottom_ne.clone(),
-        )
+    /// The checkpoints kept, oldest first
+    pub fn checkpoints(&self) -> Vec<Checkpoint> {
+        self.checkpoints.list()
     }
 
-    fn compute_level2(&mut self, node: &Rc<Node>) -> Rc<Node> {
-        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
-            unreachable!();
+    /// Go back (or forward) to the checkpoint numbered `id`. The jump is one
+    /// entry in the step history, so `step_back` undoes it, and the next
+    /// checkpoint is due an interval after this one. Returns false if there
+    /// is no such checkpoint.
+    pub fn restore_checkpoint(&mut self, id: u64) -> bool {
+        let Some((root, generation, inverted)) = self.checkpoints.get(id) else {
+            return false;
         };
+        self.record_history();
+        self.root = root;
+        self.generation = generation;
+        self.inverted = inverted;
+        self.interrupt_adaptive();
+        self.record_stats();
+        self.journal_state();
+        true
+    }
 
-        // Extract 16 cells from 4x4 area
-        let mut cells = [[false; 4]; 4];
-        self.extract_2x2(nw, &mut cells, 0, 0);
-        self.extract_2x2(ne, &mut cells, 2, 0);
-        self.extract_2x2(sw, &mut cells, 0, 2);
-        self.extract_2x2(se, &mut cells, 2, 2);
-
-        // Apply Conway's rules to center 2x2 area
-        let mut result = [[false; 2]; 2];
-        for y in 0..2 {
-            for x in 0..2 {
-                let cx = x + 1;
-                let cy = y + 1;
-                let neighbors = self.count_neighbors_array(&cells, cx, cy);
-                result[y][x] = match (cells[cy][cx], neighbors) {
-                    (true, 2) | (true, 3) => true,
-                    (false, 3) => true,
-                    _ => false,
-                };
-            }
+    /// Record a timeline of at most `limit` keyframes from the current
+    /// generation on, for `scrub_to` (see the `timeline` module), or stop
+    /// recording with 0. Any earlier timeline is dropped.
+    pub fn set_timeline_limit(&mut self, limit: usize) {
+        self.reset_timeline(limit);
+    }
+
+    pub fn timeline_limit(&self) -> usize {
+        self.timeline.limit()
+    }
+
+    /// Generations of the timeline's keyframes, oldest first
+    pub fn timeline_keyframes(&self) -> Vec<u128> {
+        self.timeline.generations()
+    }
+
+    /// Fewest generations between keyframes recorded from now on
+    pub fn timeline_interval(&self) -> u128 {
+        self.timeline.interval()
+    }
+
+    /// The first and last generations of the timeline, which `scrub_to` can
+    /// reach, or None if it is not recording
+    pub fn timeline_range(&self) -> Option<(u128, u128)> {
+        self.timeline.range()
+    }
+
+    /// Go to any generation of the timeline, rebuilding it from the keyframe
+    /// before it. The jump is one entry in the step history, so `step_back`
+    /// undoes it; stepping on from an earlier generation follows the recorded
+    /// run, and editing ends the timeline there.
+    pub fn scrub_to(&mut self, generation: u128) -> Result<(), TimelineError> {
+        let (keyframe, root, inverted) = self.timeline.keyframe(generation)?;
+        self.record_history();
+        self.root = root;
+        self.generation = keyframe;
+        self.inverted = inverted;
+        self.advance_generations(generation - keyframe);
+        self.interrupt_adaptive();
+        self.record_stats();
+        self.journal_state();
+        Ok(())
+    }
+
+    fn reset_timeline(&mut self, limit: usize) {
+        self.timeline.reset(limit, self.generation, self.root.clone(), self.inverted);
+    }
+
+    /// Take a checkpoint or timeline keyframe if one is due after a step
+    pub(crate) fn record_progress(&mut self) {
+        let time = checkpoint::now();
+        if self.checkpoints.is_due(self.generation, time) {
+            self.checkpoints.save(self.root.clone(), self.generation, self.inverted, time);
         }
+        self.timeline.stepped(self.generation, &self.root, self.inverted);
+    }
 
-        // Build result node (level 1 = 2x2)
-        let r_nw = self.cache.get_leaf(result[0][0]);
-        let r_ne = self.cache.get_leaf(result[0][1]);
-        let r_sw = self.cache.get_leaf(result[1][0]);
-        let r_se = self.cache.get_leaf(result[1][1]);
+    /// Record the population after each step, keeping at most `limit` samples
+    /// (0 stops recording). Samples are thinned out to every second one when
+    /// the limit is reached, so a long run keeps an evenly spaced record.
+    /// Starts over from the current generation.
+    pub fn set_population_history_limit(&mut self, limit: usize) {
+        self.population_history.reset(limit);
+        self.record_stats();
+    }
 
-        self.cache.get_inner(r_nw, r_ne, r_sw, r_se)
+    pub fn population_history_limit(&self) -> usize {
+        self.population_history.limit()
     }
 
-    fn extract_2x2(&self, node: &Rc<Node>, cells: &mut [[bool; 4]; 4], 
-                   offset_x: usize, offset_y: usize) {
-        if node.level == 0 {
-            cells[offset_y][offset_x] = node.is_alive();
-        } else {
-            let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
-                unreachable!();
-            };
-            self.extract_2x2(nw, cells, offset_x, offset_y);
-            self.extract_2x2(ne, cells, offset_x + 1, offset_y);
-            self.extract_2x2(sw, cells, offset_x, offset_y + 1);
-            self.extract_2x2(se, cells, offset_x + 1, offset_y + 1);
+    /// `(generation, population)` samples recorded so far, oldest first.
+    /// Stepping back drops the samples after the generation returned to.
+    pub fn population_history(&self) -> &[(u128, u64)] {
+        self.population_history.samples()
+    }
+
+    /// Track how many generations each live (non-background) cell has been
+    /// alive, for colouring cells by age. This visits every live cell after
+    /// each step. A cell alive before and after a superstep counts as alive
+    /// throughout it, and cells set by hand are newborn until the next step.
+    /// Turning tracking on counts every cell alive now as newborn.
+    pub fn set_age_tracking(&mut self, enabled: bool) {
+        self.ages = enabled.then(CellAges::default);
+        self.record_stats();
+    }
+
+    pub fn is_age_tracking(&self) -> bool {
+        self.ages.is_some()
+    }
+
+    /// Generations the cell has been alive, or None if it is dead or ages
+    /// are not tracked
+    pub fn cell_age(&self, x: i64, y: i64) -> Option<u128> {
+        let ages = self.ages.as_ref()?;
+        if self.get_cell_state(x, y) == self.background() {
+            return None;
         }
+        Some(ages.born(x, y).map_or(0, |born| self.generation.saturating_sub(born)))
     }
 
-    fn count_neighbors_array(&self, cells: &[[bool; 4]; 4], x: usize, y: usize) -> u8 {
-        let mut count = 0;
-        for dy in -1..=1i32 {
-            for dx in -1..=1i32 {
-                if dx == 0 && dy == 0 {
-                    continue;
-                }
-                let nx = (x as i32 + dx) as usize;
-                let ny = (y as i32 + dy) as usize;
-                if nx < 4 && ny < 4 && cells[ny][nx] {
-                    count += 1;
-                }
+    /// `(x, y, age)` of each live cell in `rect`, sorted by position, or
+    /// nothing if ages are not tracked
+    pub fn cell_ages(&self, rect: Rect) -> Vec<(i64, i64, u128)> {
+        let Some(ages) = &self.ages else {
+            return Vec::new();
+        };
+        let mut cells: Vec<_> = self.cells_in(rect).into_iter()
+            .map(|(x, y, _)| (x, y, ages.born(x, y).map_or(0, |born| self.generation.saturating_sub(born))))
+            .collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    /// Bring the population history and cell ages up to date after a step
+    fn record_stats(&mut self) {
+        let population = self.population();
+        self.population_history.record(self.generation, population);
+        if self.ages.is_some() {
+            let live_cells = self.live_cells();
+            if let Some(ages) = &mut self.ages {
+                ages.update(self.generation, live_cells.into_iter().map(|(x, y, _)| (x, y)));
             }
         }
-        count
     }
 
-    pub fn generation(&self) -> u64 {
-        self.generation
+    /// Set a cell at the given coordinates
+    pub fn set_cell(&mut self, x: i64, y: i64, alive: bool) {
+        self.set_cell_state(x, y, alive as State);
     }
 
-    pub fn population(&self) -> u64 {
-        self.root.population
+    /// Set the state of a cell at the given coordinates (0 = dead), and of its
+    /// mirror images if an edit symmetry is set.
+    /// On a finite grid with joined edges the coordinates wrap around (see
+    /// `Topology::map`); cells beyond the edges of a bounded grid, or beyond
+    /// `MIN_COORDINATE` and `MAX_COORDINATE` on the plane, cannot be changed.
+    pub fn set_cell_state(&mut self, x: i64, y: i64, state: State) {
+        let before = self.root.clone();
+        let images = match self.edit_symmetry {
+            Some(symmetry) => symmetry.images(x, y),
+            None => vec![(x, y)],
+        };
+        for (x, y) in images {
+            let Some((x, y)) = self.topology.map(x, y).filter(|&(x, y)| EXTENT.contains(x, y)) else {
+                continue;
+            };
+            self.interrupt_adaptive();
+            let state = self.to_stored(state);
+            self.set_stored_state(x, y, state);
+        }
+        self.edited(&before);
     }
 
-    /// Get the root level of the universe (for determining node sizes)
-    pub fn root_level(&self) -> u8 {
-        self.root.level
+    /// Set a cell as `set_cell_state` does, or fail if the plane cannot hold it
+    pub fn try_set_cell_state(&mut self, x: i64, y: i64, state: State) -> Result<(), CoordinateError> {
+        if self.topology.is_infinite() && !EXTENT.contains(x, y) {
+            return Err(CoordinateError::OutOfRange { x, y });
+        }
+        self.set_cell_state(x, y, state);
+        Ok(())
     }
 
-    /// Collect renderable regions from the quadtree.
-    /// This uses the tree structure to efficiently skip empty regions and aggregate small areas.
-    /// 
-    /// Returns a vector of (x, y, size, density) tuples where:
-    /// - x, y: world coordinates of the top-left corner of the region
-    /// - size: the side length of the region in world units
-    /// - density: population divided by area (0.0 to 1.0)
-    /// 
-    /// min_render_size: minimum region size in world units to render
-    /// (smaller nodes will be aggregated as density)
-    pub fn collect_render_regions(
-        &self,
-        view_x_min: i64,
-        view_y_min: i64,
-        view_x_max: i64,
-        view_y_max: i64,
-        min_render_size: u32,
-    ) -> Vec<(i64, i64, u32, f32)> {
-        let mut result = Vec::new();
-        let size = 1i64 << self.root.level;
-        let half_size = size / 2;
-        
-        self.collect_render_regions_recursive(
-            &self.root,
-            -half_size,
-            -half_size,
-            view_x_min,
-            view_y_min,
-            view_x_max,
-            view_y_max,
-            min_render_size,
-            &mut result,
-        );
-        
-        result
+    /// Mirror later `set_cell_state` and `paste` calls with the given
+    /// symmetry, or stop mirroring them. Panics if the symmetry swaps the
+    /// axes and only one coordinate of its centre lies between cells.
+    pub fn set_edit_symmetry(&mut self, symmetry: Option<EditSymmetry>) {
+        if let Some(EditSymmetry { symmetry, centre: (x, y) }) = symmetry {
+            assert!(!symmetry.swaps_axes() || (x - y) % 2 == 0, "{:?} needs a centre on a cell or a corner", symmetry);
+        }
+        self.edit_symmetry = symmetry;
     }
 
-    fn collect_render_regions_recursive(
-        &self,
-        node: &Rc<Node>,
-        node_x: i64,
-        node_y: i64,
-        view_x_min: i64,
-        view_y_min: i64,
-        view_x_max: i64,
-        view_y_max: i64,
-        min_render_size: u32,
-        result: &mut Vec<(i64, i64, u32, f32)>,
-    ) {
-        // Skip if node is completely empty
-        if node.population == 0 {
+    pub fn edit_symmetry(&self) -> Option<EditSymmetry> {
+        self.edit_symmetry
+    }
+
+    /// Set many cells at once, as `set_cell_state` would one at a time but
+    /// rebuilding each changed node only once. If a cell is given more than
+    /// once, the last state wins.
+    pub fn set_cell_states(&mut self, cells: &[(i64, i64, State)]) {
+        let mut cells: Vec<_> = cells.iter()
+            .filter_map(|&(x, y, state)| self.topology.map(x, y).map(|(x, y)| (x, y, self.to_stored(state))))
+            .filter(|&(x, y, _)| EXTENT.contains(x, y))
+            .collect();
+        if cells.is_empty() {
             return;
         }
+        self.interrupt_adaptive();
+        let fits = |level: u8| {
+            let half_size = 1i64 << (level - 1);
+            cells.iter().all(|&(x, y, _)| (-half_size..half_size).contains(&x) && (-half_size..half_size).contains(&y))
+        };
+        while !fits(self.root.level) {
+            self.expand();
+        }
+        let half_size = 1i64 << (self.root.level - 1);
+        let root = self.root.clone();
+        self.root = self.set_cells_recursive(&root, &mut cells, -half_size, -half_size);
+        self.edited(&root);
+    }
 
-        let node_size = 1i64 << node.level;
-        let node_x_max = node_x + node_size;
-        let node_y_max = node_y + node_size;
+    /// A node of the given level centred on the origin holding only `cells`,
+    /// whose states are stored as given
+    pub(crate) fn build_node(&mut self, cells: &mut [(i64, i64, State)], level: u8) -> Rc<Node> {
+        let empty = self.cache.get_empty(level);
+        let half_size = 1i64 << (level - 1);
+        self.set_cells_recursive(&empty, cells, -half_size, -half_size)
+    }
 
-        // Skip if node is completely outside the view
-        if node_x >= view_x_max || node_x_max <= view_x_min ||
-           node_y >= view_y_max || node_y_max <= view_y_min {
-            return;
+    fn set_cells_recursive(&mut self, node: &Rc<Node>, cells: &mut [(i64, i64, State)],
+                           node_x: i64, node_y: i64) -> Rc<Node> {
+        if cells.is_empty() {
+            return node.clone();
+        }
+        if node.level == 0 {
+            return self.cache.get_leaf(cells[cells.len() - 1].2);
         }
 
-        // If node is small enough or is a leaf, emit it as a rendered region
-        if node_size as u32 <= min_render_size || node.level == 0 {
-            let area = (node_size * node_size) as f32;
-            let density = node.population as f32 / area;
-            result.push((node_x, node_y, node_size as u32, density));
-            return;
+        let half_size = 1i64 << (node.level - 1);
+        let quadrant = |&(x, y, _): &(i64, i64, State)| {
+            2 * (y >= node_y + half_size) as usize + (x >= node_x + half_size) as usize
+        };
+        // A stable sort keeps repeated cells in order
+        cells.sort_by_key(quadrant);
+        let mut children = node.children().map(Rc::clone);
+        let mut rest = cells;
+        for (i, child) in children.iter_mut().enumerate() {
+            let count = rest.iter().take_while(|cell| quadrant(cell) == i).count();
+            let (these, others) = rest.split_at_mut(count);
+            let (x, y) = (node_x + half_size * (i % 2) as i64, node_y + half_size * (i / 2) as i64);
+            *child = self.set_cells_recursive(child, these, x, y);
+            rest = others;
+        }
+        let [nw, ne, sw, se] = children;
+        self.cache.get_inner(nw, ne, sw, se)
+    }
+
+    fn set_stored_state(&mut self, x: i64, y: i64, state: State) {
+        let size = 1i64 << self.root.level;
+        let half_size = size / 2;
+        
+        if x < -half_size || x >= half_size || y < -half_size || y >= half_size {
+            self.expand();
+            return self.set_stored_state(x, y, state);
+        }
+        
+        let root = self.root.clone();
+        self.root = self.set_cell_recursive(&root, x, y, state, -half_size, -half_size);
+    }
+
+    fn set_cell_recursive(&mut self, node: &Rc<Node>, x: i64, y: i64, state: State,
+                          node_x: i64, node_y: i64) -> Rc<Node> {
+        if node.level == 0 {
+            return self.cache.get_leaf(state);
         }
 
-        // Recurse into children
         let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
             unreachable!();
         };
 
-        let half_size = node_size / 2;
+        let half_size = 1i64 << (node.level - 1);
         let mid_x = node_x + half_size;
         let mid_y = node_y + half_size;
 
-        // Recurse into each quadrant
-        self.collect_render_regions_recursive(
-            nw, node_x, node_y,
-            view_x_min, view_y_min, view_x_max, view_y_max,
-            min_render_size, result,
-        );
-        self.collect_render_regions_recursive(
-            ne, mid_x, node_y,
-            view_x_min, view_y_min, view_x_max, view_y_max,
-            min_render_size, result,
-        );
-        self.collect_render_regions_recursive(
-            sw, node_x, mid_y,
-            view_x_min, view_y_min, view_x_max, view_y_max,
-            min_render_size, result,
-        );
-        self.collect_render_regions_recursive(
-            se, mid_x, mid_y,
-            view_x_min, view_y_min, view_x_max, view_y_max,
-            min_render_size, result,
-        );
+        if x < mid_x && y < mid_y {
+            let new_nw = self.set_cell_recursive(nw, x, y, state, node_x, node_y);
+            self.cache.get_inner(new_nw, ne.clone(), sw.clone(), se.clone())
+        } else if x >= mid_x && y < mid_y {
+            let new_ne = self.set_cell_recursive(ne, x, y, state, mid_x, node_y);
+            self.cache.get_inner(nw.clone(), new_ne, sw.clone(), se.clone())
+        } else if x < mid_x && y >= mid_y {
+            let new_sw = self.set_cell_recursive(sw, x, y, state, node_x, mid_y);
+            self.cache.get_inner(nw.clone(), ne.clone(), new_sw, se.clone())
+        } else {
+            let new_se = self.set_cell_recursive(se, x, y, state, mid_x, mid_y);
+            self.cache.get_inner(nw.clone(), ne.clone(), sw.clone(), new_se)
+        }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn test_empty_universe() {
-        let universe = Universe::new(4);
-        assert_eq!(universe.population(), 0);
-        assert_eq!(universe.generation(), 0);
+    /// Get cell value at coordinates (true for any non-dead state)
+    pub fn get_cell(&self, x: i64, y: i64) -> bool {
+        self.get_cell_state(x, y) != 0
     }
 
-    #[test]
-    fn test_set_get_cell() {
-        let mut universe = Universe::new(4);
-        universe.set_cell(0, 0, true);
-        universe.set_cell(1, 0, true);
-        universe.set_cell(0, 1, true);
+    /// Get the state of the cell at coordinates
+    pub fn get_cell_state(&self, x: i64, y: i64) -> State {
+        let Some((x, y)) = self.topology.map(x, y) else {
+            return self.topology.edge().map_or(0, Edge::state);
+        };
+        let size = 1i64 << self.root.level;
+        let half_size = size / 2;
         
-        assert!(universe.get_cell(0, 0));
-        assert!(universe.get_cell(1, 0));
-        assert!(universe.get_cell(0, 1));
-        assert!(!universe.get_cell(2, 2));
+        if x < -half_size || x >= half_size || y < -half_size || y >= half_size {
+            return self.to_stored(0);
+        }
+        
+        self.to_stored(self.get_cell_recursive(&self.root, x, y, -half_size, -half_size))
     }
 
-    #[test]
-    fn test_blinker() {
-        let mut universe = Universe::new(4);
-        
-        // Create horizontal blinker
-        universe.set_cell(0, 0, true);
-        universe.set_cell(1, 0, true);
-        universe.set_cell(2, 0, true);
+    /// State of the infinite background. Always 0 except for B0 rules, whose
+    /// background is alive on odd generations (or, with S-max, from generation 1
+    /// on), and rules switched to while it was alive. A finite grid has no
+    /// background beyond it, so there this is always 0.
+    /// `population` and `live_cells` count cells that differ from this state.
+    pub fn background(&self) -> State {
+        if self.topology.bounds().is_some() { 0 } else { self.inverted as State }
+    }
+
+    /// Real state of the cells the tree stores as 0: the background, except on
+    /// a finite grid, whose tree holds the complement of the grid while a B0
+    /// rule has it alive. Tree walks such as the render regions and `diff`
+    /// compare stored cells against this state.
+    pub(crate) fn stored_background(&self) -> State {
+        self.inverted as State
+    }
+
+    /// Convert between real and stored cell states (the mapping is its own inverse)
+    pub(crate) fn to_stored(&self, state: State) -> State {
+        if self.inverted {
+            (state == 0) as State
+        } else {
+            state
+        }
+    }
+
+    fn get_cell_recursive(&self, node: &Rc<Node>, x: i64, y: i64, 
+                          node_x: i64, node_y: i64) -> State {
+        if node.level == 0 {
+            return node.state();
+        }
+
+        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
+            unreachable!();
+        };
+
+        let half_size = 1i64 << (node.level - 1);
+        let mid_x = node_x + half_size;
+        let mid_y = node_y + half_size;
+
+        if x < mid_x && y < mid_y {
+            self.get_cell_recursive(nw, x, y, node_x, node_y)
+        } else if x >= mid_x && y < mid_y {
+            self.get_cell_recursive(ne, x, y, mid_x, node_y)
+        } else if x < mid_x && y >= mid_y {
+            self.get_cell_recursive(sw, x, y, node_x, mid_y)
+        } else {
+            self.get_cell_recursive(se, x, y, mid_x, mid_y)
+        }
+    }
+
+    fn expand(&mut self) {
+        assert!(self.root.level < MAX_LEVEL, "the root cannot grow past level {}", MAX_LEVEL);
+        let root = self.root.clone();
+        self.root = self.pad(&root);
+    }
+
+    /// A node one level up with `node` at its centre and empty space around it
+    pub(crate) fn pad(&mut self, node: &Rc<Node>) -> Rc<Node> {
+        let empty = self.cache.get_empty(node.level - 1);
+        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
+            unreachable!();
+        };
+
+        let new_nw = self.cache.get_inner(
+            empty.clone(), empty.clone(),
+            empty.clone(), nw.clone()
+        );
+        let new_ne = self.cache.get_inner(
+            empty.clone(), empty.clone(),
+            ne.clone(), empty.clone()
+        );
+        let new_sw = self.cache.get_inner(
+            empty.clone(), sw.clone(),
+            empty.clone(), empty.clone()
+        );
+        let new_se = self.cache.get_inner(
+            se.clone(), empty.clone(),
+            empty.clone(), empty.clone()
+        );
+
+        self.cache.get_inner(new_nw, new_ne, new_sw, new_se)
+    }
+
+    /// Name, author, comments and rule of the pattern as its file gave them
+    pub fn metadata(&self) -> &PatternMetadata {
+        &self.metadata
+    }
+
+    pub fn set_metadata(&mut self, metadata: PatternMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// The rule this universe evolves under
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Before a step on a finite grid: fill the border one neighbourhood radius
+    /// wide around the grid with the cells the stepper should see across each
+    /// edge, either the fixed edge state or copies of the cells on the far side
+    fn fill_border(&mut self, bounds: (i64, i64, i64, i64)) {
+        let (left, top, width, height) = bounds;
+        let r = self.rule.radius() as i64;
+        if let Some(edge) = self.topology.edge() {
+            let state = self.to_stored(edge.state());
+            if state == 0 {
+                return;
+            }
+            for y in top - r..top + height + r {
+                let outside = !(top..top + height).contains(&y);
+                let xs: Vec<i64> = if outside {
+                    (left - r..left + width + r).collect()
+                } else {
+                    (left - r..left).chain(left + width..left + width + r).collect()
+                };
+                for x in xs {
+                    self.set_stored_state(x, y, state);
+                }
+            }
+            return;
+        }
+
+        let mut cells = Vec::new();
+        let root = self.root.clone();
+        let half = 1i64 << (root.level - 1);
+        let inner = (left + r, top + r, width - 2 * r, height - 2 * r);
+        collect_cells(&root, -half, -half, bounds, inner, &mut cells);
+
+        let (tiles_x, tiles_y) = ((r + width - 1) / width, (r + height - 1) / height);
+        for (x, y, state) in cells {
+            for dy in -tiles_y..=tiles_y {
+                for dx in -tiles_x..=tiles_x {
+                    if dx == 0 && dy == 0 {
+                        continue;
+                    }
+                    let (ix, iy) = self.topology.image(x, y, dx, dy);
+                    if (left - r..left + width + r).contains(&ix) && (top - r..top + height + r).contains(&iy) {
+                        self.set_stored_state(ix, iy, state);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Clear every cell outside the given rectangle
+    pub(crate) fn clip(&mut self, node: &Rc<Node>, node_x: i64, node_y: i64, bounds: (i64, i64, i64, i64)) -> Rc<Node> {
+        let (left, top, width, height) = bounds;
+        let size = 1i64 << node.level;
+        if node.population == 0 {
+            return node.clone();
+        }
+        if node_x >= left && node_y >= top && node_x + size <= left + width && node_y + size <= top + height {
+            return node.clone();
+        }
+        if node_x >= left + width || node_y >= top + height || node_x + size <= left || node_y + size <= top {
+            return self.cache.get_empty(node.level);
+        }
+
+        let [nw, ne, sw, se] = node.children();
+        let half = size / 2;
+        let nw = self.clip(nw, node_x, node_y, bounds);
+        let ne = self.clip(ne, node_x + half, node_y, bounds);
+        let sw = self.clip(sw, node_x, node_y + half, bounds);
+        let se = self.clip(se, node_x + half, node_y + half, bounds);
+        self.cache.get_inner(nw, ne, sw, se)
+    }
+
+    /// True if all live cells lie in the central quarter of the root, so that
+    /// one generation of growth cannot leave the region returned by the stepper
+    fn is_padded(&self) -> bool {
+        let [nw, ne, sw, se] = self.root.children();
+        nw.population == nw.children()[3].children()[3].population
+            && ne.population == ne.children()[2].children()[2].population
+            && sw.population == sw.children()[1].children()[1].population
+            && se.population == se.children()[0].children()[0].population
+    }
+
+    /// Step forward in time by exactly one generation
+    /// This ensures proper step-by-step progression for UI display
+    pub fn step(&mut self) {
+        self.record_history();
+        self.advance_root(0);
+        self.record_stats();
+        self.record_progress();
+        self.journal(|_| JournalEntry::Step);
+    }
+
+    /// Advance `2^exponent` generations in one HashLife superstep. `step_back`
+    /// undoes the whole superstep. Finite topologies are stepped one generation
+    /// at a time, since the cells across their edges change every generation,
+    /// until the pattern repeats; this fails with `StepToError::TooManySteps`,
+    /// leaving the universe unchanged, if it does not repeat within
+    /// `MAX_FINITE_STEPS` generations.
+    pub fn step_pow2(&mut self, exponent: u32) -> Result<(), StepToError> {
+        assert!(exponent <= MAX_STEP_EXPONENT, "superstep exponent {} is too large", exponent);
+        if self.topology.is_infinite() {
+            self.record_history();
+            self.advance_root(exponent as u8);
+        } else {
+            let start = self.snapshot();
+            if let Err(e) = self.try_advance_generations(1 << exponent) {
+                self.restore(start);
+                return Err(e);
+            }
+            self.push_history(start);
+        }
+        self.record_stats();
+        self.record_progress();
+        self.journal(|_| JournalEntry::Superstep(exponent));
+        Ok(())
+    }
+
+    /// Advance to exactly generation `target`, using the largest supersteps that
+    /// fit. A target in the past is reached by rewinding through the step
+    /// history, which fails with `StepToError::InPast` (leaving the universe
+    /// unchanged) if the history does not reach back that far. Finite grids
+    /// fail with `StepToError::TooManySteps` as in `step_pow2`.
+    pub fn step_to(&mut self, target: u128) -> Result<(), StepToError> {
+        if target < self.generation {
+            let earliest = self.history.front().map_or(self.generation, |&(_, generation, _)| generation);
+            if target < earliest {
+                return Err(StepToError::InPast { target, earliest });
+            }
+            while self.generation > target {
+                self.rewind();
+            }
+        }
+        if target != self.generation {
+            let start = self.snapshot();
+            if let Err(e) = self.try_advance_generations(target - self.generation) {
+                self.restore(start);
+                return Err(e);
+            }
+            self.push_history(start);
+            self.record_stats();
+            self.record_progress();
+        }
+        self.journal(|_| JournalEntry::StepTo(target));
+        Ok(())
+    }
+
+    /// Advance `generations` generations using the largest supersteps that fit,
+    /// without recording history
+    pub(crate) fn advance_generations(&mut self, generations: u128) {
+        if !self.topology.is_infinite() {
+            self.advance_finite(generations, u128::MAX);
+            return;
+        }
+        let mut remaining = generations;
+        while remaining > 0 {
+            let exponent = (127 - remaining.leading_zeros()).min(MAX_STEP_EXPONENT);
+            self.advance_root(exponent as u8);
+            remaining -= 1 << exponent;
+        }
+    }
+
+    /// `advance_generations`, but giving up on a finite grid after
+    /// `MAX_FINITE_STEPS` single steps, part of the way
+    fn try_advance_generations(&mut self, generations: u128) -> Result<(), StepToError> {
+        if !self.topology.is_infinite() && !self.advance_finite(generations, MAX_FINITE_STEPS) {
+            return Err(StepToError::TooManySteps { generations });
+        }
+        if self.topology.is_infinite() {
+            self.advance_generations(generations);
+        }
+        Ok(())
+    }
+
+    /// Step a finite grid `generations` generations one at a time, skipping
+    /// whole periods once the pattern repeats. This is Brent's cycle finding:
+    /// each position is compared with one saved whenever the distance since
+    /// the last save reaches a power of two. Returns false, part of the way,
+    /// if `budget` steps go by without a repeat.
+    fn advance_finite(&mut self, mut generations: u128, mut budget: u128) -> bool {
+        let mut saved = self.snapshot();
+        let (mut power, mut distance) = (1, 0);
+        while generations > 0 {
+            if budget == 0 {
+                return false;
+            }
+            self.advance_root(0);
+            (generations, budget, distance) = (generations - 1, budget - 1, distance + 1);
+            // Nodes are canonical, so equal grids have the same root
+            if Rc::ptr_eq(&self.root, &saved.root) && self.inverted == saved.inverted {
+                let skipped = generations - generations % distance;
+                self.generation += skipped;
+                generations -= skipped;
+                // Fewer generations than one period are left
+                (power, budget) = (u128::MAX, u128::MAX);
+            } else if distance == power {
+                saved = self.snapshot();
+                (power, distance) = (power * 2, 0);
+            }
+        }
+        true
+    }
+
+    /// Whether the tree is stored complemented `generations` from now, given
+    /// whether it is now
+    fn inverted_after(&self, inverted: bool, generations: u128) -> bool {
+        // The sequence is periodic with period 1 or 2 from the first step on
+        let steps = if generations > 2 { 2 + generations % 2 } else { generations };
+        (0..steps).fold(inverted, |inverted, _| self.phases[inverted as usize].output)
+    }
+
+    /// Advance the root `2^exponent` generations without recording history
+    pub(crate) fn advance_root(&mut self, exponent: u8) {
+        let generations = 1u128 << exponent;
+        let bounds = self.topology.bounds();
+        if let Some(bounds) = bounds {
+            self.fill_border(bounds);
+        }
+
+        let phase = self.inverted as usize;
+        if self.root.population == 0 {
+            self.generation += generations;
+            self.inverted = self.inverted_after(self.inverted, generations);
+            return;
+        }
+        // A node at level L can advance 2^(L - base_level) generations, and the
+        // pattern must be far enough from the edges that it cannot outgrow the result
+        let min_level = self.rule.base_level() + exponent + 1;
+        while self.root.level < min_level || !self.is_padded() {
+            if self.root.level == MAX_LEVEL {
+                // The root cannot grow, so cells too far out to step are lost,
+                // as at the edge of a bounded grid
+                let root = self.root.clone();
+                let (half, eighth) = (1i64 << (MAX_LEVEL - 1), 1i64 << (MAX_LEVEL - 3));
+                self.root = self.clip(&root, -half, -half, (-eighth, -eighth, 2 * eighth, 2 * eighth));
+                break;
+            }
+            self.expand();
+        }
+
+        let root = self.root.clone();
+        let result = self.advance(&root, exponent, phase);
+        
+        // The result is at level (root.level - 1), representing the center portion
+        // We need to embed it back at the original level with empty borders
+        
+        // Extract quadrants from the result (each at level result.level - 1)
+        let NodeContent::Inner { nw: r_nw, ne: r_ne, sw: r_sw, se: r_se, .. } = &result.content else {
+            unreachable!();
+        };
+        
+        // Create empty border at the same level as result's quadrants
+        let border = self.cache.get_empty(result.level - 1);
+        
+        // Build new quadrants at level result.level by adding borders
+        let new_nw = self.cache.get_inner(border.clone(), border.clone(), border.clone(), r_nw.clone());
+        let new_ne = self.cache.get_inner(border.clone(), border.clone(), r_ne.clone(), border.clone());
+        let new_sw = self.cache.get_inner(border.clone(), r_sw.clone(), border.clone(), border.clone());
+        let new_se = self.cache.get_inner(r_se.clone(), border.clone(), border.clone(), border.clone());
+        
+        // Combine into new root at original level
+        self.root = self.cache.get_inner(new_nw, new_ne, new_sw, new_se);
+        self.generation += generations;
+        self.inverted = self.inverted_after(self.inverted, generations);
+
+        if let Some(bounds) = bounds {
+            let root = self.root.clone();
+            let half = 1i64 << (root.level - 1);
+            self.root = self.clip(&root, -half, -half, bounds);
+        }
+    }
+
+    /// Advance the centre of `node` (one level down) by `2^exponent` generations,
+    /// starting in `phase`. Requires `exponent <= node.level - base_level`.
+    fn advance(&mut self, node: &Rc<Node>, exponent: u8, phase: usize) -> Rc<Node> {
+        // Empty space stays empty
+        if node.population == 0 {
+            return self.cache.get_empty(node.level - 1);
+        }
+
+        // Fast path for two-state radius-1 rules: a table lookup is cheaper than the memo cache
+        if node.level == 2 && self.phases[phase].block_table.is_some() {
+            return self.compute_base_table(node, phase);
+        }
+
+        // Check if we already computed the result for this node
+        let node_key = (Rc::as_ptr(node) as usize, phase as u8, exponent);
+        if let Some((_, cached_result)) = self.results.get(&node_key) {
+            self.counters.result_hits += 1;
+            return cached_result.clone();
+        }
+
+        if node.level == 3 && self.phases[phase].bit_rule.is_some() {
+            let result = self.compute_base_bits(node, exponent, phase);
+            self.counters.result_misses += 1;
+            self.results.insert(node_key, (Rc::downgrade(node), result.clone()));
+            return result;
+        }
+
+        let base_level = self.rule.base_level();
+        if node.level == base_level {
+            // Base case: compute_base advances by 1 generation
+            let result = self.compute_base(node, phase);
+            self.counters.result_misses += 1;
+            self.results.insert(node_key, (Rc::downgrade(node), result.clone()));
+            return result;
+        }
+
+        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
+            unreachable!();
+        };
+
+        // Get the 9 overlapping subnodes one level down that cover the interior
+        let center_nw_ne = self.center_subnode_horizontal(nw, ne);
+        let center_nw_sw = self.center_subnode_vertical(nw, sw);
+        let center_ne_se = self.center_subnode_vertical(ne, se);
+        let center_sw_se = self.center_subnode_horizontal(sw, se);
+        let center = self.center_node(node);
+        let subnodes = [
+            nw.clone(), center_nw_ne, ne.clone(),
+            center_nw_sw, center, center_ne_se,
+            sw.clone(), center_sw_se, se.clone(),
+        ];
+
+        // At the largest step this level allows, both halves of the recursion
+        // advance 2^(exponent - 1) generations. Smaller steps take the centres
+        // of the 9 subnodes unchanged and advance only in the second half.
+        let full = exponent == node.level - base_level;
+        let (second_exponent, second_phase) = if full {
+            (exponent - 1, self.inverted_after(phase != 0, 1 << (exponent - 1)) as usize)
+        } else {
+            (exponent, phase)
+        };
+        let mut n = Vec::with_capacity(9);
+        for subnode in &subnodes {
+            n.push(if full {
+                self.advance(subnode, exponent - 1, phase)
+            } else {
+                self.center_node(subnode)
+            });
+        }
+
+        // Combine the 9 results (level node.level - 2) into 4 overlapping
+        // nodes one level up, then advance those for the second half
+        let mut quadrants = Vec::with_capacity(4);
+        for (a, b, c, d) in [(0, 1, 3, 4), (1, 2, 4, 5), (3, 4, 6, 7), (4, 5, 7, 8)] {
+            let combined = self.cache.get_inner(n[a].clone(), n[b].clone(), n[c].clone(), n[d].clone());
+            quadrants.push(self.advance(&combined, second_exponent, second_phase));
+        }
+
+        // Return result at level (node.level - 1)
+        let [result_nw, result_ne, result_sw, result_se]: [Rc<Node>; 4] = quadrants.try_into().unwrap();
+        let result = self.cache.get_inner(result_nw, result_ne, result_sw, result_se);
+        // Nodes in use further up the recursion are held there, so collecting
+        // garbage here keeps them
+        if self.memory_limit.is_due(self.cache.len()) {
+            self.enforce_memory_limit();
+        }
+        
+        // Cache the result for this node
+        self.counters.result_misses += 1;
+        self.results.insert(node_key, (Rc::downgrade(node), result.clone()));
+        
+        result
+    }
+
+    fn center_node(&mut self, node: &Rc<Node>) -> Rc<Node> {
+        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
+            unreachable!();
+        };
+
+        let NodeContent::Inner { se: nw_se, .. } = &nw.content else { unreachable!(); };
+        let NodeContent::Inner { sw: ne_sw, .. } = &ne.content else { unreachable!(); };
+        let NodeContent::Inner { ne: sw_ne, .. } = &sw.content else { unreachable!(); };
+        let NodeContent::Inner { nw: se_nw, .. } = &se.content else { unreachable!(); };
+
+        self.cache.get_inner(
+            nw_se.clone(),
+            ne_sw.clone(),
+            sw_ne.clone(),
+            se_nw.clone(),
+        )
+    }
+
+    fn center_subnode_horizontal(&mut self, left: &Rc<Node>, right: &Rc<Node>) -> Rc<Node> {
+        let NodeContent::Inner { ne: left_ne, se: left_se, .. } = &left.content else { unreachable!(); };
+        let NodeContent::Inner { nw: right_nw, sw: right_sw, .. } = &right.content else { unreachable!(); };
+
+        self.cache.get_inner(
+            left_ne.clone(),
+            right_nw.clone(),
+            left_se.clone(),
+            right_sw.clone(),
+        )
+    }
+
+    fn center_subnode_vertical(&mut self, top: &Rc<Node>, bottom: &Rc<Node>) -> Rc<Node> {
+        let NodeContent::Inner { sw: top_sw, se: top_se, .. } = &top.content else { unreachable!(); };
+        let NodeContent::Inner { nw: bottom_nw, ne: bottom_ne, .. } = &bottom.content else { unreachable!(); };
+
+        self.cache.get_inner(
+            top_sw.clone(),
+            top_se.clone(),
+            bottom_nw.clone(),
+            bottom_ne.clone(),
+        )
+    }
+
+    /// Advance a 4x4 node one generation through the two-state lookup table
+    fn compute_base_table(&mut self, node: &Rc<Node>, phase: usize) -> Rc<Node> {
+        let Some(table) = &self.phases[phase].block_table else {
+            unreachable!();
+        };
+
+        let mut block = 0u16;
+        for (i, quadrant) in node.children().into_iter().enumerate() {
+            let (qx, qy) = ((i & 1) * 2, (i >> 1) * 2);
+            for (j, leaf) in quadrant.children().into_iter().enumerate() {
+                let (x, y) = (qx + (j & 1), qy + (j >> 1));
+                block |= ((leaf.state() == 1) as u16) << (4 * y + x);
+            }
+        }
+
+        let bits = table[block as usize];
+        let [nw, ne, sw, se] = [0, 1, 2, 3].map(|i| self.cache.get_leaf((bits >> i) & 1));
+        self.cache.get_inner(nw, ne, sw, se)
+    }
+
+    /// Advance an 8x8 node by 2^exponent generations (1 or 2) with every cell
+    /// evolved at once, returning its centre. This takes the place of the 13
+    /// table lookups the recursion would make, and the nodes it would build,
+    /// when the results are not yet memoized.
+    fn compute_base_bits(&mut self, node: &Rc<Node>, exponent: u8, phase: usize) -> Rc<Node> {
+        let mut cells = [0; 64];
+        Self::extract_cells(node, &mut cells, 8, 0, 0);
+        let mut block = cells.iter().enumerate().fold(0u64, |block, (i, &state)| block | (state as u64) << i);
+
+        let mut phase = phase;
+        for _ in 0..1 << exponent {
+            let Some(rule) = &self.phases[phase].bit_rule else {
+                unreachable!();
+            };
+            block = rule.step(block);
+            phase = self.inverted_after(phase != 0, 1) as usize;
+        }
+
+        let cells: Vec<State> = (0..64).map(|i| (block >> i & 1) as State).collect();
+        self.build_from_cells(&cells, 8, 2, 2, 2)
+    }
+
+    /// Advance a node at the rule's base level by one generation by brute force,
+    /// returning its centre one level down
+    fn compute_base(&mut self, node: &Rc<Node>, phase: usize) -> Rc<Node> {
+        let side = 1usize << node.level;
+        let mut cells = vec![0; side * side];
+        Self::extract_cells(node, &mut cells, side, 0, 0);
+
+        let rule = &self.phases[phase].rule;
+        let half = side / 2;
+        let offset = side / 4;
+        let mut result = vec![0; half * half];
+
+        if !rule.counts_only_live() {
+            // Multi-state families look at how many neighbors are in each state
+            let offsets = rule.neighbour_offsets();
+            let mut counts = vec![0u32; rule.states() as usize];
+            for y in 0..half {
+                for x in 0..half {
+                    let (cx, cy) = ((x + offset) as i32, (y + offset) as i32);
+                    counts.fill(0);
+                    for &(dx, dy) in &offsets {
+                        let neighbor = cells[(cy + dy) as usize * side + (cx + dx) as usize];
+                        if let Some(count) = counts.get_mut(neighbor as usize) {
+                            *count += 1;
+                        }
+                    }
+                    let state = cells[cy as usize * side + cx as usize];
+                    result[y * half + x] = rule.transition(state, &counts);
+                }
+            }
+        } else if rule.neighbourhood() == Neighbourhood::Moore {
+            // Prefix sums of live (state 1) cells so every square neighbourhood
+            // count is O(1) regardless of the radius
+            let stride = side + 1;
+            let mut sums = vec![0u32; stride * stride];
+            for y in 0..side {
+                for x in 0..side {
+                    let live = (cells[y * side + x] == 1) as u32;
+                    sums[(y + 1) * stride + x + 1] = live + sums[y * stride + x + 1]
+                        + sums[(y + 1) * stride + x] - sums[y * stride + x];
+                }
+            }
+
+            let radius = rule.radius() as usize;
+            let include_center = rule.includes_center();
+            for y in 0..half {
+                for x in 0..half {
+                    let (cx, cy) = (x + offset, y + offset);
+                    let (x0, y0) = (cx - radius, cy - radius);
+                    let (x1, y1) = (cx + radius + 1, cy + radius + 1);
+                    let state = cells[cy * side + cx];
+                    let mut neighbors = sums[y1 * stride + x1] + sums[y0 * stride + x0]
+                        - sums[y0 * stride + x1] - sums[y1 * stride + x0];
+                    if state == 1 && !include_center {
+                        neighbors -= 1;
+                    }
+                    result[y * half + x] = rule.next_state(state, neighbors);
+                }
+            }
+        } else {
+            let offsets = rule.neighbour_offsets();
+            for y in 0..half {
+                for x in 0..half {
+                    let (cx, cy) = ((x + offset) as i32, (y + offset) as i32);
+                    let neighbors = offsets.iter()
+                        .filter(|&&(dx, dy)| {
+                            cells[(cy + dy) as usize * side + (cx + dx) as usize] == 1
+                        })
+                        .count() as u32;
+                    let state = cells[cy as usize * side + cx as usize];
+                    result[y * half + x] = rule.next_state(state, neighbors);
+                }
+            }
+        }
+
+        self.build_from_cells(&result, half, 0, 0, node.level - 1)
+    }
+
+    /// Copy the cells of a node into a row-major grid with the given row stride
+    pub(crate) fn extract_cells(node: &Rc<Node>, cells: &mut [State], stride: usize,
+                     offset_x: usize, offset_y: usize) {
+        if node.population == 0 {
+            return;
+        }
+        if node.level == 0 {
+            cells[offset_y * stride + offset_x] = node.state();
+            return;
+        }
+        let half = 1usize << (node.level - 1);
+        let [nw, ne, sw, se] = node.children();
+        Self::extract_cells(nw, cells, stride, offset_x, offset_y);
+        Self::extract_cells(ne, cells, stride, offset_x + half, offset_y);
+        Self::extract_cells(sw, cells, stride, offset_x, offset_y + half);
+        Self::extract_cells(se, cells, stride, offset_x + half, offset_y + half);
+    }
+
+    /// Build a canonical node of the given level from a square region of a row-major grid
+    pub(crate) fn build_from_cells(&mut self, cells: &[State], stride: usize,
+                        offset_x: usize, offset_y: usize, level: u8) -> Rc<Node> {
+        if level == 0 {
+            return self.cache.get_leaf(cells[offset_y * stride + offset_x]);
+        }
+        let half = 1usize << (level - 1);
+        let nw = self.build_from_cells(cells, stride, offset_x, offset_y, level - 1);
+        let ne = self.build_from_cells(cells, stride, offset_x + half, offset_y, level - 1);
+        let sw = self.build_from_cells(cells, stride, offset_x, offset_y + half, level - 1);
+        let se = self.build_from_cells(cells, stride, offset_x + half, offset_y + half, level - 1);
+        self.cache.get_inner(nw, ne, sw, se)
+    }
+
+    /// Generations stepped so far. Supersteps pass 2^64 generations in a
+    /// short run, so it is a u128, which they cannot reach.
+    pub fn generation(&self) -> u128 {
+        self.generation
+    }
+
+    /// Number of non-dead cells. For B0 rules whose background is currently
+    /// alive (see `background`), this counts the dead cells instead, except on
+    /// finite grids where the live cells can still be counted.
+    ///
+    /// Saturates at `u64::MAX`; see `exact_population` for patterns larger
+    /// than that.
+    pub fn population(&self) -> u64 {
+        u64::try_from(self.exact_population()).unwrap_or(u64::MAX)
+    }
+
+    /// Number of non-dead cells counted as in `population`, without saturating
+    pub fn exact_population(&self) -> u128 {
+        match self.topology.bounds() {
+            Some((_, _, width, height)) if self.inverted => width as u128 * height as u128 - self.root.population,
+            _ => self.root.population,
+        }
+    }
+
+    /// `exact_population` as a float, for display
+    pub fn population_f64(&self) -> f64 {
+        self.exact_population() as f64
+    }
+
+    /// Number of cells currently in the given state (state 0 is not counted),
+    /// saturating at `u64::MAX`
+    pub fn state_population(&self, state: State) -> u64 {
+        let count = match self.topology.bounds() {
+            // Only two-state rules invert, and the live cells are the grid's unstored ones
+            Some(_) if self.inverted => if state == 1 { self.exact_population() } else { 0 },
+            _ => self.root.state_population(state),
+        };
+        u64::try_from(count).unwrap_or(u64::MAX)
+    }
+
+    /// Every cell that differs from the background, with its state, in no particular order
+    pub fn live_cells(&self) -> Vec<(i64, i64, State)> {
+        let half = 1i64 << (self.root.level - 1);
+        self.cells_in(Rect::new(-half, -half, 2 * half, 2 * half))
+    }
+
+    /// The cells in `rect` that differ from the background, with their
+    /// states. On a finite grid storing its complement these are the cells of
+    /// the grid the tree does not hold, so the grid is visited cell by cell.
+    pub(crate) fn cells_in(&self, rect: Rect) -> Vec<(i64, i64, State)> {
+        let mut cells = self.stored_cells(rect);
+        if let Some((left, top, width, height)) = self.topology.bounds().filter(|_| self.inverted) {
+            let Some(rect) = rect.intersection(Rect::new(left, top, width, height)) else {
+                return Vec::new();
+            };
+            let dead: HashSet<_> = cells.into_iter().map(|(x, y, _)| (x, y)).collect();
+            return (rect.top..rect.top + rect.height)
+                .flat_map(|y| (rect.left..rect.left + rect.width).map(move |x| (x, y, 1)))
+                .filter(|&(x, y, _)| !dead.contains(&(x, y)))
+                .collect();
+        }
+        for cell in &mut cells {
+            cell.2 = self.to_stored(cell.2);
+        }
+        cells
+    }
+
+    /// The stored cells in `rect` that are not stored as 0, with their stored states
+    pub(crate) fn stored_cells(&self, rect: Rect) -> Vec<(i64, i64, State)> {
+        let mut cells = Vec::new();
+        let half = 1i64 << (self.root.level - 1);
+        let outer = (rect.left, rect.top, rect.width, rect.height);
+        collect_cells(&self.root, -half, -half, outer, (0, 0, 0, 0), &mut cells);
+        cells
+    }
+
+    /// Smallest rectangle `(left, top, width, height)` containing every cell
+    /// that differs from the background, or None if there are none
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        if self.topology.bounds().is_some() && self.inverted {
+            let cells = self.live_cells();
+            let (left, right) = (cells.iter().map(|cell| cell.0).min()?, cells.iter().map(|cell| cell.0).max()?);
+            let (top, bottom) = (cells.iter().map(|cell| cell.1).min()?, cells.iter().map(|cell| cell.1).max()?);
+            return Some((left, top, right - left + 1, bottom - top + 1));
+        }
+        if self.root.population == 0 {
+            return None;
+        }
+        let size = 1i64 << self.root.level;
+        let half = size / 2;
+        let mut memo = HashMap::new();
+        let mut edge = |side| first_line(&self.root, side, &mut memo).unwrap() as i64;
+        let (west, east, north, south) = (edge(Side::West), edge(Side::East), edge(Side::North), edge(Side::South));
+        Some((west - half, north - half, size - west - east, size - north - south))
+    }
+
+    /// Save the current position, for analyses that look ahead and come back
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot { root: self.root.clone(), generation: self.generation, inverted: self.inverted }
+    }
+
+    pub(crate) fn restore(&mut self, snapshot: Snapshot) {
+        self.root = snapshot.root;
+        self.generation = snapshot.generation;
+        self.inverted = snapshot.inverted;
+    }
+
+    /// The quadtree holding the stored cells, centred on the origin
+    pub(crate) fn root(&self) -> &Rc<Node> {
+        &self.root
+    }
+
+    /// Canonical inner node, for building trees outside the stepper
+    pub(crate) fn inner_node(&mut self, nw: Rc<Node>, ne: Rc<Node>, sw: Rc<Node>, se: Rc<Node>) -> Rc<Node> {
+        self.cache.get_inner(nw, ne, sw, se)
+    }
+
+    pub(crate) fn leaf_node(&self, state: State) -> Rc<Node> {
+        self.cache.get_leaf(state)
+    }
+
+    pub(crate) fn empty_node(&mut self, level: u8) -> Rc<Node> {
+        self.cache.get_empty(level)
+    }
+
+    /// Whether the stored cells are complemented `generations` after a start
+    /// on a dead background
+    pub(crate) fn inverted_at(&self, generations: u128) -> bool {
+        self.inverted_after(false, generations)
+    }
+
+    /// Replace every cell with a tree built by `inner_node` and `build_from_cells`,
+    /// centred on the origin, as it is at `generation` with the given stored
+    /// background (see `inverted_at`). The step history is dropped.
+    pub(crate) fn set_root(&mut self, root: Rc<Node>, generation: u128, inverted: bool) {
+        self.root = root;
+        while self.root.level < 3 {
+            self.expand();
+        }
+        self.generation = generation;
+        self.inverted = inverted;
+        self.history.clear();
+        self.interrupt_adaptive();
+        if let Some(bounds) = self.topology.bounds() {
+            let half = 1i64 << (self.root.level - 1);
+            let root = self.root.clone();
+            self.root = self.clip(&root, -half, -half, bounds);
+        }
+    }
+
+    /// Grow the root until it covers `rect`
+    pub(crate) fn expand_to(&mut self, rect: Rect) {
+        loop {
+            let half = 1i64 << (self.root.level - 1);
+            if rect.left >= -half && rect.top >= -half && rect.left + rect.width <= half && rect.top + rect.height <= half {
+                return;
+            }
+            self.expand();
+        }
+    }
+
+    /// Halve the root while everything it holds lies in its central quarter,
+    /// down to the smallest root a universe has
+    pub(crate) fn shrink(&mut self) {
+        while self.root.level > 3 {
+            let [nw, ne, sw, se] = self.root.children();
+            if nw.population != nw.children()[3].population || ne.population != ne.children()[2].population
+                || sw.population != sw.children()[1].population || se.population != se.children()[0].population {
+                return;
+            }
+            let root = self.root.clone();
+            self.root = self.center_node(&root);
+        }
+    }
+
+    /// Replace the tree with an edited one centred on the origin, keeping the
+    /// generation and step history as `set_cell_state` does
+    pub(crate) fn replace_root(&mut self, root: Rc<Node>) {
+        self.interrupt_adaptive();
+        let before = std::mem::replace(&mut self.root, root);
+        while self.root.level < 3 {
+            self.expand();
+        }
+        self.edited(&before);
+    }
+
+    /// Become `universe`, as loading a file does, but keep the journal and
+    /// write the new state to it. Checkpoints and the timeline start over
+    /// with the same settings.
+    pub(crate) fn load_universe(&mut self, universe: Universe) {
+        let journal = self.journal.take();
+        let (interval, limit) = (self.checkpoint_interval(), self.checkpoint_limit());
+        let (timeline_limit, undo_limit) = (self.timeline_limit(), self.undo_limit());
+        let memory_limit = self.memory_limit();
+        *self = universe;
+        self.journal = journal;
+        self.set_memory_limit(memory_limit);
+        self.set_undo_limit(undo_limit);
+        self.set_checkpoint_limit(limit);
+        self.set_checkpoint_interval(interval);
+        self.set_timeline_limit(timeline_limit);
+        self.journal_state();
+    }
+
+    /// Write every later change to `sink` as a `JournalEntry`, starting with
+    /// the whole universe, so that `Universe::replay` can rebuild it after a
+    /// crash (see the `journal` module). None stops journaling.
+    pub fn set_journal(&mut self, sink: Option<JournalSink>) {
+        self.journal = sink.map(|sink| Journal::new(sink, self.root.clone(), self.stored_background()));
+        self.journal_state();
+    }
+
+    pub fn is_journaling(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Journal the whole universe
+    fn journal_state(&mut self) {
+        let Some(mut journal) = self.journal.take() else {
+            return;
+        };
+        for entry in self.state_entries() {
+            journal.write(&entry, &self.root, self.stored_background());
+        }
+        self.journal = Some(journal);
+    }
+
+    /// Journal an operation other than an edit once it is made, or the whole
+    /// universe if it went back further than a replay could
+    pub(crate) fn journal(&mut self, entry: impl FnOnce(&Universe) -> JournalEntry) {
+        if self.journal.as_ref().is_some_and(Journal::is_lost) {
+            return self.journal_state();
+        }
+        let Some(mut journal) = self.journal.take() else {
+            return;
+        };
+        journal.write(&entry(self), &self.root, self.stored_background());
+        self.journal = Some(journal);
+    }
+
+    /// Undo the last edit, if the universe still holds what it made (see the
+    /// `undo` module). Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        let edit = self.undo.undo().unwrap();
+        self.replace_edited_root(edit.before);
+        true
+    }
+
+    /// Make the last undone edit again, if the universe still holds what
+    /// undoing it left. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        if !self.can_redo() {
+            return false;
+        }
+        let edit = self.undo.redo().unwrap();
+        self.replace_edited_root(edit.after);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undo.last_undo().is_some_and(|edit| self.holds(&edit.after, edit.inverted))
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.undo.last_redo().is_some_and(|edit| self.holds(&edit.before, edit.inverted))
+    }
+
+    /// Keep at most `limit` edits for `undo` (`DEFAULT_UNDO_LIMIT` unless set;
+    /// 0 stops keeping them)
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.undo.set_limit(limit);
+    }
+
+    pub fn undo_limit(&self) -> usize {
+        self.undo.limit()
+    }
+
+    /// Forget the edits kept for undo and redo
+    pub fn clear_undo(&mut self) {
+        self.undo.clear();
+    }
+
+    /// Whether the universe holds the same cells as `root`, stored as given.
+    /// Trees of different sizes can hold the same cells, so they are compared
+    /// rather than their pointers.
+    fn holds(&self, root: &Rc<Node>, inverted: bool) -> bool {
+        inverted == self.inverted
+            && (Rc::ptr_eq(root, &self.root)
+                || crate::analysis::diff_trees((root, 0), (&self.root, 0)).is_empty())
+    }
+
+    fn replace_edited_root(&mut self, root: Rc<Node>) {
+        self.interrupt_adaptive();
+        let before = std::mem::replace(&mut self.root, root);
+        if !Rc::ptr_eq(&before, &self.root) {
+            self.note_edit();
+        }
+    }
+
+    /// Start an edit made of several smaller ones, which `undo` undoes at once
+    pub(crate) fn begin_edit_group(&mut self) {
+        self.undo.begin_group(&self.root);
+    }
+
+    /// Finish an edit started with `begin_edit_group`
+    pub(crate) fn end_edit_group(&mut self) {
+        self.undo.end_group(&self.root, self.inverted);
+    }
+
+    /// Bring the undo stack, timeline and journal up to date after an edit
+    /// that started from the tree `before`
+    fn edited(&mut self, before: &Rc<Node>) {
+        if Rc::ptr_eq(before, &self.root) {
+            return;
+        }
+        self.undo.record(before, &self.root, self.inverted);
+        self.note_edit();
+    }
+
+    fn note_edit(&mut self) {
+        self.timeline.edited(self.generation, &self.root, self.inverted);
+        let background = self.stored_background();
+        if let Some(journal) = &mut self.journal {
+            journal.write_edits(&self.root, background);
+        }
+    }
+
+    /// Check the invariants of the quadtree: every child is one level below
+    /// its parent, populations, per-state counts and content hashes agree
+    /// with the children's, every node (empty ones included) is the node
+    /// cache's canonical copy, and nothing is stored outside a finite grid.
+    /// Each distinct node is checked once. Available in debug builds, and in
+    /// release builds with the `validate` feature.
+    #[cfg(any(debug_assertions, feature = "validate"))]
+    pub fn validate(&self) -> Result<(), TreeError> {
+        if self.root.level < 3 {
+            return Err(TreeError::RootTooSmall { level: self.root.level });
+        }
+        let mut checked = std::collections::HashSet::new();
+        self.validate_node(&self.root, &mut checked)?;
+        if let Some((left, top, width, height)) = self.topology.bounds() {
+            let half = 1i64 << (self.root.level - 1);
+            let mut outside = Vec::new();
+            collect_cells(&self.root, -half, -half, (-half, -half, 2 * half, 2 * half),
+                          (left, top, width, height), &mut outside);
+            if let Some(&(x, y, _)) = outside.first() {
+                return Err(TreeError::OutsideGrid { x, y });
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(any(debug_assertions, feature = "validate"))]
+    fn validate_node(&self, node: &Rc<Node>, checked: &mut std::collections::HashSet<*const Node>)
+                     -> Result<(), TreeError> {
+        if !checked.insert(Rc::as_ptr(node)) {
+            return Ok(());
+        }
+        let level = node.level;
+        let NodeContent::Inner { nw, ne, sw, se } = &node.content else {
+            if !Rc::ptr_eq(node, &self.cache.get_leaf(node.state())) || level != 0 {
+                return Err(TreeError::NotCanonical { level });
+            }
+            return Ok(());
+        };
+        let children = [nw, ne, sw, se];
+        for child in children {
+            if child.level + 1 != level {
+                return Err(TreeError::ChildLevel { level, child_level: child.level });
+            }
+            self.validate_node(child, checked)?;
+        }
+        let sum = children.iter().map(|child| child.population).sum();
+        if node.population != sum {
+            return Err(TreeError::Population { level, population: node.population, children: sum });
+        }
+        if node.state_counts != Node::sum_state_counts(children) {
+            return Err(TreeError::StateCounts { level });
+        }
+        if node.content_hash != Node::combine_hashes(level, children.map(|child| child.content_hash)) {
+            return Err(TreeError::ContentHash { level });
+        }
+        if !self.cache.is_canonical(node) {
+            return Err(TreeError::NotCanonical { level });
+        }
+        Ok(())
+    }
+
+    /// Get the root level of the universe (for determining node sizes)
+    pub fn root_level(&self) -> u8 {
+        self.root.level
+    }
+
+    /// Collect renderable regions from the quadtree.
+    /// This uses the tree structure to efficiently skip empty regions and aggregate small areas.
+    /// 
+    /// Returns a vector of (x, y, size, density) tuples where:
+    /// - x, y: world coordinates of the top-left corner of the region
+    /// - size: the side length of the region in world units
+    /// - density: population divided by area (0.0 to 1.0)
+    /// 
+    /// min_render_size: minimum region size in world units to render
+    /// (smaller nodes will be aggregated as density)
+    pub fn collect_render_regions(
+        &self,
+        view_x_min: i64,
+        view_y_min: i64,
+        view_x_max: i64,
+        view_y_max: i64,
+        min_render_size: u32,
+    ) -> Vec<(i64, i64, u32, f32)> {
+        self.collect_state_regions(view_x_min, view_y_min, view_x_max, view_y_max, min_render_size)
+            .into_iter()
+            .map(|(x, y, size, density, _)| (x, y, size, density))
+            .collect()
+    }
+
+    /// Like `collect_render_regions`, but each tuple also carries the cell state
+    /// to draw: the state of the cell for single-cell regions, or the most common
+    /// non-dead state for aggregated regions. Used for multi-state rules.
+    pub fn collect_state_regions(
+        &self,
+        view_x_min: i64,
+        view_y_min: i64,
+        view_x_max: i64,
+        view_y_max: i64,
+        min_render_size: u32,
+    ) -> Vec<(i64, i64, u32, f32, State)> {
+        let mut result = Vec::new();
+        let size = 1i64 << self.root.level;
+        let half_size = size / 2;
+        
+        self.collect_render_regions_recursive(
+            &self.root,
+            -half_size,
+            -half_size,
+            view_x_min,
+            view_y_min,
+            view_x_max,
+            view_y_max,
+            min_render_size,
+            &mut result,
+        );
+        
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn collect_render_regions_recursive(
+        &self,
+        node: &Rc<Node>,
+        node_x: i64,
+        node_y: i64,
+        view_x_min: i64,
+        view_y_min: i64,
+        view_x_max: i64,
+        view_y_max: i64,
+        min_render_size: u32,
+        result: &mut Vec<(i64, i64, u32, f32, State)>,
+    ) {
+        // Skip if node is completely empty
+        if node.population == 0 {
+            return;
+        }
+
+        let node_size = 1i64 << node.level;
+        let node_x_max = node_x + node_size;
+        let node_y_max = node_y + node_size;
+
+        // Skip if node is completely outside the view
+        if node_x >= view_x_max || node_x_max <= view_x_min ||
+           node_y >= view_y_max || node_y_max <= view_y_min {
+            return;
+        }
+
+        // If node is small enough or is a leaf, emit it as a rendered region
+        if node_size as u32 <= min_render_size || node.level == 0 {
+            let area = (node_size * node_size) as f32;
+            let density = node.population as f32 / area;
+            result.push((node_x, node_y, node_size as u32, density, node.dominant_state()));
+            return;
+        }
+
+        // Recurse into children
+        let NodeContent::Inner { nw, ne, sw, se, .. } = &node.content else {
+            unreachable!();
+        };
+
+        let half_size = node_size / 2;
+        let mid_x = node_x + half_size;
+        let mid_y = node_y + half_size;
+
+        // Recurse into each quadrant
+        self.collect_render_regions_recursive(
+            nw, node_x, node_y,
+            view_x_min, view_y_min, view_x_max, view_y_max,
+            min_render_size, result,
+        );
+        self.collect_render_regions_recursive(
+            ne, mid_x, node_y,
+            view_x_min, view_y_min, view_x_max, view_y_max,
+            min_render_size, result,
+        );
+        self.collect_render_regions_recursive(
+            sw, node_x, mid_y,
+            view_x_min, view_y_min, view_x_max, view_y_max,
+            min_render_size, result,
+        );
+        self.collect_render_regions_recursive(
+            se, mid_x, mid_y,
+            view_x_min, view_y_min, view_x_max, view_y_max,
+            min_render_size, result,
+        );
+    }
+}
+
+/// A side of a node, for `first_line`
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Side {
+    West,
+    East,
+    North,
+    South,
+}
+
+/// Distance from the given side of `node` to its nearest non-dead row or column.
+/// Memoized per node, so the cost is linear in the number of distinct nodes.
+fn first_line(node: &Rc<Node>, side: Side, memo: &mut HashMap<(usize, Side), Option<u64>>) -> Option<u64> {
+    if node.population == 0 {
+        return None;
+    }
+    if node.level == 0 {
+        return Some(0);
+    }
+    let key = (Rc::as_ptr(node) as usize, side);
+    if let Some(&distance) = memo.get(&key) {
+        return distance;
+    }
+
+    let [nw, ne, sw, se] = node.children();
+    let (near, far) = match side {
+        Side::West => ([nw, sw], [ne, se]),
+        Side::East => ([ne, se], [nw, sw]),
+        Side::North => ([nw, ne], [sw, se]),
+        Side::South => ([sw, se], [nw, ne]),
+    };
+    let mut nearest = |children: [&Rc<Node>; 2]| {
+        children.into_iter().filter_map(|child| first_line(child, side, memo)).min()
+    };
+    let half = 1u64 << (node.level - 1);
+    let distance = nearest(near).or_else(|| nearest(far).map(|d| d + half));
+    memo.insert(key, distance);
+    distance
+}
+
+/// Collect the non-dead cells of `node` (whose top-left cell is `(node_x, node_y)`)
+/// that lie inside `outer` but not inside `inner`, both given as `(left, top, width, height)`
+fn collect_cells(node: &Rc<Node>, node_x: i64, node_y: i64, outer: (i64, i64, i64, i64),
+                      inner: (i64, i64, i64, i64), cells: &mut Vec<(i64, i64, State)>) {
+    let size = 1i64 << node.level;
+    let overlaps = |(left, top, width, height): (i64, i64, i64, i64)| {
+        node_x < left + width && node_y < top + height && node_x + size > left && node_y + size > top
+    };
+    let (left, top, width, height) = inner;
+    let within_inner = node_x >= left && node_y >= top
+        && node_x + size <= left + width && node_y + size <= top + height;
+    if node.population == 0 || !overlaps(outer) || within_inner {
+        return;
+    }
+
+    if node.level == 0 {
+        cells.push((node_x, node_y, node.state()));
+        return;
+    }
+    let half = size / 2;
+    let [nw, ne, sw, se] = node.children();
+    collect_cells(nw, node_x, node_y, outer, inner, cells);
+    collect_cells(ne, node_x + half, node_y, outer, inner, cells);
+    collect_cells(sw, node_x, node_y + half, outer, inner, cells);
+    collect_cells(se, node_x + half, node_y + half, outer, inner, cells);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Twist;
+
+    #[test]
+    fn test_empty_universe() {
+        let universe = Universe::new(4);
+        assert_eq!(universe.population(), 0);
+        assert_eq!(universe.generation(), 0);
+    }
+
+    #[test]
+    fn test_set_get_cell() {
+        let mut universe = Universe::new(4);
+        universe.set_cell(0, 0, true);
+        universe.set_cell(1, 0, true);
+        universe.set_cell(0, 1, true);
+        
+        assert!(universe.get_cell(0, 0));
+        assert!(universe.get_cell(1, 0));
+        assert!(universe.get_cell(0, 1));
+        assert!(!universe.get_cell(2, 2));
+    }
+
+    #[test]
+    fn test_set_cell_states() {
+        let cells = [(0, 0, 1), (-300, 5, 2), (7, 7, 3), (0, 0, 0), (7, 7, 1), (1000, -1000, 2)];
+        let mut bulk = Universe::with_rule(4, Rule::wireworld());
+        bulk.set_cell_states(&cells);
+        let mut single = Universe::with_rule(4, Rule::wireworld());
+        for &(x, y, state) in &cells {
+            single.set_cell_state(x, y, state);
+        }
+        assert_eq!(bulk.root_level(), single.root_level());
+        let live = |universe: &Universe| {
+            let mut cells = universe.live_cells();
+            cells.sort_unstable();
+            cells
+        };
+        assert_eq!(live(&bulk), live(&single));
+        assert_eq!(bulk.get_cell_state(7, 7), 1);
+        assert_eq!(bulk.population(), 3);
+
+        let mut torus = Universe::with_topology(4, Rule::conway(), Topology::Torus { width: 8, height: 8 });
+        torus.set_cell_states(&[(9, -1, 1)]);
+        assert!(torus.get_cell(1, 7));
+    }
+
+    #[test]
+    fn test_blinker() {
+        let mut universe = Universe::new(4);
+        
+        // Create horizontal blinker
+        universe.set_cell(0, 0, true);
+        universe.set_cell(1, 0, true);
+        universe.set_cell(2, 0, true);
         
         assert_eq!(universe.population(), 3);
         assert_eq!(universe.generation(), 0);
@@ -660,48 +2550,1026 @@ mod tests {
         // Step now advances by exactly 1 generation
         // Horizontal blinker -> vertical (1 step)
         universe.step();
-        
-        assert_eq!(universe.generation(), 1);
-        // After 1 generation, should be vertical
+        
+        assert_eq!(universe.generation(), 1);
+        // After 1 generation, should be vertical
+        assert!(!universe.get_cell(0, 0));
+        assert!(universe.get_cell(1, 0));
+        assert!(!universe.get_cell(2, 0));
+        assert!(universe.get_cell(1, -1));
+        assert!(universe.get_cell(1, 1));
+        assert_eq!(universe.population(), 3);
+        
+        // Step again -> back to horizontal (2 steps total)
+        universe.step();
+        
+        assert_eq!(universe.generation(), 2);
+        assert!(universe.get_cell(0, 0));
+        assert!(universe.get_cell(1, 0));
+        assert!(universe.get_cell(2, 0));
+        assert!(!universe.get_cell(1, -1));
+        assert!(!universe.get_cell(1, 1));
+        assert_eq!(universe.population(), 3);
+    }
+
+    #[test]
+    fn test_block() {
+        let mut universe = Universe::new(4);
+        
+        // Create block (still life)
+        universe.set_cell(0, 0, true);
+        universe.set_cell(1, 0, true);
+        universe.set_cell(0, 1, true);
+        universe.set_cell(1, 1, true);
+        
+        assert_eq!(universe.population(), 4);
+        
+        // Step - should remain the same (still life)
+        universe.step();
+        
+        assert!(universe.get_cell(0, 0));
+        assert!(universe.get_cell(1, 0));
+        assert!(universe.get_cell(0, 1));
+        assert!(universe.get_cell(1, 1));
+        assert_eq!(universe.population(), 4);
+    }
+
+    #[test]
+    fn test_glider_near_edge() {
+        // A glider in the smallest universe must not lose cells at the root boundary
+        let mut universe = Universe::new(3);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+
+        for _ in 0..40 {
+            universe.step();
+            assert_eq!(universe.population(), 5);
+        }
+        // After 40 generations the glider has moved 10 cells diagonally
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            assert!(universe.get_cell(x + 10, y + 10));
+        }
+    }
+
+    #[test]
+    fn test_custom_rule() {
+        // Seeds (B2/S): every cell dies, cells with exactly 2 neighbors are born
+        let mut universe = Universe::with_rule(4, "B2/S".parse().unwrap());
+        universe.set_cell(0, 0, true);
+        universe.set_cell(1, 0, true);
+
+        universe.step();
+
+        assert_eq!(universe.population(), 4);
+        assert!(!universe.get_cell(0, 0));
+        assert!(!universe.get_cell(1, 0));
+        for (x, y) in [(0, -1), (1, -1), (0, 1), (1, 1)] {
+            assert!(universe.get_cell(x, y));
+        }
+    }
+
+    #[test]
+    fn test_brians_brain() {
+        // Brian's Brain (B2/S/C3): live cells always start dying the next generation
+        let mut universe = Universe::with_rule(4, "/2/3".parse().unwrap());
+        universe.set_cell(0, 0, true);
+        universe.set_cell(1, 0, true);
+
+        universe.step();
+
+        assert_eq!(universe.get_cell_state(0, 0), 2);
+        assert_eq!(universe.get_cell_state(1, 0), 2);
+        assert_eq!(universe.state_population(1), 4);
+        assert_eq!(universe.state_population(2), 2);
+        assert_eq!(universe.population(), 6);
+
+        universe.step();
+
+        // Dying cells are gone, the 4 newborn cells are now dying
+        assert_eq!(universe.get_cell_state(0, 0), 0);
+        assert_eq!(universe.state_population(2), 4);
+        assert_eq!(universe.population(), universe.state_population(1) + 4);
+    }
+
+    #[test]
+    fn test_collect_state_regions() {
+        let mut universe = Universe::with_rule(4, "/2/4".parse().unwrap());
+        universe.set_cell_state(0, 0, 3);
+        universe.set_cell_state(1, 0, 2);
+        universe.set_cell_state(0, 1, 2);
+
+        let mut regions = universe.collect_state_regions(-10, -10, 10, 10, 1);
+        regions.sort_by_key(|&(x, y, ..)| (y, x));
+        let states: Vec<_> = regions.iter().map(|&(x, y, _, _, state)| (x, y, state)).collect();
+        assert_eq!(states, vec![(0, 0, 3), (1, 0, 2), (0, 1, 2)]);
+
+        // Aggregated into one 2x2 region, state 2 dominates
+        let regions = universe.collect_state_regions(-10, -10, 10, 10, 2);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].4, 2);
+        assert!((regions[0].3 - 0.75).abs() < 0.001);
+    }
+
+    /// Brute-force one generation of a two-state rule over a set of live cells
+    fn naive_step(cells: &std::collections::HashSet<(i64, i64)>, rule: &Rule)
+        -> std::collections::HashSet<(i64, i64)> {
+        let r = rule.radius() as i64;
+        let offsets = rule.neighbour_offsets();
+        let mut candidates = std::collections::HashSet::new();
+        for &(x, y) in cells {
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    candidates.insert((x + dx, y + dy));
+                }
+            }
+        }
+        candidates.into_iter()
+            .filter(|&(x, y)| {
+                let neighbors = offsets.iter()
+                    .filter(|&&(dx, dy)| cells.contains(&(x + dx as i64, y + dy as i64)))
+                    .count() as u32;
+                rule.next_state(cells.contains(&(x, y)) as State, neighbors) == 1
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        for rulestring in [
+            "B3/S23",
+            "R2,C0,M1,S5..9,B6..8,NM",
+            "B2/S34H",
+            "R2,C0,M0,S3..7,B4..6,NH",
+            "B13/S012V",
+            "R2,C0,M1,S3..6,B3..5,NN",
+        ] {
+            let rule: Rule = rulestring.parse().unwrap();
+            let mut universe = Universe::with_rule(4, rule.clone());
+
+            // A deterministic blob of cells
+            let mut cells = std::collections::HashSet::new();
+            let mut seed = 12345u32;
+            for y in -6..6 {
+                for x in -6..6 {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    if (seed >> 16) & 1 == 0 {
+                        cells.insert((x, y));
+                        universe.set_cell(x, y, true);
+                    }
+                }
+            }
+
+            for _ in 0..8 {
+                universe.step();
+                cells = naive_step(&cells, &rule);
+                assert_eq!(universe.population(), cells.len() as u64, "{}", rulestring);
+                for &(x, y) in &cells {
+                    assert!(universe.get_cell(x, y), "{}", rulestring);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hexagonal_neighbourhood() {
+        // Cells on the NE/SW diagonal are not hex neighbors, so nothing is born between them
+        let mut universe = Universe::with_rule(4, "B2/S34H".parse().unwrap());
+        universe.set_cell(1, -1, true);
+        universe.set_cell(-1, 1, true);
+        universe.step();
         assert!(!universe.get_cell(0, 0));
-        assert!(universe.get_cell(1, 0));
-        assert!(!universe.get_cell(2, 0));
-        assert!(universe.get_cell(1, -1));
-        assert!(universe.get_cell(1, 1));
-        assert_eq!(universe.population(), 3);
-        
-        // Step again -> back to horizontal (2 steps total)
+
+        // Cells on the NW/SE diagonal are
+        let mut universe = Universe::with_rule(4, "B2/S34H".parse().unwrap());
+        universe.set_cell(-1, -1, true);
+        universe.set_cell(1, 1, true);
         universe.step();
-        
-        assert_eq!(universe.generation(), 2);
         assert!(universe.get_cell(0, 0));
-        assert!(universe.get_cell(1, 0));
-        assert!(universe.get_cell(2, 0));
-        assert!(!universe.get_cell(1, -1));
+    }
+
+    #[test]
+    fn test_von_neumann_neighbourhood() {
+        // B1/S: only orthogonal neighbors of the seed are born
+        let mut universe = Universe::with_rule(4, "B1/SV".parse().unwrap());
+        universe.set_cell(0, 0, true);
+        universe.step();
+
+        assert_eq!(universe.population(), 4);
+        for (x, y) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            assert!(universe.get_cell(x, y));
+        }
         assert!(!universe.get_cell(1, 1));
+    }
+
+    #[test]
+    fn test_immigration() {
+        // A blinker with two colours: the newborn cells take the majority colour
+        let mut universe = Universe::with_rule(4, Rule::immigration());
+        universe.set_cell_state(0, 0, 1);
+        universe.set_cell_state(1, 0, 2);
+        universe.set_cell_state(2, 0, 2);
+
+        universe.step();
+
+        assert_eq!(universe.get_cell_state(1, 0), 2);
+        assert_eq!(universe.get_cell_state(1, -1), 2);
+        assert_eq!(universe.get_cell_state(1, 1), 2);
+        assert_eq!(universe.population(), 3);
+
+        universe.step();
+
+        // The survivor keeps its colour, the reborn ends take state 2 as well
+        assert_eq!(universe.state_population(2), 3);
+        assert!(universe.get_cell(0, 0) && universe.get_cell(2, 0));
+    }
+
+    /// Step a finite topology cell by cell, looking neighbors up through `Topology::map`
+    fn naive_topology_test(topology: Topology, rule: &str, generations: u64) {
+        let rule: Rule = rule.parse().unwrap();
+        let mut universe = Universe::with_topology(3, rule.clone(), topology);
+        let (left, top, width, height) = topology.bounds().unwrap();
+        let offsets = rule.neighbour_offsets();
+
+        let mut grid = std::collections::HashMap::new();
+        let mut seed = 987654321u32;
+        for y in top..top + height {
+            for x in left..left + width {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let state = (seed >> 16).is_multiple_of(3) as State;
+                grid.insert((x, y), state);
+                universe.set_cell_state(x, y, state);
+            }
+        }
+
+        for generation in 1..=generations {
+            grid = grid.iter()
+                .map(|(&(x, y), &state)| {
+                    let neighbors = offsets.iter()
+                        .filter(|&&(dx, dy)| {
+                            topology.map(x + dx as i64, y + dy as i64)
+                                .map_or(topology.edge().map_or(0, Edge::state), |cell| grid[&cell]) == 1
+                        })
+                        .count() as u32;
+                    ((x, y), rule.next_state(state, neighbors))
+                })
+                .collect();
+            universe.step();
+
+            for (&(x, y), &state) in &grid {
+                assert_eq!(universe.get_cell_state(x, y), state, "{:?} gen {} at ({}, {})",
+                           topology, generation, x, y);
+            }
+            let population = grid.values().filter(|&&state| state != 0).count() as u64;
+            assert_eq!(universe.population(), population, "{:?} gen {}", topology, generation);
+        }
+    }
+
+    #[test]
+    fn test_torus() {
+        // A glider on an 8x8 torus is back where it started after 32 generations
+        let mut universe = Universe::with_topology(3, Rule::conway(), Topology::Torus { width: 8, height: 8 });
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        for &(x, y) in &glider {
+            universe.set_cell(x, y, true);
+        }
+        for _ in 0..32 {
+            universe.step();
+            assert_eq!(universe.population(), 5);
+        }
+        for &(x, y) in &glider {
+            assert!(universe.get_cell(x, y));
+            assert!(universe.get_cell(x + 8, y - 16));
+        }
+
+        // Once it repeats, whole periods are skipped
+        let mut skipped = universe.fork();
+        skipped.step_pow2(MAX_STEP_EXPONENT).unwrap();
+        assert_eq!(skipped.generation(), 32 + (1 << MAX_STEP_EXPONENT));
+        assert_eq!(skipped.live_cells(), universe.live_cells());
+        skipped.step_to(skipped.generation() + (1 << 40) + 3).unwrap();
+        universe.step_to(35).unwrap();
+        assert_eq!(skipped.live_cells(), universe.live_cells());
+        assert!(skipped.step_back());
+        assert_eq!(skipped.generation(), 32 + (1 << MAX_STEP_EXPONENT));
+
+        // A glider on a 129x128 torus takes 4 * 129 * 128 generations to come round
+        let mut long = Universe::with_topology(3, Rule::conway(), Topology::Torus { width: 129, height: 128 });
+        for &(x, y) in &glider {
+            long.set_cell(x, y, true);
+        }
+        assert_eq!(long.step_pow2(20), Err(StepToError::TooManySteps { generations: 1 << 20 }));
+        assert_eq!((long.generation(), long.live_cells().len()), (0, 5));
+        assert!(!long.step_back());
+
+        naive_topology_test(Topology::Torus { width: 11, height: 7 }, "B3/S23", 20);
+        naive_topology_test(Topology::Torus { width: 9, height: 10 }, "R2,C0,M1,S5..9,B6..8,NM", 10);
+        naive_topology_test(Topology::Torus { width: 6, height: 5 }, "B2/S34H", 10);
+        naive_topology_test(Topology::Torus { width: 3, height: 2 }, "R3,C0,M0,S2..4,B3..5,NN", 5);
+
+        // The largest grid still wraps: a blinker across the corner seams turns
+        let size = crate::topology::MAX_GRID_SIZE;
+        let mut universe = Universe::with_topology(3, Rule::conway(), Topology::Torus { width: size, height: size });
+        let (left, top, width, height) = universe.topology().bounds().unwrap();
+        let (right, bottom) = (left + width - 1, top + height - 1);
+        for x in [right, left, left + 1] {
+            universe.set_cell(x, top, true);
+        }
+        universe.step();
         assert_eq!(universe.population(), 3);
+        assert!(universe.get_cell(left, bottom) && universe.get_cell(left, top) && universe.get_cell(left, top + 1));
     }
 
     #[test]
-    fn test_block() {
+    fn test_bounded() {
+        // A glider crashing into a dead edge turns into a block in the corner
+        let bounded = Topology::Bounded { width: 8, height: 8, edge: Edge::Dead };
+        let mut universe = Universe::with_topology(3, Rule::conway(), bounded);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        universe.set_cell(10, 10, true);
+        assert!(!universe.get_cell(10, 10));
+        for _ in 0..40 {
+            universe.step();
+        }
+        assert_eq!(universe.population(), 4);
+        assert!(universe.get_cell(2, 2) && universe.get_cell(3, 3));
+
+        let alive = Topology::Bounded { width: 6, height: 5, edge: Edge::Alive };
+        let universe = Universe::with_topology(3, Rule::conway(), alive);
+        assert!(universe.get_cell(3, 0) && !universe.get_cell(2, 0));
+
+        naive_topology_test(bounded, "B3/S23", 20);
+        naive_topology_test(alive, "B3/S23", 20);
+        naive_topology_test(Topology::Bounded { width: 9, height: 12, edge: Edge::Alive }, "R2,C0,M1,S5..9,B6..8,NM", 10);
+        naive_topology_test(Topology::Bounded { width: 9, height: 7, edge: Edge::Dead }, "B3/S23V", 10);
+        naive_topology_test(Topology::Bounded { width: 9, height: 7, edge: Edge::Dead }, "B03/S23", 10);
+        naive_topology_test(Topology::Bounded { width: 9, height: 7, edge: Edge::Alive }, "B0123478/S01234678", 10);
+        naive_topology_test(Topology::Torus { width: 9, height: 7 }, "B03/S23", 10);
+    }
+
+    #[test]
+    fn test_twisted_topologies() {
+        // A glider on an 8x8 Klein bottle comes back through the bottom edge mirrored
+        let klein = Topology::KleinBottle { width: 8, height: 8, twist: Twist::TopBottom };
+        let mut universe = Universe::with_topology(3, Rule::conway(), klein);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        for _ in 0..32 {
+            universe.step();
+        }
+        assert_eq!(universe.population(), 5);
+        for (x, y) in [(-2, 0), (-3, 1), (-1, 2), (-2, 2), (-3, 2)] {
+            assert!(universe.get_cell(x, y), "({}, {})", x, y);
+        }
+
+        for topology in [
+            klein,
+            Topology::KleinBottle { width: 7, height: 9, twist: Twist::LeftRight },
+            Topology::CrossSurface { width: 10, height: 7 },
+        ] {
+            naive_topology_test(topology, "B3/S23", 20);
+            naive_topology_test(topology, "B2/S34H", 10);
+            naive_topology_test(topology, "B03/S23", 10);
+        }
+        naive_topology_test(Topology::CrossSurface { width: 3, height: 4 }, "R3,C0,M0,S2..4,B3..5,NN", 5);
+        naive_topology_test(Topology::CrossSurface { width: 9, height: 9 }, "R2,C0,M1,S5..9,B6..8,NM", 10);
+    }
+
+    #[test]
+    fn test_wireworld() {
+        // An electron travels one cell per generation along a straight wire
+        let mut universe = Universe::with_rule(4, Rule::wireworld());
+        for x in 0..12 {
+            universe.set_cell_state(x, 0, 3);
+        }
+        universe.set_cell_state(1, 0, 1);
+        universe.set_cell_state(0, 0, 2);
+
+        for generation in 1..=10 {
+            universe.step();
+            assert_eq!(universe.get_cell_state(generation + 1, 0), 1);
+            assert_eq!(universe.get_cell_state(generation, 0), 2);
+            assert_eq!(universe.state_population(3), 10);
+        }
+
+        // It leaves the end of the wire, which goes back to plain conductor
+        universe.step();
+        universe.step();
+        assert_eq!(universe.state_population(3), 12);
+        assert_eq!(universe.population(), 12);
+    }
+
+    /// Run a small pattern through `segments` of (rulestring, generations), switching
+    /// rules between them, and compare every generation against a brute-force
+    /// grid whose outside is the current background
+    fn check_rule_segments(segments: &[(&str, u64)]) {
+        const W: i64 = 48;
+        let rules: Vec<Rule> = segments.iter().map(|(s, _)| s.parse().unwrap()).collect();
+        let mut universe = Universe::with_rule(4, rules[0].clone());
+        let mut grid = vec![0 as State; (W * W) as usize];
+        let mut background = 0;
+        for (x, y) in [(0, 0), (1, 0), (2, 0), (2, -1), (1, -2), (5, 3)] {
+            universe.set_cell(x, y, true);
+            grid[((y + W / 2) * W + x + W / 2) as usize] = 1;
+        }
+
+        for (rule, &(rulestring, generations)) in rules.iter().zip(segments) {
+            universe.set_rule(rule.clone()).unwrap();
+            let offsets = rule.neighbour_offsets();
+            let size = rule.neighbourhood_size();
+
+            for generation in 1..=generations {
+                let at = |grid: &[State], x: i64, y: i64| {
+                    if (0..W).contains(&x) && (0..W).contains(&y) {
+                        grid[(y * W + x) as usize]
+                    } else {
+                        background
+                    }
+                };
+                let mut next = grid.clone();
+                for y in 0..W {
+                    for x in 0..W {
+                        let n = offsets.iter()
+                            .filter(|&&(dx, dy)| at(&grid, x + dx as i64, y + dy as i64) == 1)
+                            .count() as u32;
+                        next[(y * W + x) as usize] = rule.next_state(at(&grid, x, y), n);
+                    }
+                }
+                background = rule.next_state(background, if background == 1 { size } else { 0 });
+                grid = next;
+                universe.step();
+
+                assert_eq!(universe.background(), background, "{} gen {}", rulestring, generation);
+                for y in 0..W {
+                    for x in 0..W {
+                        assert_eq!(universe.get_cell_state(x - W / 2, y - W / 2), grid[(y * W + x) as usize],
+                                   "{} gen {} at ({}, {})", rulestring, generation, x - W / 2, y - W / 2);
+                    }
+                }
+                assert_eq!(universe.get_cell_state(1000, 1000), background);
+            }
+        }
+    }
+
+    #[test]
+    fn test_b0_rules() {
+        // B03/S23 strobes the background; AntiLife (B0123478/S01234678) keeps it alive
+        for rulestring in ["B03/S23", "B0123478/S01234678", "B013/S1V"] {
+            check_rule_segments(&[(rulestring, 12)]);
+        }
+    }
+
+    #[test]
+    fn test_set_rule() {
+        check_rule_segments(&[("B3/S23", 5), ("B36/S23", 5), ("R2,C0,M1,S5..9,B6..8,NM", 3), ("B3/S23", 4)]);
+        // Switching while the background is alive, into and out of B0 rules
+        check_rule_segments(&[("B03/S23", 3), ("B0123478/S01234678", 3), ("B3/S238", 3), ("B3/S23", 3)]);
+        check_rule_segments(&[("B0123478/S01234678", 2), ("B03/S23", 3), ("B2/S/C3", 4)]);
+
+        let mut universe = Universe::with_rule(4, "B0123478/S01234678".parse().unwrap());
+        universe.set_cell(0, 0, true);
+        universe.step();
+        assert_eq!(universe.set_rule("B2/S/C3".parse().unwrap()), Err(SetRuleError::LiveBackground));
+        assert_eq!(universe.rule().to_string(), "B0123478/S01234678");
+        assert_eq!(universe.set_rule(Rule::conway()), Ok(()));
+        assert_eq!(universe.history_len(), 0);
+    }
+
+    #[test]
+    fn test_step_pow2() {
+        for rulestring in [
+            "B3/S23", "R2,C0,M1,S5..9,B6..8,NM", "B2/S34H", "B03/S23",
+            "B0123478/S01234678", "B2/S/C3", "Immigration", "WireWorld",
+        ] {
+            let rule: Rule = rulestring.parse().unwrap();
+            let mut single = Universe::with_rule(3, rule.clone());
+            let mut supersteps = Universe::with_rule(3, rule.clone());
+            let mut seed = 42u32;
+            for y in -8..8 {
+                for x in -8..8 {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    let state = ((seed >> 16) % rule.states() as u32) as State;
+                    single.set_cell_state(x, y, state);
+                    supersteps.set_cell_state(x, y, state);
+                }
+            }
+
+            for exponent in [0, 3, 1, 4, 2] {
+                for _ in 0..1 << exponent {
+                    single.step();
+                }
+                supersteps.step_pow2(exponent).unwrap();
+                assert_eq!(supersteps.generation(), single.generation());
+                assert_eq!(supersteps.background(), single.background(), "{}", rulestring);
+                assert_eq!(supersteps.population(), single.population(), "{} gen {}", rulestring, single.generation());
+                for y in -40..40 {
+                    for x in -40..40 {
+                        assert_eq!(supersteps.get_cell_state(x, y), single.get_cell_state(x, y),
+                                   "{} gen {} at ({}, {})", rulestring, single.generation(), x, y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_step_to() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut universe = Universe::new(3);
+        for &(x, y) in &glider {
+            universe.set_cell(x, y, true);
+        }
+
+        // A glider moves one cell diagonally every 4 generations
+        universe.step_to(1000).unwrap();
+        assert_eq!(universe.generation(), 1000);
+        assert_eq!(universe.population(), 5);
+        for &(x, y) in &glider {
+            assert!(universe.get_cell(x + 250, y + 250));
+        }
+
+        // Going back rewinds through the history
+        universe.step_to(1001).unwrap();
+        universe.step_to(1000).unwrap();
+        assert!(universe.get_cell(251, 250));
+        assert_eq!(universe.step_to(8), Ok(()));
+        assert!(universe.get_cell(3, 2) && universe.get_cell(4, 4));
+
+        universe.clear_history();
+        assert_eq!(universe.step_to(5), Err(StepToError::InPast { target: 5, earliest: 8 }));
+        assert_eq!(universe.generation(), 8);
+
+        // Finite grids get there one generation at a time
+        let mut torus = Universe::with_topology(3, Rule::conway(), Topology::Torus { width: 8, height: 8 });
+        for &(x, y) in &glider {
+            torus.set_cell(x, y, true);
+        }
+        torus.step_to(96).unwrap();
+        assert_eq!(torus.population(), 5);
+        for &(x, y) in &glider {
+            assert!(torus.get_cell(x, y));
+        }
+    }
+
+    #[test]
+    fn test_adaptive_stepping() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut universe = Universe::new(3);
+        for &(x, y) in &glider {
+            universe.set_cell(x, y, true);
+        }
+        let mut reference = Universe::new(3);
+        for &(x, y) in &glider {
+            reference.set_cell(x, y, true);
+        }
+
+        // A lone glider is cheap, so the step size keeps doubling
+        universe.set_adaptive_stepping(true);
+        for exponent in 0..10 {
+            assert_eq!(universe.step_exponent(), exponent);
+            universe.step_adaptive();
+        }
+        assert_eq!(universe.generation(), 1023);
+        reference.step_to(1023).unwrap();
+        for y in 250..260 {
+            for x in 250..260 {
+                assert_eq!(universe.get_cell(x, y), reference.get_cell(x, y));
+            }
+        }
+
+        // Interaction drops back to single generations
+        universe.set_cell(0, 0, true);
+        assert_eq!(universe.step_exponent(), 0);
+        universe.step_adaptive();
+        assert_eq!(universe.generation(), 1024);
+
+        // Without adaptive stepping the step size stays put
+        universe.set_adaptive_stepping(false);
+        universe.set_step_exponent(3);
+        universe.step_adaptive();
+        universe.step_adaptive();
+        assert_eq!((universe.generation(), universe.step_exponent()), (1040, 3));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let mut universe = Universe::new(3);
+        assert_eq!(universe.bounding_box(), None);
+        for (x, y) in [(-5, 3), (7, -2), (0, 0), (1000, 12)] {
+            universe.set_cell_state(x, y, 2);
+        }
+        assert_eq!(universe.bounding_box(), Some((-5, -2, 1006, 15)));
+
+        let mut cells = universe.live_cells();
+        cells.sort();
+        assert_eq!(cells, vec![(-5, 3, 2), (0, 0, 2), (7, -2, 2), (1000, 12, 2)]);
+    }
+
+    #[test]
+    fn test_step_back() {
         let mut universe = Universe::new(4);
-        
-        // Create block (still life)
         universe.set_cell(0, 0, true);
         universe.set_cell(1, 0, true);
-        universe.set_cell(0, 1, true);
-        universe.set_cell(1, 1, true);
-        
-        assert_eq!(universe.population(), 4);
-        
-        // Step - should remain the same (still life)
+        universe.set_cell(2, 0, true);
+
+        assert!(!universe.step_back());
+
         universe.step();
-        
+        universe.step();
+        assert_eq!(universe.history_len(), 2);
+
+        assert!(universe.step_back());
+        assert_eq!(universe.generation(), 1);
+        assert!(universe.get_cell(1, -1));
+        assert!(!universe.get_cell(0, 0));
+
+        assert!(universe.step_back());
+        assert_eq!(universe.generation(), 0);
         assert!(universe.get_cell(0, 0));
-        assert!(universe.get_cell(1, 0));
-        assert!(universe.get_cell(0, 1));
-        assert!(universe.get_cell(1, 1));
-        assert_eq!(universe.population(), 4);
+        assert!(!universe.get_cell(1, -1));
+        assert!(!universe.step_back());
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut universe = Universe::new(4);
+        universe.set_cell(0, 0, true);
+        universe.set_history_limit(3);
+
+        for _ in 0..10 {
+            universe.step();
+        }
+        assert_eq!(universe.history_len(), 3);
+
+        universe.set_history_limit(0);
+        assert_eq!(universe.history_len(), 0);
+        universe.step();
+        assert!(!universe.step_back());
+    }
+
+    #[test]
+    fn test_population_history() {
+        // An R-pentomino, sampled each step and then through supersteps
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        assert!(universe.population_history().is_empty());
+        universe.set_population_history_limit(64);
+        for _ in 0..3 {
+            universe.step();
+        }
+        assert_eq!(universe.population_history(), &[(0, 5), (1, 6), (2, 7), (3, 9)]);
+        universe.step_back();
+        assert_eq!(universe.population_history(), &[(0, 5), (1, 6), (2, 7)]);
+
+        universe.step_to(1103).unwrap();
+        universe.step_pow2(4).unwrap();
+        assert_eq!(universe.population_history().last(), Some(&(1119, 116)));
+        for _ in 0..200 {
+            universe.step();
+        }
+        let history = universe.population_history();
+        assert!(history.len() <= 64);
+        assert_eq!(history[0], (0, 5));
+        assert!(history.iter().all(|&(generation, _)| generation <= 1319));
+
+        universe.set_population_history_limit(0);
+        universe.step();
+        assert!(universe.population_history().is_empty());
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        universe.step_to(8).unwrap();
+        let mut fork = universe.fork();
+        assert!(Rc::ptr_eq(fork.root(), universe.root()));
+        assert_eq!((fork.generation(), fork.history_len()), (8, 1));
+
+        // A block in the glider's path, in the fork only
+        for (x, y) in [(8, 8), (9, 8), (8, 9), (9, 9)] {
+            fork.set_cell(x, y, true);
+        }
+        fork.step_to(40).unwrap();
+        assert_ne!(fork.population(), 5);
+        assert_eq!((universe.generation(), universe.population()), (8, 5));
+        universe.step_to(40).unwrap();
+        assert_eq!(universe.population(), 5);
+
+        fork.step_back();
+        fork.step_back();
+        assert_eq!((fork.generation(), fork.population()), (0, 5));
+    }
+
+    #[test]
+    fn test_shared_cache() {
+        let cache = NodeCache::new();
+        assert!(cache.is_empty());
+        let mut conway = Universe::with_cache(4, Rule::conway(), Topology::Plane, cache.clone());
+        let mut highlife = Universe::with_cache(4, "B36/S23".parse().unwrap(), Topology::Plane, cache.clone());
+        let mut alone = Universe::with_rule(4, "B36/S23".parse().unwrap());
+        for universe in [&mut conway, &mut highlife, &mut alone] {
+            for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+                universe.set_cell(x, y, true);
+            }
+        }
+        // The same cells are the same nodes, stored once
+        assert!(Rc::ptr_eq(conway.root(), highlife.root()));
+        assert!(conway.node_cache().same_as(&cache) && !alone.node_cache().same_as(&cache));
+        assert_eq!(cache.len(), alone.node_cache().len());
+
+        // Memoized steps stay with each universe's rule
+        conway.step_to(100).unwrap();
+        highlife.step_to(100).unwrap();
+        alone.step_to(100).unwrap();
+        assert_eq!(highlife.diff(&alone), []);
+        assert_ne!(conway.population(), highlife.population());
+
+        let mut both = highlife.fork();
+        assert!(both.node_cache().same_as(&cache));
+        both.union(&conway);
+        let mut imported = alone.fork();
+        imported.union(&conway);
+        assert_eq!(both.diff(&imported), []);
+    }
+
+    #[test]
+    fn test_extreme_coordinates() {
+        let mut universe = Universe::new(3);
+        universe.set_cell(i64::MAX, 0, true);
+        assert_eq!(universe.try_set_cell_state(0, i64::MIN, 1), Err(CoordinateError::OutOfRange { x: 0, y: i64::MIN }));
+        assert_eq!((universe.population(), universe.root_level()), (0, 3));
+        assert!(!universe.get_cell(i64::MAX, i64::MIN));
+
+        universe.try_set_cell_state(MAX_COORDINATE, MIN_COORDINATE, 1).unwrap();
+        assert_eq!(universe.root_level(), MAX_LEVEL);
+        assert_eq!(universe.bounding_box(), Some((MAX_COORDINATE, MIN_COORDINATE, 1, 1)));
+        universe.paste(&crate::pattern::Pattern::from_live_cells([(0, 0), (1, 0)]), i64::MAX - 1, 0, crate::edit::PasteMode::Or);
+        universe.fill_region(Rect::new(i64::MAX - 5, i64::MAX - 5, 10, 10), 1);
+        assert_eq!(universe.population(), 1);
+
+        // Moving cells past the edge loses them
+        universe.translate(1, 0);
+        assert_eq!(universe.population(), 0);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            universe.set_cell(x, y, true);
+            universe.set_cell(x + (1 << 60), y, true);
+        }
+        universe.translate(-(1 << 30), 0);
+        assert_eq!((universe.population(), universe.bounding_box().unwrap().2), (8, (1 << 60) + 2));
+
+        // Stepping keeps what has room to grow and drops the rest
+        universe.step();
+        assert_eq!(universe.live_cells().len(), 4);
+        assert!(universe.get_cell(-(1 << 30), 0));
+        universe.step_pow2(20).unwrap();
+        assert_eq!((universe.population(), universe.generation()), (4, 1 + (1 << 20)));
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut universe = Universe::new(4);
+        assert!(!universe.undo());
+        universe.set_cell(0, 0, true);
+        universe.draw_rect(Rect::new(-10, -10, 5, 4), 1, false);
+        universe.set_cell(0, 0, true);
+        let pattern = crate::pattern::Pattern::from_cells([(0, 0, 1), (1, 0, 1), (2, 0, 1)]);
+        universe.paste(&pattern, 20, 20, crate::edit::PasteMode::Or);
+        assert_eq!(universe.population(), 18);
+
+        // The paste and the rectangle go in one undo each; setting a live cell
+        // again changed nothing
+        assert!(universe.undo());
+        assert_eq!(universe.population(), 15);
+        assert!(universe.undo());
+        assert_eq!(universe.live_cells(), [(0, 0, 1)]);
+        assert!(universe.redo() && universe.redo() && !universe.redo());
+        assert_eq!(universe.population(), 18);
+
+        // A new edit ends the redos
+        universe.undo();
+        universe.translate(1, 0);
+        assert!(!universe.can_redo());
+        universe.undo();
+        assert_eq!(universe.population(), 15);
+
+        // Nothing to undo past a step until stepping back to it
+        universe.set_cell(20, 20, true);
+        universe.step();
+        assert!(!universe.can_undo());
+        universe.step_back();
+        assert!(universe.undo());
+        assert_eq!(universe.get_cell_state(20, 20), 0);
+
+        universe.set_undo_limit(1);
+        universe.set_cell(30, 30, true);
+        universe.set_cell(31, 31, true);
+        assert!(universe.undo() && !universe.undo());
+        assert_eq!(universe.get_cell_state(30, 30), 1);
+    }
+
+    #[test]
+    fn test_fx_hasher() {
+        // Addresses 64 bytes apart still land in most of a small table's buckets
+        let buckets: std::collections::HashSet<u64> = (0..1024usize).map(|i| {
+            let mut hasher = FxHasher::default();
+            (0x7f00_0000_0000 + 64 * i, 0usize, 0usize, 0usize).hash(&mut hasher);
+            hasher.finish() % 1024
+        }).collect();
+        assert!(buckets.len() > 600, "{}", buckets.len());
+    }
+
+    #[test]
+    fn test_big_population() {
+        let mut universe = Universe::new(3);
+        universe.fill_region(Rect::new(MIN_COORDINATE, MIN_COORDINATE, 1 << 40, 1 << 40), 1);
+        universe.set_cell(0, 0, true);
+        assert_eq!(universe.exact_population(), (1 << 80) + 1);
+        assert_eq!(universe.population(), u64::MAX);
+        assert_eq!(universe.population_f64(), 2f64.powi(80));
+        assert_eq!(universe.statistics().quadrants, [1 << 80, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let r_pentomino = |universe: &mut Universe| {
+            for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+                universe.set_cell(x, y, true);
+            }
+        };
+        let mut universe = Universe::new(4);
+        r_pentomino(&mut universe);
+        assert_eq!(universe.scrub_to(0), Err(TimelineError::NotRecording));
+        universe.step();
+        universe.set_timeline_limit(8);
+        for _ in 0..50 {
+            universe.step();
+        }
+        for _ in 0..20 {
+            universe.step_pow2(3).unwrap();
+        }
+        assert_eq!(universe.timeline_range(), Some((1, 211)));
+        let keyframes = universe.timeline_keyframes();
+        assert!(keyframes.len() <= 8 && keyframes[0] == 1);
+        assert!(universe.timeline_interval() > 1);
+
+        let mut expected = Universe::new(4);
+        r_pentomino(&mut expected);
+        for generation in [1, 2, 37, 100, 150, 210, 211] {
+            expected.step_to(generation).unwrap();
+            universe.scrub_to(generation).unwrap();
+            assert_eq!(universe.generation(), generation);
+            assert_eq!(universe.diff(&expected), [], "generation {}", generation);
+        }
+        assert_eq!(universe.scrub_to(212), Err(TimelineError::OutOfRange { generation: 212, first: 1, last: 211 }));
+        universe.step_back();
+        assert_eq!(universe.generation(), 210);
+
+        // An edit ends the run at its generation
+        universe.scrub_to(100).unwrap();
+        universe.set_cell(-50, -50, true);
+        assert_eq!(universe.timeline_range(), Some((1, 100)));
+        universe.step();
+        universe.scrub_to(50).unwrap();
+        universe.scrub_to(101).unwrap();
+        assert_eq!(universe.population(), {
+            let mut edited = Universe::new(4);
+            r_pentomino(&mut edited);
+            edited.step_to(100).unwrap();
+            edited.set_cell(-50, -50, true);
+            edited.step();
+            edited.population()
+        });
+
+        universe.set_timeline_limit(0);
+        assert_eq!(universe.timeline_range(), None);
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        universe.step();
+        assert!(universe.checkpoints().is_empty());
+        universe.set_checkpoint_limit(3);
+        universe.set_checkpoint_interval(Some(CheckpointInterval::Generations(10)));
+        assert_eq!(universe.checkpoints(), [Checkpoint { id: 0, generation: 1, population: 6 }]);
+        for _ in 0..25 {
+            universe.step();
+        }
+        universe.step_pow2(5).unwrap();
+        universe.step_to(100).unwrap();
+        let generations: Vec<_> = universe.checkpoints().iter().map(|checkpoint| checkpoint.generation).collect();
+        assert_eq!(generations, [21, 58, 100]);
+
+        let at_21 = universe.checkpoints()[0];
+        let mut expected = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            expected.set_cell(x, y, true);
+        }
+        expected.step_to(21).unwrap();
+        assert!(universe.restore_checkpoint(at_21.id));
+        assert_eq!((universe.generation(), universe.population()), (21, at_21.population));
+        assert_eq!(universe.diff(&expected), []);
+        assert!(!universe.restore_checkpoint(at_21.id - 1));
+        // Restoring is undone like a step and later checkpoints count from it
+        universe.step_pow2(3).unwrap();
+        assert_eq!(universe.checkpoints().len(), 3);
+        universe.step_pow2(1).unwrap();
+        assert_eq!(universe.checkpoints().last().map(|checkpoint| checkpoint.generation), Some(31));
+        universe.step_back();
+        universe.step_back();
+        universe.step_back();
+        assert_eq!(universe.generation(), 100);
+
+        universe.set_checkpoint_interval(Some(CheckpointInterval::Seconds(0.0)));
+        universe.step();
+        assert_eq!(universe.checkpoints().last().map(|checkpoint| checkpoint.generation), Some(101));
+        universe.set_rule(Rule::conway()).unwrap();
+        assert!(universe.checkpoints().is_empty());
+        universe.set_checkpoint_interval(None);
+        universe.step();
+        assert!(universe.checkpoints().is_empty());
+    }
+
+    #[cfg(any(debug_assertions, feature = "validate"))]
+    #[test]
+    fn test_validate() {
+        use crate::edit::PasteMode;
+        use crate::pattern::Pattern;
+
+        let mut universe = Universe::new(3);
+        assert_eq!(universe.validate(), Ok(()));
+        universe.fill_random(Rect::new(-20, -20, 40, 40), 0.4, 7);
+        universe.step_pow2(5).unwrap();
+        universe.translate(1000, -3);
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        universe.paste(&glider, -500, 500, PasteMode::Xor);
+        universe.crop(Rect::new(-600, -600, 1700, 1200));
+        assert_eq!(universe.validate(), Ok(()));
+
+        let mut torus = Universe::with_topology(4, "B3/S23".parse().unwrap(), Topology::Torus { width: 10, height: 6 });
+        torus.fill_random(Rect::new(-5, -3, 10, 6), 0.5, 3);
+        torus.step_pow2(3).unwrap();
+        assert_eq!(torus.validate(), Ok(()));
+        let mut generations = Universe::with_rule(4, "B0/S8".parse().unwrap());
+        generations.set_cell(2, 2, true);
+        generations.step_pow2(2).unwrap();
+        assert_eq!(generations.validate(), Ok(()));
+
+        // A node built outside the cache, and one with a wrong population
+        let (empty, empty_quadrant) = (universe.empty_node(1), universe.empty_node(2));
+        let leaf = universe.leaf_node(1);
+        let block = universe.inner_node(leaf.clone(), leaf.clone(), leaf.clone(), leaf);
+        let stray = Rc::new(Node::inner(block, empty.clone(), empty.clone(), empty));
+        let root = universe.inner_node(stray, empty_quadrant.clone(), empty_quadrant.clone(), empty_quadrant);
+        universe.set_root(root, 0, false);
+        assert_eq!(universe.validate(), Err(TreeError::NotCanonical { level: 2 }));
+        let mut miscounted = (*universe.empty_node(3)).clone();
+        miscounted.population = 1;
+        universe.root = Rc::new(miscounted);
+        assert_eq!(universe.validate(), Err(TreeError::Population { level: 3, population: 1, children: 0 }));
+    }
+
+    #[test]
+    fn test_cell_ages() {
+        let mut universe = Universe::new(4);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1), (5, -1), (5, 0), (5, 1)] {
+            universe.set_cell(x, y, true);
+        }
+        assert_eq!(universe.cell_age(0, 0), None);
+        universe.set_age_tracking(true);
+        assert_eq!(universe.cell_age(0, 0), Some(0));
+        for _ in 0..3 {
+            universe.step();
+        }
+        assert_eq!(universe.cell_age(1, 1), Some(3));
+        assert_eq!(universe.cell_age(5, 0), Some(3));
+        assert_eq!(universe.cell_age(4, 0), Some(0));
+        assert_eq!(universe.cell_age(5, -1), None);
+        assert_eq!(universe.cell_ages(Rect::new(4, -1, 3, 3)), vec![(4, 0, 0), (5, 0, 3), (6, 0, 0)]);
+
+        // Supersteps count cells alive at both ends as alive throughout
+        universe.step_pow2(3).unwrap();
+        assert_eq!(universe.cell_age(0, 0), Some(11));
+        assert_eq!(universe.cell_age(4, 0), Some(8));
+        universe.set_cell(10, 10, true);
+        assert_eq!(universe.cell_age(10, 10), Some(0));
+        universe.step_back();
+        assert_eq!(universe.cell_age(0, 0), Some(3));
+
+        universe.set_age_tracking(false);
+        assert_eq!(universe.cell_age(0, 0), None);
+        assert!(universe.cell_ages(Rect::new(0, 0, 2, 2)).is_empty());
     }
 
     #[test]
@@ -0,0 +1,435 @@
+//! A write-ahead journal of the changes made to a universe, for recovering
+//! it after a crash
+//!
+//! `Universe::set_journal` hands each change to a caller's sink as a
+//! `JournalEntry` as soon as it is made. The sink usually writes the entry's
+//! one-line text form to a file or local storage:
+//!
+//! ```text
+//! state 5649564501...
+//! history 256
+//! cells 0,0,1 1,0,1 2,0,1
+//! step
+//! superstep 4
+//! stepto 100
+//! back
+//! rule B36/S23
+//! ```
+//!
+//! `Universe::replay` rebuilds the universe from that text. Edits of any kind
+//! (setting cells, pasting, filling, cropping, combining universes) are
+//! journaled as the cells they changed, found by comparing the tree with the
+//! one at the last entry, so the journal does not depend on how an edit was
+//! made. Steps are journaled as the step calls themselves, which replay to the
+//! same cells and step history. Loading a pattern, and going further back
+//! than the journal can replay, write the whole universe as a snapshot.
+
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::analysis::diff_trees;
+use crate::formats::snapshot::SnapshotError;
+use crate::hashlife::{Node, SetRuleError, StepToError, Universe};
+use crate::rule::{ParseRuleError, Rule, State};
+
+/// One change to a universe
+#[derive(Clone, Debug, PartialEq)]
+pub enum JournalEntry {
+    /// The whole universe, as written by `Universe::to_bytes`. Journals start
+    /// with one, and loading a pattern writes another.
+    State(Vec<u8>),
+    /// A new `history_limit`
+    HistoryLimit(usize),
+    /// Cells changed by an edit, with their new states
+    Cells(Vec<(i64, i64, State)>),
+    /// `step`
+    Step,
+    /// `step_pow2` with this exponent (which `step_adaptive` also makes)
+    Superstep(u32),
+    /// `step_to` this generation (which `run_until_stable` also makes)
+    StepTo(u128),
+    /// `step_back`
+    StepBack,
+    /// `set_rule`
+    Rule(Rule),
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalEntry::State(bytes) => {
+                write!(f, "state ")?;
+                bytes.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            }
+            JournalEntry::HistoryLimit(limit) => write!(f, "history {}", limit),
+            JournalEntry::Cells(cells) => {
+                write!(f, "cells")?;
+                cells.iter().try_for_each(|(x, y, state)| write!(f, " {},{},{}", x, y, state))
+            }
+            JournalEntry::Step => write!(f, "step"),
+            JournalEntry::Superstep(exponent) => write!(f, "superstep {}", exponent),
+            JournalEntry::StepTo(generation) => write!(f, "stepto {}", generation),
+            JournalEntry::StepBack => write!(f, "back"),
+            JournalEntry::Rule(rule) => write!(f, "rule {}", rule),
+        }
+    }
+}
+
+/// Where `Universe::set_journal` sends the entries
+pub type JournalSink = Box<dyn FnMut(&JournalEntry)>;
+
+/// Error returned when a line of a journal is not an entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseJournalError {
+    UnknownEntry(String),
+    /// A number, cell or snapshot that cannot be read
+    InvalidArgument(String),
+    InvalidRule(ParseRuleError),
+}
+
+impl fmt::Display for ParseJournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseJournalError::UnknownEntry(entry) => write!(f, "unknown journal entry: {}", entry),
+            ParseJournalError::InvalidArgument(argument) => write!(f, "invalid journal argument: {}", argument),
+            ParseJournalError::InvalidRule(e) => write!(f, "invalid rule in journal: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseJournalError {}
+
+impl FromStr for JournalEntry {
+    type Err = ParseJournalError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let invalid = || ParseJournalError::InvalidArgument(argument.to_string());
+        match name {
+            "state" => {
+                if argument.len() % 2 != 0 || !argument.is_ascii() {
+                    return Err(invalid());
+                }
+                let bytes = (0..argument.len()).step_by(2)
+                    .map(|i| u8::from_str_radix(&argument[i..i + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| invalid())?;
+                Ok(JournalEntry::State(bytes))
+            }
+            "history" => Ok(JournalEntry::HistoryLimit(number(argument)?)),
+            "cells" => {
+                let cells = argument.split_whitespace()
+                    .map(|cell| {
+                        let mut fields = cell.split(',');
+                        let mut field = || fields.next().ok_or_else(invalid);
+                        let (x, y, state) = (field()?, field()?, field()?);
+                        match (x.parse(), y.parse(), state.parse(), fields.next()) {
+                            (Ok(x), Ok(y), Ok(state), None) => Ok((x, y, state)),
+                            _ => Err(ParseJournalError::InvalidArgument(cell.to_string())),
+                        }
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(JournalEntry::Cells(cells))
+            }
+            "step" if argument.is_empty() => Ok(JournalEntry::Step),
+            "superstep" => Ok(JournalEntry::Superstep(number(argument)?)),
+            "stepto" => Ok(JournalEntry::StepTo(number(argument)?)),
+            "back" if argument.is_empty() => Ok(JournalEntry::StepBack),
+            "rule" => argument.parse().map(JournalEntry::Rule).map_err(ParseJournalError::InvalidRule),
+            _ => Err(ParseJournalError::UnknownEntry(line.trim().to_string())),
+        }
+    }
+}
+
+fn number<T: FromStr>(argument: &str) -> Result<T, ParseJournalError> {
+    argument.parse().map_err(|_| ParseJournalError::InvalidArgument(argument.to_string()))
+}
+
+/// Error returned by `Universe::replay`. Lines are numbered from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The journal does not start with a `state` entry
+    MissingState,
+    Parse(usize, ParseJournalError),
+    InvalidState(usize, SnapshotError),
+    InvalidRuleChange(usize, SetRuleError),
+    InvalidStepTo(usize, StepToError),
+    /// A `back` entry with no step history to go back through
+    NoHistory(usize),
+    /// A superstep exponent above the largest `step_pow2` takes
+    InvalidSuperstep(usize),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::MissingState => write!(f, "journal does not start with a state"),
+            ReplayError::Parse(line, e) => write!(f, "line {}: {}", line, e),
+            ReplayError::InvalidState(line, e) => write!(f, "line {}: {}", line, e),
+            ReplayError::InvalidRuleChange(line, e) => write!(f, "line {}: {}", line, e),
+            ReplayError::InvalidStepTo(line, e) => write!(f, "line {}: {}", line, e),
+            ReplayError::NoHistory(line) => write!(f, "line {}: no step history to go back through", line),
+            ReplayError::InvalidSuperstep(line) => write!(f, "line {}: superstep exponent is too large", line),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// The sink a universe writes its journal to, and what it has written
+pub(crate) struct Journal {
+    sink: JournalSink,
+    /// The tree and background as of the last entry, to find what edits changed
+    root: Rc<Node>,
+    background: State,
+    /// Entries at the end of the step history made by journaled steps, which
+    /// a replay has too; going back past them needs a full state
+    replayable: usize,
+    /// Set when the universe went back past `replayable`, so the next entry
+    /// must be a full state
+    lost: bool,
+}
+
+impl Journal {
+    pub(crate) fn new(sink: JournalSink, root: Rc<Node>, background: State) -> Self {
+        Journal { sink, root, background, replayable: 0, lost: false }
+    }
+
+    /// Send `entry` to the sink, now that the universe holds `root`
+    pub(crate) fn write(&mut self, entry: &JournalEntry, root: &Rc<Node>, background: State) {
+        if let JournalEntry::State(_) = entry {
+            self.replayable = 0;
+            self.lost = false;
+        }
+        (self.sink)(entry);
+        self.root = root.clone();
+        self.background = background;
+    }
+
+    /// Write the cells changed since the last entry, if any
+    pub(crate) fn write_edits(&mut self, root: &Rc<Node>, background: State) {
+        if Rc::ptr_eq(root, &self.root) && background == self.background {
+            return;
+        }
+        let cells = diff_trees((&self.root, self.background), (root, background));
+        if !cells.is_empty() {
+            (self.sink)(&JournalEntry::Cells(cells));
+        }
+        self.root = root.clone();
+        self.background = background;
+    }
+
+    /// Note that a journaled step added an entry to the step history
+    pub(crate) fn recorded(&mut self) {
+        self.replayable += 1;
+    }
+
+    /// Note that the universe is going back through the step history, which
+    /// holds `history_len` entries beforehand
+    pub(crate) fn rewound(&mut self, history_len: usize) {
+        match self.replayable.min(history_len) {
+            0 => self.lost = true,
+            replayable => self.replayable = replayable - 1,
+        }
+    }
+
+    /// Whether the next entry must be a full state
+    pub(crate) fn is_lost(&self) -> bool {
+        self.lost
+    }
+}
+
+impl Universe {
+    /// The full state, as the first entries of a journal
+    pub(crate) fn state_entries(&self) -> [JournalEntry; 2] {
+        [JournalEntry::State(self.to_bytes()), JournalEntry::HistoryLimit(self.history_limit())]
+    }
+
+    /// Rebuild a universe from the text of its journal, one entry per line
+    /// as `JournalEntry` displays them. A last line with no newline after it
+    /// (cut off by a crash while it was written) is ignored, and so are blank
+    /// lines. The step history is rebuilt too, back to the first state in the
+    /// journal; settings other than the history limit are not journaled.
+    pub fn replay(journal: &str) -> Result<Universe, ReplayError> {
+        let complete = &journal[..journal.rfind('\n').map_or(0, |end| end + 1)];
+        let mut universe: Option<Universe> = None;
+        for (i, line) in complete.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let number = i + 1;
+            let entry: JournalEntry = line.parse().map_err(|e| ReplayError::Parse(number, e))?;
+            match (&mut universe, entry) {
+                (_, JournalEntry::State(bytes)) => {
+                    let limit = universe.as_ref().map(Universe::history_limit);
+                    let mut state = Universe::from_bytes(&bytes).map_err(|e| ReplayError::InvalidState(number, e))?;
+                    if let Some(limit) = limit {
+                        state.set_history_limit(limit);
+                    }
+                    universe = Some(state);
+                }
+                (None, _) => return Err(ReplayError::MissingState),
+                (Some(universe), entry) => universe.apply_journal_entry(&entry, number)?,
+            }
+        }
+        universe.ok_or(ReplayError::MissingState)
+    }
+
+    /// Make the change an entry records, other than a full state
+    fn apply_journal_entry(&mut self, entry: &JournalEntry, line: usize) -> Result<(), ReplayError> {
+        match entry {
+            JournalEntry::State(_) => unreachable!(),
+            JournalEntry::HistoryLimit(limit) => self.set_history_limit(*limit),
+            JournalEntry::Cells(cells) => self.set_cell_states(cells),
+            JournalEntry::Step => self.step(),
+            JournalEntry::Superstep(exponent) => {
+                if *exponent > crate::hashlife::MAX_STEP_EXPONENT {
+                    return Err(ReplayError::InvalidSuperstep(line));
+                }
+                self.step_pow2(*exponent).map_err(|e| ReplayError::InvalidStepTo(line, e))?;
+            }
+            JournalEntry::StepTo(generation) => {
+                self.step_to(*generation).map_err(|e| ReplayError::InvalidStepTo(line, e))?;
+            }
+            JournalEntry::StepBack => {
+                if !self.step_back() {
+                    return Err(ReplayError::NoHistory(line));
+                }
+            }
+            JournalEntry::Rule(rule) => {
+                self.set_rule(rule.clone()).map_err(|e| ReplayError::InvalidRuleChange(line, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::analysis::RunLimits;
+    use crate::edit::{EditSymmetry, PasteMode};
+    use crate::pattern::{Pattern, Rect, Symmetry};
+
+    /// A universe journaling into a shared string, one entry per line
+    fn journaled(universe: &mut Universe) -> Rc<RefCell<String>> {
+        let text = Rc::new(RefCell::new(String::new()));
+        let sink = text.clone();
+        universe.set_journal(Some(Box::new(move |entry| sink.borrow_mut().push_str(&format!("{}\n", entry)))));
+        text
+    }
+
+    /// The same cells, generation, rule and grid, if not the same tree size
+    fn assert_same(a: &Universe, b: &Universe) {
+        assert_eq!((a.generation(), a.background(), a.rule(), a.topology()),
+                   (b.generation(), b.background(), b.rule(), b.topology()));
+        assert_eq!(a.diff(b), []);
+    }
+
+    fn assert_replays(universe: &Universe, journal: &str) {
+        let replayed = Universe::replay(journal).unwrap();
+        assert_same(&replayed, universe);
+        assert_eq!((replayed.history_len(), replayed.history_limit()), (universe.history_len(), universe.history_limit()));
+    }
+
+    #[test]
+    fn test_entries() {
+        let entries = [
+            JournalEntry::State(vec![0x56, 0x0f, 0xa0]),
+            JournalEntry::HistoryLimit(8),
+            JournalEntry::Cells(vec![(-3, 4, 1), (5, -6, 2)]),
+            JournalEntry::Step,
+            JournalEntry::Superstep(3),
+            JournalEntry::StepTo(1 << 40),
+            JournalEntry::StepBack,
+            JournalEntry::Rule("R2,C0,M1,S5..9,B6..8,NM".parse().unwrap()),
+        ];
+        for entry in entries {
+            assert_eq!(entry.to_string().parse::<JournalEntry>(), Ok(entry));
+        }
+        assert_eq!(JournalEntry::Cells(vec![(0, 1, 1)]).to_string(), "cells 0,1,1");
+        assert_eq!("stop".parse::<JournalEntry>(), Err(ParseJournalError::UnknownEntry("stop".into())));
+        assert_eq!("cells 1,2".parse::<JournalEntry>(), Err(ParseJournalError::InvalidArgument("1,2".into())));
+        assert_eq!("state abc".parse::<JournalEntry>(), Err(ParseJournalError::InvalidArgument("abc".into())));
+        assert!(matches!("rule B9".parse::<JournalEntry>(), Err(ParseJournalError::InvalidRule(_))));
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut universe = Universe::new(4);
+        universe.set_cell(40, 40, true);
+        let journal = journaled(&mut universe);
+        assert!(universe.is_journaling());
+        assert!(journal.borrow().starts_with("state 56495645"));
+        assert_replays(&universe, &journal.borrow());
+
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        universe.paste(&glider, 0, 0, PasteMode::Or);
+        universe.step();
+        universe.step_pow2(3).unwrap();
+        universe.fill_random(Rect::new(-30, -30, 10, 10), 0.5, 7);
+        universe.set_edit_symmetry(Some(EditSymmetry { symmetry: Symmetry::C4, centre: (0, 0) }));
+        universe.set_cell(-20, 5, true);
+        universe.set_edit_symmetry(None);
+        universe.translate(3, -2);
+        assert_replays(&universe, &journal.borrow());
+        assert!(journal.borrow().lines().any(|line| line == "superstep 3"));
+
+        universe.step_back();
+        universe.step_to(30).unwrap();
+        universe.step_to(1).unwrap();
+        universe.set_history_limit(2);
+        universe.crop(Rect::new(-40, -40, 60, 60));
+        universe.set_rule("B36/S23".parse().unwrap()).unwrap();
+        assert_replays(&universe, &journal.borrow());
+        universe.run_until_stable(RunLimits { max_generations: 50, ..RunLimits::default() });
+        assert_replays(&universe, &journal.borrow());
+
+        // Going back through history from before the journal started needs the whole state
+        let mut old = Universe::new(4);
+        old.set_cell(0, 0, true);
+        old.step();
+        let journal = journaled(&mut old);
+        old.step();
+        old.step_back();
+        assert!(journal.borrow().ends_with("back\n"));
+        old.step_back();
+        assert!(journal.borrow().lines().last().unwrap().starts_with("history"));
+        assert_same(&Universe::replay(&journal.borrow()).unwrap(), &old);
+
+        // A B0 rule stores its cells complemented half of the time
+        let mut b0 = Universe::with_rule(4, "B0123478/S34678".parse().unwrap());
+        let journal = journaled(&mut b0);
+        b0.fill_random(Rect::new(-8, -8, 16, 16), 0.4, 3);
+        b0.step();
+        b0.set_cell(2, 2, false);
+        b0.step();
+        b0.set_cell(2, 3, true);
+        assert_replays(&b0, &journal.borrow());
+    }
+
+    #[test]
+    fn test_replay_after_load() {
+        let mut universe = Universe::new(4);
+        let journal = journaled(&mut universe);
+        universe.set_cell(0, 0, true);
+        universe.load_rle("x = 3, y = 1, rule = B3/S23\n3o!", 0, 0).unwrap();
+        assert!(universe.is_journaling());
+        universe.step();
+        assert_replays(&universe, &journal.borrow());
+        assert_eq!(journal.borrow().lines().filter(|line| line.starts_with("state")).count(), 2);
+
+        // A line cut off part way is left out
+        let cut = format!("{}stepto 10", journal.borrow());
+        assert_eq!(Universe::replay(&cut).unwrap().generation(), 1);
+        universe.set_journal(None);
+        universe.step();
+        assert_eq!(Universe::replay(&journal.borrow()).unwrap().generation(), 1);
+
+        assert_eq!(Universe::replay("step\n").err(), Some(ReplayError::MissingState));
+        assert_eq!(Universe::replay("").err(), Some(ReplayError::MissingState));
+        let header = journal.borrow().lines().next().unwrap().to_string();
+        assert_eq!(Universe::replay(&format!("{}\nback\n", header)).err(), Some(ReplayError::NoHistory(2)));
+        assert!(matches!(Universe::replay(&format!("{}\nbogus\n", header)), Err(ReplayError::Parse(2, _))));
+    }
+}
@@ -1,7 +1,35 @@
+pub mod analysis;
+pub mod apgcode;
+pub mod automaton;
+pub mod builder;
+pub mod census;
+pub mod checkpoint;
+pub mod draw;
+pub mod edit;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod font;
+pub mod formats;
 pub mod hashlife;
+pub mod journal;
+pub mod library;
+pub mod memory;
+#[cfg(any(test, feature = "naive"))]
+pub mod naive;
+pub mod pattern;
+pub mod random;
+pub mod render;
+pub mod rule;
+pub mod search;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod stats;
+pub mod timeline;
+pub mod topology;
+pub mod undo;
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(feature = "wasm")]
 pub mod wasm;
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(feature = "wasm")]
 pub use wasm::*;
@@ -0,0 +1,118 @@
+//! A small library of well-known Conway's Game of Life patterns, built in so
+//! demos and tests can start from one by name
+
+use crate::hashlife::Universe;
+
+/// A named pattern in the library
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LibraryPattern {
+    /// Lower-case name with hyphens, such as "gosper-glider-gun"
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The pattern as RLE, with its rule in the header
+    pub rle: &'static str,
+}
+
+/// The patterns, still lifes and oscillators first, then spaceships, guns
+/// and methuselahs
+pub const PATTERNS: &[LibraryPattern] = &[
+    LibraryPattern { name: "block", description: "2 by 2 still life", rle: "x = 2, y = 2, rule = B3/S23\n2o$2o!" },
+    LibraryPattern { name: "beehive", description: "Six-cell still life", rle: "x = 4, y = 3, rule = B3/S23\nb2o$o2bo$b2o!" },
+    LibraryPattern { name: "blinker", description: "Period 2 oscillator", rle: "x = 3, y = 1, rule = B3/S23\n3o!" },
+    LibraryPattern { name: "toad", description: "Period 2 oscillator", rle: "x = 4, y = 2, rule = B3/S23\nb3o$3o!" },
+    LibraryPattern { name: "beacon", description: "Period 2 oscillator", rle: "x = 4, y = 4, rule = B3/S23\n2o$2o$2b2o$2b2o!" },
+    LibraryPattern {
+        name: "pulsar",
+        description: "Period 3 oscillator",
+        rle: "x = 13, y = 13, rule = B3/S23\n2b3o3b3o2$o4bobo4bo$o4bobo4bo$o4bobo4bo$2b3o3b3o2$2b3o3b3o$o4bobo4bo$\
+              o4bobo4bo$o4bobo4bo2$2b3o3b3o!",
+    },
+    LibraryPattern {
+        name: "pentadecathlon",
+        description: "Period 15 oscillator",
+        rle: "x = 10, y = 3, rule = B3/S23\n2bo4bo$2ob4ob2o$2bo4bo!",
+    },
+    LibraryPattern { name: "glider", description: "c/4 diagonal spaceship", rle: "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!" },
+    LibraryPattern {
+        name: "lwss",
+        description: "Lightweight spaceship, c/2 orthogonal",
+        rle: "x = 5, y = 4, rule = B3/S23\nbo2bo$o$o3bo$4o!",
+    },
+    LibraryPattern {
+        name: "mwss",
+        description: "Middleweight spaceship, c/2 orthogonal",
+        rle: "x = 6, y = 5, rule = B3/S23\n3bo$bo3bo$o$o4bo$5o!",
+    },
+    LibraryPattern {
+        name: "hwss",
+        description: "Heavyweight spaceship, c/2 orthogonal",
+        rle: "x = 7, y = 5, rule = B3/S23\n3b2o$bo4bo$o$o5bo$6o!",
+    },
+    LibraryPattern {
+        name: "gosper-glider-gun",
+        description: "The first known gun, firing a glider every 30 generations",
+        rle: "x = 36, y = 9, rule = B3/S23\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b\
+              obo$10bo5bo7bo$11bo3bo$12b2o!",
+    },
+    LibraryPattern {
+        name: "r-pentomino",
+        description: "Methuselah that stabilises after 1103 generations",
+        rle: "x = 3, y = 3, rule = B3/S23\nb2o$2o$bo!",
+    },
+    LibraryPattern {
+        name: "diehard",
+        description: "Methuselah that dies out after 130 generations",
+        rle: "x = 8, y = 3, rule = B3/S23\n6bo$2o$bo3b3o!",
+    },
+    LibraryPattern {
+        name: "acorn",
+        description: "Methuselah that stabilises after 5206 generations",
+        rle: "x = 7, y = 3, rule = B3/S23\nbo$3bo$2o2b3o!",
+    },
+];
+
+/// The library pattern called `name`
+pub fn find(name: &str) -> Option<&'static LibraryPattern> {
+    PATTERNS.iter().find(|pattern| pattern.name == name)
+}
+
+impl Universe {
+    /// A universe holding the library pattern called `name`, centred on the
+    /// origin, or None if there is no such pattern
+    pub fn from_library(name: &str) -> Option<Universe> {
+        let pattern = find(name)?;
+        let mut universe = Universe::new(3);
+        // The library's patterns are known to parse
+        universe.load_rle(pattern.rle, 0, 0).ok()?;
+        if let Some((left, top, width, height)) = universe.bounding_box() {
+            universe.translate(-(left + width / 2), -(top + height / 2));
+        }
+        Some(universe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library() {
+        let period = |name: &str| Universe::from_library(name).unwrap().find_period(100).map(|p| (p.period, p.dx, p.dy));
+        assert_eq!(period("block"), Some((1, 0, 0)));
+        assert_eq!(period("beacon"), Some((2, 0, 0)));
+        assert_eq!(period("pulsar"), Some((3, 0, 0)));
+        assert_eq!(period("pentadecathlon"), Some((15, 0, 0)));
+        assert_eq!(period("glider"), Some((4, 1, 1)));
+        for name in ["lwss", "mwss", "hwss"] {
+            assert_eq!(period(name).map(|(period, dx, dy)| (period, dx.abs() + dy.abs())), Some((4, 2)), "{}", name);
+        }
+
+        let mut diehard = Universe::from_library("diehard").unwrap();
+        diehard.step_to(130).unwrap();
+        assert_eq!(diehard.population(), 0);
+        let gun = Universe::from_library("gosper-glider-gun").unwrap();
+        assert_eq!((gun.population(), gun.bounding_box()), (36, Some((-18, -4, 36, 9))));
+        assert!(Universe::from_library("unknown").is_none());
+        assert!(PATTERNS.iter().all(|pattern| Universe::from_library(pattern.name).is_some()));
+    }
+}
@@ -0,0 +1,174 @@
+//! Estimates of the memory a universe uses
+//!
+//! The figures count what the universe's tables hold, from the sizes of their
+//! entries and how many they have room for, rather than asking the
+//! allocator, so they are approximate but cheap to take after every step.
+//!
+//! `Universe::set_memory_limit` keeps a universe within a budget of these
+//! bytes. As a step creates nodes the universe checks its usage, and when it
+//! is over the limit it collects garbage: canonical nodes that nothing refers
+//! to any more are freed, with their memoized results. If that is not enough
+//! it drops every memoized result too and collects again, then carries on
+//! stepping, as Golly does. When the nodes still in use need more than the
+//! limit, the universe has to go over it.
+
+use std::mem::size_of;
+
+/// Approximate bytes used by a universe, from `Universe::memory_usage`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Canonical nodes and the table that finds them. Universes sharing a
+    /// node cache each count all of it.
+    pub nodes: usize,
+    /// Memoized step results
+    pub results: usize,
+    /// Step history, checkpoints, timeline keyframes and undo stack. These
+    /// hold roots, whose nodes are counted in `nodes`.
+    pub history: usize,
+    /// Rule lookup tables, recorded population and cell ages
+    pub other: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.nodes + self.results + self.history + self.other
+    }
+}
+
+/// New canonical nodes between checks of the memory limit
+const CHECK_INTERVAL: usize = 1 << 14;
+
+/// A universe's memory limit and when to check it next
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MemoryLimit {
+    bytes: Option<usize>,
+    /// Number of canonical nodes at which the next check is due
+    next_check: usize,
+}
+
+impl MemoryLimit {
+    pub(crate) fn bytes(&self) -> Option<usize> {
+        self.bytes
+    }
+
+    pub(crate) fn set(&mut self, bytes: Option<usize>) {
+        *self = MemoryLimit { bytes, next_check: 0 };
+    }
+
+    /// Whether a node cache of `nodes` nodes is due a check
+    pub(crate) fn is_due(&self, nodes: usize) -> bool {
+        self.bytes.is_some() && nodes >= self.next_check
+    }
+
+    /// Whether `used` bytes are over the limit
+    pub(crate) fn is_over(&self, used: usize) -> bool {
+        self.bytes.is_some_and(|bytes| used > bytes)
+    }
+
+    /// Note a check that left `nodes` nodes using `used` bytes. While still
+    /// over the limit, the next check waits for the cache to grow by half, so
+    /// that a pattern too large for the limit is not collected over and over.
+    pub(crate) fn checked(&mut self, nodes: usize, used: usize) {
+        let interval = if self.is_over(used) { (nodes / 2).max(CHECK_INTERVAL) } else { CHECK_INTERVAL };
+        self.next_check = nodes + interval;
+    }
+}
+
+/// Bytes a hash table with room for `capacity` entries of `(K, V)` takes,
+/// with one control byte per entry
+pub(crate) fn table_size<K, V>(capacity: usize) -> usize {
+    capacity * (size_of::<(K, V)>() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hashlife::Universe;
+
+    #[test]
+    fn test_memory_usage() {
+        let mut universe = Universe::new(3);
+        let empty = universe.memory_usage();
+        assert!(empty.nodes > 0 && empty.history == 0);
+        // Conway's Game of Life has a lookup table for each phase
+        assert_eq!(empty.other, 2 << 16);
+
+        universe.set_history_limit(10);
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        for _ in 0..100 {
+            universe.step();
+        }
+        let usage = universe.memory_usage();
+        assert!(usage.nodes > empty.nodes && usage.results > 0 && usage.history > 0);
+        assert_eq!(usage.total(), usage.nodes + usage.results + usage.history + usage.other);
+    }
+
+    fn r_pentomino() -> Universe {
+        let mut universe = Universe::new(3);
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        universe
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut universe = r_pentomino();
+        for _ in 0..200 {
+            universe.step();
+        }
+        let nodes = universe.node_cache().len();
+        universe.collect_garbage();
+        assert!(universe.node_cache().len() < nodes);
+
+        // What is left still steps as before
+        let mut expected = r_pentomino();
+        expected.step_to(400).unwrap();
+        universe.step_to(400).unwrap();
+        assert_eq!(universe.live_cells(), expected.live_cells());
+
+        // A shared cache is collected too, without upsetting the memoized
+        // results of the other universes using it
+        let mut fork = universe.fork();
+        fork.set_history_limit(0);
+        for _ in 0..200 {
+            fork.step();
+        }
+        let nodes = universe.node_cache().len();
+        universe.collect_garbage();
+        assert!(universe.node_cache().len() < nodes);
+        fork.step_to(800).unwrap();
+        expected.step_to(800).unwrap();
+        assert_eq!(fork.live_cells(), expected.live_cells());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut unlimited = r_pentomino();
+        for _ in 0..500 {
+            unlimited.step();
+        }
+        let peak = unlimited.memory_usage().total();
+
+        let mut universe = r_pentomino();
+        universe.set_memory_limit(Some(peak / 4));
+        for _ in 0..500 {
+            universe.step();
+            assert!(universe.memory_usage().total() < peak / 2);
+        }
+        assert_eq!(universe.live_cells(), unlimited.live_cells());
+        assert_eq!(universe.memory_limit(), Some(peak / 4));
+
+        // The limit holds for a universe sharing its cache with an idle one
+        let idle = r_pentomino();
+        let mut fork = idle.fork();
+        fork.set_memory_limit(Some(peak / 4));
+        for _ in 0..500 {
+            fork.step();
+            assert!(fork.memory_usage().total() < peak / 2);
+        }
+        assert_eq!(fork.live_cells(), unlimited.live_cells());
+        drop(idle);
+    }
+}
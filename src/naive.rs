@@ -0,0 +1,300 @@
+//! A plain cell-by-cell engine, for checking HashLife's results
+//!
+//! `Grid` steps every cell near the pattern (or every cell of a finite grid)
+//! directly from the rule's transition, with none of the quadtree machinery,
+//! so it is slow but easy to trust. Run a `Grid` and a `Universe` side by
+//! side and compare them with `assert_equivalent`:
+//!
+//! ```
+//! # use vive_hashlife::hashlife::Universe;
+//! # use vive_hashlife::naive::{assert_equivalent, Grid};
+//! # use vive_hashlife::pattern::Rect;
+//! let mut universe = Universe::new(4);
+//! for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+//!     universe.set_cell(x, y, true);
+//! }
+//! let mut grid = Grid::from_universe(&universe);
+//! for _ in 0..8 {
+//!     universe.step();
+//!     grid.step();
+//! }
+//! assert_equivalent(&universe, &grid, Rect::new(-10, -10, 20, 20));
+//! ```
+
+use std::collections::HashMap;
+
+use crate::automaton::CellularAutomaton;
+use crate::hashlife::Universe;
+use crate::pattern::Rect;
+use crate::rule::{Rule, State};
+use crate::topology::{Edge, Topology};
+
+/// Cells stepped one by one under a rule and topology
+#[derive(Clone, Debug)]
+pub struct Grid {
+    rule: Rule,
+    topology: Topology,
+    /// Cells that differ from the background
+    cells: HashMap<(i64, i64), State>,
+    /// State of every cell not in `cells`: on finite grids always 0, on the
+    /// plane whatever the rule makes of an infinite uniform background
+    background: State,
+    generation: u128,
+    /// Neighbour offsets of the rule, with (0, 0) if it counts the centre
+    offsets: Vec<(i32, i32)>,
+}
+
+impl Grid {
+    /// An empty grid
+    pub fn new(rule: Rule, topology: Topology) -> Self {
+        topology.validate();
+        let offsets = rule.neighbour_offsets();
+        Grid { rule, topology, cells: HashMap::new(), background: 0, generation: 0, offsets }
+    }
+
+    /// A grid with the cells, rule, topology and generation of `universe`
+    pub fn from_universe(universe: &Universe) -> Self {
+        let mut grid = Grid::new(universe.rule().clone(), universe.topology());
+        grid.background = universe.background();
+        grid.generation = universe.generation();
+        grid.cells = universe.live_cells().into_iter().map(|(x, y, state)| ((x, y), state)).collect();
+        grid
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    pub fn generation(&self) -> u128 {
+        self.generation
+    }
+
+    pub fn background(&self) -> State {
+        self.background
+    }
+
+    /// Number of cells that differ from the background
+    pub fn population(&self) -> u64 {
+        self.cells.len() as u64
+    }
+
+    /// State of a cell, wrapping around a finite grid as `Universe::get_cell_state` does
+    pub fn get_cell_state(&self, x: i64, y: i64) -> State {
+        match self.topology.map(x, y) {
+            Some(cell) => self.cells.get(&cell).copied().unwrap_or(self.background),
+            None => self.topology.edge().map_or(0, Edge::state),
+        }
+    }
+
+    /// Set a cell. Cells beyond the edge of a finite grid are ignored.
+    pub fn set_cell_state(&mut self, x: i64, y: i64, state: State) {
+        let Some(cell) = self.topology.map(x, y) else {
+            return;
+        };
+        if state == self.background {
+            self.cells.remove(&cell);
+        } else {
+            self.cells.insert(cell, state);
+        }
+    }
+
+    /// Advance one generation
+    pub fn step(&mut self) {
+        let candidates: Vec<(i64, i64)> = match self.topology.bounds() {
+            Some((left, top, width, height)) => (top..top + height)
+                .flat_map(|y| (left..left + width).map(move |x| (x, y)))
+                .collect(),
+            None => {
+                // A cell can only change if it or a cell it counts differs from the background
+                let mut candidates: Vec<_> = self.cells.keys()
+                    .flat_map(|&(x, y)| self.offsets.iter().map(move |&(dx, dy)| (x - dx as i64, y - dy as i64)))
+                    .chain(self.cells.keys().copied())
+                    .collect();
+                candidates.sort_unstable();
+                candidates.dedup();
+                candidates
+            }
+        };
+
+        let mut counts = vec![0; self.rule.states() as usize];
+        let background = if self.topology.is_infinite() {
+            counts[self.background as usize] = self.offsets.len() as u32;
+            self.rule.transition(self.background, &counts)
+        } else {
+            0
+        };
+        let mut cells = HashMap::new();
+        for (x, y) in candidates {
+            counts.fill(0);
+            for &(dx, dy) in &self.offsets {
+                counts[self.get_cell_state(x + dx as i64, y + dy as i64) as usize] += 1;
+            }
+            let state = self.rule.transition(self.get_cell_state(x, y), &counts);
+            if state != background {
+                cells.insert((x, y), state);
+            }
+        }
+        self.cells = cells;
+        self.background = background;
+        self.generation += 1;
+    }
+
+    /// Advance `generations` generations
+    pub fn step_by(&mut self, generations: u64) {
+        for _ in 0..generations {
+            self.step();
+        }
+    }
+}
+
+impl CellularAutomaton for Grid {
+    fn step(&mut self) {
+        Grid::step(self);
+    }
+
+    fn generation(&self) -> u128 {
+        self.generation
+    }
+
+    fn get_cell_state(&self, x: i64, y: i64) -> State {
+        Grid::get_cell_state(self, x, y)
+    }
+
+    fn set_cell_state(&mut self, x: i64, y: i64, state: State) {
+        Grid::set_cell_state(self, x, y, state);
+    }
+
+    fn population(&self) -> u64 {
+        Grid::population(self)
+    }
+
+    fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let mut cells = self.cells.keys();
+        let &(x, y) = cells.next()?;
+        let (left, top, right, bottom) = cells.fold((x, y, x, y), |(left, top, right, bottom), &(x, y)| {
+            (left.min(x), top.min(y), right.max(x), bottom.max(y))
+        });
+        Some((left, top, right - left + 1, bottom - top + 1))
+    }
+}
+
+/// The first cell of `region`, row by row, where `universe` and `grid`
+/// disagree, as `(x, y, universe_state, grid_state)`
+pub fn first_difference(universe: &Universe, grid: &Grid, region: Rect) -> Option<(i64, i64, State, State)> {
+    (region.top..region.top + region.height)
+        .flat_map(|y| (region.left..region.left + region.width).map(move |x| (x, y)))
+        .map(|(x, y)| (x, y, universe.get_cell_state(x, y), grid.get_cell_state(x, y)))
+        .find(|&(_, _, ours, theirs)| ours != theirs)
+}
+
+/// Panic unless `universe` and `grid` are at the same generation, have the
+/// same background and agree on every cell of `region`
+pub fn assert_equivalent(universe: &Universe, grid: &Grid, region: Rect) {
+    assert_eq!(universe.generation(), grid.generation(), "generations differ");
+    assert_eq!(universe.background(), grid.background(), "backgrounds differ at generation {}", grid.generation());
+    if let Some((x, y, ours, theirs)) = first_difference(universe, grid, region) {
+        panic!("cell ({}, {}) differs at generation {}: state {} in the universe, {} in the grid",
+               x, y, grid.generation(), ours, theirs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    /// Random cells in states 1 to `states - 1` over `rect`, in both engines
+    fn seeded(rule: &str, topology: Topology, rect: Rect, seed: u64) -> (Universe, Grid) {
+        let rule: Rule = rule.parse().unwrap();
+        let mut universe = Universe::with_topology(4, rule.clone(), topology);
+        let mut grid = Grid::new(rule.clone(), topology);
+        let mut rng = Rng::new(seed);
+        for y in rect.top..rect.top + rect.height {
+            for x in rect.left..rect.left + rect.width {
+                if rng.chance(0.4) {
+                    let state = 1 + rng.below(rule.states() as u64 - 1) as State;
+                    universe.set_cell_state(x, y, state);
+                    grid.set_cell_state(x, y, state);
+                }
+            }
+        }
+        (universe, grid)
+    }
+
+    #[test]
+    fn test_matches_universe() {
+        let soup = Rect::new(-8, -8, 16, 16);
+        let view = Rect::new(-40, -40, 80, 80);
+        for rule in ["B3/S23", "B36/S23", "B2/S34H", "B13/S012V", "R2,C0,M1,S5..9,B6..8,NM", "B2/S/C4",
+                     "B0123478/S34678", "B0/S8"] {
+            let (mut universe, mut grid) = seeded(rule, Topology::Plane, soup, 11);
+            for generation in 0..12 {
+                assert_equivalent(&universe, &grid, view);
+                if generation % 3 == 0 {
+                    universe.step_pow2(1).unwrap();
+                    grid.step_by(2);
+                } else {
+                    universe.step();
+                    grid.step();
+                }
+            }
+        }
+
+        let torus = Topology::Torus { width: 12, height: 10 };
+        let (mut universe, mut grid) = seeded("B3/S23", torus, Rect::new(-6, -5, 12, 10), 5);
+        for _ in 0..20 {
+            universe.step();
+            grid.step();
+            assert_equivalent(&universe, &grid, Rect::new(-12, -10, 24, 20));
+        }
+    }
+
+    #[test]
+    fn test_b0_torus() {
+        // The tree stores the complement of the grid on odd generations, which
+        // must not show through: a finite grid has no background beyond it
+        let rule: Rule = "B03/S23".parse().unwrap();
+        let mut universe = Universe::with_topology(3, rule, Topology::Torus { width: 6, height: 6 });
+        universe.set_cell(0, 0, true);
+        universe.set_cell(1, 0, true);
+        universe.set_age_tracking(true);
+        let mut grid = Grid::from_universe(&universe);
+        let view = Rect::new(-6, -6, 12, 12);
+        for _ in 0..6 {
+            universe.step();
+            grid.step();
+            assert_equivalent(&universe, &grid, view);
+            let copy = Grid::from_universe(&universe);
+            assert_eq!(first_difference(&universe, &copy, view), None);
+            assert_eq!((copy.population(), universe.state_population(1)), (grid.population(), grid.population()));
+            assert_eq!(universe.bounding_box(), grid.bounding_box());
+        }
+
+        universe.step();
+        assert_eq!(universe.background(), 0);
+        for y in -3..3 {
+            for x in -3..3 {
+                assert_eq!(universe.cell_age(x, y).is_some(), universe.get_cell(x, y), "({}, {})", x, y);
+            }
+        }
+        assert_eq!(universe.cell_ages(Rect::new(-3, -3, 6, 6)).len() as u64, universe.population());
+    }
+
+    #[test]
+    fn test_first_difference() {
+        let (mut universe, grid) = seeded("B3/S23", Topology::Plane, Rect::new(0, 0, 4, 4), 2);
+        let region = Rect::new(-5, -5, 10, 10);
+        assert_eq!(first_difference(&universe, &grid, region), None);
+        universe.set_cell(-3, 4, true);
+        universe.set_cell(2, -4, true);
+        assert_eq!(first_difference(&universe, &grid, region), Some((2, -4, 1, 0)));
+        assert_eq!(Grid::from_universe(&universe).population(), universe.population());
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| assert_equivalent(&universe, &grid, region)));
+        assert!(result.is_err());
+    }
+}
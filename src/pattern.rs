@@ -0,0 +1,523 @@
+use std::collections::BTreeMap;
+
+use crate::hashlife::Universe;
+use crate::rule::{Rule, State};
+
+/// One of the eight rotations and reflections of the grid (the symmetries of
+/// a square). Rotations are clockwise as displayed, with y increasing downwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transform {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirror left to right
+    FlipX,
+    /// Mirror top to bottom
+    FlipY,
+    /// Swap x and y
+    FlipDiagonal,
+    /// Swap x and y and negate both
+    FlipAntiDiagonal,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Identity,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipX,
+        Transform::FlipY,
+        Transform::FlipDiagonal,
+        Transform::FlipAntiDiagonal,
+    ];
+
+    /// Where the transform takes the cell `(x, y)`, turning about the origin
+    pub fn apply(self, x: i64, y: i64) -> (i64, i64) {
+        match self {
+            Transform::Identity => (x, y),
+            Transform::Rotate90 => (-y, x),
+            Transform::Rotate180 => (-x, -y),
+            Transform::Rotate270 => (y, -x),
+            Transform::FlipX => (-x, y),
+            Transform::FlipY => (x, -y),
+            Transform::FlipDiagonal => (y, x),
+            Transform::FlipAntiDiagonal => (-y, -x),
+        }
+    }
+}
+
+/// A symmetry group of the grid, named as on Catagolue
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    /// Half turns
+    C2,
+    /// Quarter turns
+    C4,
+    /// Mirroring left to right
+    D2,
+    /// Mirroring left to right and top to bottom
+    D4,
+    /// Every rotation and reflection
+    D8,
+}
+
+impl Symmetry {
+    /// The transforms of the group, starting with the identity
+    pub fn transforms(self) -> &'static [Transform] {
+        use Transform::*;
+        match self {
+            Symmetry::C2 => &[Identity, Rotate180],
+            Symmetry::C4 => &[Identity, Rotate90, Rotate180, Rotate270],
+            Symmetry::D2 => &[Identity, FlipX],
+            Symmetry::D4 => &[Identity, FlipX, FlipY, Rotate180],
+            Symmetry::D8 => &Transform::ALL,
+        }
+    }
+
+    /// Whether the group swaps the axes, which needs a centre whose
+    /// coordinates are both on cells or both between cells
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Symmetry::C4 | Symmetry::D8)
+    }
+}
+
+/// A rectangle of cells
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub left: i64,
+    pub top: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+impl Rect {
+    pub fn new(left: i64, top: i64, width: i64, height: i64) -> Self {
+        Rect { left, top, width, height }
+    }
+
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        (self.left..self.left + self.width).contains(&x) && (self.top..self.top + self.height).contains(&y)
+    }
+
+    /// The cells in both rectangles, or None if they do not overlap
+    pub fn intersection(&self, other: Rect) -> Option<Rect> {
+        let (left, top) = (self.left.max(other.left), self.top.max(other.top));
+        let right = self.left.saturating_add(self.width).min(other.left.saturating_add(other.width));
+        let bottom = self.top.saturating_add(self.height).min(other.top.saturating_add(other.height));
+        (left < right && top < bottom).then(|| Rect::new(left, top, right - left, bottom - top))
+    }
+}
+
+/// A finite arrangement of cells, independent of any universe
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    /// `(x, y, state)` of every non-zero cell, sorted by position
+    cells: Vec<(i64, i64, State)>,
+}
+
+impl Pattern {
+    pub fn new() -> Self {
+        Pattern::default()
+    }
+
+    /// Pattern from `(x, y, state)` triples. Zero states are dropped, and a
+    /// later triple for the same position replaces an earlier one.
+    pub fn from_cells<I: IntoIterator<Item = (i64, i64, State)>>(cells: I) -> Self {
+        let cells: BTreeMap<(i64, i64), State> = cells.into_iter().map(|(x, y, state)| ((x, y), state)).collect();
+        Pattern {
+            cells: cells.into_iter().filter(|&(_, state)| state != 0).map(|((x, y), state)| (x, y, state)).collect(),
+        }
+    }
+
+    /// Two-state pattern with the given cells alive
+    pub fn from_live_cells<I: IntoIterator<Item = (i64, i64)>>(cells: I) -> Self {
+        Pattern::from_cells(cells.into_iter().map(|(x, y)| (x, y, 1)))
+    }
+
+    /// Pattern of the dark pixels of a PNG, GIF or BMP image, so pixel art and
+    /// logos drawn dark on light can be placed in a universe. A pixel is alive
+    /// when its brightness, after blending any transparency onto white, is
+    /// below `threshold` (0 to 255); pixel (x, y) becomes cell (x, y).
+    #[cfg(feature = "image")]
+    pub fn from_image(bytes: &[u8], threshold: u8) -> Result<Pattern, image::ImageError> {
+        let image = image::load_from_memory(bytes)?.into_luma_alpha8();
+        Ok(Pattern::from_live_cells(image.enumerate_pixels().filter_map(|(x, y, pixel)| {
+            let [luma, alpha] = pixel.0.map(u32::from);
+            let brightness = (luma * alpha + 255 * (255 - alpha)) / 255;
+            (brightness < threshold as u32).then_some((x as i64, y as i64))
+        })))
+    }
+
+    /// `(x, y, state)` of every non-zero cell, sorted by x then y
+    pub fn cells(&self) -> &[(i64, i64, State)] {
+        &self.cells
+    }
+
+    pub fn population(&self) -> u64 {
+        self.cells.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> State {
+        match self.cells.binary_search_by_key(&(x, y), |&(cx, cy, _)| (cx, cy)) {
+            Ok(i) => self.cells[i].2,
+            Err(_) => 0,
+        }
+    }
+
+    /// Smallest rectangle `(left, top, width, height)` containing every cell,
+    /// or None if the pattern is empty
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let (first, last) = (self.cells.first()?, self.cells.last()?);
+        let top = self.cells.iter().map(|c| c.1).min()?;
+        let bottom = self.cells.iter().map(|c| c.1).max()?;
+        Some((first.0, top, last.0 - first.0 + 1, bottom - top + 1))
+    }
+
+    pub fn translated(&self, dx: i64, dy: i64) -> Pattern {
+        Pattern { cells: self.cells.iter().map(|&(x, y, state)| (x + dx, y + dy, state)).collect() }
+    }
+
+    /// The pattern moved so its bounding box starts at the origin
+    pub fn normalized(&self) -> Pattern {
+        match self.bounding_box() {
+            Some((left, top, _, _)) => self.translated(-left, -top),
+            None => Pattern::new(),
+        }
+    }
+
+    /// The pattern rotated or reflected about the origin
+    pub fn transformed(&self, transform: Transform) -> Pattern {
+        let cells = self.cells.iter().map(|&(x, y, state)| {
+            let (x, y) = transform.apply(x, y);
+            (x, y, state)
+        });
+        Pattern::from_cells(cells)
+    }
+
+    /// The eight rotations and reflections of the pattern, each normalized
+    pub(crate) fn orientations(&self) -> Vec<Pattern> {
+        Transform::ALL.iter().map(|&transform| self.transformed(transform).normalized()).collect()
+    }
+
+    /// Normal form of the pattern: of its eight rotations and reflections, each
+    /// moved so its bounding box starts at the origin, the one whose sorted cell
+    /// list is lexicographically smallest. Two patterns have the same normal form
+    /// exactly when one is a moved, rotated or reflected copy of the other.
+    pub fn canonicalize(&self) -> Pattern {
+        self.orientations().into_iter().min_by(|a, b| a.cells.cmp(&b.cells)).unwrap()
+    }
+
+    /// The rotations and reflections that leave the pattern as it was, up to
+    /// where it is, in the order of `Transform::ALL` (so the identity first).
+    /// A block has all eight, a boat a diagonal mirror and a glider none but the identity.
+    pub fn symmetries(&self) -> Vec<Transform> {
+        let orientations = self.orientations();
+        Transform::ALL.into_iter().zip(&orientations)
+            .filter(|&(_, orientation)| *orientation == orientations[0])
+            .map(|(transform, _)| transform)
+            .collect()
+    }
+
+    /// `nx` by `ny` copies of the pattern in a grid, each `gap` cells from its
+    /// neighbours (measured between bounding boxes), with the first copy where
+    /// the pattern is
+    pub fn tile(&self, nx: u32, ny: u32, gap: i64) -> Pattern {
+        let Some((_, _, width, height)) = self.bounding_box() else {
+            return Pattern::new();
+        };
+        let mut parts = Vec::with_capacity((nx * ny) as usize);
+        for j in 0..ny as i64 {
+            for i in 0..nx as i64 {
+                parts.push((self.clone(), i * (width + gap), j * (height + gap), Transform::Identity));
+            }
+        }
+        Pattern::compose(&parts)
+    }
+
+    /// One pattern from several, each transformed about the origin and then
+    /// moved by `(dx, dy)`. Where parts overlap, later parts win.
+    pub fn compose(parts: &[(Pattern, i64, i64, Transform)]) -> Pattern {
+        Pattern::from_cells(parts.iter().flat_map(|(pattern, dx, dy, transform)| {
+            pattern.cells.iter().map(move |&(x, y, state)| {
+                let (x, y) = transform.apply(x, y);
+                (x + dx, y + dy, state)
+            })
+        }))
+    }
+}
+
+/// Two-state pattern with the given cells alive, as `Pattern::from_live_cells`
+impl FromIterator<(i64, i64)> for Pattern {
+    fn from_iter<I: IntoIterator<Item = (i64, i64)>>(cells: I) -> Self {
+        Pattern::from_live_cells(cells)
+    }
+}
+
+/// The positions of the pattern's cells, sorted by x then y
+impl IntoIterator for Pattern {
+    type Item = (i64, i64);
+    type IntoIter = LiveCoordinates;
+
+    fn into_iter(self) -> LiveCoordinates {
+        LiveCoordinates(self.cells.into_iter())
+    }
+}
+
+/// Iterator over the positions of cells from a `Pattern` or `&Universe`,
+/// leaving out their states
+#[derive(Clone, Debug)]
+pub struct LiveCoordinates(std::vec::IntoIter<(i64, i64, State)>);
+
+impl Iterator for LiveCoordinates {
+    type Item = (i64, i64);
+
+    fn next(&mut self) -> Option<(i64, i64)> {
+        self.0.next().map(|(x, y, _)| (x, y))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl ExactSizeIterator for LiveCoordinates {}
+
+/// What a pattern file says about its pattern besides the cells
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PatternMetadata {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    /// Other comment lines, in order
+    pub comments: Vec<String>,
+    /// Rule the file gave, which the universe may since have changed from
+    pub rule: Option<Rule>,
+}
+
+impl Universe {
+    /// The cells that differ from the background, as a pattern
+    pub fn to_pattern(&self) -> Pattern {
+        Pattern::from_cells(self.live_cells())
+    }
+
+    /// A Conway's Game of Life universe with the cells of `rows` alive where
+    /// they are true, row `j` starting at `(origin.0, origin.1 + j)`. Rows
+    /// may differ in length.
+    pub fn from_grid<R: AsRef<[bool]>>(rows: &[R], origin: (i64, i64)) -> Universe {
+        let (left, top) = origin;
+        let cells: Vec<_> = rows.iter().zip(top..)
+            .flat_map(|(row, y)| row.as_ref().iter().zip(left..).filter(|&(&alive, _)| alive).map(move |(_, x)| (x, y, 1)))
+            .collect();
+        let mut universe = Universe::new(3);
+        universe.set_cell_states(&cells);
+        universe.clear_undo();
+        universe
+    }
+
+    /// The cells of `rect` row by row, true for any non-dead state
+    pub fn to_grid(&self, rect: Rect) -> Vec<Vec<bool>> {
+        (rect.top..rect.top + rect.height)
+            .map(|y| (rect.left..rect.left + rect.width).map(|x| self.get_cell(x, y)).collect())
+            .collect()
+    }
+}
+
+/// A Conway's Game of Life universe with the given cells alive
+impl FromIterator<(i64, i64)> for Universe {
+    fn from_iter<I: IntoIterator<Item = (i64, i64)>>(cells: I) -> Self {
+        let mut universe = Universe::new(3);
+        universe.extend(cells);
+        universe.clear_undo();
+        universe
+    }
+}
+
+/// Set the given cells alive, as one edit
+impl Extend<(i64, i64)> for Universe {
+    fn extend<I: IntoIterator<Item = (i64, i64)>>(&mut self, cells: I) {
+        let cells: Vec<_> = cells.into_iter().map(|(x, y)| (x, y, 1)).collect();
+        self.set_cell_states(&cells);
+    }
+}
+
+/// The positions of the cells that differ from the background, in no
+/// particular order (see `Universe::live_cells`)
+impl IntoIterator for &Universe {
+    type Item = (i64, i64);
+    type IntoIter = LiveCoordinates;
+
+    fn into_iter(self) -> LiveCoordinates {
+        LiveCoordinates(self.live_cells().into_iter())
+    }
+}
+
+/// Rows of cells with the first at the origin, as in `Universe::from_grid`
+impl<R: AsRef<[bool]>> From<&[R]> for Universe {
+    fn from(rows: &[R]) -> Self {
+        Universe::from_grid(rows, (0, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern() {
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(glider.population(), 5);
+        assert_eq!(glider.get(2, 1), 1);
+        assert_eq!(glider.get(0, 0), 0);
+        assert_eq!(glider.bounding_box(), Some((0, 0, 3, 3)));
+        assert_eq!(glider.translated(-4, 7).normalized(), glider);
+
+        let overwritten = Pattern::from_cells([(0, 0, 1), (0, 0, 2), (1, 0, 1), (1, 0, 0)]);
+        assert_eq!(overwritten.cells(), &[(0, 0, 2)]);
+        assert_eq!(Pattern::new().bounding_box(), None);
+        assert_eq!(Rect::new(0, 0, 4, 4).intersection(Rect::new(2, -1, 5, 2)), Some(Rect::new(2, 0, 2, 1)));
+        assert_eq!(Rect::new(0, 0, 4, 4).intersection(Rect::new(4, 0, 1, 1)), None);
+
+        // A glider has eight distinct orientations, a block only one
+        let mut orientations = glider.orientations();
+        orientations.sort_by(|a, b| a.cells().cmp(b.cells()));
+        orientations.dedup();
+        assert_eq!(orientations.len(), 8);
+        let block = Pattern::from_live_cells([(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert!(block.orientations().iter().all(|p| *p == block));
+
+        assert_eq!(Transform::Rotate90.apply(1, 0), (0, 1));
+        for transform in Transform::ALL {
+            assert_eq!(glider.transformed(transform).transformed(transform).transformed(transform)
+                .transformed(transform).normalized(), glider);
+        }
+
+        let mut universe = Universe::new(4);
+        for &(x, y, _) in glider.cells() {
+            universe.set_cell(x, y, true);
+        }
+        assert_eq!(universe.to_pattern(), glider);
+    }
+
+    #[test]
+    fn test_grid() {
+        let rows = [vec![false, true], vec![false, false, true], vec![true, true, true]];
+        let universe = Universe::from_grid(&rows, (5, -2));
+        assert_eq!(universe.to_pattern(), Pattern::from_live_cells([(6, -2), (7, -1), (5, 0), (6, 0), (7, 0)]));
+        assert!(!universe.can_undo());
+        let grid = universe.to_grid(Rect::new(5, -2, 3, 3));
+        assert_eq!(grid, [[false, true, false], [false, false, true], [true, true, true]]);
+        assert_eq!(Universe::from_grid(&grid, (5, -2)).to_pattern(), universe.to_pattern());
+
+        let block: &[[bool; 2]] = &[[true; 2]; 2];
+        assert_eq!(Universe::from(block).to_grid(Rect::new(-1, 0, 3, 2)), [[false, true, true]; 2]);
+        assert_eq!(Universe::from(&[] as &[Vec<bool>]).population(), 0);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let glider: Pattern = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)].into_iter().collect();
+        assert_eq!(glider.clone().into_iter().len(), 5);
+        assert_eq!(glider.clone().into_iter().collect::<Vec<_>>(), [(0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]);
+
+        let mut universe: Universe = glider.clone().into_iter().map(|(x, y)| (x + 10, y)).collect();
+        assert_eq!(universe.to_pattern(), glider.translated(10, 0));
+        universe.extend([(0, 0), (0, 1)]);
+        assert_eq!(universe.population(), 7);
+        universe.undo();
+        let moved: Pattern = universe.into_iter().map(|(x, y)| (x - 10, y)).collect();
+        assert_eq!(moved, glider);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_from_image() {
+        use image::{ImageFormat, LumaA, Rgb, RgbImage};
+
+        // A glider drawn black on white, with a grey pixel between the thresholds
+        let mut image = RgbImage::from_pixel(4, 3, Rgb([255, 255, 255]));
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            image.put_pixel(x, y, Rgb([0, 0, 0]));
+        }
+        image.put_pixel(3, 0, Rgb([128, 128, 128]));
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(Pattern::from_image(bytes.get_ref(), 100).unwrap(), glider);
+        assert_eq!(Pattern::from_image(bytes.get_ref(), 200).unwrap().population(), 6);
+
+        // Transparent pixels are background whatever their colour
+        let transparent = image::GrayAlphaImage::from_pixel(2, 2, LumaA([0, 0]));
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        transparent.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        assert!(Pattern::from_image(bytes.get_ref(), 128).unwrap().is_empty());
+
+        assert!(Pattern::from_image(b"not an image", 128).is_err());
+    }
+
+    #[test]
+    fn test_tile() {
+        let block = Pattern::from_live_cells([(0, 0), (1, 0), (0, 1), (1, 1)]).translated(5, 5);
+        let wall = block.tile(3, 2, 1);
+        assert_eq!(wall.population(), 24);
+        assert_eq!(wall.bounding_box(), Some((5, 5, 8, 5)));
+        assert_eq!(wall.get(8, 5), 1);
+        assert_eq!(wall.get(7, 5), 0);
+        assert_eq!(block.tile(1, 1, 10), block);
+        assert_eq!(block.tile(0, 4, 0), Pattern::new());
+
+        // Parts are transformed, then moved, and later ones overwrite earlier ones
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        let pair = Pattern::compose(&[
+            (glider.clone(), 0, 0, Transform::Identity),
+            (glider.clone(), 10, 0, Transform::FlipX),
+        ]);
+        assert_eq!(pair.population(), 10);
+        assert_eq!(pair.get(8, 1), 1);
+        let overlapping = Pattern::compose(&[
+            (Pattern::from_cells([(0, 0, 2), (1, 0, 2)]), 0, 0, Transform::Identity),
+            (Pattern::from_cells([(0, 0, 1)]), 1, 0, Transform::Identity),
+        ]);
+        assert_eq!(overlapping.cells(), &[(0, 0, 2), (1, 0, 1)]);
+    }
+
+    #[test]
+    fn test_symmetries() {
+        use Transform::*;
+        let block = Pattern::from_live_cells([(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(block.symmetries(), Transform::ALL);
+        let glider = Pattern::from_live_cells([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(glider.symmetries(), [Identity]);
+        let boat = Pattern::from_live_cells([(0, 0), (1, 0), (0, 1), (2, 1), (1, 2)]);
+        assert_eq!(boat.symmetries(), [Identity, FlipDiagonal]);
+        let r_pentomino = Pattern::from_live_cells([(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)]);
+        assert_eq!(r_pentomino.symmetries(), [Identity]);
+        let beehive = Pattern::from_live_cells([(1, 0), (2, 0), (0, 1), (3, 1), (1, 2), (2, 2)]);
+        assert_eq!(beehive.symmetries(), [Identity, Rotate180, FlipX, FlipY]);
+        let mixed = Pattern::from_cells([(0, 0, 1), (1, 0, 2)]);
+        assert_eq!(mixed.symmetries(), [Identity, FlipY]);
+        assert_eq!(Pattern::new().symmetries(), Transform::ALL);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let r_pentomino = Pattern::from_live_cells([(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)]);
+        let canonical = r_pentomino.canonicalize();
+        assert_eq!(canonical.bounding_box().map(|b| (b.0, b.1)), Some((0, 0)));
+        assert_eq!(canonical.canonicalize(), canonical);
+        for transform in Transform::ALL {
+            assert_eq!(r_pentomino.transformed(transform).translated(17, -3).canonicalize(), canonical);
+        }
+
+        // States are part of the shape
+        let two_state = Pattern::from_cells([(0, 0, 1), (1, 0, 2)]);
+        assert_eq!(two_state.canonicalize(), Pattern::from_cells([(0, 0, 1), (0, 1, 2)]));
+        assert_ne!(two_state.canonicalize(), Pattern::from_cells([(0, 0, 1), (1, 0, 1)]).canonicalize());
+        assert_ne!(r_pentomino.canonicalize(), Pattern::from_live_cells([(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)]));
+        assert_eq!(Pattern::new().canonicalize(), Pattern::new());
+    }
+}
@@ -0,0 +1,603 @@
+//! Drawing a region of a universe as an image
+//!
+//! `rasterize` draws into an RGB `Image` at any scale. Below one pixel per
+//! cell, each pixel is shaded by the fraction of its cells that are alive, the
+//! way the web view draws zoomed-out patterns, and `rasterize_ages` colours
+//! cells by how long they have been alive. With the `png` feature,
+//! `to_png` encodes the drawing as a PNG file and `record_apng` and
+//! `png_frames_zip` record a run of the universe as an animated PNG or as
+//! numbered frames; with the `gif` feature `record_gif` records a GIF.
+//! `to_svg` draws the same way as vector graphics.
+
+use std::collections::BTreeMap;
+
+use crate::hashlife::Universe;
+use crate::pattern::Rect;
+use crate::rule::State;
+
+/// A colour as red, green and blue bytes
+pub type Rgb = [u8; 3];
+
+/// Colours for drawing cells
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// Colour of dead cells
+    pub background: Rgb,
+    /// Colours of states 1, 2, ..., used in turn again for rules with more states
+    pub states: Vec<Rgb>,
+}
+
+impl Default for Palette {
+    /// The web view's green on dark blue, with more hues for further states
+    fn default() -> Self {
+        Palette {
+            background: [0x0f, 0x0f, 0x1e],
+            states: vec![
+                [0x00, 0xff, 0x88],
+                [0xff, 0x55, 0x55],
+                [0x55, 0x99, 0xff],
+                [0xff, 0xcc, 0x33],
+                [0xcc, 0x66, 0xff],
+                [0x33, 0xdd, 0xdd],
+            ],
+        }
+    }
+}
+
+impl Palette {
+    pub fn colour(&self, state: State) -> Rgb {
+        if state == 0 || self.states.is_empty() {
+            self.background
+        } else {
+            self.states[(state as usize - 1) % self.states.len()]
+        }
+    }
+}
+
+/// An RGB image, row by row from the top
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Three bytes per pixel
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// An image filled with one colour
+    pub fn new(width: u32, height: u32, colour: Rgb) -> Self {
+        Image { width, height, pixels: colour.repeat((width * height) as usize) }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Rgb {
+        let i = 3 * (y * self.width + x) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, colour: Rgb) {
+        let i = 3 * (y * self.width + x) as usize;
+        self.pixels[i..i + 3].copy_from_slice(&colour);
+    }
+
+    /// The image as a PNG file
+    #[cfg(feature = "png")]
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Writing to memory only fails for an empty image, which `Image` never is here
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.pixels).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+}
+
+/// Mix `from` towards `to` by `amount`, from 0 to 1
+fn blend(from: Rgb, to: Rgb, amount: f32) -> Rgb {
+    std::array::from_fn(|i| (from[i] as f32 + (to[i] as f32 - from[i] as f32) * amount).round() as u8)
+}
+
+/// Draw the cells of `rect` with each cell `cell_px` pixels wide. Below 1
+/// pixel per cell a pixel covers several cells and is drawn in their most
+/// common state, shaded by the fraction that are alive. The image is at
+/// least 1 by 1 pixel.
+pub fn rasterize(universe: &Universe, rect: Rect, cell_px: f64, palette: &Palette) -> Image {
+    let width = ((rect.width as f64 * cell_px).ceil() as u32).max(1);
+    let height = ((rect.height as f64 * cell_px).ceil() as u32).max(1);
+    let background = universe.stored_background();
+    let mut image = Image::new(width, height, palette.colour(background));
+
+    // Collect single cells when zoomed in, and otherwise the largest
+    // power-of-two squares that fit in a pixel
+    let cells_per_px = 1.0 / cell_px;
+    let min_size = 1u32 << (cells_per_px.max(1.0).log2().floor() as u32).min(31);
+    let regions = universe.collect_state_regions(
+        rect.left, rect.top, rect.left + rect.width, rect.top + rect.height, min_size,
+    );
+
+    // Live cells counted in each pixel, with the state that contributed most
+    let mut pixels = vec![(0.0f32, 0 as State, 0.0f32); (width * height) as usize];
+    let span = |start: i64, size: i64, limit: u32| {
+        let low = ((start as f64 * cell_px).floor() as i64).clamp(0, limit as i64);
+        let high = (((start + size) as f64 * cell_px).floor() as i64).clamp(low + 1, limit as i64);
+        low as u32..high as u32
+    };
+    for (x, y, size, density, state) in regions {
+        let (xs, ys) = (span(x - rect.left, size as i64, width), span(y - rect.top, size as i64, height));
+        let population = density * (size as f32).powi(2) / (xs.len() * ys.len()).max(1) as f32;
+        for py in ys {
+            for px in xs.clone() {
+                let pixel = &mut pixels[(py * width + px) as usize];
+                pixel.0 += population;
+                if population > pixel.2 {
+                    (pixel.1, pixel.2) = (state, population);
+                }
+            }
+        }
+    }
+
+    // On a live background the tree stores dead cells, drawn in state 0
+    let capacity = (cells_per_px * cells_per_px) as f32;
+    for (i, &(population, state, _)) in pixels.iter().enumerate() {
+        if population > 0.0 {
+            let density = (population / capacity).min(1.0);
+            let colour = palette.colour(if background == 0 { state } else { 0 });
+            let base = palette.colour(background);
+            image.set_pixel(i as u32 % width, i as u32 / width, blend(base, colour, 0.3 + 0.7 * density));
+        }
+    }
+    image
+}
+
+/// Draw the cells of `rect` like `rasterize`, but with each live cell
+/// coloured by its age (see `Universe::set_age_tracking`): from the state 1
+/// colour of the palette when newborn to `old` at `max_age` generations and
+/// beyond. A pixel covering several cells shows their mean age. Without age
+/// tracking this is `rasterize`.
+pub fn rasterize_ages(universe: &Universe, rect: Rect, cell_px: f64, palette: &Palette, old: Rgb, max_age: u64) -> Image {
+    if !universe.is_age_tracking() {
+        return rasterize(universe, rect, cell_px, palette);
+    }
+    let width = ((rect.width as f64 * cell_px).ceil() as u32).max(1);
+    let height = ((rect.height as f64 * cell_px).ceil() as u32).max(1);
+    let base = palette.colour(universe.background());
+    let mut image = Image::new(width, height, base);
+
+    // Live cells and their total age in each pixel
+    let mut pixels = vec![(0u64, 0u64); (width * height) as usize];
+    let pixel = |offset: i64, limit: u32| ((offset as f64 * cell_px).floor() as i64).clamp(0, limit as i64 - 1) as u32;
+    for (x, y, age) in universe.cell_ages(rect) {
+        let (px, py) = (pixel(x - rect.left, width), pixel(y - rect.top, height));
+        let (count, total) = &mut pixels[(py * width + px) as usize];
+        *count += 1;
+        *total += age.min(max_age as u128) as u64;
+    }
+
+    let capacity = (1.0 / (cell_px * cell_px)).max(1.0) as f32;
+    for (i, &(count, total)) in pixels.iter().enumerate() {
+        if count > 0 {
+            let age = total as f32 / count as f32 / max_age.max(1) as f32;
+            let colour = blend(palette.colour(1), old, age);
+            let density = (count as f32 / capacity).min(1.0);
+            image.set_pixel(i as u32 % width, i as u32 / width, blend(base, colour, 0.3 + 0.7 * density));
+        }
+    }
+    image
+}
+
+/// `rect` drawn by `rasterize`, as a PNG file
+#[cfg(feature = "png")]
+pub fn to_png(universe: &Universe, rect: Rect, cell_px: f64, palette: &Palette) -> Vec<u8> {
+    rasterize(universe, rect, cell_px, palette).to_png()
+}
+
+/// Draw `frames` frames of `rect` for `record_gif` and the like, advancing
+/// the universe `step_per_frame` generations between them
+#[cfg_attr(not(any(feature = "gif", feature = "png")), allow(dead_code))]
+fn record_frames(universe: &mut Universe, rect: Rect, frames: usize, step_per_frame: u64,
+                 cell_px: f64, palette: &Palette) -> Vec<Image> {
+    let mut images = Vec::with_capacity(frames);
+    for i in 0..frames {
+        if i > 0 {
+            universe.step_to(universe.generation() + step_per_frame as u128)
+                .expect("frames of a finite grid are at most MAX_FINITE_STEPS generations apart");
+        }
+        images.push(rasterize(universe, rect, cell_px, palette));
+    }
+    images
+}
+
+/// Time each frame of an animation is shown, in milliseconds
+#[cfg(any(feature = "gif", feature = "png"))]
+const FRAME_DELAY_MS: u16 = 100;
+
+/// Record an animated GIF of `rect`, looping forever: the current generation,
+/// then `frames - 1` more frames `step_per_frame` generations apart, each
+/// drawn as `rasterize` draws. The universe is left at the last frame's
+/// generation. Panics if the image would be wider or taller than the 65535
+/// pixels GIF allows.
+#[cfg(feature = "gif")]
+pub fn record_gif(universe: &mut Universe, rect: Rect, frames: usize, step_per_frame: u64,
+                  cell_px: f64, palette: &Palette) -> Vec<u8> {
+    use std::borrow::Cow;
+    use std::collections::{BTreeSet, HashMap};
+
+    let images = record_frames(universe, rect, frames, step_per_frame, cell_px, palette);
+    let Some(first) = images.first() else {
+        return Vec::new();
+    };
+    let (width, height) = (u16::try_from(first.width).unwrap(), u16::try_from(first.height).unwrap());
+
+    // Frames share one palette when they fit in 256 colours, as they do
+    // unless many shades of density appear
+    let colours: BTreeSet<Rgb> = images.iter()
+        .flat_map(|image| image.pixels.chunks(3).map(|pixel| [pixel[0], pixel[1], pixel[2]]))
+        .collect();
+    let shared = colours.len() <= 256;
+    let global_palette: Vec<u8> = if shared { colours.iter().flatten().copied().collect() } else { Vec::new() };
+    let indices: HashMap<Rgb, u8> = colours.iter().enumerate().map(|(i, &colour)| (colour, i as u8)).collect();
+
+    let mut bytes = Vec::new();
+    let mut encoder = gif::Encoder::new(&mut bytes, width, height, &global_palette).unwrap();
+    encoder.set_repeat(gif::Repeat::Infinite).unwrap();
+    for image in &images {
+        let mut frame = if shared {
+            let buffer = image.pixels.chunks(3).map(|pixel| indices[&[pixel[0], pixel[1], pixel[2]]]).collect();
+            gif::Frame { width, height, buffer: Cow::Owned(buffer), ..gif::Frame::default() }
+        } else {
+            gif::Frame::from_rgb_speed(width, height, &image.pixels, 10)
+        };
+        frame.delay = FRAME_DELAY_MS / 10;
+        encoder.write_frame(&frame).unwrap();
+    }
+    drop(encoder);
+    bytes
+}
+
+/// Record an animated PNG of `rect`, looping forever, with the same frames
+/// as `record_gif`. Browsers show it at full colour, where GIF is limited to
+/// 256 colours. Returns an empty buffer when `frames` is 0.
+#[cfg(feature = "png")]
+pub fn record_apng(universe: &mut Universe, rect: Rect, frames: usize, step_per_frame: u64,
+                   cell_px: f64, palette: &Palette) -> Vec<u8> {
+    let images = record_frames(universe, rect, frames, step_per_frame, cell_px, palette);
+    let Some(first) = images.first() else {
+        return Vec::new();
+    };
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, first.width, first.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(images.len() as u32, 0).unwrap();
+    encoder.set_frame_delay(FRAME_DELAY_MS, 1000).unwrap();
+    let mut writer = encoder.write_header().unwrap();
+    for image in &images {
+        writer.write_image_data(&image.pixels).unwrap();
+    }
+    writer.finish().unwrap();
+    bytes
+}
+
+/// Record the frames of `record_apng` as a zip archive of PNG files named
+/// `frame_0000.png`, `frame_0001.png` and so on, for assembling in other tools
+#[cfg(feature = "png")]
+pub fn png_frames_zip(universe: &mut Universe, rect: Rect, frames: usize, step_per_frame: u64,
+                      cell_px: f64, palette: &Palette) -> Vec<u8> {
+    let images = record_frames(universe, rect, frames, step_per_frame, cell_px, palette);
+    let files: Vec<_> = images.iter().enumerate()
+        .map(|(i, image)| (format!("frame_{:04}.png", i), image.to_png()))
+        .collect();
+    zip_stored(&files)
+}
+
+/// CRC-32 as used by zip
+#[cfg(feature = "png")]
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A zip archive holding the files uncompressed, which suits PNG files as
+/// they are compressed already
+#[cfg(feature = "png")]
+fn zip_stored(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    // Version 2.0, no flags, stored, dated 1980-01-01
+    const ENTRY: [u16; 5] = [20, 0, 0, 0, 0x21];
+    let u16s = |bytes: &mut Vec<u8>, values: &[u16]| values.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+    let u32s = |bytes: &mut Vec<u8>, values: &[u32]| values.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+
+    let mut zip = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in files {
+        let (crc, size, offset) = (crc32(data), data.len() as u32, zip.len() as u32);
+        u32s(&mut zip, &[0x0403_4b50]);
+        u16s(&mut zip, &ENTRY);
+        u32s(&mut zip, &[crc, size, size]);
+        u16s(&mut zip, &[name.len() as u16, 0]);
+        zip.extend(name.as_bytes());
+        zip.extend(data);
+
+        u32s(&mut directory, &[0x0201_4b50]);
+        u16s(&mut directory, &[20]);
+        u16s(&mut directory, &ENTRY);
+        u32s(&mut directory, &[crc, size, size]);
+        u16s(&mut directory, &[name.len() as u16, 0, 0, 0, 0]);
+        u32s(&mut directory, &[0, offset]);
+        directory.extend(name.as_bytes());
+    }
+    let (directory_offset, directory_size) = (zip.len() as u32, directory.len() as u32);
+    zip.extend(directory);
+    u32s(&mut zip, &[0x0605_4b50]);
+    u16s(&mut zip, &[0, 0, files.len() as u16, files.len() as u16]);
+    u32s(&mut zip, &[directory_size, directory_offset]);
+    u16s(&mut zip, &[0]);
+    zip
+}
+
+/// Options for `to_svg`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgOptions {
+    /// Width of a cell in SVG units
+    pub cell_size: f64,
+    /// Colour of lines between cells, or None for no grid. Lines are only
+    /// drawn while every cell is drawn on its own.
+    pub grid: Option<Rgb>,
+    /// Most shapes to write. When there are more live cells, squares of
+    /// cells are drawn instead, shaded by how many of them are alive.
+    pub max_elements: usize,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions { cell_size: 10.0, grid: None, max_elements: 100_000 }
+    }
+}
+
+fn hex(colour: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour[0], colour[1], colour[2])
+}
+
+/// Draw the cells of `rect` as an SVG document, with live cells as squares
+/// grouped by colour
+pub fn to_svg(universe: &Universe, rect: Rect, palette: &Palette, options: &SvgOptions) -> String {
+    let (right, bottom) = (rect.left + rect.width, rect.top + rect.height);
+    let mut min_size = 1u32;
+    let regions = loop {
+        let regions = universe.collect_state_regions(rect.left, rect.top, right, bottom, min_size);
+        if regions.len() <= options.max_elements || min_size >= 1 << 31 {
+            break regions;
+        }
+        min_size *= 2;
+    };
+
+    let background = universe.stored_background();
+    let base = palette.colour(background);
+    let mut by_colour: BTreeMap<Rgb, Vec<(i64, i64, i64, i64)>> = BTreeMap::new();
+    for (x, y, size, density, state) in regions {
+        let colour = palette.colour(if background == 0 { state } else { 0 });
+        let colour = if min_size == 1 { colour } else { blend(base, colour, 0.3 + 0.7 * density) };
+        let (x0, y0) = (x.max(rect.left), y.max(rect.top));
+        let (x1, y1) = ((x + size as i64).min(right), (y + size as i64).min(bottom));
+        by_colour.entry(colour).or_default().push((x0 - rect.left, y0 - rect.top, x1 - x0, y1 - y0));
+    }
+
+    let scale = options.cell_size;
+    let (width, height) = (rect.width as f64 * scale, rect.height as f64 * scale);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+        width, height,
+    );
+    svg.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", width, height, hex(base)));
+    for (colour, squares) in by_colour {
+        svg.push_str(&format!("<g fill=\"{}\">\n", hex(colour)));
+        for (x, y, w, h) in squares {
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+                x as f64 * scale, y as f64 * scale, w as f64 * scale, h as f64 * scale,
+            ));
+        }
+        svg.push_str("</g>\n");
+    }
+    if let (Some(colour), 1) = (options.grid, min_size) {
+        let mut path = String::new();
+        for x in 0..=rect.width {
+            path.push_str(&format!("M{} 0V{}", x as f64 * scale, height));
+        }
+        for y in 0..=rect.height {
+            path.push_str(&format!("M0 {}H{}", y as f64 * scale, width));
+        }
+        svg.push_str(&format!("<path d=\"{}\" stroke=\"{}\" stroke-width=\"{}\"/>\n", path, hex(colour), scale / 20.0));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rule::Rule;
+
+    #[test]
+    fn test_rasterize() {
+        let palette = Palette::default();
+        let (dead, live) = (palette.background, palette.colour(1));
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        let image = rasterize(&universe, Rect::new(0, 0, 3, 3), 2.0, &palette);
+        assert_eq!((image.width, image.height), (6, 6));
+        assert_eq!(image.pixel(0, 0), dead);
+        assert_eq!(image.pixel(2, 0), live);
+        assert_eq!(image.pixel(3, 1), live);
+        assert_eq!(image.pixel(5, 5), live);
+        assert_eq!(image.pixel(5, 0), dead);
+
+        // Zoomed out, a pixel is shaded by the share of its cells alive
+        let mut universe = Universe::new(4);
+        for y in 0..4 {
+            for x in 0..4 {
+                universe.set_cell(x, y, true);
+                universe.set_cell(4 + 2 * x, y, true);
+            }
+        }
+        let image = rasterize(&universe, Rect::new(0, 0, 16, 4), 0.25, &palette);
+        assert_eq!((image.width, image.height), (4, 1));
+        assert_eq!(image.pixel(0, 0), live);
+        assert_eq!(image.pixel(1, 0), blend(dead, live, 0.65));
+        assert_eq!(image.pixel(3, 0), dead);
+        assert_eq!(rasterize(&universe, Rect::new(0, 0, 16, 4), 0.1, &palette).width, 2);
+
+        let mut wire = Universe::with_rule(4, Rule::wireworld());
+        wire.set_cell_state(0, 0, 3);
+        let image = rasterize(&wire, Rect::new(0, 0, 1, 1), 1.0, &palette);
+        assert_eq!(image.pixel(0, 0), palette.colour(3));
+
+        // A live background
+        let mut universe = Universe::with_rule(4, "B0123478/S34678".parse().unwrap());
+        universe.set_cell(0, 0, true);
+        universe.step();
+        let image = rasterize(&universe, Rect::new(-2, -2, 5, 5), 1.0, &palette);
+        assert!(!universe.get_cell(0, 0));
+        assert_eq!(image.pixel(0, 0), live);
+        assert_eq!(image.pixel(2, 2), dead);
+    }
+
+    #[test]
+    fn test_rasterize_ages() {
+        let palette = Palette::default();
+        let old = [0xff, 0xff, 0xff];
+        let mut universe = Universe::new(4);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1), (5, 1), (5, 2), (5, 3)] {
+            universe.set_cell(x, y, true);
+        }
+        let rect = Rect::new(0, 0, 7, 4);
+        assert_eq!(rasterize_ages(&universe, rect, 1.0, &palette, old, 4), rasterize(&universe, rect, 1.0, &palette));
+
+        // The block stays put and ages; the blinker's ends keep being reborn
+        universe.set_age_tracking(true);
+        universe.step();
+        universe.step();
+        let image = rasterize_ages(&universe, rect, 1.0, &palette, old, 4);
+        assert_eq!(image.pixel(0, 0), blend(palette.colour(1), old, 0.5));
+        assert_eq!(image.pixel(5, 2), blend(palette.colour(1), old, 0.5));
+        assert_eq!(image.pixel(5, 1), palette.colour(1));
+        assert_eq!(image.pixel(4, 2), palette.background);
+        let zoomed_out = rasterize_ages(&universe, Rect::new(0, 0, 2, 2), 0.5, &palette, old, 2);
+        assert_eq!(zoomed_out.pixel(0, 0), old);
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let palette = Palette::default();
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        let options = SvgOptions { grid: Some([0x33, 0x33, 0x33]), ..SvgOptions::default() };
+        let svg = to_svg(&universe, Rect::new(0, 0, 3, 3), &palette, &options);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"30\" height=\"30\""));
+        assert!(svg.contains("<g fill=\"#00ff88\">\n<rect x=\"10\" y=\"0\" width=\"10\" height=\"10\"/>\n"));
+        assert_eq!(svg.matches("<rect").count(), 6);
+        assert!(svg.contains("<path d=\"M0 0V30M10 0V30"));
+
+        // Over the budget, cells are merged into shaded squares and the grid is left out
+        let mut universe = Universe::new(4);
+        for x in 0..64 {
+            universe.set_cell(2 * x, 0, true);
+        }
+        let options = SvgOptions { max_elements: 10, ..options };
+        let svg = to_svg(&universe, Rect::new(0, 0, 128, 1), &palette, &options);
+        assert!(svg.matches("<rect").count() <= 11);
+        assert!(!svg.contains("<path"));
+        assert!(!svg.contains("#00ff88"));
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn test_record_gif() {
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        let bytes = record_gif(&mut universe, Rect::new(0, 0, 8, 8), 5, 4, 2.0, &Palette::default());
+        assert!(bytes.starts_with(b"GIF89a"));
+        assert_eq!(universe.generation(), 16);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(bytes.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (16, 16));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 10);
+            // The glider has moved one cell diagonally every four generations
+            let live = |x: usize, y: usize| frame.buffer[4 * (2 * y * 16 + 2 * x) + 1] == 0xff;
+            assert!(live(1 + frames, frames) && live(frames, 2 + frames) && !live(frames, frames));
+            frames += 1;
+        }
+        assert_eq!(frames, 5);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_record_apng() {
+        let mut universe = Universe::new(4);
+        for x in 0..3 {
+            universe.set_cell(x, 0, true);
+        }
+        let bytes = record_apng(&mut universe, Rect::new(-1, -1, 5, 3), 3, 1, 1.0, &Palette::default());
+        assert_eq!(universe.generation(), 2);
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (3, 0));
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        // The blinker lies across, then upright
+        for horizontal in [true, false, true] {
+            reader.next_frame(&mut pixels).unwrap();
+            let live = |x: usize, y: usize| pixels[3 * (5 * y + x) + 1] == 0xff;
+            assert_eq!((live(2, 1), live(1, 1), live(2, 0)), (true, horizontal, !horizontal));
+        }
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png_frames_zip() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let mut universe = Universe::new(4);
+        universe.set_cell(0, 0, true);
+        let zip = png_frames_zip(&mut universe, Rect::new(0, 0, 2, 2), 12, 1, 1.0, &Palette::default());
+        assert!(zip.starts_with(b"PK\x03\x04"));
+        let end = &zip[zip.len() - 22..];
+        assert!(end.starts_with(b"PK\x05\x06"));
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 12);
+        // The first entry is the first frame, stored as it is
+        let name_len = u16::from_le_bytes([zip[26], zip[27]]) as usize;
+        assert_eq!(&zip[30..30 + name_len], b"frame_0000.png");
+        assert!(zip[30 + name_len..].starts_with(b"\x89PNG"));
+        assert!(zip.windows(14).any(|name| name == b"frame_0011.png"));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_to_png() {
+        let mut universe = Universe::new(4);
+        universe.set_cell(0, 0, true);
+        let bytes = to_png(&universe, Rect::new(-1, -1, 3, 2), 4.0, &Palette::default());
+        assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (12, 8));
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(&pixels[3 * (4 * 12 + 4)..][..3], &Palette::default().colour(1));
+    }
+}
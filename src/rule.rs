@@ -0,0 +1,949 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Cell state: 0 is dead, 1 is alive, higher values are rule-specific
+pub type State = u8;
+
+/// Largest neighbourhood radius accepted for Larger-than-Life rules
+pub const MAX_RADIUS: u32 = 500;
+
+/// How cell states evolve, on top of the birth/survival neighbor counts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Family {
+    /// Outer-totalistic birth/survival, with Generations-style decay when the
+    /// rule has more than two states
+    #[default]
+    Totalistic,
+    /// Life with two live colours (states 1 and 2): both count as live
+    /// neighbors and a newborn cell takes the majority colour of its neighbors
+    Immigration,
+    /// WireWorld circuits: empty (0), electron head (1), electron tail (2) and
+    /// conductor (3). Heads become tails, tails become conductors, and a
+    /// conductor becomes a head when one or two of its neighbors are heads.
+    WireWorld,
+}
+
+/// Which cells around a cell count as its neighbors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Neighbourhood {
+    /// The surrounding square (8 cells at radius 1)
+    #[default]
+    Moore,
+    /// A hexagonal grid mapped onto the square grid by ignoring the NE and SW
+    /// diagonals, as in Golly (6 cells at radius 1, `H` suffix)
+    Hexagonal,
+    /// The orthogonally adjacent cells (4 cells at radius 1, `V` suffix);
+    /// at larger radii, the diamond of cells within Manhattan distance `radius`
+    VonNeumann,
+}
+
+impl Neighbourhood {
+    /// True if the offset (dx, dy) lies within the given radius (the centre included)
+    fn contains(self, dx: i32, dy: i32, radius: i32) -> bool {
+        match self {
+            Neighbourhood::Moore => dx.abs() <= radius && dy.abs() <= radius,
+            Neighbourhood::Hexagonal => {
+                dx.abs() <= radius && dy.abs() <= radius && (dx - dy).abs() <= radius
+            }
+            Neighbourhood::VonNeumann => dx.abs() + dy.abs() <= radius,
+        }
+    }
+
+    /// Suffix used in B/S notation
+    fn bs_suffix(self) -> &'static str {
+        match self {
+            Neighbourhood::Moore => "",
+            Neighbourhood::Hexagonal => "H",
+            Neighbourhood::VonNeumann => "V",
+        }
+    }
+
+    /// Letter used after `N` in HROT notation
+    fn hrot_code(self) -> char {
+        match self {
+            Neighbourhood::Moore => 'M',
+            Neighbourhood::Hexagonal => 'H',
+            Neighbourhood::VonNeumann => 'N',
+        }
+    }
+}
+
+/// An outer-totalistic rule, either in B/S notation (`B3/S23`) or in the
+/// Larger-than-Life/HROT notation (`R5,C0,M1,S34..58,B34..45,NM`), optionally
+/// with Generations-style decay states, e.g. `B2/S/C3` (Brian's Brain)
+///
+/// `birth[n]` is true if a dead cell with `n` live neighbors becomes alive,
+/// `survival[n]` is true if a live cell with `n` live neighbors stays alive.
+/// With more than two states, a live cell that does not survive passes through
+/// states 2, 3, ... `states - 1` before becoming dead again. Only state 1
+/// counts as a live neighbor. Other multi-state families, such as
+/// Immigration, are selected by name and encoded in `family`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rule {
+    family: Family,
+    birth: Vec<bool>,
+    survival: Vec<bool>,
+    states: u8,
+    /// Neighbourhood radius (1 for ordinary Life-like rules)
+    radius: u32,
+    neighbourhood: Neighbourhood,
+    /// Whether a live cell counts itself as one of its neighbors (HROT `M1`)
+    include_center: bool,
+}
+
+/// A two-state radius-1 rule applied to all 64 cells of an 8x8 block at
+/// once, with the block packed into a word (bit `8 * y + x` is cell `(x, y)`)
+/// and the neighbor counts added up one bit of the count per word
+#[derive(Clone, Debug)]
+pub(crate) struct BitRule {
+    /// The neighbor offsets as distances between bits
+    shifts: Vec<i32>,
+    /// Bit `n` is set if a cell with `n` live neighbors is born (or survives)
+    birth: u16,
+    survival: u16,
+}
+
+impl BitRule {
+    /// The block one generation on. Cells on its edge are missing neighbors,
+    /// so only the 6x6 inside is correct.
+    pub(crate) fn step(&self, block: u64) -> u64 {
+        // Four words hold the four bits of every cell's count (at most 9)
+        let mut count = [0u64; 4];
+        for &shift in &self.shifts {
+            let mut carry = if shift >= 0 { block >> shift } else { block << -shift };
+            for bit in &mut count {
+                let sum = *bit ^ carry;
+                carry &= *bit;
+                *bit = sum;
+            }
+        }
+        let with_count = |counts: u16| (0..10).filter(|n| counts >> n & 1 != 0).fold(0, |cells, n| {
+            cells | count.iter().enumerate()
+                .fold(!0u64, |equal, (i, &bit)| equal & if n >> i & 1 != 0 { bit } else { !bit })
+        });
+        (!block & with_count(self.birth)) | (block & with_count(self.survival))
+    }
+}
+
+/// Error returned when a rulestring cannot be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseRuleError {
+    /// The rulestring was empty
+    Empty,
+    /// The rulestring is not in `B.../S...` or `S.../B...` form
+    InvalidFormat(String),
+    /// A neighbor count outside the neighbourhood size (or a non-digit) was found
+    InvalidCount(String),
+    /// The number of Generations states is not in 2..=255
+    InvalidStates(String),
+    /// The HROT radius is not in 1..=MAX_RADIUS
+    InvalidRadius(String),
+    /// The HROT neighbourhood type is not supported
+    UnsupportedNeighbourhood(String),
+    /// Birth on zero neighbors is only supported for two-state totalistic rules
+    UnsupportedB0,
+}
+
+impl fmt::Display for ParseRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseRuleError::Empty => write!(f, "empty rulestring"),
+            ParseRuleError::InvalidFormat(s) => write!(f, "invalid rulestring: {:?}", s),
+            ParseRuleError::InvalidCount(c) => write!(f, "invalid neighbor count: {:?}", c),
+            ParseRuleError::InvalidStates(s) => write!(f, "invalid number of states: {:?}", s),
+            ParseRuleError::InvalidRadius(s) => write!(f, "invalid neighbourhood radius: {:?}", s),
+            ParseRuleError::UnsupportedNeighbourhood(s) => {
+                write!(f, "unsupported neighbourhood: {:?}", s)
+            }
+            ParseRuleError::UnsupportedB0 => {
+                write!(f, "B0 is only supported for two-state rules")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseRuleError {}
+
+impl Rule {
+    /// Conway's Game of Life (`B3/S23`)
+    pub fn conway() -> Self {
+        Rule::from_counts(&[3], &[2, 3])
+    }
+
+    /// Build a radius-1 rule from lists of birth and survival neighbor counts.
+    /// Counts above 8 are ignored.
+    pub fn from_counts(birth: &[u8], survival: &[u8]) -> Self {
+        let mut rule = Rule {
+            family: Family::Totalistic,
+            birth: vec![false; 9],
+            survival: vec![false; 9],
+            states: 2,
+            radius: 1,
+            neighbourhood: Neighbourhood::Moore,
+            include_center: false,
+        };
+        for &n in birth.iter().filter(|&&n| n <= 8) {
+            rule.birth[n as usize] = true;
+        }
+        for &n in survival.iter().filter(|&&n| n <= 8) {
+            rule.survival[n as usize] = true;
+        }
+        rule
+    }
+
+    /// Immigration: Conway's Life with two live colours
+    pub fn immigration() -> Self {
+        Rule {
+            family: Family::Immigration,
+            states: 3,
+            ..Rule::conway()
+        }
+    }
+
+    /// WireWorld, the four-state rule for simulating digital circuits
+    pub fn wireworld() -> Self {
+        Rule {
+            family: Family::WireWorld,
+            birth: vec![false; 9],
+            survival: vec![false; 9],
+            states: 4,
+            ..Rule::conway()
+        }
+    }
+
+    pub fn family(&self) -> Family {
+        self.family
+    }
+
+    /// Turn this rule into a Generations rule with `states` states in total.
+    /// Values below 2 are clamped to 2 (a plain two-state rule).
+    pub fn with_states(mut self, states: u8) -> Self {
+        self.states = states.max(2);
+        self
+    }
+
+    /// Number of cell states, 2 for ordinary Life-like rules
+    pub fn states(&self) -> u8 {
+        self.states
+    }
+
+    /// Neighbourhood radius, 1 for ordinary Life-like rules
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+
+    /// Use a different neighbourhood shape. Counts that no longer fit in the
+    /// smaller neighbourhood are dropped.
+    pub fn with_neighbourhood(mut self, neighbourhood: Neighbourhood) -> Self {
+        self.neighbourhood = neighbourhood;
+        let len = self.neighbourhood_size() as usize + 1;
+        self.birth.resize(len, false);
+        self.survival.resize(len, false);
+        self
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    /// Whether a cell is counted as part of its own neighbourhood
+    pub fn includes_center(&self) -> bool {
+        self.include_center
+    }
+
+    /// Number of cells in the neighbourhood, i.e. the largest possible neighbor count
+    pub fn neighbourhood_size(&self) -> u32 {
+        let r = self.radius;
+        let cells = match self.neighbourhood {
+            Neighbourhood::Moore => (2 * r + 1) * (2 * r + 1) - 1,
+            Neighbourhood::Hexagonal => 3 * r * (r + 1),
+            Neighbourhood::VonNeumann => 2 * r * (r + 1),
+        };
+        cells + self.include_center as u32
+    }
+
+    /// Offsets of the neighbors of a cell, including (0, 0) if the rule counts the centre
+    pub(crate) fn neighbour_offsets(&self) -> Vec<(i32, i32)> {
+        let r = self.radius as i32;
+        let mut offsets = Vec::new();
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let is_center = dx == 0 && dy == 0;
+                if (!is_center || self.include_center) && self.neighbourhood.contains(dx, dy, r) {
+                    offsets.push((dx, dy));
+                }
+            }
+        }
+        offsets
+    }
+
+    /// Smallest quadtree level whose nodes can be advanced one generation by
+    /// the brute-force base case: a node of side 2^level yields its centre of
+    /// side 2^(level-1), so the border of 2^(level-2) must cover the radius.
+    pub(crate) fn base_level(&self) -> u8 {
+        let mut level = 2;
+        while (1u32 << (level - 2)) < self.radius {
+            level += 1;
+        }
+        level
+    }
+
+    /// Next state of a cell given its current state and the number of its
+    /// neighbors in each state (`counts[s]` neighbors are in state `s`).
+    /// This is the general transition used by every rule family.
+    pub fn transition(&self, state: State, counts: &[u32]) -> State {
+        let count = |s: usize| counts.get(s).copied().unwrap_or(0);
+        match self.family {
+            Family::Totalistic => self.next_state(state, count(1)),
+            Family::Immigration => {
+                let live = (count(1) + count(2)) as usize;
+                match state {
+                    0 if self.birth[live] => if count(1) >= count(2) { 1 } else { 2 },
+                    1 | 2 if self.survival[live] => state,
+                    _ => 0,
+                }
+            }
+            Family::WireWorld => match state {
+                1 => 2,
+                2 => 3,
+                3 if (1..=2).contains(&count(1)) => 1,
+                3 => 3,
+                _ => 0,
+            },
+        }
+    }
+
+    /// True if the rule only depends on the number of state-1 neighbors, so
+    /// the base case does not need to count neighbors per state
+    pub(crate) fn counts_only_live(&self) -> bool {
+        self.family == Family::Totalistic
+    }
+
+    /// Next state of a cell in a totalistic rule given its current state and
+    /// number of live (state 1) neighbors
+    #[inline]
+    pub fn next_state(&self, state: State, neighbors: u32) -> State {
+        let n = neighbors as usize;
+        match state {
+            0 => self.birth.get(n).copied().unwrap_or(false) as State,
+            1 if self.survival.get(n).copied().unwrap_or(false) => 1,
+            _ if state >= self.states - 1 => 0,
+            _ => state + 1,
+        }
+    }
+
+    pub fn is_birth(&self, neighbors: u32) -> bool {
+        self.birth.get(neighbors as usize).copied().unwrap_or(false)
+    }
+
+    pub fn is_survival(&self, neighbors: u32) -> bool {
+        self.survival.get(neighbors as usize).copied().unwrap_or(false)
+    }
+
+    fn parse_states(digits: &str) -> Result<u8, ParseRuleError> {
+        match digits.parse::<u8>() {
+            Ok(n) if n >= 2 => Ok(n),
+            _ => Err(ParseRuleError::InvalidStates(digits.to_string())),
+        }
+    }
+
+    fn parse_counts(digits: &str) -> Result<Vec<bool>, ParseRuleError> {
+        let mut counts = vec![false; 9];
+        for c in digits.chars() {
+            match c.to_digit(10) {
+                Some(n) if n <= 8 => counts[n as usize] = true,
+                _ => return Err(ParseRuleError::InvalidCount(c.to_string())),
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Parse B/S notation: `B3/S23`, `b3s23`, `S23/B3`, `23/3`, the
+    /// Generations forms `B2/S/C3` (or `G3`) and `/2/3`, with an optional
+    /// `H` (hexagonal) or `V` (von Neumann) neighbourhood suffix
+    fn parse_bs(s: &str) -> Result<Rule, ParseRuleError> {
+        let invalid = || ParseRuleError::InvalidFormat(s.to_string());
+        let mut upper = s.to_ascii_uppercase();
+        let neighbourhood = match upper.chars().last() {
+            Some('H') => Neighbourhood::Hexagonal,
+            Some('V') => Neighbourhood::VonNeumann,
+            _ => Neighbourhood::Moore,
+        };
+        if neighbourhood != Neighbourhood::Moore {
+            upper.pop();
+        }
+        let (birth, survival, states) = if upper.starts_with(['B', 'S']) {
+            // Split "B3/S23/C8" or "B3S23" into its tagged parts
+            let mut birth = None;
+            let mut survival = None;
+            let mut states = None;
+            let mut rest = upper.as_str();
+            while !rest.is_empty() {
+                let tag = rest.as_bytes()[0];
+                let body = &rest[1..];
+                let end = body.find(['/', 'B', 'S', 'C', 'G']).unwrap_or(body.len());
+                let slot = match tag {
+                    b'B' => &mut birth,
+                    b'S' => &mut survival,
+                    b'C' | b'G' => &mut states,
+                    _ => return Err(invalid()),
+                };
+                if slot.is_some() {
+                    return Err(invalid());
+                }
+                *slot = Some(&body[..end]);
+                rest = body[end..].strip_prefix('/').unwrap_or(&body[end..]);
+            }
+            match (birth, survival) {
+                (Some(b), Some(s)) => (b, s, states),
+                _ => return Err(invalid()),
+            }
+        } else {
+            // Classic "S/B" or Generations "S/B/C" notation, e.g. "23/3" or "345/2/4"
+            let parts: Vec<&str> = upper.split('/').collect();
+            match parts.as_slice() {
+                [survival, birth] => (*birth, *survival, None),
+                [survival, birth, states] => (*birth, *survival, Some(*states)),
+                _ => return Err(invalid()),
+            }
+        };
+
+        Ok(Rule {
+            family: Family::Totalistic,
+            birth: Rule::parse_counts(birth)?,
+            survival: Rule::parse_counts(survival)?,
+            states: states.map(Rule::parse_states).transpose()?.unwrap_or(2),
+            radius: 1,
+            neighbourhood,
+            include_center: false,
+        })
+    }
+
+    /// Parse HROT notation, e.g. `R5,C0,M1,S34..58,B34..45,NM`.
+    /// Count lists are comma-separated values or `a..b`/`a-b` ranges.
+    fn parse_hrot(s: &str) -> Result<Rule, ParseRuleError> {
+        let invalid = || ParseRuleError::InvalidFormat(s.to_string());
+        let upper = s.to_ascii_uppercase();
+
+        let mut radius = None;
+        let mut neighbourhood = Neighbourhood::Moore;
+        let mut states = 2;
+        let mut include_center = false;
+        let mut birth: Vec<(u32, u32)> = Vec::new();
+        let mut survival: Vec<(u32, u32)> = Vec::new();
+        // Which count list bare values after a comma belong to
+        let mut current: Option<&mut Vec<(u32, u32)>> = None;
+        let mut seen = String::new();
+
+        for token in upper.split(',').map(str::trim) {
+            let Some(first) = token.chars().next() else {
+                return Err(invalid());
+            };
+            let (list_token, value) = if first.is_ascii_digit() {
+                (true, token)
+            } else {
+                if seen.contains(first) {
+                    return Err(invalid());
+                }
+                seen.push(first);
+                (false, &token[1..])
+            };
+
+            if list_token || first == 'S' || first == 'B' {
+                if !list_token {
+                    current = Some(if first == 'S' { &mut survival } else { &mut birth });
+                }
+                let list = current.as_mut().ok_or_else(invalid)?;
+                if !value.is_empty() {
+                    list.push(Rule::parse_range(value)?);
+                }
+                continue;
+            }
+
+            current = None;
+            match first {
+                'R' => match value.parse::<u32>() {
+                    Ok(r) if (1..=MAX_RADIUS).contains(&r) => radius = Some(r),
+                    _ => return Err(ParseRuleError::InvalidRadius(value.to_string())),
+                },
+                'C' => {
+                    states = match value {
+                        "0" | "1" => 2,
+                        _ => Rule::parse_states(value)?,
+                    }
+                }
+                'M' => {
+                    include_center = match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(invalid()),
+                    }
+                }
+                'N' => {
+                    neighbourhood = match value {
+                        "M" => Neighbourhood::Moore,
+                        "H" => Neighbourhood::Hexagonal,
+                        "N" => Neighbourhood::VonNeumann,
+                        _ => return Err(ParseRuleError::UnsupportedNeighbourhood(value.to_string())),
+                    }
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        let radius = radius.ok_or_else(invalid)?;
+        if !seen.contains('S') || !seen.contains('B') {
+            return Err(invalid());
+        }
+
+        let mut rule = Rule {
+            family: Family::Totalistic,
+            birth: Vec::new(),
+            survival: Vec::new(),
+            states,
+            radius,
+            neighbourhood,
+            include_center,
+        };
+        let max_count = rule.neighbourhood_size();
+        let to_table = |ranges: &[(u32, u32)]| -> Result<Vec<bool>, ParseRuleError> {
+            let mut table = vec![false; max_count as usize + 1];
+            for &(lo, hi) in ranges {
+                if hi > max_count {
+                    return Err(ParseRuleError::InvalidCount(hi.to_string()));
+                }
+                table[lo as usize..=hi as usize].fill(true);
+            }
+            Ok(table)
+        };
+        rule.birth = to_table(&birth)?;
+        rule.survival = to_table(&survival)?;
+        Ok(rule)
+    }
+
+    fn parse_range(value: &str) -> Result<(u32, u32), ParseRuleError> {
+        let invalid = || ParseRuleError::InvalidCount(value.to_string());
+        let (lo, hi) = value.split_once("..")
+            .or_else(|| value.split_once('-'))
+            .unwrap_or((value, value));
+        let lo = lo.parse::<u32>().map_err(|_| invalid())?;
+        let hi = hi.parse::<u32>().map_err(|_| invalid())?;
+        if lo > hi {
+            return Err(invalid());
+        }
+        Ok((lo, hi))
+    }
+
+    /// True if dead cells with no live neighbors are born, so the infinite background strobes
+    pub fn has_b0(&self) -> bool {
+        self.birth[0]
+    }
+
+    /// Whether the infinite background is alive one generation after it was
+    /// `alive`. A B0 rule without S-max (survival with every neighbor alive)
+    /// turns the background on and off every generation; with S-max it stays
+    /// alive once born. The engine stores the complement of the cells while
+    /// the background is alive, so the stored tree stays finite.
+    pub(crate) fn next_background(&self, alive: bool) -> bool {
+        if alive {
+            self.survival[self.neighbourhood_size() as usize]
+        } else {
+            self.has_b0()
+        }
+    }
+
+    /// The rule to apply to stored cells for one generation when the cells
+    /// read (`input`) and/or written (`output`) are stored complemented.
+    /// When `output` follows `input` as `next_background` says, the result has no B0.
+    pub(crate) fn complemented(&self, input: bool, output: bool) -> Rule {
+        let size = self.neighbourhood_size() as usize;
+        let next = |stored: bool, n: usize| {
+            let (alive, n) = if input { (!stored, size - n) } else { (stored, n) };
+            let table = if alive { &self.survival } else { &self.birth };
+            table[n] != output
+        };
+        Rule {
+            birth: (0..=size).map(|n| next(false, n)).collect(),
+            survival: (0..=size).map(|n| next(true, n)).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Lookup table advancing any 4x4 block of a two-state radius-1 rule by one
+    /// generation, the fast path for the base case. Bit `4 * y + x` of the
+    /// index is cell (x, y) of the block; bit `2 * y + x` of the entry is cell
+    /// (x, y) of the 2x2 centre. Returns None for rules it cannot express.
+    pub(crate) fn block_table(&self) -> Option<Box<[u8]>> {
+        if self.family != Family::Totalistic || self.states != 2 || self.radius != 1 {
+            return None;
+        }
+
+        // Neighbourhood mask and own bit for each of the four centre cells
+        let offsets = self.neighbour_offsets();
+        let masks: Vec<(u16, u16)> = [(1, 1), (2, 1), (1, 2), (2, 2)].iter()
+            .map(|&(cx, cy)| {
+                let mask = offsets.iter()
+                    .fold(0u16, |mask, &(dx, dy)| mask | 1 << (4 * (cy + dy) + cx + dx));
+                (mask, 1 << (4 * cy + cx))
+            })
+            .collect();
+
+        let table = (0..=u16::MAX)
+            .map(|block| {
+                masks.iter().enumerate().fold(0u8, |result, (i, &(mask, own))| {
+                    let alive = (block & own != 0) as State;
+                    let next = self.next_state(alive, (block & mask).count_ones());
+                    result | next << i
+                })
+            })
+            .collect();
+        Some(table)
+    }
+
+    /// The rule in bit-parallel form, for the same rules as `block_table`
+    pub(crate) fn bit_rule(&self) -> Option<BitRule> {
+        if self.family != Family::Totalistic || self.states != 2 || self.radius != 1 {
+            return None;
+        }
+        let counts = |table: &[bool]| table.iter().enumerate()
+            .fold(0u16, |counts, (n, &on)| counts | (on as u16) << n);
+        Some(BitRule {
+            shifts: self.neighbour_offsets().iter().map(|&(dx, dy)| 8 * dy + dx).collect(),
+            birth: counts(&self.birth),
+            survival: counts(&self.survival),
+        })
+    }
+
+    /// True if the rule can be written in plain B/S notation
+    fn is_bs(&self) -> bool {
+        self.radius == 1 && !self.include_center
+    }
+
+    /// Write a count table as comma-separated HROT values and `a..b` ranges
+    fn write_ranges(f: &mut fmt::Formatter<'_>, table: &[bool]) -> fmt::Result {
+        let mut separator = "";
+        let mut n = 0;
+        while n < table.len() {
+            if !table[n] {
+                n += 1;
+                continue;
+            }
+            let start = n;
+            while n + 1 < table.len() && table[n + 1] {
+                n += 1;
+            }
+            if start == n {
+                write!(f, "{}{}", separator, start)?;
+            } else {
+                write!(f, "{}{}..{}", separator, start, n)?;
+            }
+            separator = ",";
+            n += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::conway()
+    }
+}
+
+impl FromStr for Rule {
+    type Err = ParseRuleError;
+
+    /// Parses B/S notation (`B3/S23`, `23/3`, `B2/S/C3`, ...) and HROT
+    /// notation (`R5,C0,M1,S34..58,B34..45,NM`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseRuleError::Empty);
+        }
+
+        if s.eq_ignore_ascii_case("immigration") {
+            return Ok(Rule::immigration());
+        }
+        if s.eq_ignore_ascii_case("wireworld") {
+            return Ok(Rule::wireworld());
+        }
+
+        let mut rule = if s.starts_with(['R', 'r']) && s.contains(',') {
+            Rule::parse_hrot(s)?
+        } else {
+            Rule::parse_bs(s)?
+        };
+
+        // Counts beyond the neighbourhood size (e.g. 7 or 8 for hexagonal rules) can never occur
+        let len = rule.neighbourhood_size() as usize + 1;
+        for table in [&mut rule.birth, &mut rule.survival] {
+            if let Some(n) = table.iter().skip(len).position(|&set| set) {
+                return Err(ParseRuleError::InvalidCount((n + len).to_string()));
+            }
+            table.truncate(len);
+        }
+        if rule.birth[0] && rule.states > 2 {
+            return Err(ParseRuleError::UnsupportedB0);
+        }
+        Ok(rule)
+    }
+}
+
+impl TryFrom<&str> for Rule {
+    type Error = ParseRuleError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.family {
+            Family::Immigration => return write!(f, "Immigration"),
+            Family::WireWorld => return write!(f, "WireWorld"),
+            Family::Totalistic => {}
+        }
+        if !self.is_bs() {
+            let states = if self.states > 2 { self.states } else { 0 };
+            write!(f, "R{},C{},M{},S", self.radius, states, self.include_center as u8)?;
+            Rule::write_ranges(f, &self.survival)?;
+            write!(f, ",B")?;
+            Rule::write_ranges(f, &self.birth)?;
+            return write!(f, ",N{}", self.neighbourhood.hrot_code());
+        }
+
+        write!(f, "B")?;
+        for n in (0..self.birth.len()).filter(|&n| self.birth[n]) {
+            write!(f, "{}", n)?;
+        }
+        write!(f, "/S")?;
+        for n in (0..self.survival.len()).filter(|&n| self.survival[n]) {
+            write!(f, "{}", n)?;
+        }
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        write!(f, "{}", self.neighbourhood.bs_suffix())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_conway() {
+        let rule: Rule = "B3/S23".parse().unwrap();
+        assert_eq!(rule, Rule::conway());
+        assert_eq!("b3s23".parse::<Rule>().unwrap(), Rule::conway());
+        assert_eq!("S23/B3".parse::<Rule>().unwrap(), Rule::conway());
+        assert_eq!("23/3".parse::<Rule>().unwrap(), Rule::conway());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in ["B36/S23", "B3678/S34678", "B2/S", "B/S012345678", "B2/S/C3"] {
+            let rule: Rule = s.parse().unwrap();
+            assert_eq!(rule.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("".parse::<Rule>(), Err(ParseRuleError::Empty));
+        assert_eq!("B39/S23".parse::<Rule>(), Err(ParseRuleError::InvalidCount("9".into())));
+        assert_eq!("B0/S8/C3".parse::<Rule>(), Err(ParseRuleError::UnsupportedB0));
+        assert!(matches!("B3".parse::<Rule>(), Err(ParseRuleError::InvalidFormat(_))));
+        assert!(matches!("B3/B3".parse::<Rule>(), Err(ParseRuleError::InvalidFormat(_))));
+        assert!(matches!("Life".parse::<Rule>(), Err(ParseRuleError::InvalidFormat(_))));
+        assert!(matches!("B2/S/C1".parse::<Rule>(), Err(ParseRuleError::InvalidStates(_))));
+        assert!(matches!("/2/300".parse::<Rule>(), Err(ParseRuleError::InvalidStates(_))));
+    }
+
+    #[test]
+    fn test_parse_generations() {
+        let brians_brain = Rule::from_counts(&[2], &[]).with_states(3);
+        assert_eq!("/2/3".parse::<Rule>().unwrap(), brians_brain);
+        assert_eq!("B2/S/C3".parse::<Rule>().unwrap(), brians_brain);
+        assert_eq!("b2s/g3".parse::<Rule>().unwrap(), brians_brain);
+
+        let star_wars: Rule = "345/2/4".parse().unwrap();
+        assert_eq!(star_wars.states(), 4);
+        assert_eq!(star_wars.to_string(), "B2/S345/C4");
+    }
+
+    #[test]
+    fn test_generations_decay() {
+        let rule: Rule = "23/3/4".parse().unwrap();
+        assert_eq!(rule.next_state(0, 3), 1);
+        assert_eq!(rule.next_state(1, 2), 1);
+        assert_eq!(rule.next_state(1, 4), 2);
+        // Decaying cells ignore their neighbors
+        assert_eq!(rule.next_state(2, 3), 3);
+        assert_eq!(rule.next_state(3, 3), 0);
+    }
+
+    #[test]
+    fn test_parse_hrot() {
+        let rule: Rule = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        assert_eq!(rule.radius(), 5);
+        assert!(rule.includes_center());
+        assert_eq!(rule.neighbourhood_size(), 121);
+        assert_eq!(rule.states(), 2);
+        assert!(rule.is_birth(34) && rule.is_birth(45) && !rule.is_birth(46));
+        assert!(rule.is_survival(58) && !rule.is_survival(33));
+        assert_eq!(rule.base_level(), 5);
+        assert_eq!(rule.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+
+        // Bare values after a list continue that list; N defaults to Moore
+        let rule: Rule = "r2,c3,m0,s2,5..6,b4".parse().unwrap();
+        assert_eq!(rule.states(), 3);
+        assert_eq!(rule.to_string(), "R2,C3,M0,S2,5..6,B4,NM");
+
+        // A radius-1 HROT rule is an ordinary B/S rule
+        let rule: Rule = "R1,C0,M0,S2..3,B3,NM".parse().unwrap();
+        assert_eq!(rule, Rule::conway());
+    }
+
+    #[test]
+    fn test_parse_hrot_errors() {
+        assert!(matches!("R0,C0,M0,S1,B2".parse::<Rule>(), Err(ParseRuleError::InvalidRadius(_))));
+        assert!(matches!("R2,C0,M0,S30,B2".parse::<Rule>(), Err(ParseRuleError::InvalidCount(_))));
+        assert!(matches!("R2,C0,M0,S1,B2,NX".parse::<Rule>(),
+                         Err(ParseRuleError::UnsupportedNeighbourhood(_))));
+        assert!(matches!("R2,C0,M0,S1".parse::<Rule>(), Err(ParseRuleError::InvalidFormat(_))));
+        assert!(matches!("R2,R3,S1,B2".parse::<Rule>(), Err(ParseRuleError::InvalidFormat(_))));
+        assert_eq!("R2,C3,M0,S1,B0..2".parse::<Rule>(), Err(ParseRuleError::UnsupportedB0));
+    }
+
+    #[test]
+    fn test_parse_hexagonal() {
+        let rule: Rule = "B2/S34H".parse().unwrap();
+        assert_eq!(rule.neighbourhood(), Neighbourhood::Hexagonal);
+        assert_eq!(rule.neighbourhood_size(), 6);
+        assert_eq!(rule.to_string(), "B2/S34H");
+        assert_eq!(rule, Rule::from_counts(&[2], &[3, 4]).with_neighbourhood(Neighbourhood::Hexagonal));
+        assert_eq!("B2/S7H".parse::<Rule>(), Err(ParseRuleError::InvalidCount("7".into())));
+
+        let rule: Rule = "R2,C0,M0,S2..5,B4,NH".parse().unwrap();
+        assert_eq!(rule.neighbourhood_size(), 18);
+        assert_eq!(rule.to_string(), "R2,C0,M0,S2..5,B4,NH");
+
+        // The NE and SW diagonals are not neighbors
+        let offsets = "B2/S34H".parse::<Rule>().unwrap().neighbour_offsets();
+        assert_eq!(offsets.len(), 6);
+        assert!(!offsets.contains(&(1, -1)) && !offsets.contains(&(-1, 1)));
+    }
+
+    #[test]
+    fn test_parse_von_neumann() {
+        let rule: Rule = "B1/S1V".parse().unwrap();
+        assert_eq!(rule.neighbourhood(), Neighbourhood::VonNeumann);
+        assert_eq!(rule.neighbourhood_size(), 4);
+        assert_eq!(rule.to_string(), "B1/S1V");
+        assert_eq!("B5/S1V".parse::<Rule>(), Err(ParseRuleError::InvalidCount("5".into())));
+
+        let mut offsets = rule.neighbour_offsets();
+        offsets.sort();
+        assert_eq!(offsets, vec![(-1, 0), (0, -1), (0, 1), (1, 0)]);
+
+        let rule: Rule = "R3,C0,M1,S1..10,B2..4,NN".parse().unwrap();
+        assert_eq!(rule.neighbourhood_size(), 25);
+        assert_eq!(rule.to_string(), "R3,C0,M1,S1..10,B2..4,NN");
+    }
+
+    #[test]
+    fn test_immigration() {
+        let rule: Rule = "Immigration".parse().unwrap();
+        assert_eq!(rule, Rule::immigration());
+        assert_eq!(rule.states(), 3);
+        assert_eq!(rule.to_string(), "Immigration");
+
+        // Births take the majority colour, survivors keep theirs
+        assert_eq!(rule.transition(0, &[5, 2, 1]), 1);
+        assert_eq!(rule.transition(0, &[5, 1, 2]), 2);
+        assert_eq!(rule.transition(2, &[6, 2, 0]), 2);
+        assert_eq!(rule.transition(1, &[4, 2, 2]), 0);
+        assert!(rule.block_table().is_none());
+    }
+
+    #[test]
+    fn test_wireworld() {
+        let rule: Rule = "wireworld".parse().unwrap();
+        assert_eq!(rule, Rule::wireworld());
+        assert_eq!(rule.states(), 4);
+        assert_eq!(rule.to_string(), "WireWorld");
+        assert!(!rule.has_b0());
+
+        assert_eq!(rule.transition(1, &[0, 2, 0, 6]), 2);
+        assert_eq!(rule.transition(2, &[0, 0, 0, 8]), 3);
+        assert_eq!(rule.transition(3, &[5, 1, 1, 1]), 1);
+        assert_eq!(rule.transition(3, &[4, 2, 0, 2]), 1);
+        assert_eq!(rule.transition(3, &[5, 3, 0, 0]), 3);
+        assert_eq!(rule.transition(0, &[6, 2, 0, 0]), 0);
+    }
+
+    #[test]
+    fn test_block_table() {
+        let table = Rule::conway().block_table().unwrap();
+        let block = |cells: &[(u16, u16)]| cells.iter().fold(0u16, |b, &(x, y)| b | 1 << (4 * y + x));
+
+        // A horizontal blinker on row 1 turns vertical around (1, 1), so of the
+        // centre cells (1,1), (2,1), (1,2), (2,2) only the first and third live
+        let blinker = block(&[(0, 1), (1, 1), (2, 1)]);
+        assert_eq!(table[blinker as usize], 0b0101);
+        assert_eq!(table[0], 0);
+        assert!(Rule::conway().with_states(3).block_table().is_none());
+    }
+
+    #[test]
+    fn test_bit_rule() {
+        let mut rng = crate::random::Rng::new(7);
+        for rulestring in ["B3/S23", "B36/S23", "B2/S34H", "B1/S1V", "R1,C0,M1,S4..9,B5..9,NM"] {
+            let rule: Rule = rulestring.parse().unwrap();
+            let bits = rule.bit_rule().unwrap();
+            for _ in 0..20 {
+                let block = rng.next_u64();
+                let next = bits.step(block);
+                for (x, y) in (1..7).flat_map(|y| (1..7).map(move |x| (x, y))) {
+                    let neighbors = rule.neighbour_offsets().iter()
+                        .filter(|&&(dx, dy)| block >> (8 * (y + dy) + x + dx) & 1 != 0)
+                        .count();
+                    let expected = rule.next_state((block >> (8 * y + x) & 1) as State, neighbors as u32);
+                    assert_eq!((next >> (8 * y + x) & 1) as State, expected, "{} at ({}, {})", rulestring, x, y);
+                }
+            }
+        }
+        assert!(Rule::wireworld().bit_rule().is_none());
+    }
+
+    #[test]
+    fn test_b0_complement() {
+        let life = Rule::conway();
+        assert!(!life.next_background(false) && !life.next_background(true));
+        assert_eq!(life.complemented(false, false), life);
+        assert!(!life.complemented(true, false).has_b0());
+
+        // B0 without S8 alternates, and neither phase rule has B0
+        let rule: Rule = "B03/S23".parse().unwrap();
+        assert!(rule.next_background(false) && !rule.next_background(true));
+        assert!(!rule.complemented(false, true).has_b0());
+        assert!(!rule.complemented(true, false).has_b0());
+
+        // AntiLife (B0123478/S01234678) is the complement of Life
+        let antilife: Rule = "B0123478/S01234678".parse().unwrap();
+        assert!(antilife.next_background(false) && antilife.next_background(true));
+        assert_eq!(antilife.complemented(true, true), Rule::conway());
+        assert!(!antilife.complemented(false, true).has_b0());
+    }
+
+    #[test]
+    fn test_next_state() {
+        let highlife: Rule = "B36/S23".parse().unwrap();
+        assert_eq!(highlife.next_state(0, 6), 1);
+        assert_eq!(highlife.next_state(0, 2), 0);
+        assert_eq!(highlife.next_state(1, 2), 1);
+        assert_eq!(highlife.next_state(1, 6), 0);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::hashlife::Universe;
+use crate::pattern::Rect;
+use crate::random::Rng;
+use crate::rule::Rule;
+
+/// Settings for `methuselah_search`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchConfig {
+    pub rule: Rule,
+    /// Size of each random soup, centred on the origin
+    pub soup_width: u32,
+    pub soup_height: u32,
+    /// Probability of each soup cell being alive
+    pub density: f64,
+    /// Number of soups to evaluate
+    pub soups: u64,
+    /// Master seed; soup `i` is generated from `soup_seed(seed, i)`
+    pub seed: u64,
+    /// Soups still active after this many generations count as unstabilised
+    pub max_generations: u64,
+    /// Longest population period recognised as the soup having stabilised
+    pub max_period: u64,
+    /// Number of candidates to report
+    pub keep: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            rule: Rule::conway(),
+            soup_width: 16,
+            soup_height: 16,
+            density: 0.5,
+            soups: 1000,
+            seed: 1,
+            max_generations: 50_000,
+            max_period: 30,
+            keep: 10,
+        }
+    }
+}
+
+/// A soup and how long it lived
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// Seed that regenerates the soup with `soup`
+    pub seed: u64,
+    /// Generation from which the population is periodic
+    pub lifespan: u64,
+    /// Population once stabilised (or at the generation limit)
+    pub final_population: u64,
+    /// False if the soup was still active at `max_generations`
+    pub stabilised: bool,
+}
+
+/// Seed of the `index`th soup of a search with master seed `seed`
+pub fn soup_seed(seed: u64, index: u64) -> u64 {
+    Rng::new(seed ^ index.wrapping_mul(0xd1b5_4a32_d192_ed03)).next_u64()
+}
+
+/// The random soup for `seed` under the config's rule, size and density
+pub fn soup(config: &SearchConfig, seed: u64) -> Universe {
+    let mut universe = Universe::with_rule(4, config.rule.clone());
+    universe.set_history_limit(0);
+    let (width, height) = (config.soup_width as i64, config.soup_height as i64);
+    universe.fill_random(Rect::new(-width / 2, -height / 2, width, height), config.density, seed);
+    universe
+}
+
+/// Step `universe` until its population becomes periodic with a period of at
+/// most `max_period`, returning the generation the periodic part started at.
+/// Escaping gliders keep the population constant, so they do not prevent
+/// stabilisation being detected. Returns None after `max_generations`.
+pub fn time_to_stabilise(universe: &mut Universe, max_generations: u64, max_period: u64) -> Option<u128> {
+    let start = universe.generation();
+    let max_period = max_period.max(1) as usize;
+    let mut populations = vec![universe.population()];
+    loop {
+        let t = populations.len() - 1;
+        // Require the cycle to repeat three times, and to hold for twice the
+        // longest period, before trusting it; active soups often keep the same
+        // population for a few generations
+        for p in 1..=max_period {
+            let window = (3 * p).max(2 * max_period);
+            if window > t {
+                break;
+            }
+            if (0..window - p).all(|j| populations[t - j] == populations[t - j - p]) {
+                let mut s = t - window + 1;
+                while s > 0 && populations[s - 1] == populations[s - 1 + p] {
+                    s -= 1;
+                }
+                return Some(start + s as u128);
+            }
+        }
+        if t as u64 >= max_generations {
+            return None;
+        }
+        universe.step();
+        populations.push(universe.population());
+    }
+}
+
+/// Evaluate `config.soups` random soups and return the `config.keep` longest
+/// lived, longest first. Unstabilised soups rank above every stabilised one.
+pub fn methuselah_search(config: &SearchConfig) -> Vec<Candidate> {
+    let mut best: Vec<Candidate> = Vec::with_capacity(config.keep + 1);
+    for index in 0..config.soups {
+        let seed = soup_seed(config.seed, index);
+        let mut universe = soup(config, seed);
+        let lifespan = time_to_stabilise(&mut universe, config.max_generations, config.max_period);
+        let candidate = Candidate {
+            seed,
+            // Soups start at generation 0
+            lifespan: lifespan.map_or(config.max_generations, |generation| generation as u64),
+            final_population: universe.population(),
+            stabilised: lifespan.is_some(),
+        };
+
+        let rank = |c: &Candidate| (!c.stabilised, c.lifespan);
+        let position = best.partition_point(|c| rank(c) >= rank(&candidate));
+        if position < config.keep {
+            best.insert(position, candidate);
+            best.truncate(config.keep);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_to_stabilise() {
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        assert_eq!(time_to_stabilise(&mut universe, 5000, 30), Some(1103));
+
+        let mut universe = Universe::new(4);
+        for (x, y) in [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        assert_eq!(time_to_stabilise(&mut universe, 500, 30), None);
+    }
+
+    #[test]
+    fn test_methuselah_search() {
+        let config = SearchConfig {
+            soups: 20, soup_width: 8, soup_height: 8, max_generations: 1000, keep: 3, ..SearchConfig::default()
+        };
+        let results = methuselah_search(&config);
+        assert_eq!(results.len(), 3);
+        assert!(results.windows(2).all(|w| w[0].lifespan >= w[1].lifespan));
+
+        // Results are reproducible from their seed
+        let mut universe = soup(&config, results[0].seed);
+        assert_eq!(time_to_stabilise(&mut universe, config.max_generations, config.max_period),
+                   results[0].stabilised.then_some(results[0].lifespan as u128));
+        assert_eq!(methuselah_search(&config), results);
+    }
+}
@@ -0,0 +1,365 @@
+//! A headless simulation server speaking JSON over WebSocket
+//!
+//! `serve` listens for WebSocket connections and gives each its own universe,
+//! run on its own thread, so a heavy simulation can run natively while a thin
+//! browser client only draws what it asks for. Each text message is one
+//! request object with a `cmd` field, answered by one response object:
+//!
+//! ```text
+//! {"cmd": "load", "rle": "bo$2bo$3o!", "x": 0, "y": 0}     -> {"ok": true}
+//! {"cmd": "load", "macrocell": "[M2] ..."}                 -> {"ok": true}
+//! {"cmd": "step_n", "n": 100}                              -> {"ok": true, "generation": 100}
+//! {"cmd": "get_region", "left": 0, "top": 0, "width": 64, "height": 64}
+//!                                   -> {"ok": true, "cells": [[x, y, state], ...]}
+//! {"cmd": "stats"}  -> {"ok": true, "generation": 100, "population": 5, "bounding_box": [...]}
+//! ```
+//!
+//! A request that fails is answered with `{"ok": false, "error": "..."}`.
+//! Requests are bounded so one client cannot take over the server: `step_n`
+//! advances at most `MAX_STEP` generations, `get_region` covers at most
+//! `MAX_REGION_AREA` cells, and each universe is kept within `MEMORY_LIMIT`.
+//! Only what the protocol needs of RFC 6455 is implemented: the handshake,
+//! masked client frames (fragmented or not), ping, and close.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::hashlife::Universe;
+use crate::pattern::Rect;
+
+/// Most cells a `get_region` request may cover, so one request cannot make
+/// the server walk an enormous area of a live background
+pub const MAX_REGION_AREA: i64 = 1 << 24;
+
+/// Most generations a `step_n` request may advance, so one request cannot
+/// keep its connection's thread busy for an unbounded time
+pub const MAX_STEP: u64 = 1 << 32;
+
+/// Memory each connection's universe is kept within (see
+/// `Universe::set_memory_limit`)
+pub const MEMORY_LIMIT: usize = 1 << 30;
+
+/// Largest message a client may send
+const MAX_MESSAGE: usize = 64 << 20;
+
+/// Listen on `addr` and serve each connection on its own thread until the
+/// listener fails
+pub fn serve(addr: impl ToSocketAddrs) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            // A client that goes away or breaks the protocol only ends its own connection
+            let _ = handle_connection(stream);
+        });
+    }
+    Ok(())
+}
+
+/// Answer requests on one connection, after the handshake, until the
+/// client closes it
+pub fn handle_connection(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    handshake(&mut reader, &mut writer)?;
+
+    let mut universe = connection_universe();
+    let mut message = Vec::new();
+    loop {
+        let frame = read_frame(&mut reader)?;
+        match frame.opcode {
+            OPCODE_CLOSE => {
+                write_frame(&mut writer, OPCODE_CLOSE, &[])?;
+                return Ok(());
+            }
+            OPCODE_PING => write_frame(&mut writer, OPCODE_PONG, &frame.payload)?,
+            OPCODE_PONG => {}
+            OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                message.extend_from_slice(&frame.payload);
+                if message.len() > MAX_MESSAGE {
+                    return Err(invalid("message too long"));
+                }
+                if frame.fin {
+                    let response = match std::str::from_utf8(&message) {
+                        Ok(text) => respond(&mut universe, text),
+                        Err(_) => failure("message is not UTF-8"),
+                    };
+                    write_frame(&mut writer, OPCODE_TEXT, response.as_bytes())?;
+                    message.clear();
+                }
+            }
+            _ => return Err(invalid("unknown opcode")),
+        }
+    }
+}
+
+/// The universe a new connection starts with: empty, with no step history
+/// and within `MEMORY_LIMIT`
+pub fn connection_universe() -> Universe {
+    let mut universe = Universe::new(3);
+    universe.set_history_limit(0);
+    universe.set_memory_limit(Some(MEMORY_LIMIT));
+    universe
+}
+
+/// The response to one request, as JSON text
+pub fn respond(universe: &mut Universe, request: &str) -> String {
+    let response = match serde_json::from_str::<Value>(request) {
+        Ok(request) => execute(universe, &request).unwrap_or_else(|e| json!({"ok": false, "error": e})),
+        Err(e) => json!({"ok": false, "error": format!("invalid JSON: {}", e)}),
+    };
+    response.to_string()
+}
+
+fn failure(error: &str) -> String {
+    json!({"ok": false, "error": error}).to_string()
+}
+
+fn execute(universe: &mut Universe, request: &Value) -> Result<Value, String> {
+    let integer = |name: &str| -> Result<i64, String> {
+        request[name].as_i64().ok_or_else(|| format!("missing or invalid {:?}", name))
+    };
+    match request["cmd"].as_str() {
+        Some("load") => {
+            if let Some(rle) = request["rle"].as_str() {
+                let (x, y) = (request["x"].as_i64().unwrap_or(0), request["y"].as_i64().unwrap_or(0));
+                universe.load_rle(rle, x, y).map_err(|e| e.to_string())?;
+            } else if let Some(macrocell) = request["macrocell"].as_str() {
+                universe.load_macrocell(macrocell).map_err(|e| e.to_string())?;
+            } else {
+                return Err("load needs \"rle\" or \"macrocell\"".to_string());
+            }
+            Ok(json!({"ok": true}))
+        }
+        Some("step_n") => {
+            let n = request["n"].as_u64().ok_or("missing or invalid \"n\"")?;
+            if n > MAX_STEP {
+                return Err(format!("n must be at most {}", MAX_STEP));
+            }
+            universe.step_to(universe.generation() + n as u128).map_err(|e| e.to_string())?;
+            Ok(json!({"ok": true, "generation": universe.generation()}))
+        }
+        Some("get_region") => {
+            let rect = Rect::new(integer("left")?, integer("top")?, integer("width")?, integer("height")?);
+            if rect.width < 0 || rect.height < 0 || rect.width.saturating_mul(rect.height) > MAX_REGION_AREA {
+                return Err(format!("region must be at most {} cells", MAX_REGION_AREA));
+            }
+            let cells: Vec<_> = universe.extract(rect).cells().iter().map(|&(x, y, state)| json!([x, y, state])).collect();
+            Ok(json!({"ok": true, "cells": cells}))
+        }
+        Some("stats") => Ok(json!({
+            "ok": true,
+            "generation": universe.generation(),
+            "population": universe.exact_population(),
+            "bounding_box": universe.bounding_box(),
+            "rule": universe.rule().to_string(),
+        })),
+        Some(cmd) => Err(format!("unknown command {:?}", cmd)),
+        None => Err("missing \"cmd\"".to_string()),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read the client's HTTP upgrade request and accept it
+fn handshake(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("connection closed during handshake"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let Some(key) = key else {
+        writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(invalid("not a WebSocket request"));
+    };
+    write!(writer, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                    Sec-WebSocket-Accept: {}\r\n\r\n", accept_key(&key))?;
+    writer.flush()
+}
+
+/// The `Sec-WebSocket-Accept` value answering a client's key
+fn accept_key(key: &str) -> String {
+    encode_base64(&sha1(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key).as_bytes()))
+}
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<Frame> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let (fin, opcode, masked) = (header[0] & 0x80 != 0, header[0] & 0x0f, header[1] & 0x80 != 0);
+    let length = match header[1] & 0x7f {
+        126 => {
+            let mut bytes = [0; 2];
+            reader.read_exact(&mut bytes)?;
+            u16::from_be_bytes(bytes) as u64
+        }
+        127 => {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            u64::from_be_bytes(bytes)
+        }
+        length => length as u64,
+    };
+    if length > MAX_MESSAGE as u64 {
+        return Err(invalid("frame too long"));
+    }
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok(Frame { fin, opcode, payload })
+}
+
+/// Write one unfragmented, unmasked frame, as servers send them
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// Standard base64 with padding
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            text.push(if i <= chunk.len() { ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char } else { '=' });
+        }
+    }
+    text
+}
+
+/// SHA-1, which the handshake needs and nothing else
+fn sha1(bytes: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(bytes.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([block[4 * i], block[4 * i + 1], block[4 * i + 2], block[4 * i + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0; 20];
+    for (i, value) in state.iter().enumerate() {
+        digest[4 * i..4 * i + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key() {
+        // The example from RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(encode_base64(b"ab"), "YWI=");
+    }
+
+    #[test]
+    fn test_frames() {
+        // A masked client frame as a browser sends it
+        let mask = [1, 2, 3, 4];
+        let mut bytes = vec![0x81, 0x80 | 5];
+        bytes.extend_from_slice(&mask);
+        bytes.extend(b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        let frame = read_frame(&mut &bytes[..]).unwrap();
+        assert_eq!((frame.fin, frame.opcode, &frame.payload[..]), (true, OPCODE_TEXT, &b"hello"[..]));
+
+        let mut written = Vec::new();
+        write_frame(&mut written, OPCODE_TEXT, &[7; 300]).unwrap();
+        assert_eq!(&written[..4], [0x81, 126, 1, 44]);
+        let frame = read_frame(&mut &written[..]).unwrap();
+        assert_eq!(frame.payload, [7; 300]);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let mut universe = connection_universe();
+        assert_eq!(universe.memory_limit(), Some(MEMORY_LIMIT));
+        let mut request = |text: &str| serde_json::from_str::<Value>(&respond(&mut universe, text)).unwrap();
+        assert_eq!(request(r#"{"cmd": "load", "rle": "bo$2bo$3o!", "x": 10, "y": 0}"#)["ok"], true);
+        assert_eq!(request(r#"{"cmd": "step_n", "n": 4}"#)["generation"], 4);
+        let stats = request(r#"{"cmd": "stats"}"#);
+        assert_eq!((&stats["population"], &stats["bounding_box"]), (&json!(5), &json!([11, 1, 3, 3])));
+        let region = request(r#"{"cmd": "get_region", "left": 11, "top": 1, "width": 2, "height": 1}"#);
+        assert_eq!(region["cells"], json!([[12, 1, 1]]));
+
+        assert_eq!(request(r#"{"cmd": "fly"}"#)["error"], "unknown command \"fly\"");
+        assert_eq!(request(r#"{"cmd": "step_n"}"#)["ok"], false);
+        let oversized = request(&format!(r#"{{"cmd": "step_n", "n": {}}}"#, MAX_STEP + 1));
+        assert_eq!(oversized["error"], format!("n must be at most {}", MAX_STEP));
+        assert_eq!(request(r#"{"cmd": "stats"}"#)["generation"], 4);
+        assert_eq!(request("not json")["ok"], false);
+    }
+}
@@ -0,0 +1,274 @@
+//! Statistics of a universe, and those recorded as it runs
+
+use std::collections::HashMap;
+
+use crate::hashlife::{Node, Universe};
+use crate::memory::table_size;
+
+/// A summary of the cells of a universe, from `Universe::statistics`. Cells
+/// are counted when they differ from the background.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    /// Exact, unlike `Universe::population`, which saturates
+    pub population: u128,
+    /// `(left, top, width, height)`, or None if there are no cells
+    pub bounding_box: Option<(i64, i64, i64, i64)>,
+    /// Fraction of the bounding box taken up by cells (0 if there are none)
+    pub density: f64,
+    /// Cells in the quadrants about the origin: NW, NE, SW, SE (x or y
+    /// negative counts as west or north)
+    pub quadrants: [u128; 4],
+    /// Shannon entropy in bits of the 2 by 2 blocks (aligned to even
+    /// coordinates) that cover the bounding box: 0 for a uniform pattern, up
+    /// to 4 for a two-state soup with every block equally likely
+    pub block_entropy: f64,
+}
+
+impl Universe {
+    /// Population, extent, density, spread over the quadrants and block
+    /// entropy of the pattern. The blocks are counted from the tree's nodes,
+    /// so the cost follows the number of distinct nodes rather than the area.
+    pub fn statistics(&self) -> Statistics {
+        let root = self.root();
+        let population = root.population();
+        let bounding_box = self.bounding_box();
+        let density = bounding_box.map_or(0.0, |(_, _, width, height)| population as f64 / (width as f64 * height as f64));
+        let quadrants = root.children().map(|quadrant| quadrant.population());
+
+        // How often each distinct non-empty 2 by 2 block occurs, passing each
+        // node's number of occurrences down to its children a level at a time
+        let mut occurrences: HashMap<*const Node, (&Node, u128)> = HashMap::new();
+        occurrences.insert(root.as_ref(), (root.as_ref(), 1));
+        for _ in 1..root.level() {
+            let mut below: HashMap<*const Node, (&Node, u128)> = HashMap::new();
+            for (node, count) in occurrences.into_values() {
+                for child in node.children() {
+                    if child.population() > 0 {
+                        below.entry(child.as_ref()).or_insert((child.as_ref(), 0)).1 += count;
+                    }
+                }
+            }
+            occurrences = below;
+        }
+
+        let block_entropy = bounding_box.map_or(0.0, |(left, top, width, height)| {
+            let blocks_across = (left + width + 1).div_euclid(2) - left.div_euclid(2);
+            let blocks_down = (top + height + 1).div_euclid(2) - top.div_euclid(2);
+            let total = blocks_across as f64 * blocks_down as f64;
+            let counts: Vec<u128> = occurrences.values().map(|&(_, count)| count).collect();
+            let empty = total - counts.iter().sum::<u128>() as f64;
+            std::iter::once(empty).chain(counts.iter().map(|&count| count as f64))
+                .filter(|&count| count > 0.0)
+                .map(|count| -(count / total) * (count / total).log2())
+                .sum::<f64>()
+                .max(0.0)
+        });
+        Statistics { population, bounding_box, density, quadrants, block_entropy }
+    }
+}
+
+/// Counts of the work the caches have done, from `Universe::cache_counters`,
+/// for telling a slow run that misses the caches from one that is just large
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheCounters {
+    /// Canonical nodes created, counted by the node cache, so universes that
+    /// share one see the same count
+    pub nodes_created: u64,
+    /// Requests for a node the node cache already held, counted likewise
+    pub node_hits: u64,
+    /// Steps of a node answered from the memoized results
+    pub result_hits: u64,
+    /// Steps of a node that had to be computed and memoized
+    pub result_misses: u64,
+    /// Garbage collections that swept the node cache
+    pub collections: u64,
+}
+
+/// `(generation, population)` samples taken after each step, thinned out as
+/// they accumulate so a long run keeps an evenly spaced record of bounded size
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PopulationHistory {
+    samples: Vec<(u128, u64)>,
+    /// Most samples kept (0 records nothing)
+    limit: usize,
+    /// Fewest generations between consecutive samples; doubles each time
+    /// the samples are thinned out
+    interval: u128,
+}
+
+impl PopulationHistory {
+    pub(crate) fn samples(&self) -> &[(u128, u64)] {
+        &self.samples
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.samples.capacity() * std::mem::size_of::<(u128, u64)>()
+    }
+
+    /// Start over, keeping at most `limit` samples
+    pub(crate) fn reset(&mut self, limit: usize) {
+        *self = PopulationHistory { samples: Vec::new(), limit, interval: 1 };
+    }
+
+    /// Note the population at `generation`. Samples after it are dropped, so
+    /// going back in time rewinds the record too, and a sample at the same
+    /// generation is replaced.
+    pub(crate) fn record(&mut self, generation: u128, population: u64) {
+        if self.limit == 0 {
+            return;
+        }
+        while self.samples.last().is_some_and(|&(sampled, _)| sampled >= generation) {
+            self.samples.pop();
+        }
+        if let Some(&(last, _)) = self.samples.last() {
+            if generation - last < self.interval {
+                return;
+            }
+        }
+        self.samples.push((generation, population));
+        if self.samples.len() > self.limit {
+            // Keep every other sample, starting with the first
+            let mut index = 0;
+            self.samples.retain(|_| {
+                index += 1;
+                index % 2 == 1
+            });
+            self.interval *= 2;
+        }
+    }
+}
+
+/// Generation each live cell was born in, brought up to date after each step
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CellAges {
+    born: HashMap<(i64, i64), u128>,
+}
+
+impl CellAges {
+    /// Forget the cells that are no longer alive and note those that are new.
+    /// A cell alive before and after a step counts as alive throughout it,
+    /// and after going back in time cells count as born no later than now.
+    pub(crate) fn update(&mut self, generation: u128, live_cells: impl IntoIterator<Item = (i64, i64)>) {
+        let previous = std::mem::take(&mut self.born);
+        self.born = live_cells.into_iter()
+            .map(|cell| (cell, previous.get(&cell).map_or(generation, |&born| born.min(generation))))
+            .collect();
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        table_size::<(i64, i64), u128>(self.born.capacity())
+    }
+
+    /// Birth generation of a cell alive at the last update
+    pub(crate) fn born(&self, x: i64, y: i64) -> Option<u128> {
+        self.born.get(&(x, y)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_counters() {
+        let mut universe = Universe::new(3);
+        assert_eq!(universe.cache_counters().result_misses, 0);
+        for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            universe.set_cell(x, y, true);
+        }
+        universe.set_history_limit(1);
+        universe.step_pow2(6).unwrap();
+        let first = universe.cache_counters();
+        assert!(first.nodes_created > 0 && first.result_misses > 0);
+
+        // A fork runs through the same steps from its memoized results
+        let mut fork = universe.fork();
+        assert!(fork.step_back());
+        fork.step_pow2(6).unwrap();
+        let second = fork.cache_counters();
+        assert!(second.result_hits > first.result_hits);
+        assert_eq!(second.result_misses, first.result_misses);
+        assert_eq!(second.collections, 0);
+    }
+
+    #[test]
+    fn test_population_history() {
+        let mut history = PopulationHistory::default();
+        history.record(0, 5);
+        assert!(history.samples().is_empty());
+
+        history.reset(4);
+        for generation in 0..4 {
+            history.record(generation, generation as u64 * 10);
+        }
+        assert_eq!(history.samples(), &[(0, 0), (1, 10), (2, 20), (3, 30)]);
+        // A fifth sample thins them out to every second generation
+        history.record(4, 40);
+        assert_eq!(history.samples(), &[(0, 0), (2, 20), (4, 40)]);
+        history.record(5, 50);
+        assert_eq!(history.samples().len(), 3);
+        history.record(6, 60);
+        assert_eq!(history.samples(), &[(0, 0), (2, 20), (4, 40), (6, 60)]);
+
+        // Rewinding drops later samples and replaces the one at the same generation
+        history.record(2, 25);
+        assert_eq!(history.samples(), &[(0, 0), (2, 25)]);
+
+        history.reset(100);
+        for generation in 0..10_000 {
+            history.record(generation, 1);
+        }
+        let samples = history.samples();
+        assert!(samples.len() <= 100 && samples.len() > 50);
+        assert_eq!(samples[0], (0, 1));
+        assert!(samples.windows(2).all(|pair| pair[1].0 - pair[0].0 == samples[1].0));
+    }
+
+    #[test]
+    fn test_statistics() {
+        let empty = Universe::new(4).statistics();
+        assert_eq!(empty, Statistics {
+            population: 0, bounding_box: None, density: 0.0, quadrants: [0; 4], block_entropy: 0.0,
+        });
+
+        // Two still-life blocks lined up with the 2 by 2 grid
+        let mut universe = Universe::new(5);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1), (4, -2), (5, -2), (4, -1), (5, -1)] {
+            universe.set_cell(x - 10, y, true);
+        }
+        let statistics = universe.statistics();
+        assert_eq!((statistics.population, statistics.bounding_box), (8, Some((-10, -2, 6, 4))));
+        assert_eq!(statistics.density, 8.0 / 24.0);
+        assert_eq!(statistics.quadrants, [4, 0, 4, 0]);
+        // Of the six grid blocks over the bounding box, two are full and four empty
+        let expected = -(2.0 / 6.0) * (2.0f64 / 6.0).log2() - (4.0 / 6.0) * (4.0f64 / 6.0).log2();
+        assert!((statistics.block_entropy - expected).abs() < 1e-12);
+
+        let mut single = Universe::new(3);
+        single.set_cell(1, 1, true);
+        let statistics = single.statistics();
+        assert_eq!((statistics.density, statistics.block_entropy), (1.0, 0.0));
+        assert_eq!(statistics.quadrants, [0, 0, 0, 1]);
+
+        // A large soup comes close to the 4-bit maximum
+        let mut soup = Universe::new(8);
+        soup.fill_random(crate::pattern::Rect::new(-100, -100, 200, 200), 0.5, 1);
+        let statistics = soup.statistics();
+        assert!(statistics.block_entropy > 3.9 && statistics.block_entropy <= 4.0, "{:?}", statistics);
+        assert!((statistics.density - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_cell_ages() {
+        let mut ages = CellAges::default();
+        ages.update(0, [(0, 0), (1, 0)]);
+        ages.update(1, [(1, 0), (2, 0)]);
+        assert_eq!((ages.born(0, 0), ages.born(1, 0), ages.born(2, 0)), (None, Some(0), Some(1)));
+        ages.update(0, [(2, 0)]);
+        assert_eq!(ages.born(2, 0), Some(0));
+    }
+}
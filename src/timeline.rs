@@ -0,0 +1,164 @@
+//! A timeline of keyframes recorded during a run, for scrubbing back and
+//! forth through it
+//!
+//! While the timeline is on, the universe keeps the root of every generation
+//! it steps to that is at least an interval past the last keyframe. The
+//! interval starts at one generation and doubles each time the keyframes fill
+//! the limit (every other one is dropped), so a run of any length is covered
+//! by a bounded number of roots, spaced more widely the longer it goes on.
+//! `Universe::scrub_to` rebuilds any generation of the run by stepping on from
+//! the keyframe before it, which takes less than one interval of supersteps.
+//!
+//! Editing the universe replaces the keyframes from the current generation on
+//! with the edited tree, so earlier generations still replay as they were run
+//! and later ones follow from the edit.
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::hashlife::Node;
+
+/// Error returned by `Universe::scrub_to`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimelineError {
+    /// The timeline is off
+    NotRecording,
+    /// The generation lies outside the recorded run
+    OutOfRange { generation: u128, first: u128, last: u128 },
+}
+
+impl fmt::Display for TimelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimelineError::NotRecording => write!(f, "the timeline is not recording"),
+            TimelineError::OutOfRange { generation, first, last } => {
+                write!(f, "generation {} is outside the timeline ({} to {})", generation, first, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TimelineError {}
+
+/// Keyframe roots, by generation, with whether their cells are stored complemented
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Timeline {
+    keyframes: Vec<(u128, Rc<Node>, bool)>,
+    /// Most keyframes kept (0 records nothing)
+    limit: usize,
+    /// Fewest generations between keyframes recorded by steps
+    interval: u128,
+    /// Latest generation the run reached
+    last: u128,
+}
+
+impl Timeline {
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn interval(&self) -> u128 {
+        self.interval
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.keyframes.capacity() * std::mem::size_of::<(u128, Rc<Node>, bool)>()
+    }
+
+    /// Start over from a first keyframe, keeping at most `limit`
+    pub(crate) fn reset(&mut self, limit: usize, generation: u128, root: Rc<Node>, inverted: bool) {
+        *self = Timeline { keyframes: Vec::new(), limit, interval: 1, last: generation };
+        if limit > 0 {
+            self.keyframes.push((generation, root, inverted));
+        }
+    }
+
+    pub(crate) fn generations(&self) -> Vec<u128> {
+        self.keyframes.iter().map(|&(generation, _, _)| generation).collect()
+    }
+
+    /// First keyframe and latest generation reached, if recording
+    pub(crate) fn range(&self) -> Option<(u128, u128)> {
+        self.keyframes.first().map(|&(first, _, _)| (first, self.last))
+    }
+
+    /// Note the root after a step. Generations up to the newest keyframe are
+    /// already covered, as after scrubbing back and stepping forward again.
+    pub(crate) fn stepped(&mut self, generation: u128, root: &Rc<Node>, inverted: bool) {
+        let Some(&(newest, _, _)) = self.keyframes.last() else {
+            return;
+        };
+        self.last = self.last.max(generation);
+        if generation > newest && generation - newest >= self.interval {
+            self.keyframes.push((generation, root.clone(), inverted));
+            if self.keyframes.len() > self.limit {
+                // Keep every other keyframe, starting with the first
+                let mut index = 0;
+                self.keyframes.retain(|_| {
+                    index += 1;
+                    index % 2 == 1
+                });
+                self.interval *= 2;
+            }
+        }
+    }
+
+    /// Replace the keyframes from `generation` on with an edited root, which
+    /// ends the run there
+    pub(crate) fn edited(&mut self, generation: u128, root: &Rc<Node>, inverted: bool) {
+        if self.keyframes.is_empty() {
+            return;
+        }
+        self.keyframes.retain(|&(keyframe, _, _)| keyframe < generation);
+        self.keyframes.push((generation, root.clone(), inverted));
+        self.last = generation;
+    }
+
+    /// The keyframe at or before `generation`, checking that it is in range
+    pub(crate) fn keyframe(&self, generation: u128) -> Result<(u128, Rc<Node>, bool), TimelineError> {
+        let (first, last) = self.range().ok_or(TimelineError::NotRecording)?;
+        if generation < first || generation > last {
+            return Err(TimelineError::OutOfRange { generation, first, last });
+        }
+        let index = self.keyframes.partition_point(|&(keyframe, _, _)| keyframe <= generation) - 1;
+        let (keyframe, root, inverted) = &self.keyframes[index];
+        Ok((*keyframe, root.clone(), *inverted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashlife::Universe;
+
+    #[test]
+    fn test_timeline() {
+        let root = Universe::new(3).root().clone();
+        let mut timeline = Timeline::default();
+        timeline.stepped(1, &root, false);
+        assert_eq!(timeline.range(), None);
+
+        timeline.reset(4, 10, root.clone(), false);
+        for generation in 11..14 {
+            timeline.stepped(generation, &root, false);
+        }
+        assert_eq!(timeline.generations(), [10, 11, 12, 13]);
+        // A fifth keyframe thins them out to every second generation
+        timeline.stepped(14, &root, false);
+        assert_eq!((timeline.generations(), timeline.interval()), (vec![10, 12, 14], 2));
+        timeline.stepped(15, &root, false);
+        timeline.stepped(17, &root, false);
+        assert_eq!(timeline.generations(), [10, 12, 14, 17]);
+        assert_eq!(timeline.range(), Some((10, 17)));
+
+        assert_eq!(timeline.keyframe(13).map(|(generation, _, _)| generation), Ok(12));
+        assert_eq!(timeline.keyframe(17).map(|(generation, _, _)| generation), Ok(17));
+        assert_eq!(timeline.keyframe(9).err(), Some(TimelineError::OutOfRange { generation: 9, first: 10, last: 17 }));
+
+        // Stepping over recorded generations adds nothing; an edit cuts the run short
+        timeline.stepped(16, &root, false);
+        assert_eq!(timeline.generations(), [10, 12, 14, 17]);
+        timeline.edited(13, &root, false);
+        assert_eq!((timeline.generations(), timeline.range()), (vec![10, 12, 13], Some((10, 13))));
+    }
+}
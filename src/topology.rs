@@ -0,0 +1,337 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::hashlife::MAX_LEVEL;
+use crate::rule::State;
+
+/// Largest width or height of a finite grid. The grid and the border filled
+/// around it before each step must stay in the central quarter of the largest
+/// tree, so grids are limited to half of that, well inside the plane.
+pub const MAX_GRID_SIZE: u64 = 1 << (MAX_LEVEL - 3);
+
+/// The shape of the world a universe lives on
+///
+/// Finite topologies cover a `width` x `height` grid whose top-left cell is
+/// `(-(width / 2), -(height / 2))`, matching Golly's bounded grids. They can be
+/// parsed from Golly's grid descriptors (the part of a rulestring after `:`,
+/// such as `T100,80` or `K40*,20`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /// The unbounded plane
+    #[default]
+    Plane,
+    /// A finite grid whose opposite edges are joined, so patterns leaving one
+    /// side come back in on the other
+    Torus { width: u64, height: u64 },
+    /// A finite grid surrounded by cells that never change
+    Bounded { width: u64, height: u64, edge: Edge },
+    /// A torus where one pair of opposite edges is reversed before being joined
+    KleinBottle { width: u64, height: u64, twist: Twist },
+    /// A torus where both pairs of opposite edges are reversed before being joined
+    CrossSurface { width: u64, height: u64 },
+}
+
+/// What lies beyond the edges of a bounded grid
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Edge {
+    /// Cells outside are permanently dead
+    #[default]
+    Dead,
+    /// Cells outside are permanently alive (state 1)
+    Alive,
+}
+
+impl Edge {
+    pub fn state(self) -> State {
+        self as State
+    }
+}
+
+/// Which pair of edges of a Klein bottle is reversed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Twist {
+    /// Leaving through the top comes back through the bottom mirrored left to
+    /// right (`K40*,20` in Golly)
+    #[default]
+    TopBottom,
+    /// Leaving through the left comes back through the right mirrored top to
+    /// bottom (`K40,20*` in Golly)
+    LeftRight,
+}
+
+/// Error returned when a grid descriptor cannot be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseTopologyError {
+    /// The descriptor does not start with one of `P`, `A`, `T`, `K` or `C`
+    UnknownTopology(String),
+    /// A dimension is missing, zero, not a number or above `MAX_GRID_SIZE`
+    InvalidSize(String),
+    /// A Klein bottle without exactly one twisted (`*`) dimension, or a twist
+    /// on a topology that does not have one
+    InvalidTwist(String),
+}
+
+impl fmt::Display for ParseTopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseTopologyError::UnknownTopology(s) => write!(f, "unknown topology: {:?}", s),
+            ParseTopologyError::InvalidSize(s) => write!(f, "invalid grid size: {:?}", s),
+            ParseTopologyError::InvalidTwist(s) => write!(f, "invalid twist: {:?}", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseTopologyError {}
+
+impl Topology {
+    /// True for the unbounded plane
+    pub fn is_infinite(&self) -> bool {
+        *self == Topology::Plane
+    }
+
+    /// True for the plane and for grids whose sides are between 1 and
+    /// `MAX_GRID_SIZE`, the topologies a universe can have
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Topology::Plane => true,
+            Topology::Torus { width, height }
+            | Topology::Bounded { width, height, .. }
+            | Topology::KleinBottle { width, height, .. }
+            | Topology::CrossSurface { width, height } => {
+                (1..=MAX_GRID_SIZE).contains(&width) && (1..=MAX_GRID_SIZE).contains(&height)
+            }
+        }
+    }
+
+    /// The grid as `(left, top, width, height)`, or None for the plane
+    pub fn bounds(&self) -> Option<(i64, i64, i64, i64)> {
+        let (width, height) = match *self {
+            Topology::Plane => return None,
+            Topology::Torus { width, height }
+            | Topology::Bounded { width, height, .. }
+            | Topology::KleinBottle { width, height, .. }
+            | Topology::CrossSurface { width, height } => (width as i64, height as i64),
+        };
+        Some((-(width / 2), -(height / 2), width, height))
+    }
+
+    /// The fixed cells beyond the grid's edges, or None if the edges wrap around
+    pub fn edge(&self) -> Option<Edge> {
+        match *self {
+            Topology::Bounded { edge, .. } => Some(edge),
+            _ => None,
+        }
+    }
+
+    /// Whether crossing the top/bottom seam mirrors x, and whether crossing the
+    /// left/right seam mirrors y
+    fn twists(&self) -> (bool, bool) {
+        match *self {
+            Topology::KleinBottle { twist: Twist::TopBottom, .. } => (true, false),
+            Topology::KleinBottle { twist: Twist::LeftRight, .. } => (false, true),
+            Topology::CrossSurface { .. } => (true, true),
+            _ => (false, false),
+        }
+    }
+
+    /// The grid cell that `(x, y)` refers to, wrapping coordinates outside the
+    /// grid (and reflecting them across twisted seams). Returns None for cells
+    /// beyond the edges of a bounded grid.
+    pub fn map(&self, x: i64, y: i64) -> Option<(i64, i64)> {
+        let Some((left, top, width, height)) = self.bounds() else {
+            return Some((x, y));
+        };
+        if self.edge().is_some() {
+            let inside = (left..left + width).contains(&x) && (top..top + height).contains(&y);
+            return inside.then_some((x, y));
+        }
+        let (dx, dy) = ((x - left).div_euclid(width), (y - top).div_euclid(height));
+        Some(self.mirror(x - dx * width, y - dy * height, dx, dy))
+    }
+
+    /// The copy of grid cell `(x, y)` in the neighbouring tile `(dx, dy)` (in
+    /// units of the grid size), used to fill the border around the grid.
+    /// Tiles an odd number of twisted seams away from the grid are mirrored.
+    pub(crate) fn image(&self, x: i64, y: i64, dx: i64, dy: i64) -> (i64, i64) {
+        let (_, _, width, height) = self.bounds().expect("the plane has no tiles");
+        let (x, y) = self.mirror(x, y, dx, dy);
+        (x + dx * width, y + dy * height)
+    }
+
+    /// Reflect grid cell `(x, y)` as seen from tile `(dx, dy)`
+    fn mirror(&self, x: i64, y: i64, dx: i64, dy: i64) -> (i64, i64) {
+        let (left, top, width, height) = self.bounds().expect("the plane has no tiles");
+        let (mirror_x, mirror_y) = self.twists();
+        let x = if mirror_x && dy & 1 == 1 { 2 * left + width - 1 - x } else { x };
+        let y = if mirror_y && dx & 1 == 1 { 2 * top + height - 1 - y } else { y };
+        (x, y)
+    }
+
+    pub(crate) fn validate(&self) {
+        assert!(self.is_valid(), "grid dimensions must be between 1 and {}: {:?}", MAX_GRID_SIZE, self);
+    }
+}
+
+impl FromStr for Topology {
+    type Err = ParseTopologyError;
+
+    /// Parses a Golly grid descriptor, with or without the leading `:`:
+    /// `P40,30` (bounded, dead edges), `T40,30` (torus), `K40*,30` or `K40,30*`
+    /// (Klein bottle) and `C40,30` (cross-surface). A single size gives a square
+    /// grid and an empty descriptor is the plane. As an extension, `A40,30` is a
+    /// bounded grid with live edges.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix(':').unwrap_or(s);
+        let mut chars = s.chars();
+        let Some(kind) = chars.next() else {
+            return Ok(Topology::Plane);
+        };
+
+        let sizes: Vec<&str> = chars.as_str().split(',').collect();
+        if sizes.len() > 2 {
+            return Err(ParseTopologyError::InvalidSize(s.to_string()));
+        }
+        let mut twisted = [false; 2];
+        let mut dims = [0u64; 2];
+        for (i, size) in sizes.iter().enumerate() {
+            let size = size.trim();
+            let (size, star) = match size.strip_suffix('*') {
+                Some(size) => (size, true),
+                None => (size, false),
+            };
+            twisted[i] = star;
+            dims[i] = size.parse()
+                .ok()
+                .filter(|n| (1..=MAX_GRID_SIZE).contains(n))
+                .ok_or_else(|| ParseTopologyError::InvalidSize(size.to_string()))?;
+        }
+        if sizes.len() == 1 {
+            dims[1] = dims[0];
+        }
+        let [width, height] = dims;
+
+        let twist = match (kind.to_ascii_uppercase(), twisted) {
+            ('K', [true, false]) => Some(Twist::TopBottom),
+            ('K', [false, true]) => Some(Twist::LeftRight),
+            (_, [false, false]) => None,
+            _ => return Err(ParseTopologyError::InvalidTwist(s.to_string())),
+        };
+        Ok(match kind.to_ascii_uppercase() {
+            'P' => Topology::Bounded { width, height, edge: Edge::Dead },
+            'A' => Topology::Bounded { width, height, edge: Edge::Alive },
+            'T' => Topology::Torus { width, height },
+            'K' => match twist {
+                Some(twist) => Topology::KleinBottle { width, height, twist },
+                None => return Err(ParseTopologyError::InvalidTwist(s.to_string())),
+            },
+            'C' => Topology::CrossSurface { width, height },
+            _ => return Err(ParseTopologyError::UnknownTopology(s.to_string())),
+        })
+    }
+}
+
+impl fmt::Display for Topology {
+    /// Writes the grid descriptor with its leading `:`, so it can be appended
+    /// to a rulestring. The plane writes nothing.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Topology::Plane => Ok(()),
+            Topology::Torus { width, height } => write!(f, ":T{},{}", width, height),
+            Topology::Bounded { width, height, edge: Edge::Dead } => write!(f, ":P{},{}", width, height),
+            Topology::Bounded { width, height, edge: Edge::Alive } => write!(f, ":A{},{}", width, height),
+            Topology::KleinBottle { width, height, twist: Twist::TopBottom } => {
+                write!(f, ":K{}*,{}", width, height)
+            }
+            Topology::KleinBottle { width, height, twist: Twist::LeftRight } => {
+                write!(f, ":K{},{}*", width, height)
+            }
+            Topology::CrossSurface { width, height } => write!(f, ":C{},{}", width, height),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_torus_map() {
+        let torus = Topology::Torus { width: 5, height: 4 };
+        assert_eq!(torus.bounds(), Some((-2, -2, 5, 4)));
+        assert_eq!(torus.map(0, 0), Some((0, 0)));
+        assert_eq!(torus.map(3, 2), Some((-2, -2)));
+        assert_eq!(torus.map(-3, -3), Some((2, 1)));
+        assert_eq!(torus.map(13, -10), Some((-2, -2)));
+        assert_eq!(Topology::Plane.map(1 << 40, -7), Some((1 << 40, -7)));
+    }
+
+    #[test]
+    fn test_bounded_map() {
+        let bounded = Topology::Bounded { width: 4, height: 4, edge: Edge::Alive };
+        assert_eq!(bounded.bounds(), Some((-2, -2, 4, 4)));
+        assert_eq!(bounded.map(1, -2), Some((1, -2)));
+        assert_eq!(bounded.map(2, 0), None);
+        assert_eq!(bounded.map(0, -3), None);
+        assert_eq!(bounded.edge().map(Edge::state), Some(1));
+    }
+
+    #[test]
+    fn test_twisted_map() {
+        // Grid x in -2..3, y in -2..2
+        let klein = Topology::KleinBottle { width: 5, height: 4, twist: Twist::TopBottom };
+        assert_eq!(klein.map(-2, 2), Some((2, -2)));
+        assert_eq!(klein.map(1, -3), Some((-1, 1)));
+        assert_eq!(klein.map(3, 0), Some((-2, 0)));
+        assert_eq!(klein.map(0, 6), Some((0, -2)));
+
+        let klein = Topology::KleinBottle { width: 5, height: 4, twist: Twist::LeftRight };
+        assert_eq!(klein.map(3, -2), Some((-2, 1)));
+        assert_eq!(klein.map(-1, 2), Some((-1, -2)));
+
+        let cross = Topology::CrossSurface { width: 5, height: 4 };
+        assert_eq!(cross.map(1, 2), Some((-1, -2)));
+        assert_eq!(cross.map(3, 0), Some((-2, -1)));
+        assert_eq!(cross.map(-3, -3), Some((-2, -2)));
+
+        // Mapping is consistent with the images used to fill the border
+        for topology in [klein, cross] {
+            for (dx, dy) in [(-1, -1), (0, 1), (1, 0), (2, -1)] {
+                assert_eq!(topology.map(0, 1).map(|(x, y)| topology.image(x, y, dx, dy))
+                           .and_then(|(x, y)| topology.map(x, y)), Some((0, 1)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_descriptor() {
+        let cases = [
+            ("", Topology::Plane),
+            (":T100,80", Topology::Torus { width: 100, height: 80 }),
+            ("T30", Topology::Torus { width: 30, height: 30 }),
+            (":P10,20", Topology::Bounded { width: 10, height: 20, edge: Edge::Dead }),
+            (":A10,20", Topology::Bounded { width: 10, height: 20, edge: Edge::Alive }),
+            (":K40*,20", Topology::KleinBottle { width: 40, height: 20, twist: Twist::TopBottom }),
+            (":K40,20*", Topology::KleinBottle { width: 40, height: 20, twist: Twist::LeftRight }),
+            (":C8,6", Topology::CrossSurface { width: 8, height: 6 }),
+        ];
+        for (descriptor, topology) in cases {
+            assert_eq!(descriptor.parse::<Topology>(), Ok(topology));
+            assert_eq!(topology.to_string().parse::<Topology>(), Ok(topology));
+        }
+        assert_eq!(Topology::Torus { width: 3, height: 4 }.to_string(), ":T3,4");
+
+        assert!(matches!("X10,10".parse::<Topology>(), Err(ParseTopologyError::UnknownTopology(_))));
+        assert!(matches!("T0,10".parse::<Topology>(), Err(ParseTopologyError::InvalidSize(_))));
+        assert!(matches!("T10,ab".parse::<Topology>(), Err(ParseTopologyError::InvalidSize(_))));
+        assert!(matches!("T9223372036854775808,4".parse::<Topology>(), Err(ParseTopologyError::InvalidSize(_))));
+        assert!(matches!("T9223372036854775807,4".parse::<Topology>(), Err(ParseTopologyError::InvalidSize(_))));
+        assert!(matches!(format!("P{},1", MAX_GRID_SIZE + 1).parse::<Topology>(), Err(ParseTopologyError::InvalidSize(_))));
+        assert_eq!(format!("T{}", MAX_GRID_SIZE).parse::<Topology>(),
+                   Ok(Topology::Torus { width: MAX_GRID_SIZE, height: MAX_GRID_SIZE }));
+        assert!(!Topology::Torus { width: 4, height: 0 }.is_valid());
+        assert!(matches!("T10*,10".parse::<Topology>(), Err(ParseTopologyError::InvalidTwist(_))));
+        assert!(matches!("K10,10".parse::<Topology>(), Err(ParseTopologyError::InvalidTwist(_))));
+        assert!(matches!("K10*,10*".parse::<Topology>(), Err(ParseTopologyError::InvalidTwist(_))));
+    }
+}
@@ -0,0 +1,125 @@
+//! Undo and redo of edits
+//!
+//! Each edit (setting cells, pasting, filling, drawing, transforming,
+//! combining universes) keeps the tree from before it and the tree it made,
+//! which share all but the changed nodes. `Universe::undo` applies while the
+//! universe still holds what the last edit made: after stepping on it is not
+//! available, and after `step_back` to the edited generation it is again.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::hashlife::Node;
+
+/// Number of edits a universe can undo unless told otherwise
+pub const DEFAULT_UNDO_LIMIT: usize = 100;
+
+/// The trees before and after an edit, and whether they are stored complemented
+#[derive(Clone)]
+pub(crate) struct Edit {
+    pub(crate) before: Rc<Node>,
+    pub(crate) after: Rc<Node>,
+    pub(crate) inverted: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct UndoStack {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    /// Most edits kept for undoing (0 keeps none)
+    limit: usize,
+    /// Nesting depth of an edit made of several smaller ones, the tree before
+    /// it, and whether any of them changed the tree
+    group: Option<(usize, Rc<Node>, bool)>,
+}
+
+impl Default for UndoStack {
+    fn default() -> Self {
+        UndoStack { undo: VecDeque::new(), redo: Vec::new(), limit: DEFAULT_UNDO_LIMIT, group: None }
+    }
+}
+
+impl UndoStack {
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.undo.len() > limit {
+            self.undo.pop_front();
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        (self.undo.capacity() + self.redo.capacity()) * std::mem::size_of::<Edit>()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Keep an edit from `before` to `after`, which can no longer be redone
+    /// past. Inside a group it only marks the group as changed.
+    pub(crate) fn record(&mut self, before: &Rc<Node>, after: &Rc<Node>, inverted: bool) {
+        if let Some((_, _, changed)) = &mut self.group {
+            *changed = true;
+            return;
+        }
+        self.push(Edit { before: before.clone(), after: after.clone(), inverted });
+    }
+
+    fn push(&mut self, edit: Edit) {
+        self.redo.clear();
+        if self.limit == 0 {
+            return;
+        }
+        if self.undo.len() == self.limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(edit);
+    }
+
+    /// Start an edit made of smaller ones, from the tree `root`
+    pub(crate) fn begin_group(&mut self, root: &Rc<Node>) {
+        match &mut self.group {
+            Some((depth, _, _)) => *depth += 1,
+            None => self.group = Some((1, root.clone(), false)),
+        }
+    }
+
+    /// Finish an edit begun with `begin_group`, which made `root`
+    pub(crate) fn end_group(&mut self, root: &Rc<Node>, inverted: bool) {
+        let Some((depth, before, changed)) = self.group.take() else {
+            return;
+        };
+        if depth > 1 {
+            self.group = Some((depth - 1, before, changed));
+        } else if changed {
+            self.push(Edit { before, after: root.clone(), inverted });
+        }
+    }
+
+    pub(crate) fn last_undo(&self) -> Option<&Edit> {
+        self.undo.back()
+    }
+
+    pub(crate) fn last_redo(&self) -> Option<&Edit> {
+        self.redo.last()
+    }
+
+    /// Move the last edit to the redo stack, returning it
+    pub(crate) fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop_back()?;
+        self.redo.push(edit.clone());
+        Some(edit)
+    }
+
+    /// Move the last undone edit back to the undo stack, returning it
+    pub(crate) fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push_back(edit.clone());
+        Some(edit)
+    }
+}
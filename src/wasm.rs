@@ -1,5 +1,11 @@
 use wasm_bindgen::prelude::*;
-use crate::hashlife::Universe;
+use crate::analysis::{GrowthClass, RunLimits, StopReason};
+use crate::checkpoint::now;
+use crate::hashlife::{Universe, MAX_STEP_EXPONENT};
+use crate::edit::EditSymmetry;
+use crate::library;
+use crate::pattern::{Rect, Symmetry};
+use crate::rule::{ParseRuleError, Rule};
 
 #[wasm_bindgen]
 pub struct WasmUniverse {
@@ -17,6 +23,21 @@ impl WasmUniverse {
         }
     }
 
+    /// A universe holding a built-in pattern by name, centred on the origin
+    /// (see `listPatterns`)
+    #[wasm_bindgen(js_name = fromPattern)]
+    pub fn from_pattern(name: &str) -> Result<WasmUniverse, JsValue> {
+        let universe = Universe::from_library(name)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown pattern: {:?}", name)))?;
+        Ok(WasmUniverse { universe, size_level: 3 })
+    }
+
+    /// Names of the built-in patterns
+    #[wasm_bindgen(js_name = listPatterns)]
+    pub fn list_patterns() -> Vec<String> {
+        library::PATTERNS.iter().map(|pattern| pattern.name.to_string()).collect()
+    }
+
     #[wasm_bindgen(js_name = setCell)]
     pub fn set_cell(&mut self, x: i32, y: i32, alive: bool) {
         self.universe.set_cell(x as i64, y as i64, alive);
@@ -27,22 +48,319 @@ impl WasmUniverse {
         self.universe.get_cell(x as i64, y as i64)
     }
 
+    #[wasm_bindgen(js_name = setCellState)]
+    pub fn set_cell_state(&mut self, x: i32, y: i32, state: u8) {
+        self.universe.set_cell_state(x as i64, y as i64, state);
+    }
+
+    #[wasm_bindgen(js_name = getCellState)]
+    pub fn get_cell_state(&self, x: i32, y: i32) -> u8 {
+        self.universe.get_cell_state(x as i64, y as i64)
+    }
+
     pub fn step(&mut self) {
         self.universe.step();
     }
 
-    pub fn generation(&self) -> u64 {
-        self.universe.generation()
+    /// Switch to the rule given by a rulestring such as "B36/S23", keeping
+    /// the cells. Throws with the reason if it does not parse or the
+    /// universe cannot switch to it.
+    #[wasm_bindgen(js_name = setRule)]
+    pub fn set_rule(&mut self, rule: &str) -> Result<(), JsValue> {
+        let rule: Rule = rule.parse().map_err(|e: ParseRuleError| JsValue::from_str(&e.to_string()))?;
+        self.universe.set_rule(rule).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// The current rule as a rulestring
+    pub fn rule(&self) -> String {
+        self.universe.rule().to_string()
+    }
+
+    /// Advance `n` generations, in the largest supersteps that fit
+    pub fn run(&mut self, n: f64) -> Result<(), JsValue> {
+        let n = generations(n)?;
+        self.universe.step_to(self.universe.generation() + n).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Advance up to `n` generations in supersteps, stopping after the first
+    /// superstep that ends `budget_ms` milliseconds or more after the call
+    /// began. Returns the generations still to run, so a page can reach a
+    /// distant generation a slice at a time between frames without freezing
+    /// the tab. One superstep of a large chaotic pattern can on its own
+    /// outlast the budget.
+    #[wasm_bindgen(js_name = runChunk)]
+    pub fn run_chunk(&mut self, n: f64, budget_ms: f64) -> Result<f64, JsValue> {
+        let mut remaining = generations(n)?;
+        let deadline = now() + budget_ms / 1000.0;
+        while remaining > 0 {
+            let exponent = if self.universe.topology().is_infinite() {
+                (127 - remaining.leading_zeros()).min(MAX_STEP_EXPONENT)
+            } else {
+                0
+            };
+            self.universe.step_pow2(exponent).map_err(|e| JsValue::from_str(&e.to_string()))?;
+            remaining -= 1 << exponent;
+            if now() >= deadline {
+                break;
+            }
+        }
+        Ok(remaining as f64)
+    }
+
+    /// Advance by the current step size, adjusting it if adaptive stepping is on
+    #[wasm_bindgen(js_name = stepAdaptive)]
+    pub fn step_adaptive(&mut self) {
+        self.universe.step_adaptive();
+    }
+
+    #[wasm_bindgen(js_name = setAdaptiveStepping)]
+    pub fn set_adaptive_stepping(&mut self, enabled: bool) {
+        self.universe.set_adaptive_stepping(enabled);
+    }
+
+    /// Generations advanced per `stepAdaptive` call, as a power of two
+    #[wasm_bindgen(js_name = stepExponent)]
+    pub fn step_exponent(&self) -> u32 {
+        self.universe.step_exponent()
     }
 
+    /// Rewind to the previous generation. Returns false if no history is left.
+    #[wasm_bindgen(js_name = stepBack)]
+    pub fn step_back(&mut self) -> bool {
+        self.universe.step_back()
+    }
+
+    #[wasm_bindgen(js_name = canStepBack)]
+    pub fn can_step_back(&self) -> bool {
+        self.universe.history_len() > 0
+    }
+
+    /// The generation in decimal, since it can pass what a u64 holds
+    pub fn generation(&self) -> String {
+        self.universe.generation().to_string()
+    }
+
+    /// The population, saturating at what a u64 holds
     pub fn population(&self) -> u64 {
         self.universe.population()
     }
 
+    /// The population in decimal, however large
+    #[wasm_bindgen(js_name = exactPopulation)]
+    pub fn exact_population(&self) -> String {
+        self.universe.exact_population().to_string()
+    }
+
+    /// The population as a JS number, for display
+    #[wasm_bindgen(js_name = populationF64)]
+    pub fn population_f64(&self) -> f64 {
+        self.universe.population_f64()
+    }
+
+    /// How the population grows over the next `window` generations:
+    /// "bounded", "linear" or "quadratic"
+    #[wasm_bindgen(js_name = growthClass)]
+    pub fn growth_class(&mut self, window: u32) -> String {
+        match self.universe.growth_rate(window as u64).class {
+            GrowthClass::Bounded => "bounded",
+            GrowthClass::Linear => "linear",
+            GrowthClass::Quadratic => "quadratic",
+        }.to_string()
+    }
+
+    /// Step until the pattern dies out or becomes periodic, for at most
+    /// `max_generations` generations (see `Universe::run_until_stable`)
+    #[wasm_bindgen(js_name = runUntilStable)]
+    pub fn run_until_stable(&mut self, max_generations: f64) -> Result<StableRun, JsValue> {
+        let max_generations = generations(max_generations)?.try_into().unwrap_or(u64::MAX);
+        let limits = RunLimits { max_generations, ..RunLimits::default() };
+        let start = self.universe.generation();
+        let reason = self.universe.run_until_stable(limits);
+        let (name, period) = match reason {
+            StopReason::Died { .. } => ("died", None),
+            StopReason::Periodic { periodicity, .. } => ("periodic", Some(periodicity.period as f64)),
+            StopReason::GenerationLimit { .. } => ("generation-limit", None),
+            StopReason::PopulationLimit { .. } => ("population-limit", None),
+        };
+        Ok(StableRun { reason: name.to_string(), period, generations_run: (self.universe.generation() - start) as f64 })
+    }
+
+    /// Hash of the cells and where they are, the same on every machine
+    pub fn fingerprint(&self) -> u64 {
+        self.universe.fingerprint()
+    }
+
+    /// Approximate bytes used, as [nodes, results, history, other]
+    #[wasm_bindgen(js_name = memoryUsage)]
+    pub fn memory_usage(&self) -> Vec<f64> {
+        let usage = self.universe.memory_usage();
+        [usage.nodes, usage.results, usage.history, usage.other].map(|bytes| bytes as f64).to_vec()
+    }
+
+    /// Keep memory use within about `megabytes`, collecting garbage as needed (0 for no limit)
+    #[wasm_bindgen(js_name = setMemoryLimit)]
+    pub fn set_memory_limit(&mut self, megabytes: u32) {
+        self.universe.set_memory_limit((megabytes > 0).then_some(megabytes as usize * (1 << 20)));
+    }
+
+    /// Keep up to `limit` population samples for graphing (0 stops recording)
+    #[wasm_bindgen(js_name = setPopulationHistoryLimit)]
+    pub fn set_population_history_limit(&mut self, limit: usize) {
+        self.universe.set_population_history_limit(limit);
+    }
+
+    /// Track how long each live cell has been alive, for colouring by age
+    #[wasm_bindgen(js_name = setAgeTracking)]
+    pub fn set_age_tracking(&mut self, enabled: bool) {
+        self.universe.set_age_tracking(enabled);
+    }
+
+    /// Live cells in the view with their ages, as a flat array: [x, y, age, ...]
+    #[wasm_bindgen(js_name = getCellAges)]
+    pub fn get_cell_ages(&self, x_min: i32, y_min: i32, x_max: i32, y_max: i32) -> Vec<f64> {
+        let rect = Rect::new(x_min as i64, y_min as i64, (x_max - x_min + 1) as i64, (y_max - y_min + 1) as i64);
+        self.universe.cell_ages(rect).into_iter()
+            .flat_map(|(x, y, age)| [x as f64, y as f64, age as f64])
+            .collect()
+    }
+
+    /// How many of the next `generations` generations the cells of a region
+    /// are alive, summed over `tile` by `tile` tiles, row by row
+    #[wasm_bindgen(js_name = activityMap)]
+    pub fn activity_map(&mut self, x: i32, y: i32, width: i32, height: i32, generations: u32, tile: u32) -> Vec<f64> {
+        let rect = Rect::new(x as i64, y as i64, width as i64, height as i64);
+        let map = self.universe.activity_map(rect, generations as u64, tile);
+        map.counts.into_iter().map(|count| count as f64).collect()
+    }
+
+    /// Recorded samples as a flat array: [generation, population, ...]
+    #[wasm_bindgen(js_name = populationHistory)]
+    pub fn population_history(&self) -> Vec<f64> {
+        self.universe.population_history().iter()
+            .flat_map(|&(generation, population)| [generation as f64, population as f64])
+            .collect()
+    }
+
+    #[wasm_bindgen(js_name = statePopulation)]
+    pub fn state_population(&self, state: u8) -> u64 {
+        self.universe.state_population(state)
+    }
+
+    /// Kill every cell in the rectangle, however large, in one pass
+    #[wasm_bindgen(js_name = clearRegion)]
+    pub fn clear_region(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.universe.clear_region(Rect::new(x as i64, y as i64, width as i64, height as i64));
+    }
+
+    #[wasm_bindgen(js_name = drawLine)]
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, state: u8) {
+        self.universe.draw_line((x0 as i64, y0 as i64), (x1 as i64, y1 as i64), state);
+    }
+
+    /// Draw a rectangle, either filled or as a one-cell-wide border
+    #[wasm_bindgen(js_name = drawRect)]
+    pub fn draw_rect(&mut self, x: i32, y: i32, width: i32, height: i32, state: u8, filled: bool) {
+        self.universe.draw_rect(Rect::new(x as i64, y as i64, width as i64, height as i64), state, filled);
+    }
+
+    /// Draw the ellipse filling a rectangle, either filled or as an outline
+    #[wasm_bindgen(js_name = drawEllipse)]
+    pub fn draw_ellipse(&mut self, x: i32, y: i32, width: i32, height: i32, state: u8, filled: bool) {
+        self.universe.draw_ellipse(Rect::new(x as i64, y as i64, width as i64, height as i64), state, filled);
+    }
+
+    /// Flip every cell in the rectangle between dead and alive
+    #[wasm_bindgen(js_name = invertRegion)]
+    pub fn invert_region(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.universe.invert_region(Rect::new(x as i64, y as i64, width as i64, height as i64));
+    }
+
+    /// Mirror later setCell calls with the symmetry "C2", "C4", "D2", "D4" or
+    /// "D8" about a centre given in half cells (see `EditSymmetry`), or stop
+    /// mirroring them with an empty string
+    #[wasm_bindgen(js_name = setEditSymmetry)]
+    pub fn set_edit_symmetry(&mut self, symmetry: &str, centre_x: i32, centre_y: i32) -> Result<(), JsValue> {
+        let symmetry = match symmetry {
+            "" => None,
+            "C2" => Some(Symmetry::C2),
+            "C4" => Some(Symmetry::C4),
+            "D2" => Some(Symmetry::D2),
+            "D4" => Some(Symmetry::D4),
+            "D8" => Some(Symmetry::D8),
+            _ => return Err(JsValue::from_str(&format!("unknown symmetry: {:?}", symmetry))),
+        };
+        if symmetry.is_some_and(|s| s.swaps_axes() && (centre_x - centre_y) % 2 != 0) {
+            return Err(JsValue::from_str("this symmetry needs a centre on a cell or a corner"));
+        }
+        let centre = (centre_x as i64, centre_y as i64);
+        self.universe.set_edit_symmetry(symmetry.map(|symmetry| EditSymmetry { symmetry, centre }));
+        Ok(())
+    }
+
+    /// Move every cell by the given offset
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        self.universe.translate(dx as i64, dy as i64);
+    }
+
+    /// Replace the cells in the rectangle with a soup of the given density;
+    /// the same seed always gives the same soup
+    #[wasm_bindgen(js_name = fillRandom)]
+    pub fn fill_random(&mut self, x: i32, y: i32, width: i32, height: i32, density: f64, seed: u64) {
+        self.universe.fill_random(Rect::new(x as i64, y as i64, width as i64, height as i64), density, seed);
+    }
+
     pub fn clear(&mut self) {
         self.universe = Universe::new(self.size_level);
     }
 
+    /// The universe as a binary snapshot, e.g. for storing in IndexedDB
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.universe.to_bytes()
+    }
+
+    /// Replace the universe with one read from `toBytes` output
+    #[wasm_bindgen(js_name = loadBytes)]
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.universe = Universe::from_bytes(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(())
+    }
+
+    /// The universe as a macrocell file, which stores the quadtree and so
+    /// keeps even gigantic patterns exactly, e.g. for storing in IndexedDB or
+    /// opening in Golly
+    #[wasm_bindgen(js_name = exportMacrocell)]
+    pub fn export_macrocell(&self) -> Vec<u8> {
+        self.universe.to_macrocell().into_bytes()
+    }
+
+    /// Replace the universe with a macrocell file, taking its rule and generation
+    #[wasm_bindgen(js_name = importMacrocell)]
+    pub fn import_macrocell(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let text = std::str::from_utf8(bytes).map_err(|_| JsValue::from_str("macrocell file is not UTF-8 text"))?;
+        self.universe.load_macrocell(text).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Replace the universe with an RLE pattern, such as one pasted from Golly
+    /// or LifeWiki, with its top left corner at `(x, y)`. A rule in the
+    /// header replaces the current rule.
+    #[wasm_bindgen(js_name = loadRLE)]
+    pub fn load_rle(&mut self, rle: &str, x: i32, y: i32) -> Result<(), JsValue> {
+        self.universe.load_rle(rle, x as i64, y as i64).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// The cells of `rect`, given as [x, y, width, height], or of the whole
+    /// pattern if it is left out, as RLE with the rule in the header
+    #[wasm_bindgen(js_name = saveRLE)]
+    pub fn save_rle(&self, rect: Option<Vec<i32>>) -> Result<String, JsValue> {
+        let region = match rect.as_deref() {
+            None => None,
+            Some(&[x, y, width, height]) => Some(Rect::new(x as i64, y as i64, width as i64, height as i64)),
+            Some(_) => return Err(JsValue::from_str("rect must be [x, y, width, height]")),
+        };
+        Ok(self.universe.to_rle(region))
+    }
+
     #[wasm_bindgen(js_name = setCells)]
     pub fn set_cells(&mut self, cells: &[i32]) {
         for i in (0..cells.len()).step_by(2) {
@@ -106,4 +424,72 @@ impl WasmUniverse {
         }
         result
     }
+
+    /// Like getRenderRegions, but returns [x, y, size, density, state] tuples,
+    /// where state is the cell state (or the most common state of an aggregated
+    /// region) for colouring multi-state rules.
+    #[wasm_bindgen(js_name = getStateRegions)]
+    pub fn get_state_regions(
+        &self,
+        view_x_min: i32,
+        view_y_min: i32,
+        view_x_max: i32,
+        view_y_max: i32,
+        min_render_size: u32,
+    ) -> Vec<f32> {
+        let regions = self.universe.collect_state_regions(
+            view_x_min as i64,
+            view_y_min as i64,
+            view_x_max as i64,
+            view_y_max as i64,
+            min_render_size,
+        );
+
+        let mut result = Vec::with_capacity(regions.len() * 5);
+        for (x, y, size, density, state) in regions {
+            result.push(x as f32);
+            result.push(y as f32);
+            result.push(size as f32);
+            result.push(density);
+            result.push(state as f32);
+        }
+        result
+    }
+}
+
+/// What `runUntilStable` found
+#[wasm_bindgen(getter_with_clone)]
+pub struct StableRun {
+    /// "died", "periodic", "generation-limit" or "population-limit"
+    pub reason: String,
+    /// Period of a periodic pattern, and otherwise undefined
+    pub period: Option<f64>,
+    #[wasm_bindgen(js_name = generationsRun)]
+    pub generations_run: f64,
+}
+
+/// A count of generations passed from JavaScript as a number
+fn generations(n: f64) -> Result<u128, JsValue> {
+    if n >= 0.0 && n.fract() == 0.0 && n < u128::MAX as f64 {
+        Ok(n as u128)
+    } else {
+        Err(JsValue::from_str(&format!("not a number of generations: {}", n)))
+    }
+}
+
+#[cfg(feature = "share")]
+#[wasm_bindgen]
+impl WasmUniverse {
+    /// The pattern as a URL-safe string (see `Universe::to_share_string`)
+    #[wasm_bindgen(js_name = toShareString)]
+    pub fn to_share_string(&self) -> String {
+        self.universe.to_share_string()
+    }
+
+    /// Replace the universe with one read from `toShareString` output
+    #[wasm_bindgen(js_name = loadShareString)]
+    pub fn load_share_string(&mut self, text: &str) -> Result<(), JsValue> {
+        self.universe = Universe::from_share_string(text).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(())
+    }
 }